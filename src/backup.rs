@@ -0,0 +1,314 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::encryption::StagedWrite;
+use crate::retention::RETENTION_LOG;
+use crate::template::TEMPLATES_FILE;
+
+/// State files that get backed up, relative to the state directory
+pub const STATE_FILES: [&str; 6] = ["sessions", "prompts", "inputs", "variables", TEMPLATES_FILE, RETENTION_LOG];
+
+const BACKUP_DIRECTORY: &str = "backups";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Keeps rotating, gzip-compressed snapshots of the state files in `<state_dir>/backups/<timestamp>/`.
+/// A snapshot taken in the same second as an earlier one gets a `-2`, `-3`, ... suffix.
+#[derive(Debug, Clone)]
+pub struct BackupManager {
+    state_directory: PathBuf,
+    keep: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub timestamp: String,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<String>,
+    pub compressed_size: u64,
+}
+
+impl BackupManager {
+    /// `keep` is the number of snapshots to retain; 0 disables backups
+    pub fn new(state_directory: &str, keep: usize) -> Self {
+        Self {
+            state_directory: PathBuf::from(state_directory),
+            keep,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.keep > 0
+    }
+
+    fn backups_directory(&self) -> PathBuf {
+        self.state_directory.join(BACKUP_DIRECTORY)
+    }
+
+    /// Snapshot all existing state files and rotate out the oldest snapshots.
+    /// Returns None when backups are disabled or there is nothing to back up.
+    pub fn create_backup(&self) -> Result<Option<BackupInfo>> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+
+        let existing: Vec<&str> = STATE_FILES
+            .iter()
+            .copied()
+            .filter(|name| self.state_directory.join(name).exists())
+            .collect();
+        if existing.is_empty() {
+            return Ok(None);
+        }
+
+        let (timestamp, snapshot_dir) = self.create_snapshot_directory(Utc::now())?;
+
+        for name in &existing {
+            let content = fs::read(self.state_directory.join(name))?;
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&content)?;
+            fs::write(snapshot_dir.join(format!("{}.gz", name)), encoder.finish()?)?;
+        }
+
+        self.rotate()?;
+        Self::read_backup(&snapshot_dir, &timestamp).map(Some)
+    }
+
+    /// Take a snapshot if a state file changed since the newest one and that one is
+    /// at least `min_age` old (or there are no snapshots yet)
+    pub fn backup_if_changed(&self, min_age: Duration) -> Result<Option<BackupInfo>> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+
+        let latest = self.list_backups()?.into_iter().next();
+        if let Some(latest) = latest {
+            if Utc::now() - latest.created_at < min_age {
+                return Ok(None);
+            }
+            // Compare against the snapshot's own files; directory names only have second precision
+            let snapshot_dir = self.backups_directory().join(&latest.timestamp);
+            let changed = STATE_FILES.iter().any(|name| {
+                let modified = |path: PathBuf| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+                match modified(self.state_directory.join(name)) {
+                    Some(state_modified) => modified(snapshot_dir.join(format!("{}.gz", name)))
+                        .map(|backup_modified| state_modified > backup_modified)
+                        .unwrap_or(true),
+                    None => false,
+                }
+            });
+            if !changed {
+                return Ok(None);
+            }
+        }
+
+        self.create_backup()
+    }
+
+    /// All snapshots, newest first
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        let backups_dir = self.backups_directory();
+        if !backups_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(&backups_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let timestamp = entry.file_name().to_string_lossy().to_string();
+            // Skip anything that isn't one of our snapshot directories
+            if parse_snapshot_name(&timestamp).is_none() {
+                continue;
+            }
+            backups.push(Self::read_backup(&entry.path(), &timestamp)?);
+        }
+
+        backups.sort_by_key(|backup| std::cmp::Reverse(parse_snapshot_name(&backup.timestamp)));
+        Ok(backups)
+    }
+
+    /// Roll the state files back to a snapshot. The current files are backed up first,
+    /// so a restore can itself be undone. Returns the restored file names.
+    pub fn restore_backup(&self, timestamp: &str) -> Result<Vec<String>> {
+        let snapshot_dir = self.backups_directory().join(timestamp);
+        if parse_snapshot_name(timestamp).is_none() || !snapshot_dir.is_dir() {
+            return Err(anyhow::anyhow!("No backup with timestamp '{}'", timestamp));
+        }
+
+        // Decompress everything before touching the state directory
+        let mut restored = Vec::new();
+        for name in STATE_FILES {
+            if let Some(content) = self.read_file(timestamp, name)? {
+                restored.push((name, content));
+            }
+        }
+        if restored.is_empty() {
+            return Err(anyhow::anyhow!("Backup '{}' contains no state files", timestamp));
+        }
+
+        self.create_backup()?;
+
+        for (name, content) in &restored {
+            fs::write(self.state_directory.join(name), content)?;
+        }
+        Ok(restored.into_iter().map(|(name, _)| name.to_string()).collect())
+    }
+
+    /// One state file from a snapshot, decompressed, or None if the snapshot doesn't have it
+    pub fn read_file(&self, timestamp: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.backups_directory().join(timestamp).join(format!("{}.gz", name));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut content = Vec::new();
+        GzDecoder::new(fs::File::open(&path)?)
+            .read_to_end(&mut content)
+            .map_err(|e| anyhow::anyhow!("Backup file {} is corrupt: {}", path.display(), e))?;
+        Ok(Some(content))
+    }
+
+    /// Rewrite the contents of every backed-up file in memory, e.g. to re-encrypt them under
+    /// a new key. Nothing is written; the caller commits the result with `commit_writes`.
+    pub fn stage_transform_backups<F>(&self, transform: F) -> Result<Vec<StagedWrite>>
+    where
+        F: Fn(&[u8]) -> Result<Vec<u8>>,
+    {
+        let mut staged = Vec::new();
+        for backup in self.list_backups()? {
+            let snapshot_dir = self.backups_directory().join(&backup.timestamp);
+            for name in &backup.files {
+                let path = snapshot_dir.join(format!("{}.gz", name));
+                let original = fs::read(&path)?;
+                let mut content = Vec::new();
+                GzDecoder::new(original.as_slice()).read_to_end(&mut content)?;
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&transform(&content)?)?;
+                staged.push(StagedWrite::new(path, original, encoder.finish()?));
+            }
+        }
+        Ok(staged)
+    }
+
+    /// Create the directory for a new snapshot, never reusing one from the same second
+    fn create_snapshot_directory(&self, created_at: DateTime<Utc>) -> Result<(String, PathBuf)> {
+        let base = created_at.format(TIMESTAMP_FORMAT).to_string();
+        fs::create_dir_all(self.backups_directory())?;
+        for counter in 1.. {
+            let timestamp = if counter == 1 { base.clone() } else { format!("{}-{}", base, counter) };
+            let snapshot_dir = self.backups_directory().join(&timestamp);
+            match fs::create_dir(&snapshot_dir) {
+                Ok(()) => return Ok((timestamp, snapshot_dir)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        unreachable!()
+    }
+
+    fn rotate(&self) -> Result<()> {
+        for backup in self.list_backups()?.into_iter().skip(self.keep) {
+            fs::remove_dir_all(self.backups_directory().join(&backup.timestamp))?;
+        }
+        Ok(())
+    }
+
+    fn read_backup(snapshot_dir: &Path, timestamp: &str) -> Result<BackupInfo> {
+        let (created_at, _) = parse_snapshot_name(timestamp)
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not a backup name", timestamp))?;
+        let mut files = Vec::new();
+        let mut compressed_size = 0;
+        for name in STATE_FILES {
+            if let Ok(metadata) = fs::metadata(snapshot_dir.join(format!("{}.gz", name))) {
+                files.push(name.to_string());
+                compressed_size += metadata.len();
+            }
+        }
+        Ok(BackupInfo {
+            timestamp: timestamp.to_string(),
+            created_at,
+            files,
+            compressed_size,
+        })
+    }
+}
+
+/// The creation time and same-second counter encoded in a snapshot directory name
+fn parse_snapshot_name(name: &str) -> Option<(DateTime<Utc>, u32)> {
+    let (timestamp, counter) = match name.get(15..) {
+        Some("") => (name, 1),
+        Some(suffix) => (&name[..15], suffix.strip_prefix('-')?.parse().ok().filter(|counter| *counter > 1)?),
+        None => return None,
+    };
+    let created_at = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?.and_utc();
+    Some((created_at, counter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_backup_at(manager: &BackupManager, timestamp: &str) {
+        let dir = manager.backups_directory().join(timestamp);
+        fs::create_dir_all(&dir).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(timestamp.as_bytes()).unwrap();
+        fs::write(dir.join("sessions.gz"), encoder.finish().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = BackupManager::new(&dir.path().to_string_lossy(), 3);
+        fs::write(dir.path().join("sessions"), "{\"saved_sessions\":{}}").unwrap();
+
+        let backup = manager.create_backup().unwrap().unwrap();
+        assert_eq!(backup.files, vec!["sessions"]);
+        assert!(manager.backup_if_changed(Duration::zero()).unwrap().is_none());
+
+        fs::write(dir.path().join("sessions"), "corrupted").unwrap();
+        assert_eq!(manager.restore_backup(&backup.timestamp).unwrap(), vec!["sessions"]);
+        assert_eq!(fs::read_to_string(dir.path().join("sessions")).unwrap(), "{\"saved_sessions\":{}}");
+
+        // The safety snapshot taken by a restore in the same second gets its own directory
+        let restored_again = manager.restore_backup(&backup.timestamp).unwrap();
+        assert_eq!(restored_again, vec!["sessions"]);
+        let snapshot = manager.read_file(&backup.timestamp, "sessions").unwrap().unwrap();
+        assert_eq!(snapshot, b"{\"saved_sessions\":{}}");
+        assert_eq!(manager.list_backups().unwrap().len(), 3);
+        let now = Utc::now();
+        let (first, _) = manager.create_snapshot_directory(now).unwrap();
+        let (second, _) = manager.create_snapshot_directory(now).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(parse_snapshot_name(&second).unwrap().1, parse_snapshot_name(&first).unwrap().1 + 1);
+
+        assert!(manager.restore_backup("20000101-000000").is_err());
+        assert!(manager.restore_backup("20000101-000000-x").is_err());
+        assert!(manager.restore_backup("../sessions").is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = BackupManager::new(&dir.path().to_string_lossy(), 2);
+        for timestamp in ["20250101-000000", "20250102-000000", "20250103-000000"] {
+            create_backup_at(&manager, timestamp);
+        }
+        fs::create_dir_all(manager.backups_directory().join("not-a-backup")).unwrap();
+
+        fs::write(dir.path().join("prompts"), "{}").unwrap();
+        let latest = manager.create_backup().unwrap().unwrap();
+
+        let timestamps: Vec<String> = manager.list_backups().unwrap().into_iter().map(|b| b.timestamp).collect();
+        assert_eq!(timestamps, vec![latest.timestamp, "20250103-000000".to_string()]);
+
+        assert!(BackupManager::new(&dir.path().to_string_lossy(), 0).create_backup().unwrap().is_none());
+    }
+}
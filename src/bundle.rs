@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::prompts::{PromptLibrary, SystemPrompt};
+use crate::session::ChatSession;
+use crate::variable::VariableSource;
+
+const BUNDLE_FORMAT: &str = "njord-session-bundle";
+const BUNDLE_VERSION: u32 = 1;
+
+/// A session packed into one file for /chat share, with everything needed to continue it elsewhere
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionBundle {
+    pub format: String,
+    pub version: u32,
+    pub shared_at: DateTime<Utc>,
+    pub session: ChatSession,
+    #[serde(default)]
+    pub variable_values: BTreeMap<String, String>, // Value of each non-file variable when shared
+    #[serde(default)]
+    pub files: BTreeMap<String, String>, // Path -> content for `@path` variables
+    #[serde(default)]
+    pub prompts: BTreeMap<String, SystemPrompt>, // Library entries the session's system prompt came from
+}
+
+impl SessionBundle {
+    /// Pack a session, evaluating its variables now. Variables that can't be evaluated
+    /// are left out and reported, so the recipient gets them as live bindings.
+    pub fn create(session: &ChatSession, prompts: &PromptLibrary) -> (Self, Vec<String>) {
+        let mut variable_values = BTreeMap::new();
+        let mut files = BTreeMap::new();
+        let mut warnings = Vec::new();
+        for (name, var) in &session.variables {
+            let value = match &var.frozen_value {
+                Some(value) => Ok(value.clone()),
+                None => var.source.evaluate_sync(),
+            };
+            match (value, &var.source) {
+                (Ok(value), VariableSource::File(path)) => {
+                    files.insert(path.display().to_string(), value);
+                }
+                (Ok(value), _) => {
+                    variable_values.insert(name.clone(), value);
+                }
+                (Err(e), _) => warnings.push(format!("Variable '{}' not included: {}", name, e)),
+            }
+        }
+
+        let prompts = match &session.system_prompt {
+            Some(system_prompt) => prompts
+                .list_prompts()
+                .into_iter()
+                .filter_map(|name| prompts.get_prompt(name).map(|prompt| (name.clone(), prompt.clone())))
+                .filter(|(_, prompt)| &prompt.content == system_prompt)
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        // The journal holds text the user undid or edited away
+        let mut session = session.clone();
+        session.journal.clear();
+
+        let bundle = Self {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            shared_at: Utc::now(),
+            session,
+            variable_values,
+            files,
+            prompts,
+        };
+        (bundle, warnings)
+    }
+
+    /// Bundles are plain JSON, never encrypted with the state key, so they can be handed over
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        if value["format"] != BUNDLE_FORMAT {
+            return Err(anyhow!("{} is not an njord session bundle", path.display()));
+        }
+        let bundle: Self = serde_json::from_value(value)?;
+        if bundle.version > BUNDLE_VERSION {
+            return Err(anyhow!("Bundle version {} is newer than this njord supports ({})", bundle.version, BUNDLE_VERSION));
+        }
+        Ok(bundle)
+    }
+
+    /// Replace command variables with their shared output, so the commands themselves
+    /// (which may embed tokens or internal hosts) don't end up in the recipient's state,
+    /// and nothing the sender wrote runs on the recipient's machine. Values recorded with
+    /// each reply and the hashes of command output are dropped as well.
+    /// Returns the names of the variables that were changed.
+    pub fn strip_secrets(&mut self) -> Vec<String> {
+        let mut stripped = Vec::new();
+        for (name, var) in self.session.variables.iter_mut() {
+            if matches!(var.source, VariableSource::Command { .. }) {
+                let value = self.variable_values.get(name).cloned().or_else(|| var.frozen_value.clone()).unwrap_or_default();
+                var.source = VariableSource::Literal(value);
+                var.frozen_value = None;
+                stripped.push(name.clone());
+            }
+        }
+        for params in self.session.messages.iter_mut().filter_map(|msg| msg.params.as_mut()) {
+            params.strip_secrets(&stripped);
+        }
+        stripped.sort();
+        stripped
+    }
+
+    /// The session with each variable frozen to the value the sender saw
+    pub fn into_session(self) -> ChatSession {
+        let mut session = self.session;
+        for (name, var) in session.variables.iter_mut() {
+            let value = match &var.source {
+                VariableSource::Literal(_) => None,
+                VariableSource::File(path) => self.files.get(&path.display().to_string()).cloned(),
+                VariableSource::Command { .. } => self.variable_values.get(name).cloned(),
+            };
+            if let Some(value) = value {
+                var.freeze(value);
+            }
+        }
+        session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Operation;
+    use crate::providers::Message;
+    use crate::session::GenerationParams;
+    use crate::variable::Variable;
+
+    fn shared_session(dir: &Path) -> ChatSession {
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, "file body").unwrap();
+
+        let mut session = ChatSession::new("gpt-4o".to_string(), 0.7, 1000, 5000);
+        session.name = Some("design".to_string());
+        session.system_prompt = Some("Be a reviewer".to_string());
+        session.add_message(Message { role: "user".to_string(), content: "See {{notes}} and {{token}}".to_string() });
+        session.variables.insert("notes".to_string(), Variable::new("notes".to_string(), VariableSource::File(notes)));
+        session.variables.insert(
+            "token".to_string(),
+            Variable::new("token".to_string(), VariableSource::parse("!echo secret-output").unwrap()),
+        );
+        session.variables.insert(
+            "broken".to_string(),
+            Variable::new("broken".to_string(), VariableSource::File(dir.join("missing.txt"))),
+        );
+        session
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = shared_session(dir.path());
+        session.journal_operation(Operation::Undo { removed: session.messages.clone() });
+        let mut library = PromptLibrary::new(dir.path().join("prompts").to_string_lossy().to_string());
+        library.save_prompt("reviewer".to_string(), "Be a reviewer".to_string()).unwrap();
+        library.save_prompt("other".to_string(), "Unrelated".to_string()).unwrap();
+
+        let (bundle, warnings) = SessionBundle::create(&session, &library);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("broken"));
+        assert_eq!(bundle.prompts.keys().collect::<Vec<_>>(), vec!["reviewer"]);
+
+        let path = dir.path().join("design.njord");
+        bundle.write(&path).unwrap();
+        fs::remove_file(dir.path().join("notes.txt")).unwrap();
+
+        let received = SessionBundle::read(&path).unwrap().into_session();
+        assert_eq!(received.messages.len(), 1);
+        assert!(received.journal.is_empty());
+        assert_eq!(received.variables["notes"].frozen_value.as_deref(), Some("file body"));
+        assert_eq!(received.variables["token"].frozen_value.as_deref(), Some("secret-output\n"));
+        assert!(!received.variables["broken"].is_frozen());
+
+        fs::write(&path, "{\"format\": \"something-else\"}").unwrap();
+        assert!(SessionBundle::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_strip_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = shared_session(dir.path());
+        session.add_message(Message { role: "assistant".to_string(), content: "Reviewed".to_string() });
+        let inline = GenerationParams::inline_key("!cat ~/.token");
+        session.messages[1].params = Some(GenerationParams {
+            temperature: 0.7,
+            max_tokens: 1000,
+            thinking_enabled: false,
+            thinking_budget: 5000,
+            system_prompt_hash: None,
+            system_prompt: None,
+            variable_hashes: BTreeMap::from([
+                ("notes".to_string(), GenerationParams::hash("file body")),
+                ("token".to_string(), GenerationParams::hash("secret-output\n")),
+                (inline.clone(), GenerationParams::hash("hunter2")),
+            ]),
+            variable_values: BTreeMap::from([(inline, "hunter2".to_string())]),
+            excluded: vec![],
+        });
+        let (mut bundle, _) = SessionBundle::create(&session, &PromptLibrary::new(String::new()));
+
+        assert_eq!(bundle.strip_secrets(), vec!["token"]);
+        let params = bundle.session.messages[1].params.as_ref().unwrap();
+        assert!(params.variable_values.is_empty());
+        assert_eq!(params.variable_hashes.keys().collect::<Vec<_>>(), vec!["notes"]);
+        let received = bundle.into_session();
+        assert_eq!(received.variables["token"].source, VariableSource::Literal("secret-output\n".to_string()));
+        assert!(matches!(received.variables["notes"].source, VariableSource::File(_)));
+    }
+}
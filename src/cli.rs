@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// Returns the default state directory following XDG Base Directory specification.
 /// Uses $XDG_DATA_HOME/njord if set, otherwise ~/.local/share/njord
@@ -45,7 +45,7 @@ pub struct Args {
     #[arg(long, default_value = "20000")]
     pub thinking_budget: u32,
     
-    /// Load a specific chat session
+    /// Load a specific chat session (with --prompt, the exchange is appended to it)
     #[arg(long)]
     pub load_session: Option<String>,
     
@@ -60,6 +60,82 @@ pub struct Args {
     /// Run in ephemeral mode (don't save any changes to disk)
     #[arg(long)]
     pub ephemeral: bool,
+    
+    /// Days to keep deleted sessions in the trash before purging (0 keeps them forever)
+    #[arg(long, default_value = "30")]
+    pub trash_retention_days: u32,
+    
+    /// Number of compressed state backups to keep (0 disables backups)
+    #[arg(long, default_value = "10")]
+    pub backup_count: usize,
+    
+    /// Minutes between automatic backups while running
+    #[arg(long, default_value = "60")]
+    pub backup_interval: u32,
+    
+    /// Keyfile for encrypting state files (otherwise NJORD_PASSPHRASE or a prompt is used)
+    #[arg(long)]
+    pub keyfile: Option<String>,
+    
+    /// Store the variable values sent with each request alongside the reply, so /reproduce
+    /// can replay them after they change (saved sessions grow accordingly)
+    #[arg(long)]
+    pub record_values: bool,
+    
+    /// Ignore any project-local .njord workspace and use the state directory directly
+    #[arg(long)]
+    pub no_workspace: bool,
+    
+    /// Send a single prompt, print the reply and exit; piped stdin is added as context
+    #[arg(short, long)]
+    pub prompt: Option<String>,
+    
+    /// System prompt for a one-shot request
+    #[arg(long)]
+    pub system: Option<String>,
+    
+    /// Use a saved prompt as the system prompt for a one-shot request
+    #[arg(long, conflicts_with = "system")]
+    pub apply_prompt: Option<String>,
+    
+    /// Run the commands and messages in a script file, then exit
+    #[arg(long, conflicts_with = "prompt")]
+    pub script: Option<String>,
+    
+    /// Keep running a --script after a step fails
+    #[arg(long, requires = "script")]
+    pub keep_going: bool,
+    
+    /// Report replies, command output and errors as JSON events (with --prompt, ask or --script)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+/// How one-shot and script runs report what happens
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// Coloured text, as in the REPL
+    #[default]
+    Text,
+    /// One JSON array of events, printed at the end
+    Json,
+    /// One JSON event per line as it happens, including streamed deltas
+    Jsonl,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Check the state files for damage and report what can be recovered
+    Doctor,
+    /// Send a single prompt, print the reply and exit (same as --prompt)
+    Ask {
+        /// The prompt; piped stdin is added as context
+        #[arg(required = true, num_args = 1..)]
+        prompt: Vec<String>,
+    },
 }
 
 #[cfg(test)]
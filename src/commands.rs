@@ -1,15 +1,19 @@
 use anyhow::Result;
 use regex::Regex;
 
+use crate::history::{parse_date_bound, SessionFilter, SessionSort};
+use crate::session::MergeOrder;
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum Command {
     Model(String),
     Models,
     ChatNew,
+    ChatNewFromTemplate(String), // template name
     ChatSave(String),
     ChatLoad(SessionReference),
-    ChatList,
+    ChatList(ChatListOptions),
     ChatDelete(Option<SessionReference>),
     ChatContinue(Option<SessionReference>),
     ChatRecent,
@@ -18,27 +22,58 @@ pub enum Command {
     ChatRename(String, Option<SessionReference>), // (new_name, old_session_ref)
     ChatAutoRename(Option<SessionReference>), // (session_ref)
     ChatAutoRenameAll,
+    ChatImport(String, String), // (format, path)
+    ChatShare(SessionReference, String, bool), // (session_ref, bundle_path, strip_secrets)
+    ChatReceive(String, Option<String>, bool), // (bundle_path, optional_new_name, allow_commands)
+    ChatTag(Vec<String>, Option<SessionReference>), // (tags, session_ref)
+    ChatUntag(Vec<String>, Option<SessionReference>), // (tags, session_ref)
+    ChatDescribe(Option<String>, Option<SessionReference>), // (description, session_ref) - None shows it
+    ChatDiff(SessionReference, Option<SessionReference>), // (a, b - defaults to current)
+    ChatMerge(SessionReference, Option<SessionReference>, MergeOrder), // (source_ref, target_ref - defaults to current, order)
+    ChatTrash,
+    ChatRestore(SessionReference, Option<String>), // (trashed_session_ref, optional_new_name)
+    ChatPurge(Option<SessionReference>, bool), // (trashed_session_ref, confirmed) - None empties the trash
     ChatName(String),
     Summarize(Option<SessionReference>), // (session_ref)
     Undo(Option<usize>),
+    Redo,
     Goto(usize),
-    History(bool), // bool is the expand flag
+    GotoMark(String),
+    Mark(MessageTarget, String), // (message, bookmark name)
+    Unmark(String),
+    Marks,
+    Note(MessageTarget, Option<String>), // (message, note); no note clears it
+    Hide(MessageTarget, bool), // (message, hidden) - /hide or /unhide
+    Pin(MessageTarget, bool), // (message, pinned) - /pin or /unpin
+    Inspect(usize), // Agent N
+    Reproduce(usize), // Agent N
+    Compare(Vec<String>, String), // (models, prompt)
+    CompareAdopt(usize), // #N from the last /compare
+    Source(String, bool), // (file, keep_going)
+    Journal,
+    JournalRevert(usize), // #N from /journal
+    History(bool, bool), // (expand, include_thinking)
     Search(String),
+    SearchOpen(usize), // #N from the last /search results
     Blocks,
     Block(usize),
     Copy(CopyType, Option<usize>), // (type, number)
+    CopyThinking(Option<usize>), // Agent N with its thinking
+    CopyMark(String),
     Save(SaveType, Option<usize>, String), // (type, number, filename)
+    SaveMark(String, String), // (bookmark, filename)
     Exec(usize),
     System(String),
     Temperature(f32),
     MaxTokens(u32),
     ThinkingBudget(u32),
     Thinking(bool),
+    ThinkingShow(Option<usize>), // Agent N, defaulting to the latest
     Tokens,
-    Export(String),
+    Export(String, ExportTarget, Option<String>, bool), // (format, target, optional_filename, include_thinking)
     Help,
     Clear,
-    Stats,
+    Stats(bool), // all_sessions
     Status,
     Retry,
     Edit(EditTarget),
@@ -70,6 +105,18 @@ pub enum Command {
     SetDefault(String, String), // (setting, value)
     GetDefaults,
     ResetDefaults,
+    // Backup commands
+    BackupList,
+    BackupCreate,
+    BackupRestore(String), // (timestamp or #N)
+    // Encryption commands
+    Encryption(EncryptionAction),
+    // Workspace commands
+    Workspace(WorkspaceAction),
+    // Retention commands
+    Retention(RetentionAction),
+    // Session template commands
+    Template(TemplateAction),
 }
 
 #[derive(Debug, Clone)]
@@ -86,6 +133,22 @@ pub enum SaveType {
     Block,
 }
 
+/// A message picked by its number among the user or agent messages, as shown in /history
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageTarget {
+    User(usize),
+    Agent(usize),
+}
+
+impl std::fmt::Display for MessageTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageTarget::User(number) => write!(f, "User {}", number),
+            MessageTarget::Agent(number) => write!(f, "Agent {}", number),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum EditTarget {
     NewMessage(String),  // /edit with optional prefix text
@@ -93,6 +156,52 @@ pub enum EditTarget {
     Agent(usize),        // /edit agent N
 }
 
+#[derive(Debug, Clone)]
+pub enum EncryptionAction {
+    Status,
+    Enable(Option<String>), // optional keyfile; prompts for a passphrase otherwise
+    Rotate(Option<String>), // optional keyfile for the new key
+    Disable,
+}
+
+#[derive(Debug, Clone)]
+pub enum WorkspaceAction {
+    Show,
+    Init(Option<String>), // project directory, defaults to the cwd
+    Switch(String),       // path inside a project, or "global"
+    AddVariable(String),  // save a loaded variable into the workspace
+    RemoveVariable(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum RetentionAction {
+    Show,
+    Preview,             // Dry run: list what would be pruned
+    Run,
+    Set(String, String), // (setting, value)
+}
+
+#[derive(Debug, Clone)]
+pub enum TemplateAction {
+    List,
+    Save(String), // save the current session's setup under this name
+    Delete(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChatListOptions {
+    pub filter: SessionFilter,
+    pub limit: Option<usize>, // Page size
+    pub page: usize,          // 1-based
+}
+
+#[derive(Debug, Clone)]
+pub enum ExportTarget {
+    Active,                     // Current session
+    Session(SessionReference),  // --session NAME|#N
+    All,                        // --all saved sessions
+}
+
 #[derive(Debug, Clone)]
 pub enum SessionReference {
     Named(String),
@@ -105,18 +214,34 @@ pub struct CommandParser {
     history_regex: Regex,
     undo_regex: Regex,
     goto_regex: Regex,
+    goto_mark_regex: Regex,
+    mark_regex: Regex,
+    unmark_regex: Regex,
+    note_regex: Regex,
+    context_flag_regex: Regex,
+    inspect_regex: Regex,
+    reproduce_regex: Regex,
+    compare_adopt_regex: Regex,
+    compare_regex: Regex,
+    source_regex: Regex,
+    journal_revert_regex: Regex,
     search_regex: Regex,
+    search_open_regex: Regex,
     block_regex: Regex,
     copy_regex: Regex,
+    copy_thinking_regex: Regex,
     copy_typed_regex: Regex,
+    copy_mark_regex: Regex,
     save_regex: Regex,
     save_typed_regex: Regex,
+    save_mark_regex: Regex,
     exec_regex: Regex,
     system_regex: Regex,
     temp_regex: Regex,
     max_tokens_regex: Regex,
     thinking_budget_regex: Regex,
     thinking_regex: Regex,
+    thinking_show_regex: Regex,
     export_regex: Regex,
     edit_regex: Regex,
     edit_typed_regex: Regex,
@@ -128,6 +253,17 @@ pub struct CommandParser {
     chat_branch_regex: Regex,
     chat_rename_regex: Regex,
     chat_auto_rename_regex: Regex,
+    chat_import_regex: Regex,
+    chat_share_regex: Regex,
+    chat_receive_regex: Regex,
+    chat_list_regex: Regex,
+    chat_tag_regex: Regex,
+    chat_untag_regex: Regex,
+    chat_describe_regex: Regex,
+    chat_merge_regex: Regex,
+    chat_diff_regex: Regex,
+    chat_restore_regex: Regex,
+    chat_purge_regex: Regex,
     summarize_regex: Regex,
     // File loading regexes
     load_regex: Regex,
@@ -148,6 +284,12 @@ pub struct CommandParser {
     prompts_export_regex: Regex,
     // Default preferences regexes
     set_default_regex: Regex,
+    backup_restore_regex: Regex,
+    encryption_key_regex: Regex,
+    workspace_regex: Regex,
+    retention_set_regex: Regex,
+    chat_new_template_regex: Regex,
+    template_regex: Regex,
 }
 
 impl CommandParser {
@@ -189,6 +331,14 @@ impl CommandParser {
         }
     }
     
+    /// Agent messages unless `user` is given, like /copy
+    fn message_target(role: Option<&str>, number: usize) -> MessageTarget {
+        match role {
+            Some("user") => MessageTarget::User(number),
+            _ => MessageTarget::Agent(number),
+        }
+    }
+    
     fn parse_load_arguments(args: &str) -> (String, Option<String>) {
         let args = args.trim();
         
@@ -412,25 +562,213 @@ impl CommandParser {
         }
     }
     
+    fn split_arguments(args: &str) -> Vec<String> {
+        // Split on whitespace, keeping quoted sections (and their quotes) together
+        let mut parts = Vec::new();
+        let mut current = String::new();
+        let mut quote: Option<char> = None;
+        let mut chars = args.trim().chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match quote {
+                Some(q) => {
+                    current.push(c);
+                    if c == '\\' {
+                        if let Some(next) = chars.next() {
+                            current.push(next);
+                        }
+                    } else if c == q {
+                        quote = None;
+                    }
+                }
+                None => {
+                    if c.is_whitespace() {
+                        if !current.is_empty() {
+                            parts.push(std::mem::take(&mut current));
+                        }
+                    } else {
+                        if (c == '"' || c == '\'') && current.is_empty() {
+                            quote = Some(c);
+                        }
+                        current.push(c);
+                    }
+                }
+            }
+        }
+
+        if !current.is_empty() {
+            parts.push(current);
+        }
+
+        parts
+    }
+
+    fn parse_export_arguments(args: &str) -> Option<Command> {
+        let parts = Self::split_arguments(args);
+        let (format, rest) = parts.split_first()?;
+
+        let mut target = ExportTarget::Active;
+        let mut filename = None;
+        let mut include_thinking = false;
+        let mut i = 0;
+
+        while i < rest.len() {
+            match rest[i].as_str() {
+                "--all" => target = ExportTarget::All,
+                "--thinking" => include_thinking = true,
+                "--session" => {
+                    i += 1;
+                    target = ExportTarget::Session(Self::parse_session_reference(rest.get(i)?));
+                }
+                other => {
+                    if filename.is_some() {
+                        // Only one output file is allowed
+                        return None;
+                    }
+                    filename = Some(Self::unquote_session_name(other));
+                }
+            }
+            i += 1;
+        }
+
+        Some(Command::Export(format.to_lowercase(), target, filename, include_thinking))
+    }
+
+    fn parse_chat_list_arguments(args: &str) -> Option<ChatListOptions> {
+        let parts = Self::split_arguments(args);
+        let mut options = ChatListOptions { page: 1, ..Default::default() };
+        let mut i = 0;
+
+        while i < parts.len() {
+            let flag = parts[i].as_str();
+            if flag == "--reverse" {
+                options.filter.reverse = true;
+                i += 1;
+                continue;
+            }
+
+            // Every other option takes a value
+            let value = Self::unquote_session_name(parts.get(i + 1)?);
+            match flag {
+                "--tag" => options.filter.tags.push(value),
+                "--model" => options.filter.model = Some(value),
+                "--provider" => options.filter.provider = Some(value.to_lowercase()),
+                "--since" => options.filter.since = Some(parse_date_bound(&value, false)?),
+                "--until" => options.filter.until = Some(parse_date_bound(&value, true)?),
+                "--name" => options.filter.name_pattern = Some(value),
+                "--sort" => options.filter.sort = SessionSort::parse(&value)?,
+                "--limit" => options.limit = Some(value.parse().ok().filter(|n| *n > 0)?),
+                "--page" => options.page = value.parse().ok().filter(|n| *n > 0)?,
+                _ => return None,
+            }
+            i += 2;
+        }
+
+        Some(options)
+    }
+
+    fn parse_tag_arguments(args: &str) -> Option<(Vec<String>, Option<SessionReference>)> {
+        let parts = Self::split_arguments(args);
+        let mut tags = Vec::new();
+        let mut session_ref = None;
+        let mut i = 0;
+
+        while i < parts.len() {
+            if parts[i] == "--session" {
+                session_ref = Some(Self::parse_session_reference(parts.get(i + 1)?));
+                i += 2;
+            } else {
+                // Allow both "/chat tag a b" and "/chat tag a,b"
+                tags.extend(
+                    Self::unquote_session_name(&parts[i])
+                        .split(',')
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty()),
+                );
+                i += 1;
+            }
+        }
+
+        if tags.is_empty() {
+            None
+        } else {
+            Some((tags, session_ref))
+        }
+    }
+
+    fn parse_describe_arguments(args: &str) -> Option<(Option<String>, Option<SessionReference>)> {
+        let args = args.trim();
+
+        if let Some(rest) = args.strip_prefix("--session") {
+            let parts = Self::split_arguments(rest);
+            let session_ref = Self::parse_session_reference(parts.first()?);
+            let description = if parts.len() > 1 {
+                Some(Self::unquote_session_name(&parts[1..].join(" ")))
+            } else {
+                None
+            };
+            Some((description, Some(session_ref)))
+        } else {
+            Some((Some(Self::unquote_session_name(args)), None))
+        }
+    }
+
+    fn parse_merge_arguments(args: &str) -> Option<(SessionReference, Option<SessionReference>, MergeOrder)> {
+        let mut order = MergeOrder::Append;
+        let mut sessions = Vec::new();
+
+        for part in Self::split_arguments(args) {
+            match part.as_str() {
+                "--append" => order = MergeOrder::Append,
+                "--interleave" => order = MergeOrder::Interleave,
+                _ if part.starts_with("--") => return None,
+                _ => sessions.push(Self::parse_session_reference(&part)),
+            }
+        }
+
+        if sessions.len() > 2 {
+            return None;
+        }
+        let mut sessions = sessions.into_iter();
+        let source = sessions.next()?;
+        Some((source, sessions.next(), order))
+    }
+
     pub fn new() -> Result<Self> {
         Ok(Self {
             model_regex: Regex::new(r"^/model\s+(.+)$")?,
-            history_regex: Regex::new(r"^/history(\s+--expand)?$")?,
+            history_regex: Regex::new(r"^/history(\s+--expand)?(\s+--thinking)?$")?,
             undo_regex: Regex::new(r"^/undo(?:\s+(\d+))?$")?,
             goto_regex: Regex::new(r"^/goto\s+(\d+)$")?,
+            goto_mark_regex: Regex::new(r"^/goto\s+([A-Za-z_][\w.-]*)$")?,
+            mark_regex: Regex::new(r"^/mark\s+(?:(agent|user)\s+)?(\d+)\s+(\S+)$")?,
+            unmark_regex: Regex::new(r"^/unmark\s+(\S+)$")?,
+            note_regex: Regex::new(r"^/note\s+(?:(agent|user)\s+)?(\d+)(?:\s+(.+))?$")?,
+            context_flag_regex: Regex::new(r"^/(hide|unhide|pin|unpin)\s+(?:(agent|user)\s+)?(\d+)$")?,
+            inspect_regex: Regex::new(r"^/inspect\s+(\d+)$")?,
+            reproduce_regex: Regex::new(r"^/reproduce\s+(\d+)$")?,
+            compare_adopt_regex: Regex::new(r"^/compare\s+adopt\s+#?(\d+)$")?,
+            compare_regex: Regex::new(r"(?s)^/compare\s+(.+?)\s+--\s+(.+)$")?,
+            source_regex: Regex::new(r"^/source\s+(.+?)(\s+--keep-going)?$")?,
+            journal_revert_regex: Regex::new(r"^/journal\s+revert\s+#?(\d+)$")?,
             search_regex: Regex::new(r"^/search\s+(.+)$")?,
+            search_open_regex: Regex::new(r"^/search\s+#(\d+)$")?,
             block_regex: Regex::new(r"^/block\s+(\d+)$")?,
             copy_regex: Regex::new(r"^/copy(?:\s+(\d+))?$")?,
+            copy_thinking_regex: Regex::new(r"^/copy(?:\s+agent)?(?:\s+(\d+))?\s+--thinking$")?,
             copy_typed_regex: Regex::new(r"^/copy\s+(agent|user|block)(?:\s+(\d+))?$")?,
+            copy_mark_regex: Regex::new(r"^/copy\s+([A-Za-z_][\w.-]*)$")?,
             save_regex: Regex::new(r"^/save\s+(.+)$")?,
             save_typed_regex: Regex::new(r"^/save\s+(agent|user|block)(?:\s+(\d+))?\s+(.+)$")?,
+            save_mark_regex: Regex::new(r"^/save\s+([A-Za-z_][\w.-]*)\s+(.+)$")?,
             exec_regex: Regex::new(r"^/exec\s+(\d+)$")?,
             system_regex: Regex::new(r"^/system\s+(.+)$")?,
             temp_regex: Regex::new(r"^/temp\s+([\d.]+)$")?,
             max_tokens_regex: Regex::new(r"^/max-tokens\s+(\d+)$")?,
             thinking_budget_regex: Regex::new(r"^/thinking-budget\s+(\d+)$")?,
             thinking_regex: Regex::new(r"^/thinking\s+(on|off|true|false)$")?,
-            export_regex: Regex::new(r"^/export\s+(\w+)$")?,
+            thinking_show_regex: Regex::new(r"^/thinking\s+show(?:\s+(\d+))?$")?,
+            export_regex: Regex::new(r"^/export\s+(.+)$")?,
             edit_regex: Regex::new(r"^/edit(?:\s+(\d+))?$")?,
             edit_typed_regex: Regex::new(r"^/edit\s+(user|agent)\s+(\d+)$")?,
             chat_save_regex: Regex::new(r"^/chat\s+save\s+(.+)$")?,
@@ -441,6 +779,17 @@ impl CommandParser {
             chat_branch_regex: Regex::new(r"^/chat\s+branch\s+(.+?)(?:\s+(.+))?$")?,
             chat_rename_regex: Regex::new(r"^/chat\s+rename\s+(.+)$")?,
             chat_auto_rename_regex: Regex::new(r"^/chat\s+auto-rename(?:\s+(.+))?$")?,
+            chat_import_regex: Regex::new(r"^/chat\s+import\s+(\S+)\s+(.+)$")?,
+            chat_share_regex: Regex::new(r#"^/chat\s+share\s+("[^"]+"|'[^']+'|\S+)\s+(.+?)(\s+--strip-secrets)?$"#)?,
+            chat_receive_regex: Regex::new(r"^/chat\s+receive\s+(.+?)(\s+--allow-commands)?(?:\s+--as\s+(.+))?$")?,
+            chat_list_regex: Regex::new(r"^/chat\s+list(?:\s+(.+))?$")?,
+            chat_tag_regex: Regex::new(r"^/chat\s+tag\s+(.+)$")?,
+            chat_untag_regex: Regex::new(r"^/chat\s+untag\s+(.+)$")?,
+            chat_describe_regex: Regex::new(r"^/chat\s+describe(?:\s+(.+))?$")?,
+            chat_merge_regex: Regex::new(r"^/chat\s+merge\s+(.+)$")?,
+            chat_diff_regex: Regex::new(r"^/chat\s+diff\s+(.+)$")?,
+            chat_restore_regex: Regex::new(r"^/chat\s+restore\s+(.+?)(?:\s+--as\s+(.+))?$")?,
+            chat_purge_regex: Regex::new(r"^/chat\s+purge(?:\s+(--yes))?(?:\s+(.+))?$")?,
             summarize_regex: Regex::new(r"^/summarize(?:\s+(.+))?$")?,
             // File loading regexes
             load_regex: Regex::new(r"^/load\s+(.+)$")?,
@@ -461,6 +810,12 @@ impl CommandParser {
             prompts_export_regex: Regex::new(r"^/prompts\s+export(?:\s+(.+))?$")?,
             // Default preferences regexes
             set_default_regex: Regex::new(r"^/set-default\s+([\w-]+)\s+(.+)$")?,
+            backup_restore_regex: Regex::new(r"^/backup\s+restore\s+(\S+)$")?,
+            encryption_key_regex: Regex::new(r"^/encryption\s+(enable|rotate)(?:\s+--keyfile\s+(.+))?$")?,
+            workspace_regex: Regex::new(r"^/workspace\s+(init|switch|add-var|remove-var)\s+(.+)$")?,
+            retention_set_regex: Regex::new(r"^/retention\s+set\s+([\w-]+)\s+(.+)$")?,
+            chat_new_template_regex: Regex::new(r"^/chat\s+new\s+--template\s+(.+)$")?,
+            template_regex: Regex::new(r"^/template\s+(save|delete)\s+(.+)$")?,
         })
     }
     
@@ -474,15 +829,19 @@ impl CommandParser {
         match input {
             "/models" => Some(Command::Models),
             "/chat new" => Some(Command::ChatNew),
-            "/chat list" => Some(Command::ChatList),
             "/chat recent" => Some(Command::ChatRecent),
             "/chat auto-rename-all" => Some(Command::ChatAutoRenameAll),
-            "/history" => Some(Command::History(false)),
+            "/chat trash" => Some(Command::ChatTrash),
+            "/history" => Some(Command::History(false, false)),
             "/blocks" => Some(Command::Blocks),
             "/tokens" => Some(Command::Tokens),
             "/help" | "/commands" => Some(Command::Help),
             "/clear" => Some(Command::Clear),
-            "/stats" => Some(Command::Stats),
+            "/redo" => Some(Command::Redo),
+            "/journal" => Some(Command::Journal),
+            "/marks" => Some(Command::Marks),
+            "/stats" => Some(Command::Stats(false)),
+            "/stats --all" => Some(Command::Stats(true)),
             "/status" => Some(Command::Status),
             "/retry" => Some(Command::Retry),
             "/system" => Some(Command::System(String::new())),
@@ -498,6 +857,16 @@ impl CommandParser {
             "/input-history stats" => Some(Command::InputHistoryStats),
             "/get-defaults" => Some(Command::GetDefaults),
             "/reset-defaults" => Some(Command::ResetDefaults),
+            "/backup" | "/backup list" => Some(Command::BackupList),
+            "/backup now" => Some(Command::BackupCreate),
+            "/encryption" | "/encryption status" => Some(Command::Encryption(EncryptionAction::Status)),
+            "/encryption disable" => Some(Command::Encryption(EncryptionAction::Disable)),
+            "/workspace" | "/workspace show" => Some(Command::Workspace(WorkspaceAction::Show)),
+            "/workspace init" => Some(Command::Workspace(WorkspaceAction::Init(None))),
+            "/retention" | "/retention show" => Some(Command::Retention(RetentionAction::Show)),
+            "/retention preview" => Some(Command::Retention(RetentionAction::Preview)),
+            "/retention run" => Some(Command::Retention(RetentionAction::Run)),
+            "/template" | "/template list" => Some(Command::Template(TemplateAction::List)),
             _ if input.starts_with("/chat name ") => {
                 let name = input[11..].trim();
                 if name.is_empty() {
@@ -515,16 +884,51 @@ impl CommandParser {
                 if let Some(caps) = self.model_regex.captures(input) {
                     Some(Command::Model(caps[1].to_string()))
                 } else if let Some(caps) = self.history_regex.captures(input) {
-                    Some(Command::History(caps.get(1).is_some()))
+                    Some(Command::History(caps.get(1).is_some(), caps.get(2).is_some()))
                 } else if let Some(caps) = self.undo_regex.captures(input) {
                     let count = caps.get(1).map(|m| m.as_str().parse().unwrap_or(1));
                     Some(Command::Undo(count))
                 } else if let Some(caps) = self.goto_regex.captures(input) {
                     Some(Command::Goto(caps[1].parse().unwrap_or(1)))
+                } else if let Some(caps) = self.goto_mark_regex.captures(input) {
+                    Some(Command::GotoMark(caps[1].to_string()))
+                } else if let Some(caps) = self.mark_regex.captures(input) {
+                    let target = Self::message_target(caps.get(1).map(|m| m.as_str()), caps[2].parse().unwrap_or(1));
+                    Some(Command::Mark(target, caps[3].to_string()))
+                } else if let Some(caps) = self.unmark_regex.captures(input) {
+                    Some(Command::Unmark(caps[1].to_string()))
+                } else if let Some(caps) = self.note_regex.captures(input) {
+                    let target = Self::message_target(caps.get(1).map(|m| m.as_str()), caps[2].parse().unwrap_or(1));
+                    Some(Command::Note(target, caps.get(3).map(|m| m.as_str().trim().to_string())))
+                } else if let Some(caps) = self.context_flag_regex.captures(input) {
+                    let target = Self::message_target(caps.get(2).map(|m| m.as_str()), caps[3].parse().unwrap_or(1));
+                    match &caps[1] {
+                        "hide" => Some(Command::Hide(target, true)),
+                        "unhide" => Some(Command::Hide(target, false)),
+                        "pin" => Some(Command::Pin(target, true)),
+                        _ => Some(Command::Pin(target, false)),
+                    }
+                } else if let Some(caps) = self.inspect_regex.captures(input) {
+                    Some(Command::Inspect(caps[1].parse().unwrap_or(1)))
+                } else if let Some(caps) = self.reproduce_regex.captures(input) {
+                    Some(Command::Reproduce(caps[1].parse().unwrap_or(1)))
+                } else if let Some(caps) = self.compare_adopt_regex.captures(input) {
+                    Some(Command::CompareAdopt(caps[1].parse().unwrap_or(1)))
+                } else if let Some(caps) = self.compare_regex.captures(input) {
+                    let models = caps[1].split_whitespace().map(str::to_string).collect();
+                    Some(Command::Compare(models, caps[2].trim().to_string()))
+                } else if let Some(caps) = self.source_regex.captures(input) {
+                    Some(Command::Source(caps[1].to_string(), caps.get(2).is_some()))
+                } else if let Some(caps) = self.journal_revert_regex.captures(input) {
+                    Some(Command::JournalRevert(caps[1].parse().unwrap_or(1)))
+                } else if let Some(caps) = self.search_open_regex.captures(input) {
+                    Some(Command::SearchOpen(caps[1].parse().unwrap_or(1)))
                 } else if let Some(caps) = self.search_regex.captures(input) {
                     Some(Command::Search(caps[1].to_string()))
                 } else if let Some(caps) = self.block_regex.captures(input) {
                     Some(Command::Block(caps[1].parse().unwrap_or(1)))
+                } else if let Some(caps) = self.copy_thinking_regex.captures(input) {
+                    Some(Command::CopyThinking(caps.get(1).map(|m| m.as_str().parse().unwrap_or(1))))
                 } else if let Some(caps) = self.copy_typed_regex.captures(input) {
                     let copy_type = match caps[1].as_ref() {
                         "agent" => CopyType::Agent,
@@ -538,6 +942,8 @@ impl CommandParser {
                     // Default to agent type for backward compatibility
                     let number = caps.get(1).map(|m| m.as_str().parse().unwrap_or(1));
                     Some(Command::Copy(CopyType::Agent, number))
+                } else if let Some(caps) = self.copy_mark_regex.captures(input) {
+                    Some(Command::CopyMark(caps[1].to_string()))
                 } else if let Some(caps) = self.save_typed_regex.captures(input) {
                     let save_type = match caps[1].as_ref() {
                         "agent" => SaveType::Agent,
@@ -548,6 +954,9 @@ impl CommandParser {
                     let number = caps.get(2).map(|m| m.as_str().parse().unwrap_or(1));
                     let filename = Self::unquote_session_name(&caps[3]);
                     Some(Command::Save(save_type, number, filename))
+                } else if let Some(caps) = self.save_mark_regex.captures(input) {
+                    // Falls back to saving to the whole text as a filename if there's no such bookmark
+                    Some(Command::SaveMark(caps[1].to_string(), Self::unquote_session_name(&caps[2])))
                 } else if let Some(caps) = self.save_regex.captures(input) {
                     // Default to agent type for backward compatibility
                     let filename = Self::unquote_session_name(&caps[1]);
@@ -565,8 +974,10 @@ impl CommandParser {
                 } else if let Some(caps) = self.thinking_regex.captures(input) {
                     let enable = matches!(caps[1].as_ref(), "on" | "true");
                     Some(Command::Thinking(enable))
+                } else if let Some(caps) = self.thinking_show_regex.captures(input) {
+                    Some(Command::ThinkingShow(caps.get(1).map(|m| m.as_str().parse().unwrap_or(1))))
                 } else if let Some(caps) = self.export_regex.captures(input) {
-                    Some(Command::Export(caps[1].to_string()))
+                    Self::parse_export_arguments(&caps[1])
                 } else if let Some(caps) = self.edit_typed_regex.captures(input) {
                     let edit_type = match caps[1].as_ref() {
                         "agent" => EditTarget::Agent(caps[2].parse().unwrap_or(1)),
@@ -604,6 +1015,53 @@ impl CommandParser {
                 } else if let Some(caps) = self.chat_auto_rename_regex.captures(input) {
                     let session_ref = caps.get(1).map(|m| Self::parse_session_reference(m.as_str()));
                     Some(Command::ChatAutoRename(session_ref))
+                } else if let Some(caps) = self.chat_import_regex.captures(input) {
+                    let format = caps[1].to_lowercase();
+                    let path = Self::unquote_session_name(&caps[2]);
+                    Some(Command::ChatImport(format, path))
+                } else if let Some(caps) = self.chat_share_regex.captures(input) {
+                    let session_ref = Self::parse_session_reference(&caps[1]);
+                    let path = Self::unquote_session_name(&caps[2]);
+                    Some(Command::ChatShare(session_ref, path, caps.get(3).is_some()))
+                } else if let Some(caps) = self.chat_receive_regex.captures(input) {
+                    let path = Self::unquote_session_name(&caps[1]);
+                    let new_name = caps.get(3).map(|m| Self::unquote_session_name(m.as_str()));
+                    Some(Command::ChatReceive(path, new_name, caps.get(2).is_some()))
+                } else if let Some(caps) = self.chat_list_regex.captures(input) {
+                    match caps.get(1) {
+                        Some(m) => Self::parse_chat_list_arguments(m.as_str()).map(Command::ChatList),
+                        None => Some(Command::ChatList(ChatListOptions { page: 1, ..Default::default() })),
+                    }
+                } else if let Some(caps) = self.chat_tag_regex.captures(input) {
+                    let (tags, session_ref) = Self::parse_tag_arguments(&caps[1])?;
+                    Some(Command::ChatTag(tags, session_ref))
+                } else if let Some(caps) = self.chat_untag_regex.captures(input) {
+                    let (tags, session_ref) = Self::parse_tag_arguments(&caps[1])?;
+                    Some(Command::ChatUntag(tags, session_ref))
+                } else if let Some(caps) = self.chat_describe_regex.captures(input) {
+                    let (description, session_ref) = match caps.get(1) {
+                        Some(m) => Self::parse_describe_arguments(m.as_str())?,
+                        None => (None, None),
+                    };
+                    Some(Command::ChatDescribe(description, session_ref))
+                } else if let Some(caps) = self.chat_merge_regex.captures(input) {
+                    let (source, target, order) = Self::parse_merge_arguments(&caps[1])?;
+                    Some(Command::ChatMerge(source, target, order))
+                } else if let Some(caps) = self.chat_diff_regex.captures(input) {
+                    let parts = Self::split_arguments(&caps[1]);
+                    if parts.len() > 2 {
+                        return None;
+                    }
+                    let left = Self::parse_session_reference(&parts[0]);
+                    let right = parts.get(1).map(|part| Self::parse_session_reference(part));
+                    Some(Command::ChatDiff(left, right))
+                } else if let Some(caps) = self.chat_restore_regex.captures(input) {
+                    let session_ref = Self::parse_session_reference(&caps[1]);
+                    let new_name = caps.get(2).map(|m| Self::unquote_session_name(m.as_str()));
+                    Some(Command::ChatRestore(session_ref, new_name))
+                } else if let Some(caps) = self.chat_purge_regex.captures(input) {
+                    let session_ref = caps.get(2).map(|m| Self::parse_session_reference(m.as_str()));
+                    Some(Command::ChatPurge(session_ref, caps.get(1).is_some()))
                 } else if let Some(caps) = self.summarize_regex.captures(input) {
                     let session_ref = caps.get(1).map(|m| Self::parse_session_reference(m.as_str()));
                     Some(Command::Summarize(session_ref))
@@ -655,6 +1113,33 @@ impl CommandParser {
                 } else if let Some(caps) = self.prompts_export_regex.captures(input) {
                     let filename = caps.get(1).map(|m| Self::unquote_session_name(m.as_str()));
                     Some(Command::PromptsExport(filename))
+                } else if let Some(caps) = self.backup_restore_regex.captures(input) {
+                    Some(Command::BackupRestore(caps[1].to_string()))
+                } else if let Some(caps) = self.chat_new_template_regex.captures(input) {
+                    Some(Command::ChatNewFromTemplate(Self::unquote_session_name(caps[1].trim())))
+                } else if let Some(caps) = self.template_regex.captures(input) {
+                    let name = Self::unquote_session_name(caps[2].trim());
+                    match &caps[1] {
+                        "save" => Some(Command::Template(TemplateAction::Save(name))),
+                        _ => Some(Command::Template(TemplateAction::Delete(name))),
+                    }
+                } else if let Some(caps) = self.retention_set_regex.captures(input) {
+                    Some(Command::Retention(RetentionAction::Set(caps[1].to_string(), Self::unquote_session_name(caps[2].trim()))))
+                } else if let Some(caps) = self.workspace_regex.captures(input) {
+                    let value = Self::unquote_session_name(caps[2].trim());
+                    let action = match &caps[1] {
+                        "init" => WorkspaceAction::Init(Some(value)),
+                        "switch" => WorkspaceAction::Switch(value),
+                        "add-var" => WorkspaceAction::AddVariable(value),
+                        _ => WorkspaceAction::RemoveVariable(value),
+                    };
+                    Some(Command::Workspace(action))
+                } else if let Some(caps) = self.encryption_key_regex.captures(input) {
+                    let keyfile = caps.get(2).map(|m| Self::unquote_session_name(m.as_str()));
+                    match &caps[1] {
+                        "enable" => Some(Command::Encryption(EncryptionAction::Enable(keyfile))),
+                        _ => Some(Command::Encryption(EncryptionAction::Rotate(keyfile))),
+                    }
                 } else if let Some(caps) = self.set_default_regex.captures(input) {
                     let setting = caps[1].to_string();
                     let value = Self::unquote_session_name(&caps[2]);
@@ -684,7 +1169,10 @@ mod tests {
         assert!(matches!(parser.parse("/quit"), Some(Command::Quit)));
         assert!(matches!(parser.parse("/clear"), Some(Command::Clear)));
         assert!(matches!(parser.parse("/status"), Some(Command::Status)));
-        assert!(matches!(parser.parse("/history"), Some(Command::History(false))));
+        assert!(matches!(parser.parse("/stats"), Some(Command::Stats(false))));
+        assert!(matches!(parser.parse("/stats --all"), Some(Command::Stats(true))));
+        assert!(matches!(parser.parse("/history"), Some(Command::History(false, false))));
+        assert!(matches!(parser.parse("/history --expand --thinking"), Some(Command::History(true, true))));
         assert!(matches!(parser.parse("/blocks"), Some(Command::Blocks)));
     }
 
@@ -741,6 +1229,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_journal_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/redo"), Some(Command::Redo)));
+        assert!(matches!(parser.parse("/journal"), Some(Command::Journal)));
+        assert!(matches!(parser.parse("/journal revert 2"), Some(Command::JournalRevert(2))));
+        assert!(matches!(parser.parse("/journal revert #3"), Some(Command::JournalRevert(3))));
+        assert!(parser.parse("/journal revert").is_none());
+    }
+
+    #[test]
+    fn test_bookmark_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/mark 3 good"), Some(Command::Mark(MessageTarget::Agent(3), name)) if name == "good"));
+        assert!(matches!(parser.parse("/mark user 2 question"), Some(Command::Mark(MessageTarget::User(2), _))));
+        assert!(matches!(parser.parse("/unmark good"), Some(Command::Unmark(name)) if name == "good"));
+        assert!(matches!(parser.parse("/marks"), Some(Command::Marks)));
+        assert!(matches!(parser.parse("/note 3 uses the old API"), Some(Command::Note(MessageTarget::Agent(3), Some(note))) if note == "uses the old API"));
+        assert!(matches!(parser.parse("/note user 1"), Some(Command::Note(MessageTarget::User(1), None))));
+
+        assert!(matches!(parser.parse("/goto good"), Some(Command::GotoMark(name)) if name == "good"));
+        assert!(matches!(parser.parse("/goto 4"), Some(Command::Goto(4))));
+        assert!(matches!(parser.parse("/copy good"), Some(Command::CopyMark(name)) if name == "good"));
+        assert!(matches!(parser.parse("/copy agent"), Some(Command::Copy(CopyType::Agent, None))));
+        assert!(matches!(parser.parse("/save good out.md"), Some(Command::SaveMark(name, file)) if name == "good" && file == "out.md"));
+        assert!(matches!(parser.parse("/save agent 2 out.md"), Some(Command::Save(SaveType::Agent, Some(2), _))));
+    }
+
+    #[test]
+    fn test_context_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/hide user 2"), Some(Command::Hide(MessageTarget::User(2), true))));
+        assert!(matches!(parser.parse("/unhide 4"), Some(Command::Hide(MessageTarget::Agent(4), false))));
+        assert!(matches!(parser.parse("/pin user 1"), Some(Command::Pin(MessageTarget::User(1), true))));
+        assert!(matches!(parser.parse("/unpin user 1"), Some(Command::Pin(MessageTarget::User(1), false))));
+        assert!(parser.parse("/hide").is_none());
+    }
+
+    #[test]
+    fn test_inspect_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/inspect 3"), Some(Command::Inspect(3))));
+        assert!(matches!(parser.parse("/reproduce 2"), Some(Command::Reproduce(2))));
+        assert!(parser.parse("/reproduce").is_none());
+    }
+
+    #[test]
+    fn test_compare_commands() {
+        let parser = create_parser();
+
+        match parser.parse("/compare gpt-4o claude-sonnet-4-5-20250929 -- Explain -- in shell flags") {
+            Some(Command::Compare(models, prompt)) => {
+                assert_eq!(models, vec!["gpt-4o", "claude-sonnet-4-5-20250929"]);
+                assert_eq!(prompt, "Explain -- in shell flags");
+            }
+            other => panic!("Expected Compare, got {:?}", other),
+        }
+        assert!(matches!(parser.parse("/compare gpt-4o o3 -- line one\nline two"), Some(Command::Compare(_, prompt)) if prompt.contains('\n')));
+        assert!(matches!(parser.parse("/compare adopt 2"), Some(Command::CompareAdopt(2))));
+        assert!(matches!(parser.parse("/compare adopt #1"), Some(Command::CompareAdopt(1))));
+        assert!(parser.parse("/compare gpt-4o o3").is_none());
+    }
+
+    #[test]
+    fn test_source_command() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/source setup.njord"), Some(Command::Source(file, false)) if file == "setup.njord"));
+        assert!(matches!(parser.parse("/source my scripts/run.njord --keep-going"), Some(Command::Source(file, true)) if file == "my scripts/run.njord"));
+        assert!(parser.parse("/source").is_none());
+    }
+
     #[test]
     fn test_goto_command() {
         let parser = create_parser();
@@ -761,6 +1325,14 @@ mod tests {
         } else {
             panic!("Expected Search command");
         }
+
+        if let Some(Command::Search(term)) = parser.parse("/search /fn \\w+/ role:assistant in:code") {
+            assert_eq!(term, "/fn \\w+/ role:assistant in:code");
+        } else {
+            panic!("Expected Search command");
+        }
+
+        assert!(matches!(parser.parse("/search #3"), Some(Command::SearchOpen(3))));
     }
 
     #[test]
@@ -895,6 +1467,188 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_chat_import_command() {
+        let parser = create_parser();
+
+        if let Some(Command::ChatImport(format, path)) = parser.parse("/chat import ChatGPT exports/conversations.json") {
+            assert_eq!(format, "chatgpt");
+            assert_eq!(path, "exports/conversations.json");
+        } else {
+            panic!("Expected ChatImport command");
+        }
+
+        if let Some(Command::ChatImport(format, path)) = parser.parse("/chat import claude \"My Export.zip\"") {
+            assert_eq!(format, "claude");
+            assert_eq!(path, "My Export.zip");
+        } else {
+            panic!("Expected ChatImport command");
+        }
+
+        assert!(parser.parse("/chat import chatgpt").is_none());
+    }
+
+    #[test]
+    fn test_chat_share_commands() {
+        let parser = create_parser();
+
+        if let Some(Command::ChatShare(SessionReference::Named(name), path, strip)) = parser.parse("/chat share \"code review\" /tmp/review.njord") {
+            assert_eq!(name, "code review");
+            assert_eq!(path, "/tmp/review.njord");
+            assert!(!strip);
+        } else {
+            panic!("Expected ChatShare command");
+        }
+        assert!(matches!(
+            parser.parse("/chat share #2 out.njord --strip-secrets"),
+            Some(Command::ChatShare(SessionReference::Ephemeral(2), ref path, true)) if path == "out.njord"
+        ));
+        assert!(parser.parse("/chat share review").is_none());
+
+        if let Some(Command::ChatReceive(path, new_name, allow_commands)) = parser.parse("/chat receive \"in box/review.njord\" --allow-commands --as mine") {
+            assert_eq!(path, "in box/review.njord");
+            assert_eq!(new_name, Some("mine".to_string()));
+            assert!(allow_commands);
+        } else {
+            panic!("Expected ChatReceive command");
+        }
+        assert!(matches!(parser.parse("/chat receive review.njord"), Some(Command::ChatReceive(_, None, false))));
+    }
+
+    #[test]
+    fn test_chat_list_command() {
+        let parser = create_parser();
+
+        if let Some(Command::ChatList(options)) = parser.parse("/chat list") {
+            assert!(options.filter.tags.is_empty());
+            assert_eq!(options.filter.sort, SessionSort::Updated);
+            assert_eq!(options.limit, None);
+            assert_eq!(options.page, 1);
+        } else {
+            panic!("Expected ChatList command");
+        }
+
+        if let Some(Command::ChatList(options)) = parser.parse(
+            "/chat list --tag work --tag rust --model gpt --provider OpenAI --since 2025-01-01 --until 2025-01-31 --name \"review *\" --sort name --reverse --limit 5 --page 2",
+        ) {
+            assert_eq!(options.filter.tags, vec!["work".to_string(), "rust".to_string()]);
+            assert_eq!(options.filter.model, Some("gpt".to_string()));
+            assert_eq!(options.filter.provider, Some("openai".to_string()));
+            assert_eq!(options.filter.since.unwrap().to_rfc3339(), "2025-01-01T00:00:00+00:00");
+            assert_eq!(options.filter.until.unwrap().to_rfc3339(), "2025-01-31T23:59:59+00:00");
+            assert_eq!(options.filter.name_pattern, Some("review *".to_string()));
+            assert_eq!(options.filter.sort, SessionSort::Name);
+            assert!(options.filter.reverse);
+            assert_eq!(options.limit, Some(5));
+            assert_eq!(options.page, 2);
+        } else {
+            panic!("Expected ChatList command");
+        }
+
+        assert!(parser.parse("/chat list --since someday").is_none());
+        assert!(parser.parse("/chat list --sort size --limit 0").is_none());
+        assert!(parser.parse("/chat list --bogus").is_none());
+    }
+
+    #[test]
+    fn test_chat_merge_command() {
+        let parser = create_parser();
+
+        if let Some(Command::ChatMerge(source, target, order)) = parser.parse("/chat merge #2") {
+            assert!(matches!(source, SessionReference::Ephemeral(2)));
+            assert!(target.is_none());
+            assert_eq!(order, MergeOrder::Append);
+        } else {
+            panic!("Expected ChatMerge command");
+        }
+
+        if let Some(Command::ChatMerge(source, target, order)) = parser.parse("/chat merge \"Bug hunt\" notes --interleave") {
+            assert!(matches!(source, SessionReference::Named(ref name) if name == "Bug hunt"));
+            assert!(matches!(target, Some(SessionReference::Named(ref name)) if name == "notes"));
+            assert_eq!(order, MergeOrder::Interleave);
+        } else {
+            panic!("Expected ChatMerge command");
+        }
+
+        assert!(parser.parse("/chat merge a b c").is_none());
+        assert!(parser.parse("/chat merge a --sideways").is_none());
+    }
+
+    #[test]
+    fn test_chat_diff_command() {
+        let parser = create_parser();
+
+        if let Some(Command::ChatDiff(left, right)) = parser.parse("/chat diff #1 \"My branch\"") {
+            assert!(matches!(left, SessionReference::Ephemeral(1)));
+            assert!(matches!(right, Some(SessionReference::Named(ref name)) if name == "My branch"));
+        } else {
+            panic!("Expected ChatDiff command");
+        }
+        assert!(matches!(parser.parse("/chat diff main"), Some(Command::ChatDiff(_, None))));
+    }
+
+    #[test]
+    fn test_chat_trash_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/chat trash"), Some(Command::ChatTrash)));
+
+        if let Some(Command::ChatRestore(session_ref, new_name)) = parser.parse("/chat restore My old chat") {
+            assert!(matches!(session_ref, SessionReference::Named(ref name) if name == "My old chat"));
+            assert!(new_name.is_none());
+        } else {
+            panic!("Expected ChatRestore command");
+        }
+
+        if let Some(Command::ChatRestore(session_ref, new_name)) = parser.parse("/chat restore #2 --as \"Recovered chat\"") {
+            assert!(matches!(session_ref, SessionReference::Ephemeral(2)));
+            assert_eq!(new_name, Some("Recovered chat".to_string()));
+        } else {
+            panic!("Expected ChatRestore command");
+        }
+
+        assert!(matches!(parser.parse("/chat purge"), Some(Command::ChatPurge(None, false))));
+        assert!(matches!(parser.parse("/chat purge --yes"), Some(Command::ChatPurge(None, true))));
+        assert!(matches!(parser.parse("/chat purge #1"), Some(Command::ChatPurge(Some(SessionReference::Ephemeral(1)), false))));
+    }
+
+    #[test]
+    fn test_chat_tag_commands() {
+        let parser = create_parser();
+
+        if let Some(Command::ChatTag(tags, session_ref)) = parser.parse("/chat tag work rust,review") {
+            assert_eq!(tags, vec!["work".to_string(), "rust".to_string(), "review".to_string()]);
+            assert!(session_ref.is_none());
+        } else {
+            panic!("Expected ChatTag command");
+        }
+
+        if let Some(Command::ChatUntag(tags, session_ref)) = parser.parse("/chat untag work --session #3") {
+            assert_eq!(tags, vec!["work".to_string()]);
+            assert!(matches!(session_ref, Some(SessionReference::Ephemeral(3))));
+        } else {
+            panic!("Expected ChatUntag command");
+        }
+
+        assert!(parser.parse("/chat tag --session #1").is_none());
+
+        if let Some(Command::ChatDescribe(description, session_ref)) = parser.parse("/chat describe Notes on the parser rewrite") {
+            assert_eq!(description, Some("Notes on the parser rewrite".to_string()));
+            assert!(session_ref.is_none());
+        } else {
+            panic!("Expected ChatDescribe command");
+        }
+
+        if let Some(Command::ChatDescribe(description, session_ref)) = parser.parse("/chat describe --session \"My Chat\" \"Design review\"") {
+            assert_eq!(description, Some("Design review".to_string()));
+            assert!(matches!(session_ref, Some(SessionReference::Named(ref name)) if name == "My Chat"));
+        } else {
+            panic!("Expected ChatDescribe command");
+        }
+
+        assert!(matches!(parser.parse("/chat describe"), Some(Command::ChatDescribe(None, None))));
+    }
+
     #[test]
     fn test_thinking_command() {
         let parser = create_parser();
@@ -922,6 +1676,12 @@ mod tests {
         } else {
             panic!("Expected Thinking command");
         }
+        
+        assert!(matches!(parser.parse("/thinking show"), Some(Command::ThinkingShow(None))));
+        assert!(matches!(parser.parse("/thinking show 3"), Some(Command::ThinkingShow(Some(3)))));
+        assert!(matches!(parser.parse("/copy --thinking"), Some(Command::CopyThinking(None))));
+        assert!(matches!(parser.parse("/copy agent 2 --thinking"), Some(Command::CopyThinking(Some(2)))));
+        assert!(matches!(parser.parse("/copy 2 --thinking"), Some(Command::CopyThinking(Some(2)))));
     }
 
     #[test]
@@ -1075,6 +1835,78 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_backup_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/backup"), Some(Command::BackupList)));
+        assert!(matches!(parser.parse("/backup list"), Some(Command::BackupList)));
+        assert!(matches!(parser.parse("/backup now"), Some(Command::BackupCreate)));
+        assert!(matches!(parser.parse("/backup restore 20250101-120000"), Some(Command::BackupRestore(ref t)) if t == "20250101-120000"));
+        assert!(matches!(parser.parse("/backup restore #2"), Some(Command::BackupRestore(ref t)) if t == "#2"));
+        assert!(parser.parse("/backup restore").is_none());
+    }
+
+    #[test]
+    fn test_encryption_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/encryption"), Some(Command::Encryption(EncryptionAction::Status))));
+        assert!(matches!(parser.parse("/encryption enable"), Some(Command::Encryption(EncryptionAction::Enable(None)))));
+        assert!(matches!(
+            parser.parse("/encryption rotate --keyfile \"/keys/njord key\""),
+            Some(Command::Encryption(EncryptionAction::Rotate(Some(ref path)))) if path == "/keys/njord key"
+        ));
+        assert!(matches!(parser.parse("/encryption disable"), Some(Command::Encryption(EncryptionAction::Disable))));
+        assert!(parser.parse("/encryption rotate now").is_none());
+    }
+
+    #[test]
+    fn test_workspace_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/workspace"), Some(Command::Workspace(WorkspaceAction::Show))));
+        assert!(matches!(parser.parse("/workspace init"), Some(Command::Workspace(WorkspaceAction::Init(None)))));
+        assert!(matches!(
+            parser.parse("/workspace init \"/code/my project\""),
+            Some(Command::Workspace(WorkspaceAction::Init(Some(ref path)))) if path == "/code/my project"
+        ));
+        assert!(matches!(parser.parse("/workspace switch global"), Some(Command::Workspace(WorkspaceAction::Switch(ref t))) if t == "global"));
+        assert!(matches!(parser.parse("/workspace add-var schema"), Some(Command::Workspace(WorkspaceAction::AddVariable(ref v))) if v == "schema"));
+        assert!(matches!(parser.parse("/workspace remove-var schema"), Some(Command::Workspace(WorkspaceAction::RemoveVariable(_)))));
+        assert!(parser.parse("/workspace switch").is_none());
+    }
+
+    #[test]
+    fn test_retention_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/retention"), Some(Command::Retention(RetentionAction::Show))));
+        assert!(matches!(parser.parse("/retention preview"), Some(Command::Retention(RetentionAction::Preview))));
+        assert!(matches!(parser.parse("/retention run"), Some(Command::Retention(RetentionAction::Run))));
+        assert!(matches!(
+            parser.parse("/retention set exempt-tags keep,pinned"),
+            Some(Command::Retention(RetentionAction::Set(ref key, ref value))) if key == "exempt-tags" && value == "keep,pinned"
+        ));
+        assert!(parser.parse("/retention set max-age").is_none());
+    }
+
+    #[test]
+    fn test_template_commands() {
+        let parser = create_parser();
+
+        assert!(matches!(parser.parse("/template"), Some(Command::Template(TemplateAction::List))));
+        assert!(matches!(parser.parse("/template list"), Some(Command::Template(TemplateAction::List))));
+        assert!(matches!(parser.parse("/template save review"), Some(Command::Template(TemplateAction::Save(ref n))) if n == "review"));
+        assert!(matches!(
+            parser.parse("/template delete \"code review\""),
+            Some(Command::Template(TemplateAction::Delete(ref n))) if n == "code review"
+        ));
+        assert!(matches!(parser.parse("/chat new"), Some(Command::ChatNew)));
+        assert!(matches!(parser.parse("/chat new --template review"), Some(Command::ChatNewFromTemplate(ref n)) if n == "review"));
+        assert!(parser.parse("/template save").is_none());
+    }
+
     #[test]
     fn test_edit_commands() {
         let parser = create_parser();
@@ -1159,4 +1991,46 @@ mod tests {
             panic!("Failed to parse freeze command");
         }
     }
+
+    #[test]
+    fn test_export_commands() {
+        let parser = create_parser();
+
+        if let Some(Command::Export(format, target, filename, thinking)) = parser.parse("/export markdown") {
+            assert_eq!(format, "markdown");
+            assert!(matches!(target, ExportTarget::Active));
+            assert_eq!(filename, None);
+            assert!(!thinking);
+        } else {
+            panic!("Expected Export command");
+        }
+
+        if let Some(Command::Export(format, target, filename, thinking)) = parser.parse("/export HTML \"my chat.html\" --thinking") {
+            assert_eq!(format, "html");
+            assert!(matches!(target, ExportTarget::Active));
+            assert_eq!(filename, Some("my chat.html".to_string()));
+            assert!(thinking);
+        } else {
+            panic!("Expected Export command");
+        }
+
+        if let Some(Command::Export(_, target, filename, _)) = parser.parse("/export json --session \"My Session\" out.json") {
+            assert!(matches!(target, ExportTarget::Session(SessionReference::Named(ref name)) if name == "My Session"));
+            assert_eq!(filename, Some("out.json".to_string()));
+        } else {
+            panic!("Expected Export command");
+        }
+
+        if let Some(Command::Export(_, target, _, _)) = parser.parse("/export txt --session #2") {
+            assert!(matches!(target, ExportTarget::Session(SessionReference::Ephemeral(2))));
+        } else {
+            panic!("Expected Export command");
+        }
+
+        assert!(matches!(parser.parse("/export md --all"), Some(Command::Export(_, ExportTarget::All, None, false))));
+
+        // Missing session reference and duplicate filenames are rejected
+        assert!(parser.parse("/export json --session").is_none());
+        assert!(parser.parse("/export json a.json b.json").is_none());
+    }
 }
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use futures::StreamExt;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::providers::{ChatRequest, LLMProvider, ThinkingBlock, ThinkingCollector};
+use crate::session::{GenerationParams, MessageTiming};
+use crate::stats::{estimate_cost, estimate_tokens};
+
+const HEADER: &str = "\x1b[1;36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// One model's reply to a /compare prompt
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub model: String,
+    pub provider: String,
+    pub content: String,
+    pub thinking: String,
+    pub thinking_blocks: Vec<ThinkingBlock>,
+    pub timing: MessageTiming,
+    pub input_tokens: usize,
+}
+
+impl Candidate {
+    /// Estimated, including thinking, which is billed as output
+    pub fn output_tokens(&self) -> usize {
+        estimate_tokens(&self.content) + estimate_tokens(&self.thinking)
+    }
+
+    pub fn cost(&self) -> Option<f64> {
+        estimate_cost(&self.model, self.input_tokens, self.output_tokens())
+    }
+}
+
+/// The last /compare run, kept so one of its replies can be adopted into the session
+pub struct Comparison {
+    pub prompt: String, // As typed, before variable substitution
+    pub params: GenerationParams,
+    pub session_id: Uuid,
+    pub message_count: usize, // Adopting requires the session to be unchanged since
+    pub candidates: Vec<Result<Candidate, String>>, // In the order the models were given
+}
+
+/// Send `request` and collect the whole reply, passing each piece of the answer (not the
+/// thinking) to `on_content` as it arrives
+pub async fn collect_with<F: FnMut(&str)>(provider: &dyn LLMProvider, provider_name: &str, request: ChatRequest, mut on_content: F) -> Result<Candidate> {
+    let model = request.model.clone();
+    let input_tokens = request.messages.iter().map(|msg| estimate_tokens(&msg.content)).sum();
+    let request_start = Instant::now();
+    let mut stream = provider.chat(request).await?;
+
+    let mut first_token_ms = None;
+    let mut content = String::new();
+    let mut thinking = ThinkingCollector::default();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        if chunk.is_empty() {
+            continue;
+        }
+        first_token_ms.get_or_insert(request_start.elapsed().as_millis() as u64);
+        if let Some(text) = chunk.strip_prefix("thinking:") {
+            thinking.push_text(text);
+        } else if let Some(signature) = chunk.strip_prefix("signature:") {
+            thinking.push_signature(signature);
+        } else {
+            let text = chunk.strip_prefix("content:").unwrap_or(&chunk);
+            on_content(text);
+            content.push_str(text);
+        }
+    }
+    if content.is_empty() {
+        return Err(anyhow::anyhow!("Empty response"));
+    }
+
+    let latency_ms = request_start.elapsed().as_millis() as u64;
+    Ok(Candidate {
+        model,
+        provider: provider_name.to_string(),
+        content,
+        thinking: thinking.text,
+        thinking_blocks: thinking.blocks,
+        timing: MessageTiming { latency_ms, first_token_ms: first_token_ms.unwrap_or(latency_ms) },
+        input_tokens,
+    })
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+fn dollars(cost: Option<f64>) -> String {
+    cost.map(|cost| format!("~${:.4}", cost)).unwrap_or_else(|| "-".to_string())
+}
+
+/// Header printed above a candidate's reply
+pub fn render_header(number: usize, model: &str, provider: &str) -> String {
+    format!("{}[{}] {} ({}){}", HEADER, number, model, provider, RESET)
+}
+
+/// Latency, tokens and cost printed below a candidate's reply
+pub fn render_details(candidate: &Candidate) -> String {
+    format!(
+        "{}{} (first token {}), ~{} tokens in, ~{} out, {}{}",
+        DIM,
+        seconds(candidate.timing.latency_ms),
+        seconds(candidate.timing.first_token_ms),
+        candidate.input_tokens,
+        candidate.output_tokens(),
+        dollars(candidate.cost()),
+        RESET
+    )
+}
+
+/// One line per model, in the order they were given
+pub fn render_summary(models: &[String], comparison: &Comparison) -> String {
+    let width = models.iter().map(|model| model.len()).max().unwrap_or(5).max(5);
+    let mut out = format!("{}Comparison{}\n", HEADER, RESET);
+    out.push_str(&format!("  {}#  {:<width$}  Latency  Tokens  Cost{}\n", DIM, "Model", RESET, width = width));
+    for (index, (model, candidate)) in models.iter().zip(&comparison.candidates).enumerate() {
+        match candidate {
+            Ok(candidate) => out.push_str(&format!(
+                "  {}  {:<width$}  {:>7}  {:>6}  {}\n",
+                index + 1,
+                model,
+                seconds(candidate.timing.latency_ms),
+                candidate.output_tokens(),
+                dollars(candidate.cost()),
+                width = width
+            )),
+            Err(error) => out.push_str(&format!("  {}  {:<width$}  failed: {}\n", index + 1, model, error, width = width)),
+        }
+    }
+    out.push_str(&format!("  {}Tokens and costs are estimates from list prices{}\n", DIM, RESET));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(model: &str, content: &str, latency_ms: u64) -> Candidate {
+        Candidate {
+            model: model.to_string(),
+            provider: "openai".to_string(),
+            content: content.to_string(),
+            thinking: String::new(),
+            thinking_blocks: Vec::new(),
+            timing: MessageTiming { latency_ms, first_token_ms: 100 },
+            input_tokens: 4000,
+        }
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let models = vec!["gpt-4o".to_string(), "llama-3".to_string(), "gpt-4o-mini".to_string()];
+        let comparison = Comparison {
+            prompt: "Hi".to_string(),
+            params: GenerationParams {
+                temperature: 0.7,
+                max_tokens: 1000,
+                thinking_enabled: false,
+                thinking_budget: 0,
+                system_prompt_hash: None,
+                system_prompt: None,
+                variable_hashes: Default::default(),
+                variable_values: Default::default(),
+                excluded: vec![],
+            },
+            session_id: Uuid::new_v4(),
+            message_count: 0,
+            candidates: vec![
+                Ok(candidate("gpt-4o", &"word ".repeat(80), 2300)),
+                Ok(candidate("llama-3", "Hello", 900)),
+                Err("API error".to_string()),
+            ],
+        };
+
+        let summary = render_summary(&models, &comparison);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert!(lines[2].contains("gpt-4o") && lines[2].contains("2.3s") && lines[2].contains("100") && lines[2].contains("~$0.0110"));
+        assert!(lines[3].contains("llama-3") && lines[3].trim_end().ends_with('-')); // No known price
+        assert!(lines[4].contains("failed: API error"));
+    }
+}
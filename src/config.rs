@@ -2,9 +2,10 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::cli::Args;
+use crate::workspace::discover_workspace;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -15,8 +16,15 @@ pub struct Config {
     pub thinking_budget: u32,
     pub load_session: Option<String>,
     pub new_session: bool,
-    pub state_directory: String,
+    pub state_directory: String,        // Active state directory: the workspace if one is open
+    pub global_state_directory: String, // From --state-directory
+    pub workspace: Option<String>,      // Project-local .njord directory in use
     pub ephemeral: bool,
+    pub trash_retention_days: u32,
+    pub backup_count: usize,
+    pub backup_interval: u32,
+    pub keyfile: Option<String>,
+    pub record_values: bool, // Keep substituted variable values with each reply, for /reproduce
 }
 
 impl Config {
@@ -32,6 +40,17 @@ impl Config {
         format!("{}/inputs", self.state_directory)
     }
 
+    /// Switch the active state directory to a workspace, or back to the global one
+    pub fn set_workspace(&mut self, workspace: Option<PathBuf>) {
+        // A workspace that is the global directory itself is just the global directory
+        let workspace = workspace.filter(|dir| {
+            let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            canonical(dir) != canonical(Path::new(&self.global_state_directory))
+        });
+        self.workspace = workspace.map(|dir| dir.to_string_lossy().to_string());
+        self.state_directory = self.workspace.clone().unwrap_or_else(|| self.global_state_directory.clone());
+    }
+
     /// Ensures the state directory exists, creating it if necessary.
     pub fn ensure_state_directory(&self) -> Result<()> {
         let path = Path::new(&self.state_directory);
@@ -49,7 +68,12 @@ impl Config {
         let env_anthropic = std::env::var("ANTHROPIC_API_KEY").ok();
         let env_gemini = std::env::var("GEMINI_API_KEY").ok();
         
-        Self::from_args_and_env(args, env_openai, env_anthropic, env_gemini)
+        let mut config = Self::from_args_and_env(args, env_openai, env_anthropic, env_gemini)?;
+        if !args.no_workspace {
+            let cwd = std::env::current_dir()?;
+            config.set_workspace(discover_workspace(&cwd));
+        }
+        Ok(config)
     }
     
     pub fn from_args_and_env(
@@ -99,7 +123,14 @@ impl Config {
             load_session: args.load_session.clone(),
             new_session: args.new_session,
             state_directory: args.state_directory.clone(),
+            global_state_directory: args.state_directory.clone(),
+            workspace: None,
             ephemeral: args.ephemeral,
+            trash_retention_days: args.trash_retention_days,
+            backup_count: args.backup_count,
+            backup_interval: args.backup_interval,
+            keyfile: args.keyfile.clone(),
+            record_values: args.record_values,
         })
     }
 }
@@ -107,7 +138,7 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cli::Args;
+    use crate::cli::{Args, OutputFormat};
 
     #[test]
     fn test_config_from_args_with_cli_keys() {
@@ -123,6 +154,19 @@ mod tests {
             new_session: true,
             state_directory: ".".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args(&args).unwrap();
@@ -153,6 +197,19 @@ mod tests {
             new_session: false,
             state_directory: ".".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args_and_env(&args_anthropic, None, None, None).unwrap();
@@ -171,6 +228,19 @@ mod tests {
             new_session: false,
             state_directory: ".".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args_and_env(&args_openai, None, None, None).unwrap();
@@ -189,6 +259,19 @@ mod tests {
             new_session: false,
             state_directory: ".".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args_and_env(&args_gemini, None, None, None).unwrap();
@@ -209,6 +292,19 @@ mod tests {
             new_session: false,
             state_directory: ".".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args_and_env(&args, None, None, None).unwrap();
@@ -231,6 +327,19 @@ mod tests {
             new_session: false,
             state_directory: ".".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args_and_env(
@@ -257,6 +366,19 @@ mod tests {
             new_session: false,
             state_directory: ".".to_string(),
             ephemeral: true,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
         
         let config = Config::from_args(&args).unwrap();
@@ -277,12 +399,33 @@ mod tests {
             new_session: false,
             state_directory: "/custom/path".to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
 
-        let config = Config::from_args(&args).unwrap();
+        let mut config = Config::from_args(&args).unwrap();
         assert_eq!(config.sessions_file(), "/custom/path/sessions");
         assert_eq!(config.prompts_file(), "/custom/path/prompts");
         assert_eq!(config.inputs_file(), "/custom/path/inputs");
+
+        config.set_workspace(Some(PathBuf::from("/work/project/.njord")));
+        assert_eq!(config.sessions_file(), "/work/project/.njord/sessions");
+        assert_eq!(config.global_state_directory, "/custom/path");
+
+        config.set_workspace(None);
+        assert_eq!(config.sessions_file(), "/custom/path/sessions");
+        assert!(config.workspace.is_none());
     }
 
     #[test]
@@ -302,6 +445,19 @@ mod tests {
             new_session: false,
             state_directory: state_path.to_string_lossy().to_string(),
             ephemeral: false,
+            trash_retention_days: 30,
+            backup_count: 10,
+            backup_interval: 60,
+            keyfile: None,
+            record_values: false,
+            no_workspace: true,
+            prompt: None,
+            system: None,
+            apply_prompt: None,
+            script: None,
+            keep_going: false,
+            output: OutputFormat::Text,
+            command: None,
         };
 
         let config = Config::from_args(&args).unwrap();
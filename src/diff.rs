@@ -0,0 +1,265 @@
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeSet;
+
+use crate::session::{ChatSession, NumberedMessage};
+
+const REMOVED: &str = "\x1b[9;31m";
+const ADDED: &str = "\x1b[1;32m";
+const HEADER: &str = "\x1b[1;36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+const DEFAULT_WIDTH: usize = 100;
+const MIN_COLUMN_WIDTH: usize = 20;
+
+/// How two sessions relate: a shared history followed by diverging messages
+pub struct SessionDiff<'a> {
+    pub common_prefix: usize,
+    pub common_exchanges: usize, // User messages within the common prefix
+    pub left: &'a [NumberedMessage],  // Messages after the common prefix
+    pub right: &'a [NumberedMessage],
+    pub settings: Vec<SettingDifference>,
+}
+
+pub struct SettingDifference {
+    pub label: String,
+    pub left: String,
+    pub right: String,
+}
+
+fn same_message(a: &NumberedMessage, b: &NumberedMessage) -> bool {
+    a.message.role == b.message.role && a.message.content == b.message.content
+}
+
+pub fn diff_sessions<'a>(left: &'a ChatSession, right: &'a ChatSession) -> SessionDiff<'a> {
+    let common_prefix = left.messages
+        .iter()
+        .zip(&right.messages)
+        .take_while(|(a, b)| same_message(a, b))
+        .count();
+
+    let mut settings = Vec::new();
+    let mut compare = |label: &str, a: String, b: String| {
+        if a != b {
+            settings.push(SettingDifference { label: label.to_string(), left: a, right: b });
+        }
+    };
+    compare("model", left.current_model.clone(), right.current_model.clone());
+    compare("system prompt", left.system_prompt.clone().unwrap_or_default(), right.system_prompt.clone().unwrap_or_default());
+    compare("temperature", left.temperature.to_string(), right.temperature.to_string());
+    compare("thinking", left.thinking_enabled.to_string(), right.thinking_enabled.to_string());
+
+    let names: BTreeSet<&String> = left.variables.keys().chain(right.variables.keys()).collect();
+    for name in names {
+        let describe = |session: &ChatSession| {
+            session.variables.get(name).map(|v| v.source.display_source()).unwrap_or_else(|| "(unset)".to_string())
+        };
+        compare(&format!("variable {{{{{}}}}}", name), describe(left), describe(right));
+    }
+
+    SessionDiff {
+        common_prefix,
+        common_exchanges: left.messages[..common_prefix].iter().filter(|msg| msg.message.role == "user").count(),
+        left: &left.messages[common_prefix..],
+        right: &right.messages[common_prefix..],
+        settings,
+    }
+}
+
+/// Terminal width from `COLUMNS`, or a default when it isn't set
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()).unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Word-level diff in two columns of `width` characters each: the left column has the old
+/// text with removed words struck through in red, the right the new text with added words in green
+pub fn side_by_side(old: &str, new: &str, width: usize) -> String {
+    let diff = TextDiff::from_words(old, new);
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Equal => {
+                left.push((change.value(), None));
+                right.push((change.value(), None));
+            }
+            ChangeTag::Delete => left.push((change.value(), Some(REMOVED))),
+            ChangeTag::Insert => right.push((change.value(), Some(ADDED))),
+        }
+    }
+
+    let left = wrap_words(&left, width);
+    let right = wrap_words(&right, width);
+    let empty = (String::new(), 0);
+    (0..left.len().max(right.len()))
+        .map(|index| {
+            let (left_text, left_len) = left.get(index).unwrap_or(&empty);
+            let (right_text, _) = right.get(index).unwrap_or(&empty);
+            format!("{}{} │ {}", left_text, " ".repeat(width - left_len), right_text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Wrap colored words into lines of at most `width` visible characters.
+/// Returns each line with its visible length, for padding.
+fn wrap_words(words: &[(&str, Option<&str>)], width: usize) -> Vec<(String, usize)> {
+    let mut lines = vec![(String::new(), 0)];
+    for (text, color) in words {
+        if text.trim().is_empty() {
+            // Whitespace: keep line breaks, and a single space between words on a line
+            for _ in 0..text.matches('\n').count() {
+                lines.push((String::new(), 0));
+            }
+            let (line, len) = lines.last_mut().unwrap();
+            if !text.contains('\n') && *len > 0 && *len < width {
+                line.push(' ');
+                *len += 1;
+            }
+            continue;
+        }
+
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in chars.chunks(width) {
+            if lines.last().map(|(_, len)| len + chunk.len() > width).unwrap_or(false) {
+                lines.push((String::new(), 0));
+            }
+            let (line, len) = lines.last_mut().unwrap();
+            let chunk: String = chunk.iter().collect();
+            match color {
+                Some(color) => line.push_str(&format!("{}{}{}", color, chunk, RESET)),
+                None => line.push_str(&chunk),
+            }
+            *len += chunk.chars().count();
+        }
+    }
+    lines
+}
+
+fn role_label(msg: &NumberedMessage) -> String {
+    let role = if msg.message.role == "user" { "User" } else { "Agent" };
+    match &msg.model {
+        Some(model) if msg.message.role == "assistant" => format!("{} ({})", role, model),
+        _ => role.to_string(),
+    }
+}
+
+/// Render the comparison for a terminal `width` characters wide, messages side by side
+pub fn render_session_diff(left_name: &str, right_name: &str, diff: &SessionDiff, width: usize) -> String {
+    let column = (width.saturating_sub(3) / 2).max(MIN_COLUMN_WIDTH);
+    let mut out = String::new();
+    out.push_str(&format!("{}Comparing '{}' (-) with '{}' (+){}\n", HEADER, left_name, right_name, RESET));
+    out.push_str(&format!(
+        "  Common prefix: {} message(s), {} exchange(s)\n",
+        diff.common_prefix, diff.common_exchanges
+    ));
+    out.push_str(&format!(
+        "  After that: {} message(s) only in '{}', {} only in '{}'\n\n",
+        diff.left.len(), left_name, diff.right.len(), right_name
+    ));
+
+    if !diff.settings.is_empty() {
+        out.push_str(&format!("{}Settings:{}\n", HEADER, RESET));
+        for setting in &diff.settings {
+            if setting.label == "system prompt" {
+                out.push_str(&format!("  {}:\n{}\n", setting.label, side_by_side(&setting.left, &setting.right, column)));
+            } else {
+                out.push_str(&format!(
+                    "  {}: {}{}{} → {}{}{}\n",
+                    setting.label, REMOVED, setting.left, RESET, ADDED, setting.right, RESET
+                ));
+            }
+        }
+        out.push('\n');
+    }
+
+    if diff.left.is_empty() && diff.right.is_empty() {
+        out.push_str("Messages are identical\n");
+        return out;
+    }
+
+    out.push_str(&format!("{}Diverging messages:{}\n", HEADER, RESET));
+    out.push_str(&format!("{:<width$} │ {}\n", left_name, right_name, width = column));
+    let paired = diff.left.len().max(diff.right.len());
+    for index in 0..paired {
+        match (diff.left.get(index), diff.right.get(index)) {
+            (Some(a), Some(b)) if a.message.role == b.message.role => {
+                let label = if a.model == b.model {
+                    role_label(a)
+                } else {
+                    format!("{} vs {}", role_label(a), role_label(b))
+                };
+                out.push_str(&format!("{}[{}] {}{}\n", DIM, a.number, label, RESET));
+                out.push_str(&side_by_side(&a.message.content, &b.message.content, column));
+                out.push_str("\n\n");
+            }
+            (a, b) => {
+                // Different roles or one side ran out: show each message against an empty column
+                if let Some(a) = a {
+                    out.push_str(&format!("{}[{}] {} only in '{}'{}\n", DIM, a.number, role_label(a), left_name, RESET));
+                    out.push_str(&side_by_side(&a.message.content, "", column));
+                    out.push_str("\n\n");
+                }
+                if let Some(b) = b {
+                    out.push_str(&format!("{}[{}] {} only in '{}'{}\n", DIM, b.number, role_label(b), right_name, RESET));
+                    out.push_str(&side_by_side("", &b.message.content, column));
+                    out.push_str("\n\n");
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Message;
+
+    fn session_with(messages: &[(&str, &str)]) -> ChatSession {
+        let mut session = ChatSession::new("gpt-4o".to_string(), 0.7, 1000, 5000);
+        for (role, content) in messages {
+            session.add_message(Message { role: role.to_string(), content: content.to_string() });
+        }
+        session
+    }
+
+    #[test]
+    fn test_diff_sessions() {
+        let left = session_with(&[("user", "hi"), ("assistant", "hello"), ("user", "use rust")]);
+        let mut right = session_with(&[("user", "hi"), ("assistant", "hello"), ("user", "use go"), ("assistant", "ok")]);
+        right.current_model = "claude-sonnet-4-20250514".to_string();
+
+        let diff = diff_sessions(&left, &right);
+        assert_eq!(diff.common_prefix, 2);
+        assert_eq!(diff.left.len(), 1);
+        assert_eq!(diff.right.len(), 2);
+        assert_eq!(diff.settings.len(), 1);
+        assert_eq!(diff.settings[0].label, "model");
+
+        let rendered = render_session_diff("a", "b", &diff, 80);
+        assert!(rendered.contains("Common prefix: 2 message(s)"));
+        assert!(rendered.contains("only in 'b'"));
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let rendered = side_by_side("use rust today", "use go today", 20);
+        assert_eq!(rendered, format!(
+            "use {}rust{} today{} │ use {}go{} today",
+            REMOVED, RESET, " ".repeat(6), ADDED, RESET
+        ));
+
+        // Long text wraps within its column, and the shorter side is padded out
+        let rendered = side_by_side("one two three four", "", 9);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(strip(lines[0]), "one two   │ ");
+        assert_eq!(strip(lines[1]), "three     │ ");
+        assert_eq!(strip(lines[2]), "four      │ ");
+        assert_eq!(strip(&side_by_side("a\nb", "a\nb", 5)), "a     │ a\nb     │ b");
+    }
+
+    fn strip(text: &str) -> String {
+        text.replace(REMOVED, "").replace(ADDED, "").replace(RESET, "")
+    }
+}
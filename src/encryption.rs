@@ -0,0 +1,447 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use zeroize::Zeroizing;
+
+use crate::backup::STATE_FILES;
+use crate::retention::archive_files;
+use crate::schema::migration_backups;
+
+/// Environment variable holding the passphrase for encrypted state files
+pub const PASSPHRASE_ENV: &str = "NJORD_PASSPHRASE";
+
+const ENVELOPE_VERSION: u32 = 1;
+const DEFAULT_LOG_N: u8 = 15; // scrypt cost: 2^15 iterations, 32 MiB
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// The cipher of each state directory unlocked in this process, None for plaintext ones.
+/// A workspace and the global state layered under it are encrypted independently.
+static STATE_CIPHERS: RwLock<Vec<(PathBuf, Option<Arc<StateCipher>>)>> = RwLock::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    Passphrase,
+    Keyfile,
+}
+
+/// On-disk format of an encrypted state file
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    njord_encrypted: u32,
+    key: KeyKind,
+    kdf: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// XChaCha20-Poly1305 with an scrypt-derived key. Derived keys are cached per salt,
+/// so only the first read or write of a run pays the key derivation cost.
+pub struct StateCipher {
+    secret: Zeroizing<Vec<u8>>,
+    kind: KeyKind,
+    salt: [u8; SALT_LEN],
+    log_n: u8,
+    keys: Mutex<HashMap<Vec<u8>, Zeroizing<[u8; 32]>>>,
+}
+
+impl StateCipher {
+    pub fn from_passphrase(passphrase: &str) -> Result<Self> {
+        if passphrase.is_empty() {
+            return Err(anyhow::anyhow!("Passphrase cannot be empty"));
+        }
+        Ok(Self::new(passphrase.as_bytes().to_vec(), KeyKind::Passphrase, DEFAULT_LOG_N))
+    }
+
+    pub fn from_keyfile(path: &str) -> Result<Self> {
+        let secret = fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read keyfile '{}': {}", path, e))?;
+        if secret.len() < 16 {
+            return Err(anyhow::anyhow!("Keyfile '{}' is too short (need at least 16 bytes)", path));
+        }
+        Ok(Self::new(secret, KeyKind::Keyfile, DEFAULT_LOG_N))
+    }
+
+    fn new(secret: Vec<u8>, kind: KeyKind, log_n: u8) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            secret: Zeroizing::new(secret),
+            kind,
+            salt,
+            log_n,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn kind(&self) -> KeyKind {
+        self.kind
+    }
+
+    fn derive_key(&self, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Zeroizing<[u8; 32]>> {
+        let cache_key = [salt, &[log_n]].concat();
+        let mut keys = self.keys.lock().map_err(|_| anyhow::anyhow!("Key cache poisoned"))?;
+        if let Some(key) = keys.get(&cache_key) {
+            return Ok(key.clone());
+        }
+
+        let params = scrypt::Params::new(log_n, r, p, 32)
+            .map_err(|e| anyhow::anyhow!("Invalid scrypt parameters: {}", e))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(&self.secret, salt, &params, key.as_mut())
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        keys.insert(cache_key, key.clone());
+        Ok(key)
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String> {
+        let key = self.derive_key(&self.salt, self.log_n, SCRYPT_R, SCRYPT_P)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+
+        let envelope = Envelope {
+            njord_encrypted: ENVELOPE_VERSION,
+            key: self.kind,
+            kdf: "scrypt".to_string(),
+            log_n: self.log_n,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: BASE64.encode(self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        Ok(serde_json::to_string_pretty(&envelope)?)
+    }
+
+    pub fn decrypt(&self, content: &str) -> Result<Vec<u8>> {
+        let envelope: Envelope = serde_json::from_str(content)?;
+        if envelope.njord_encrypted != ENVELOPE_VERSION || envelope.kdf != "scrypt" {
+            return Err(anyhow::anyhow!("Unsupported encryption format (version {})", envelope.njord_encrypted));
+        }
+
+        let salt = BASE64.decode(&envelope.salt)?;
+        let nonce = BASE64.decode(&envelope.nonce)?;
+        let ciphertext = BASE64.decode(&envelope.ciphertext)?;
+        if nonce.len() != 24 {
+            return Err(anyhow::anyhow!("Invalid nonce in encrypted file"));
+        }
+
+        let key = self.derive_key(&salt, envelope.log_n, envelope.r, envelope.p)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| match envelope.key {
+                KeyKind::Passphrase => anyhow::anyhow!("Wrong passphrase or corrupted file"),
+                KeyKind::Keyfile => anyhow::anyhow!("Wrong keyfile or corrupted file"),
+            })
+    }
+}
+
+/// Whether file content is an encrypted envelope rather than plaintext JSON
+pub fn is_encrypted(content: &str) -> bool {
+    // Cheap check first so large plaintext files aren't parsed twice
+    content.contains("\"njord_encrypted\"")
+        && serde_json::from_str::<serde_json::Value>(content)
+            .map(|value| value.get("njord_encrypted").is_some())
+            .unwrap_or(false)
+}
+
+/// Which kind of key an encrypted file needs, if it is encrypted
+pub fn required_key_kind(content: &str) -> Option<KeyKind> {
+    if !is_encrypted(content) {
+        return None;
+    }
+    serde_json::from_str::<Envelope>(content).ok().map(|envelope| envelope.key)
+}
+
+pub fn set_state_cipher(state_directory: &str, cipher: Option<Arc<StateCipher>>) {
+    if let Ok(mut ciphers) = STATE_CIPHERS.write() {
+        ciphers.retain(|(directory, _)| directory != Path::new(state_directory));
+        ciphers.push((PathBuf::from(state_directory), cipher));
+    }
+}
+
+pub fn state_cipher(state_directory: &str) -> Option<Arc<StateCipher>> {
+    let ciphers = STATE_CIPHERS.read().ok()?;
+    ciphers
+        .iter()
+        .find(|(directory, _)| directory == Path::new(state_directory))
+        .and_then(|(_, cipher)| cipher.clone())
+}
+
+fn is_unlocked(state_directory: &str) -> bool {
+    STATE_CIPHERS
+        .read()
+        .map(|ciphers| ciphers.iter().any(|(directory, _)| directory == Path::new(state_directory)))
+        .unwrap_or(false)
+}
+
+/// The cipher for a file: that of the nearest unlocked state directory containing it
+fn cipher_for(path: &Path) -> Option<Arc<StateCipher>> {
+    let ciphers = STATE_CIPHERS.read().ok()?;
+    ciphers
+        .iter()
+        .filter(|(directory, _)| path.starts_with(directory))
+        .max_by_key(|(directory, _)| directory.components().count())
+        .and_then(|(_, cipher)| cipher.clone())
+}
+
+/// Read a state file, decrypting it if needed
+pub fn read_state_file(path: &Path) -> Result<String> {
+    decode_state_content(fs::read_to_string(path)?, path)
+}
+
+/// Decrypt state file content read from somewhere else, e.g. a backup; `path` names it in errors
+pub fn decode_state_content(content: String, path: &Path) -> Result<String> {
+    if !is_encrypted(&content) {
+        return Ok(content);
+    }
+
+    let cipher = cipher_for(path).ok_or_else(|| anyhow::anyhow!(
+        "{} is encrypted. Set {} or pass --keyfile to unlock it",
+        path.display(),
+        PASSPHRASE_ENV
+    ))?;
+    let plaintext = cipher
+        .decrypt(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to decrypt {}: {}", path.display(), e))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Write a state file, encrypting it when its state directory has a cipher
+pub fn write_state_file(path: &Path, content: &str) -> Result<()> {
+    match cipher_for(path) {
+        Some(cipher) => fs::write(path, cipher.encrypt(content.as_bytes())?)?,
+        None => fs::write(path, content)?,
+    }
+    Ok(())
+}
+
+/// Work out and register the cipher for a state directory, unless it already has one:
+/// `--keyfile`, then `NJORD_PASSPHRASE`, then a key unlocked for another state directory,
+/// then an interactive prompt if the directory already holds encrypted files.
+/// The key is checked against an existing encrypted file so a typo fails fast.
+pub fn unlock_state_directory(state_directory: &str, keyfile: Option<&str>) -> Result<()> {
+    if is_unlocked(state_directory) {
+        return Ok(());
+    }
+    let encrypted: Vec<(String, String)> = STATE_FILES
+        .iter()
+        .filter_map(|name| {
+            let path = Path::new(state_directory).join(name);
+            let content = fs::read_to_string(&path).ok()?;
+            is_encrypted(&content).then(|| (path.display().to_string(), content))
+        })
+        .collect();
+
+    let cipher = if let Some(keyfile) = keyfile {
+        StateCipher::from_keyfile(keyfile)?
+    } else if let Some(passphrase) = std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()) {
+        StateCipher::from_passphrase(&passphrase)?
+    } else if let Some((path, content)) = encrypted.first() {
+        let unlocked: Vec<Arc<StateCipher>> = STATE_CIPHERS
+            .read()
+            .map(|ciphers| ciphers.iter().filter_map(|(_, cipher)| cipher.clone()).collect())
+            .unwrap_or_default();
+        if let Some(cipher) = unlocked.into_iter().find(|cipher| cipher.decrypt(content).is_ok()) {
+            set_state_cipher(state_directory, Some(cipher));
+            return Ok(());
+        }
+        if required_key_kind(content) == Some(KeyKind::Keyfile) {
+            return Err(anyhow::anyhow!("{} is encrypted with a keyfile. Pass it with --keyfile", path));
+        }
+        let passphrase = rpassword::prompt_password("Passphrase for encrypted njord state: ")?;
+        StateCipher::from_passphrase(&passphrase)?
+    } else {
+        set_state_cipher(state_directory, None);
+        return Ok(());
+    };
+
+    if let Some((path, content)) = encrypted.first() {
+        cipher
+            .decrypt(content)
+            .map_err(|e| anyhow::anyhow!("Cannot unlock {}: {}", path, e))?;
+    }
+    set_state_cipher(state_directory, Some(Arc::new(cipher)));
+    Ok(())
+}
+
+/// A file rewrite prepared in memory, e.g. by a rekey: the file's current and new content
+pub struct StagedWrite {
+    path: PathBuf,
+    original: Vec<u8>,
+    content: Vec<u8>,
+}
+
+impl StagedWrite {
+    pub fn new(path: PathBuf, original: Vec<u8>, content: Vec<u8>) -> Self {
+        Self { path, original, content }
+    }
+}
+
+/// Re-encrypt every state file, migration backup and archived session under `new_cipher`
+/// (plaintext when None), in memory. Nothing is written; pass the result to `commit_writes` together with
+/// the backups.
+pub fn stage_rekey_state_files(state_directory: &str, new_cipher: Option<&StateCipher>) -> Result<Vec<StagedWrite>> {
+    let current = state_cipher(state_directory);
+    let mut paths: Vec<PathBuf> = STATE_FILES.iter().map(|name| Path::new(state_directory).join(name)).collect();
+    paths.extend(migration_backups(state_directory)?);
+    paths.extend(archive_files(state_directory)?);
+
+    let mut staged = Vec::new();
+    for path in paths {
+        if path.exists() {
+            let original = fs::read(&path)?;
+            let content = reencrypt(&original, current.as_deref(), new_cipher)?;
+            staged.push(StagedWrite::new(path, original, content));
+        }
+    }
+    Ok(staged)
+}
+
+/// Replace a set of files as one unit. Every new file is written to a temporary file next
+/// to its target before any target is touched, then renamed into place; if a rename
+/// fails, the files already replaced get their original content back.
+pub fn commit_writes(writes: &[StagedWrite]) -> Result<()> {
+    let mut temp_files = Vec::new();
+    for write in writes {
+        let directory = write.path.parent().unwrap_or(Path::new("."));
+        let mut temp_file = tempfile::NamedTempFile::new_in(directory)?;
+        temp_file.write_all(&write.content)?;
+        temp_file.as_file().sync_all()?;
+        temp_files.push(temp_file);
+    }
+
+    for (index, (temp_file, write)) in temp_files.into_iter().zip(writes).enumerate() {
+        if let Err(e) = temp_file.persist(&write.path) {
+            for done in &writes[..index] {
+                let _ = fs::write(&done.path, &done.original);
+            }
+            return Err(anyhow::anyhow!("Failed to replace {}: {}", write.path.display(), e.error));
+        }
+    }
+    Ok(())
+}
+
+/// Re-write state file content with `new_cipher` (or as plaintext when None), decrypting
+/// with `current` first. Used to encrypt existing data and rotate keys.
+pub fn reencrypt(content: &[u8], current: Option<&StateCipher>, new_cipher: Option<&StateCipher>) -> Result<Vec<u8>> {
+    let content = String::from_utf8(content.to_vec())?;
+    let plaintext = if is_encrypted(&content) {
+        let cipher = current.ok_or_else(|| anyhow::anyhow!("Data is encrypted but no key is loaded"))?;
+        cipher.decrypt(&content)?
+    } else {
+        content.into_bytes()
+    };
+
+    match new_cipher {
+        Some(cipher) => Ok(cipher.encrypt(&plaintext)?.into_bytes()),
+        None => Ok(plaintext),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cipher(passphrase: &str) -> StateCipher {
+        // Cheap scrypt cost keeps the tests fast
+        StateCipher::new(passphrase.as_bytes().to_vec(), KeyKind::Passphrase, 4)
+    }
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let cipher = test_cipher("correct horse");
+        let encrypted = cipher.encrypt(b"{\"saved_sessions\":{}}").unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("saved_sessions"));
+        assert_eq!(required_key_kind(&encrypted), Some(KeyKind::Passphrase));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), b"{\"saved_sessions\":{}}");
+
+        // A new cipher from the same passphrase has a different salt but can still decrypt
+        assert_eq!(test_cipher("correct horse").decrypt(&encrypted).unwrap(), b"{\"saved_sessions\":{}}");
+        assert!(test_cipher("wrong").decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let cipher = test_cipher("secret");
+        let encrypted = cipher.encrypt(b"hello").unwrap();
+        let mut envelope: Envelope = serde_json::from_str(&encrypted).unwrap();
+        let mut ciphertext = BASE64.decode(&envelope.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        envelope.ciphertext = BASE64.encode(ciphertext);
+
+        assert!(cipher.decrypt(&serde_json::to_string(&envelope).unwrap()).is_err());
+        assert!(!is_encrypted("{\"saved_sessions\":{}}"));
+        assert_eq!(required_key_kind("{}"), None);
+    }
+
+    #[test]
+    fn test_commit_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = dir.path().join("sessions");
+        let prompts = dir.path().join("prompts");
+        fs::write(&sessions, "old sessions").unwrap();
+        fs::write(&prompts, "old prompts").unwrap();
+
+        let writes = vec![
+            StagedWrite::new(sessions.clone(), b"old sessions".to_vec(), b"new sessions".to_vec()),
+            StagedWrite::new(prompts.clone(), b"old prompts".to_vec(), b"new prompts".to_vec()),
+        ];
+        commit_writes(&writes).unwrap();
+        assert_eq!(fs::read_to_string(&sessions).unwrap(), "new sessions");
+        assert_eq!(fs::read_to_string(&prompts).unwrap(), "new prompts");
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // A target that can't be replaced fails before anything else is touched
+        let writes = vec![
+            StagedWrite::new(sessions.clone(), b"new sessions".to_vec(), b"newer sessions".to_vec()),
+            StagedWrite::new(dir.path().join("missing").join("prompts"), Vec::new(), b"x".to_vec()),
+        ];
+        assert!(commit_writes(&writes).is_err());
+        assert_eq!(fs::read_to_string(&sessions).unwrap(), "new sessions");
+    }
+
+    #[test]
+    fn test_state_directories_are_keyed_separately() {
+        let global = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let global_dir = global.path().to_string_lossy().to_string();
+        let project_dir = project.path().to_string_lossy().to_string();
+        let other_dir = other.path().to_string_lossy().to_string();
+
+        set_state_cipher(&global_dir, Some(Arc::new(test_cipher("global secret"))));
+        set_state_cipher(&project_dir, None);
+        write_state_file(&global.path().join("prompts"), "global prompts").unwrap();
+        write_state_file(&project.path().join("prompts"), "project prompts").unwrap();
+
+        assert!(is_encrypted(&fs::read_to_string(global.path().join("prompts")).unwrap()));
+        assert_eq!(fs::read_to_string(project.path().join("prompts")).unwrap(), "project prompts");
+        assert_eq!(read_state_file(&global.path().join("prompts")).unwrap(), "global prompts");
+
+        // A directory encrypted with a key that is already unlocked opens without asking again
+        fs::write(other.path().join("sessions"), test_cipher("global secret").encrypt(b"{}").unwrap()).unwrap();
+        unlock_state_directory(&other_dir, None).unwrap();
+        assert_eq!(read_state_file(&other.path().join("sessions")).unwrap(), "{}");
+    }
+}
@@ -0,0 +1,197 @@
+use regex::Regex;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use crate::cli::OutputFormat;
+use crate::session::CodeBlock;
+
+static OUTPUT_FORMAT: RwLock<OutputFormat> = RwLock::new(OutputFormat::Text);
+static PENDING: Mutex<Pending> = Mutex::new(Pending { text: String::new(), events: Vec::new() });
+
+/// Text printed since the last event, and with --output json every event so far
+struct Pending {
+    text: String,
+    events: Vec<Event>,
+}
+
+/// What njord reports with --output json|jsonl, one JSON object per event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Step { line: usize, input: String }, // A script line about to run
+    Info { message: String },
+    Error { message: String },
+    Output { text: String }, // Anything else a command printed, without colours
+    ContentDelta { number: usize, text: String }, // Streamed part of Agent N's reply (jsonl only)
+    ThinkingDelta { number: usize, text: String },
+    Message {
+        number: usize, // As in "Agent N" or /goto N
+        role: String,
+        content: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thinking: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        provider: Option<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        code_blocks: Vec<CodeBlock>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        usage: Option<Usage>,
+    },
+}
+
+/// Estimated token counts and timing for a reply
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    pub input_tokens: usize,
+    pub output_tokens: usize, // Including thinking
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    pub latency_ms: u64,
+    pub first_token_ms: u64,
+}
+
+pub fn set_output_format(format: OutputFormat) {
+    if let Ok(mut active) = OUTPUT_FORMAT.write() {
+        *active = format;
+    }
+}
+
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.read().map(|format| *format).unwrap_or_default()
+}
+
+/// Whether output is being reported as events rather than text
+pub fn is_structured() -> bool {
+    output_format() != OutputFormat::Text
+}
+
+/// Report `event`; does nothing in text mode, where the caller prints as usual
+pub fn emit(event: Event) {
+    let format = output_format();
+    if format == OutputFormat::Text {
+        return;
+    }
+    let Ok(mut pending) = PENDING.lock() else {
+        return;
+    };
+    let text = std::mem::take(&mut pending.text);
+    for event in output_event(&text).into_iter().chain(std::iter::once(event)) {
+        match format {
+            OutputFormat::Jsonl => {
+                let mut stdout = std::io::stdout().lock();
+                let _ = writeln!(stdout, "{}", render(&event));
+                let _ = stdout.flush();
+            }
+            // A single document can't stream, so the deltas would only repeat the messages
+            _ if matches!(event, Event::ContentDelta { .. } | Event::ThinkingDelta { .. }) => {}
+            _ => pending.events.push(event),
+        }
+    }
+}
+
+/// Print command output, or hold it for the next `output` event
+pub fn print_text(text: &str) {
+    if !is_structured() {
+        print!("{}", text);
+        return;
+    }
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.text.push_str(text);
+    }
+}
+
+/// Turn text printed so far into an `output` event
+pub fn flush_text() {
+    let text = PENDING.lock().map(|mut pending| std::mem::take(&mut pending.text)).unwrap_or_default();
+    if let Some(event) = output_event(&text) {
+        emit(event);
+    }
+}
+
+/// Report an error that ends the run, as an event or on stderr
+pub fn report_error(message: &str) {
+    if is_structured() {
+        emit(Event::Error { message: message.to_string() });
+    } else {
+        eprintln!("Error: {}", message);
+    }
+}
+
+/// Emit anything still pending; with --output json this prints the whole document
+pub fn finish() {
+    flush_text();
+    if output_format() != OutputFormat::Json {
+        return;
+    }
+    let events = PENDING.lock().map(|mut pending| std::mem::take(&mut pending.events)).unwrap_or_default();
+    println!("{}", serde_json::to_string_pretty(&events).unwrap_or_else(|_| "[]".to_string()));
+}
+
+fn output_event(text: &str) -> Option<Event> {
+    let text = strip_ansi(text);
+    let text = text.trim_end_matches('\n');
+    (!text.trim().is_empty()).then(|| Event::Output { text: text.to_string() })
+}
+
+fn render(event: &Event) -> String {
+    serde_json::to_string(event).unwrap_or_default()
+}
+
+fn strip_ansi(text: &str) -> String {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    let ansi = ANSI.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap());
+    ansi.replace_all(text, "").to_string()
+}
+
+/// `print!` for command output; with --output json|jsonl the text becomes an `output` event
+macro_rules! out {
+    ($($arg:tt)*) => { $crate::events::print_text(&format!($($arg)*)) };
+}
+
+/// `println!` for command output; with --output json|jsonl the text becomes an `output` event
+macro_rules! outln {
+    () => { $crate::events::print_text("\n") };
+    ($($arg:tt)*) => { $crate::events::print_text(&format!("{}\n", format_args!($($arg)*))) };
+}
+
+pub(crate) use {out, outln};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_events() {
+        let message = Event::Message {
+            number: 2,
+            role: "assistant".to_string(),
+            content: "Use `ls`:\n```sh\nls -la\n```".to_string(),
+            thinking: None,
+            model: Some("gpt-4o".to_string()),
+            provider: Some("openai".to_string()),
+            code_blocks: vec![CodeBlock { number: 1, language: Some("sh".to_string()), content: "ls -la".to_string() }],
+            usage: Some(Usage { input_tokens: 12, output_tokens: 8, cost: None, latency_ms: 900, first_token_ms: 300 }),
+        };
+        let json: serde_json::Value = serde_json::from_str(&render(&message)).unwrap();
+        assert_eq!(json["type"], "message");
+        assert_eq!(json["number"], 2);
+        assert_eq!(json["code_blocks"][0]["language"], "sh");
+        assert_eq!(json["usage"]["latency_ms"], 900);
+        assert!(json.get("thinking").is_none() && json["usage"].get("cost").is_none());
+
+        assert_eq!(render(&Event::ContentDelta { number: 1, text: "Hi".to_string() }), r#"{"type":"content_delta","number":1,"text":"Hi"}"#);
+        assert_eq!(render(&Event::Step { line: 3, input: "/model o3".to_string() }), r#"{"type":"step","line":3,"input":"/model o3"}"#);
+    }
+
+    #[test]
+    fn test_output_event() {
+        assert_eq!(
+            output_event("\x1b[1;36mSession:\x1b[0m probe\n  2 messages\n"),
+            Some(Event::Output { text: "Session: probe\n  2 messages".to_string() })
+        );
+        assert_eq!(output_event("\x1b[2m\x1b[0m\n\n"), None);
+    }
+}
@@ -0,0 +1,636 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::session::{ChatSession, NumberedMessage};
+
+/// Output format for `/export`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+    Text,
+}
+
+impl ExportFormat {
+    /// Parse a format name as typed by the user
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            "html" | "htm" => Ok(ExportFormat::Html),
+            "txt" | "text" => Ok(ExportFormat::Text),
+            _ => Err(anyhow!(
+                "Unknown export format '{}'. Supported formats: markdown, json, html, txt",
+                name
+            )),
+        }
+    }
+
+    /// Conventional file extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+            ExportFormat::Text => "txt",
+        }
+    }
+}
+
+/// Render one or more sessions into a single document.
+///
+/// A single JSON session is emitted as an object, several as an array.
+pub fn export_sessions(sessions: &[&ChatSession], format: ExportFormat, include_thinking: bool) -> Result<String> {
+    if sessions.is_empty() {
+        return Err(anyhow!("No sessions to export"));
+    }
+
+    match format {
+        ExportFormat::Json => export_json(sessions, include_thinking),
+        ExportFormat::Markdown => Ok(sessions
+            .iter()
+            .map(|session| render_markdown(session, include_thinking))
+            .collect::<Vec<_>>()
+            .join("\n---\n\n")),
+        ExportFormat::Text => Ok(sessions
+            .iter()
+            .map(|session| render_text(session, include_thinking))
+            .collect::<Vec<_>>()
+            .join("\n")),
+        ExportFormat::Html => Ok(render_html_document(sessions, include_thinking)),
+    }
+}
+
+fn export_json(sessions: &[&ChatSession], include_thinking: bool) -> Result<String> {
+    let prepared: Vec<ChatSession> = sessions
+        .iter()
+        .map(|session| {
+            let mut session = (*session).clone();
+            // The journal holds text the user undid or edited away
+            session.journal.clear();
+            if !include_thinking {
+                for msg in &mut session.messages {
+                    msg.thinking = None;
+                    msg.thinking_blocks.clear();
+                }
+            }
+            session
+        })
+        .collect();
+
+    if prepared.len() == 1 {
+        Ok(serde_json::to_string_pretty(&prepared[0])?)
+    } else {
+        Ok(serde_json::to_string_pretty(&prepared)?)
+    }
+}
+
+fn session_title(session: &ChatSession) -> String {
+    session.name.clone().unwrap_or_else(|| session.generate_auto_name())
+}
+
+fn role_label(role: &str) -> &'static str {
+    match role {
+        "user" => "User",
+        "assistant" => "Agent",
+        "system" => "System",
+        _ => "Message",
+    }
+}
+
+/// Number messages the same way `/history` does: each user message starts a new exchange
+fn exchange_numbers(session: &ChatSession) -> Vec<usize> {
+    let mut index = 0;
+    session
+        .messages
+        .iter()
+        .map(|msg| {
+            if msg.message.role == "user" {
+                index += 1;
+            }
+            index
+        })
+        .collect()
+}
+
+fn message_details(msg: &NumberedMessage) -> String {
+    let mut details = msg.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+    match (&msg.provider, &msg.model) {
+        (Some(provider), Some(model)) => details.push_str(&format!(" ({}:{})", provider, model)),
+        (Some(provider), None) => details.push_str(&format!(" ({})", provider)),
+        (None, Some(model)) => details.push_str(&format!(" ({})", model)),
+        (None, None) => {}
+    }
+    details
+}
+
+fn code_block_summary(msg: &NumberedMessage) -> Option<String> {
+    if msg.code_blocks.is_empty() {
+        return None;
+    }
+
+    let languages: Vec<String> = msg
+        .code_blocks
+        .iter()
+        .map(|block| block.language.clone().unwrap_or_else(|| "text".to_string()))
+        .collect();
+    Some(format!("{} code block(s): {}", msg.code_blocks.len(), languages.join(", ")))
+}
+
+fn render_markdown(session: &ChatSession, include_thinking: bool) -> String {
+    let mut out = String::new();
+
+    out.push_str(&format!("# {}\n\n", session_title(session)));
+    out.push_str(&format!("- **Created:** {}\n", session.created_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("- **Updated:** {}\n", session.updated_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("- **Model:** {}\n", session.current_model));
+    out.push_str(&format!("- **Temperature:** {}\n", session.temperature));
+    out.push_str(&format!("- **Messages:** {}\n\n", session.messages.len()));
+
+    if let Some(system_prompt) = &session.system_prompt {
+        out.push_str("## System Prompt\n\n");
+        out.push_str(system_prompt.trim_end());
+        out.push_str("\n\n");
+    }
+
+    for (msg, number) in session.messages.iter().zip(exchange_numbers(session)) {
+        out.push_str(&format!("## [{}] {}\n\n", number, role_label(&msg.message.role)));
+        out.push_str(&format!("*{}*\n\n", message_details(msg)));
+
+        if include_thinking {
+            if let Some(thinking) = &msg.thinking {
+                out.push_str("<details>\n<summary>Thinking</summary>\n\n");
+                out.push_str(thinking.trim_end());
+                out.push_str("\n\n</details>\n\n");
+            }
+        }
+
+        out.push_str(msg.message.content.trim_end());
+        out.push_str("\n\n");
+    }
+
+    out
+}
+
+fn render_text(session: &ChatSession, include_thinking: bool) -> String {
+    let mut out = String::new();
+    let title = session_title(session);
+
+    out.push_str(&format!("{}\n{}\n", title, "=".repeat(title.chars().count())));
+    out.push_str(&format!("Created: {}\n", session.created_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("Updated: {}\n", session.updated_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("Model: {}\n", session.current_model));
+    out.push_str(&format!("Temperature: {}\n", session.temperature));
+
+    if let Some(system_prompt) = &session.system_prompt {
+        out.push_str(&format!("\nSystem prompt:\n{}\n", system_prompt.trim_end()));
+    }
+    out.push('\n');
+
+    for (msg, number) in session.messages.iter().zip(exchange_numbers(session)) {
+        out.push_str(&format!("[{}] {} {}\n", number, role_label(&msg.message.role), message_details(msg)));
+
+        if include_thinking {
+            if let Some(thinking) = &msg.thinking {
+                out.push_str(&format!("--- thinking ---\n{}\n--- end thinking ---\n", thinking.trim_end()));
+            }
+        }
+
+        out.push_str(msg.message.content.trim_end());
+        out.push('\n');
+        if let Some(summary) = code_block_summary(msg) {
+            out.push_str(&format!("({})\n", summary));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+const HTML_STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; max-width: 56rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; background: #ffffff; line-height: 1.5; }
+h1 { border-bottom: 1px solid #d0d7de; padding-bottom: 0.3rem; }
+.session { margin-bottom: 3rem; }
+.meta { color: #59636e; font-size: 0.9rem; }
+.meta td { padding-right: 1rem; }
+.system-prompt { background: #fff8c5; border-left: 4px solid #d4a72c; padding: 0.5rem 1rem; white-space: pre-wrap; }
+.message { border: 1px solid #d0d7de; border-radius: 6px; margin: 1rem 0; }
+.message header { padding: 0.4rem 1rem; font-weight: 600; border-bottom: 1px solid #d0d7de; }
+.message header .details { font-weight: normal; color: #59636e; font-size: 0.85rem; margin-left: 0.5rem; }
+.message.user header { background: #ddf4ff; }
+.message.assistant header { background: #fbefff; }
+.message .body { padding: 0 1rem; }
+.text { white-space: pre-wrap; margin: 0.75rem 0; }
+.thinking { margin: 0.75rem 0; color: #59636e; font-style: italic; }
+.thinking pre { white-space: pre-wrap; font-family: inherit; }
+.code { margin: 0.75rem 0; }
+.code .lang { font-size: 0.75rem; color: #59636e; }
+pre.code-block { background: #0d1117; color: #e6edf3; padding: 0.75rem 1rem; border-radius: 6px; overflow-x: auto; }
+.tok-kw { color: #ff7b72; }
+.tok-str { color: #a5d6ff; }
+.tok-com { color: #8b949e; font-style: italic; }
+.tok-num { color: #79c0ff; }
+"#;
+
+fn render_html_document(sessions: &[&ChatSession], include_thinking: bool) -> String {
+    let title = if sessions.len() == 1 {
+        session_title(sessions[0])
+    } else {
+        format!("{} sessions", sessions.len())
+    };
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+    out.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", HTML_STYLE));
+
+    for session in sessions {
+        out.push_str(&render_html_session(session, include_thinking));
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn render_html_session(session: &ChatSession, include_thinking: bool) -> String {
+    let mut out = String::new();
+
+    out.push_str("<article class=\"session\">\n");
+    out.push_str(&format!("<h1>{}</h1>\n", escape_html(&session_title(session))));
+    out.push_str("<table class=\"meta\">\n");
+    out.push_str(&format!("<tr><td>Created</td><td>{}</td></tr>\n", session.created_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("<tr><td>Updated</td><td>{}</td></tr>\n", session.updated_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("<tr><td>Model</td><td>{}</td></tr>\n", escape_html(&session.current_model)));
+    out.push_str(&format!("<tr><td>Temperature</td><td>{}</td></tr>\n", session.temperature));
+    out.push_str("</table>\n");
+
+    if let Some(system_prompt) = &session.system_prompt {
+        out.push_str("<h2>System Prompt</h2>\n");
+        out.push_str(&format!("<div class=\"system-prompt\">{}</div>\n", escape_html(system_prompt.trim_end())));
+    }
+
+    for (msg, number) in session.messages.iter().zip(exchange_numbers(session)) {
+        out.push_str(&format!("<section class=\"message {}\">\n", escape_html(&msg.message.role)));
+        out.push_str(&format!(
+            "<header>[{}] {}<span class=\"details\">{}</span></header>\n",
+            number,
+            role_label(&msg.message.role),
+            escape_html(&message_details(msg))
+        ));
+        out.push_str("<div class=\"body\">\n");
+
+        if include_thinking {
+            if let Some(thinking) = &msg.thinking {
+                out.push_str(&format!(
+                    "<details class=\"thinking\"><summary>Thinking</summary><pre>{}</pre></details>\n",
+                    escape_html(thinking.trim_end())
+                ));
+            }
+        }
+
+        out.push_str(&render_html_content(&msg.message.content));
+        out.push_str("</div>\n</section>\n");
+    }
+
+    out.push_str("</article>\n");
+    out
+}
+
+/// Split message content into prose and fenced code blocks, highlighting the code
+fn render_html_content(content: &str) -> String {
+    // Same fence pattern as ChatSession::extract_code_blocks
+    static CODE_BLOCK: OnceLock<Regex> = OnceLock::new();
+    let code_block_regex = CODE_BLOCK.get_or_init(|| Regex::new(r"(?s)```(\w+)?\n(.*?)\n```").unwrap());
+    let mut out = String::new();
+    let mut last_end = 0;
+
+    for captures in code_block_regex.captures_iter(content) {
+        let whole = captures.get(0).unwrap();
+        push_html_text(&mut out, &content[last_end..whole.start()]);
+
+        let language = captures.get(1).map(|m| m.as_str());
+        let code = captures.get(2).map(|m| m.as_str()).unwrap_or_default();
+        out.push_str("<div class=\"code\">");
+        if let Some(lang) = language {
+            out.push_str(&format!("<div class=\"lang\">{}</div>", escape_html(lang)));
+        }
+        out.push_str(&format!(
+            "<pre class=\"code-block\"><code class=\"language-{}\">{}</code></pre></div>\n",
+            escape_html(language.unwrap_or("text")),
+            highlight_code(code, language)
+        ));
+
+        last_end = whole.end();
+    }
+
+    push_html_text(&mut out, &content[last_end..]);
+    out
+}
+
+fn push_html_text(out: &mut String, text: &str) {
+    let trimmed = text.trim_matches('\n');
+    if !trimmed.trim().is_empty() {
+        out.push_str(&format!("<div class=\"text\">{}</div>\n", escape_html(trimmed)));
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct LanguageRules {
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    single_quote_strings: bool,
+}
+
+fn language_rules(language: Option<&str>) -> LanguageRules {
+    let lang = language.map(|l| l.to_lowercase()).unwrap_or_default();
+    match lang.as_str() {
+        "rust" | "rs" => LanguageRules {
+            keywords: &[
+                "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "false",
+                "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+                "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+                "where", "while", "Some", "None", "Ok", "Err",
+            ],
+            line_comments: &["//"],
+            // Avoid treating lifetimes like 'a as strings
+            single_quote_strings: false,
+        },
+        "python" | "py" => LanguageRules {
+            keywords: &[
+                "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+                "else", "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is",
+                "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while",
+                "with", "yield",
+            ],
+            line_comments: &["#"],
+            single_quote_strings: true,
+        },
+        "javascript" | "js" | "typescript" | "ts" | "jsx" | "tsx" => LanguageRules {
+            keywords: &[
+                "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete",
+                "else", "export", "extends", "false", "finally", "for", "from", "function", "if", "import", "in",
+                "instanceof", "interface", "let", "new", "null", "of", "return", "switch", "this", "throw",
+                "true", "try", "type", "typeof", "undefined", "var", "while", "yield",
+            ],
+            line_comments: &["//"],
+            single_quote_strings: true,
+        },
+        "go" | "golang" => LanguageRules {
+            keywords: &[
+                "break", "case", "chan", "const", "continue", "default", "defer", "else", "false", "for", "func",
+                "go", "if", "import", "interface", "map", "nil", "package", "range", "return", "select",
+                "struct", "switch", "true", "type", "var",
+            ],
+            line_comments: &["//"],
+            single_quote_strings: true,
+        },
+        "bash" | "sh" | "shell" | "zsh" => LanguageRules {
+            keywords: &[
+                "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for", "function",
+                "if", "in", "local", "return", "then", "until", "while",
+            ],
+            line_comments: &["#"],
+            single_quote_strings: true,
+        },
+        "c" | "cpp" | "c++" | "h" | "hpp" | "java" | "csharp" | "cs" | "kotlin" | "swift" => LanguageRules {
+            keywords: &[
+                "auto", "bool", "break", "case", "catch", "char", "class", "const", "continue", "default",
+                "do", "double", "else", "enum", "extends", "false", "final", "float", "for", "if", "import",
+                "include", "int", "long", "namespace", "new", "null", "nullptr", "private", "protected",
+                "public", "return", "static", "struct", "switch", "this", "throw", "true", "try", "typedef",
+                "using", "void", "while",
+            ],
+            line_comments: &["//"],
+            single_quote_strings: true,
+        },
+        "sql" => LanguageRules {
+            keywords: &[
+                "SELECT", "FROM", "WHERE", "INSERT", "INTO", "VALUES", "UPDATE", "SET", "DELETE", "CREATE",
+                "TABLE", "JOIN", "LEFT", "RIGHT", "INNER", "ON", "AND", "OR", "NOT", "NULL", "GROUP", "BY",
+                "ORDER", "LIMIT", "AS", "select", "from", "where", "insert", "into", "values", "update", "set",
+                "delete", "create", "table", "join", "on", "and", "or", "not", "null", "group", "by", "order",
+                "limit", "as",
+            ],
+            line_comments: &["--"],
+            single_quote_strings: true,
+        },
+        "yaml" | "yml" | "toml" | "ruby" | "rb" | "perl" | "r" => LanguageRules {
+            keywords: &["true", "false", "null", "nil"],
+            line_comments: &["#"],
+            single_quote_strings: true,
+        },
+        _ => LanguageRules {
+            keywords: &[],
+            line_comments: &[],
+            single_quote_strings: true,
+        },
+    }
+}
+
+fn wrap_token(out: &mut String, class: &str, token: &str) {
+    out.push_str(&format!("<span class=\"{}\">{}</span>", class, escape_html(token)));
+}
+
+/// Lightweight syntax highlighting: keywords, strings, numbers and line comments
+fn highlight_code(code: &str, language: Option<&str>) -> String {
+    let rules = language_rules(language);
+    let chars: Vec<char> = code.chars().collect();
+    let mut out = String::with_capacity(code.len() * 2);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // Line comments run to the end of the line
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if rules.line_comments.iter().any(|prefix| rest.starts_with(prefix)) {
+            let start = i;
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            wrap_token(&mut out, "tok-com", &token);
+            continue;
+        }
+
+        // String literals, stopping at the closing quote or end of line
+        if c == '"' || c == '`' || (c == '\'' && rules.single_quote_strings) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == c {
+                i += 1;
+            }
+            let end = i.min(chars.len());
+            let token: String = chars[start..end].iter().collect();
+            wrap_token(&mut out, "tok-str", &token);
+            continue;
+        }
+
+        // Numbers that aren't part of an identifier
+        if c.is_ascii_digit() && (i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_')) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || chars[i] == '_') {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            wrap_token(&mut out, "tok-num", &token);
+            continue;
+        }
+
+        // Identifiers and keywords
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            if rules.keywords.contains(&token.as_str()) {
+                wrap_token(&mut out, "tok-kw", &token);
+            } else {
+                out.push_str(&escape_html(&token));
+            }
+            continue;
+        }
+
+        out.push_str(&escape_html(&c.to_string()));
+        i += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Operation;
+    use crate::providers::{Message, ThinkingBlock};
+
+    fn create_session() -> ChatSession {
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 4096, 20000);
+        session.name = Some("Export Test".to_string());
+        session.system_prompt = Some("Be brief".to_string());
+        session.add_message(Message {
+            role: "user".to_string(),
+            content: "Show me <code>".to_string(),
+        });
+        session.add_message_with_metadata(
+            Message {
+                role: "assistant".to_string(),
+                content: "Here:\n```rust\nfn main() { let x = \"hi\"; }\n```".to_string(),
+            },
+            Some("openai".to_string()),
+            Some("gpt-4".to_string()),
+        );
+        session.messages[1].thinking = Some("Pondering".to_string());
+        session.messages[1].thinking_blocks = vec![ThinkingBlock { thinking: "Pondering".to_string(), signature: "c2lnbmVk".to_string() }];
+        session
+    }
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!(ExportFormat::parse("md").unwrap(), ExportFormat::Markdown);
+        assert_eq!(ExportFormat::parse("JSON").unwrap(), ExportFormat::Json);
+        assert_eq!(ExportFormat::parse("html").unwrap(), ExportFormat::Html);
+        assert_eq!(ExportFormat::parse("text").unwrap(), ExportFormat::Text);
+        assert!(ExportFormat::parse("pdf").is_err());
+        assert_eq!(ExportFormat::Markdown.extension(), "md");
+    }
+
+    #[test]
+    fn test_markdown_export() {
+        let session = create_session();
+
+        let output = export_sessions(&[&session], ExportFormat::Markdown, false).unwrap();
+        assert!(output.starts_with("# Export Test"));
+        assert!(output.contains("## System Prompt\n\nBe brief"));
+        assert!(output.contains("## [1] Agent"));
+        assert!(output.contains("(openai:gpt-4)"));
+        assert!(output.contains("```rust\nfn main()"));
+        assert!(!output.contains("Pondering"));
+
+        let with_thinking = export_sessions(&[&session], ExportFormat::Markdown, true).unwrap();
+        assert!(with_thinking.contains("<summary>Thinking</summary>\n\nPondering"));
+    }
+
+    #[test]
+    fn test_json_export() {
+        let mut session = create_session();
+        session.journal_operation(Operation::Edit { number: 1, previous: "draft with a password".to_string(), replacement: "Show me <code>".to_string() });
+
+        let single = export_sessions(&[&session], ExportFormat::Json, false).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&single).unwrap();
+        assert_eq!(parsed["name"], "Export Test");
+        assert!(parsed.get("journal").is_none());
+        assert!(!single.contains("password"));
+        assert!(parsed["messages"][1].get("thinking").is_none());
+        assert!(parsed["messages"][1].get("thinking_blocks").is_none());
+        assert_eq!(parsed["messages"][1]["code_blocks"][0]["language"], "rust");
+
+        let batch = export_sessions(&[&session, &session], ExportFormat::Json, true).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&batch).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        assert_eq!(parsed[0]["messages"][1]["thinking"], "Pondering");
+        assert_eq!(parsed[0]["messages"][1]["thinking_blocks"][0]["signature"], "c2lnbmVk");
+    }
+
+    #[test]
+    fn test_html_export() {
+        let session = create_session();
+
+        let output = export_sessions(&[&session], ExportFormat::Html, false).unwrap();
+        assert!(output.starts_with("<!DOCTYPE html>"));
+        assert!(output.contains("<style>"));
+        assert!(output.contains("Show me &lt;code&gt;"));
+        assert!(output.contains("<code class=\"language-rust\">"));
+        assert!(output.contains("<span class=\"tok-kw\">fn</span>"));
+        assert!(output.contains("<span class=\"tok-str\">&quot;hi&quot;</span>"));
+    }
+
+    #[test]
+    fn test_text_export() {
+        let session = create_session();
+
+        let output = export_sessions(&[&session], ExportFormat::Text, true).unwrap();
+        assert!(output.starts_with("Export Test\n==========="));
+        assert!(output.contains("System prompt:\nBe brief"));
+        assert!(output.contains("--- thinking ---\nPondering"));
+        assert!(output.contains("(1 code block(s): rust)"));
+    }
+
+    #[test]
+    fn test_highlight_comments_and_numbers() {
+        let highlighted = highlight_code("x = 42 # answer", Some("python"));
+        assert!(highlighted.contains("<span class=\"tok-num\">42</span>"));
+        assert!(highlighted.contains("<span class=\"tok-com\"># answer</span>"));
+
+        // Unknown languages still get escaped
+        assert_eq!(highlight_code("a < b", None), "a &lt; b");
+    }
+
+    #[test]
+    fn test_empty_export_is_an_error() {
+        assert!(export_sessions(&[], ExportFormat::Markdown, false).is_err());
+    }
+}
@@ -1,10 +1,14 @@
 use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::session::ChatSession;
+use crate::encryption::write_state_file;
+use crate::journal::{self, JournalEntry, Operation};
+use crate::retention::RetentionPolicy;
+use crate::schema::{self, StateFile};
+use crate::session::{ChatSession, NumberedMessage};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct History {
@@ -12,10 +16,25 @@ pub struct History {
     pub saved_sessions: HashMap<String, ChatSession>,
     #[serde(default)]
     pub default_preferences: DefaultPreferences,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
     #[serde(skip)]
     pub history_file_path: String,
     #[serde(skip)]
     pub removed_sessions: std::collections::HashSet<String>,
+    #[serde(default)]
+    pub trash: HashMap<String, TrashedSession>,
+    #[serde(skip)]
+    pub removed_trash: std::collections::HashSet<String>,
+    #[serde(default)]
+    pub journal: Vec<JournalEntry>, // Session deletes and renames, for /journal
+}
+
+/// A deleted session kept around so it can be restored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedSession {
+    pub session: ChatSession,
+    pub deleted_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -34,8 +53,12 @@ impl History {
             current_session: None,
             saved_sessions: HashMap::new(),
             default_preferences: DefaultPreferences::default(),
+            retention: RetentionPolicy::default(),
             history_file_path,
             removed_sessions: std::collections::HashSet::new(),
+            trash: HashMap::new(),
+            removed_trash: std::collections::HashSet::new(),
+            journal: Vec::new(),
         }
     }
     
@@ -46,10 +69,10 @@ impl History {
             return Ok(Self::new(history_file_path));
         }
         
-        let content = fs::read_to_string(&path)?;
-        let mut history: History = serde_json::from_str(&content)?;
+        let mut history: History = schema::load(&path, StateFile::Sessions)?;
         history.history_file_path = history_file_path;
         history.removed_sessions = std::collections::HashSet::new();
+        history.removed_trash = std::collections::HashSet::new();
         Ok(history)
     }
     
@@ -72,15 +95,165 @@ impl History {
         sessions.into_iter().map(|(name, _)| name).collect()
     }
     
+    /// Move a session to the trash; it can be brought back with `restore_session`
     pub fn delete_session(&mut self, name: &str) -> Result<bool> {
+        Ok(self.trash_session(name)?.is_some())
+    }
+
+    /// Move a saved session to the trash, returning the name it is kept under there
+    pub fn trash_session(&mut self, name: &str) -> Result<Option<String>> {
+        let Some(session) = self.saved_sessions.remove(name) else {
+            return Ok(None);
+        };
+
+        // Deleting the same name twice keeps both copies in the trash
+        let mut trash_name = name.to_string();
+        let mut counter = 2;
+        while self.trash.contains_key(&trash_name) {
+            trash_name = format!("{} ({})", name, counter);
+            counter += 1;
+        }
+
+        self.trash.insert(trash_name.clone(), TrashedSession {
+            session,
+            deleted_at: Utc::now(),
+        });
+        self.removed_trash.remove(&trash_name);
+
+        // Track that we've removed this session to prevent it from being merged back
+        self.removed_sessions.insert(name.to_string());
+        self.save_with_merge()?;
+        Ok(Some(trash_name))
+    }
+
+    /// Write everything, e.g. after recovering a damaged sessions file
+    pub fn save(&self) -> Result<()> {
+        self.save_with_merge()
+    }
+
+    pub fn journal_operation(&mut self, operation: Operation) -> Result<()> {
+        journal::record(&mut self.journal, operation);
+        self.save_with_merge()
+    }
+
+    /// Reverse a journaled delete or rename. Returns what was reverted.
+    pub fn revert_operation(&mut self, index: usize) -> Result<String> {
+        let entry = self.journal.get(index)
+            .ok_or_else(|| anyhow::anyhow!("No such journal entry"))?;
+        if entry.reverted {
+            return Err(anyhow::anyhow!("Already reverted: {}", entry.operation.describe()));
+        }
+        
+        let description = entry.operation.describe();
+        match entry.operation.clone() {
+            Operation::Delete { session, trash_name } => {
+                if !self.trash.contains_key(&trash_name) {
+                    return Err(anyhow::anyhow!("'{}' is no longer in the trash", session));
+                }
+                self.restore_session(&trash_name, Some(&session))?;
+            }
+            Operation::Rename { from, to } => {
+                if !self.rename_session(&to, &from)? {
+                    return Err(anyhow::anyhow!("Session '{}' no longer exists", to));
+                }
+            }
+            _ => return Err(anyhow::anyhow!("Message operations are reverted through their session")),
+        }
+        
+        self.journal[index].reverted = true;
+        self.save_with_merge()?;
+        Ok(description)
+    }
+
+    /// Drop a saved session without keeping it in the trash (used when a session changes name)
+    pub fn forget_session(&mut self, name: &str) -> Result<bool> {
         let existed = self.saved_sessions.remove(name).is_some();
         if existed {
-            // Track that we've removed this session to prevent it from being merged back
             self.removed_sessions.insert(name.to_string());
             self.save_with_merge()?;
         }
         Ok(existed)
     }
+
+    /// Trashed session names, most recently deleted first
+    pub fn list_trash(&self) -> Vec<(&String, &TrashedSession)> {
+        let mut trashed: Vec<_> = self.trash.iter().collect();
+        trashed.sort_by(|a, b| b.1.deleted_at.cmp(&a.1.deleted_at));
+        trashed
+    }
+
+    /// Move a session out of the trash, optionally under a new name.
+    /// Returns the name it was restored as.
+    pub fn restore_session(&mut self, trash_name: &str, new_name: Option<&str>) -> Result<String> {
+        let trashed = self.trash.get(trash_name)
+            .ok_or_else(|| anyhow::anyhow!("No session named '{}' in the trash", trash_name))?;
+
+        let name = match new_name {
+            Some(name) => name.to_string(),
+            None => trashed.session.name.clone().unwrap_or_else(|| trash_name.to_string()),
+        };
+        if self.saved_sessions.contains_key(&name) {
+            return Err(anyhow::anyhow!(
+                "Session '{}' already exists. Restore it under another name with /chat restore {} --as NEW_NAME",
+                name, trash_name
+            ));
+        }
+
+        if let Some(mut trashed) = self.trash.remove(trash_name) {
+            trashed.session.name = Some(name.clone());
+            self.saved_sessions.insert(name.clone(), trashed.session);
+            self.removed_sessions.remove(&name);
+            self.removed_trash.insert(trash_name.to_string());
+            self.save_with_merge()?;
+        }
+        Ok(name)
+    }
+
+    /// Permanently delete one trashed session, or the whole trash when `name` is None.
+    /// Returns the number of sessions purged.
+    pub fn purge_trash(&mut self, name: Option<&str>) -> Result<usize> {
+        let purged: Vec<String> = match name {
+            Some(name) => {
+                if !self.trash.contains_key(name) {
+                    return Err(anyhow::anyhow!("No session named '{}' in the trash", name));
+                }
+                vec![name.to_string()]
+            }
+            None => self.trash.keys().cloned().collect(),
+        };
+
+        for name in &purged {
+            self.trash.remove(name);
+            self.removed_trash.insert(name.clone());
+        }
+        if !purged.is_empty() {
+            self.save_with_merge()?;
+        }
+        Ok(purged.len())
+    }
+
+    /// Purge trashed sessions older than the retention period (0 days keeps them forever)
+    pub fn purge_expired_trash(&mut self, retention_days: u32) -> Result<Vec<String>> {
+        if retention_days == 0 {
+            return Ok(Vec::new());
+        }
+        let cutoff = Utc::now() - Duration::days(retention_days as i64);
+        let mut expired: Vec<String> = self.trash
+            .iter()
+            .filter(|(_, trashed)| trashed.deleted_at < cutoff)
+            .map(|(name, _)| name.clone())
+            .collect();
+        expired.sort();
+
+        for name in &expired {
+            self.trash.remove(name);
+            self.removed_trash.insert(name.clone());
+        }
+        if !expired.is_empty() {
+            self.save_with_merge()?;
+        }
+        Ok(expired)
+    }
     
     pub fn rename_session(&mut self, old_name: &str, new_name: &str) -> Result<bool> {
         // Check if old session exists
@@ -134,32 +307,168 @@ impl History {
     }
 
     
+    pub fn import_sessions(&mut self, sessions: Vec<ChatSession>) -> Result<SessionImportResult> {
+        let mut already_imported: std::collections::HashSet<String> = self.saved_sessions
+            .values()
+            .filter_map(|session| session.imported_from.clone())
+            .collect();
+
+        let mut imported_count = 0;
+        let mut skipped_count = 0;
+
+        for mut session in sessions {
+            if let Some(source) = &session.imported_from {
+                if !already_imported.insert(source.clone()) {
+                    skipped_count += 1;
+                    continue;
+                }
+            }
+
+            // Titled chats keep their title; untitled ones get a timestamp name like auto-save
+            let base_name = match &session.name {
+                Some(name) => name.clone(),
+                None => {
+                    session.name_source = Some(crate::session::NameSource::Timestamp);
+                    session.generate_auto_name()
+                }
+            };
+
+            let mut name = base_name.clone();
+            let mut counter = 2;
+            while self.saved_sessions.contains_key(&name) {
+                name = format!("{} ({})", base_name, counter);
+                counter += 1;
+            }
+
+            session.name = Some(name.clone());
+            self.saved_sessions.insert(name, session);
+            imported_count += 1;
+        }
+
+        if imported_count > 0 {
+            self.save_with_merge()?;
+        }
+
+        Ok(SessionImportResult {
+            imported_count,
+            skipped_count,
+        })
+    }
+
     pub fn get_recent_sessions(&self, limit: usize) -> Vec<(&String, &ChatSession)> {
         let mut sessions: Vec<_> = self.saved_sessions.iter().collect();
         sessions.sort_by(|a, b| b.1.updated_at.cmp(&a.1.updated_at));
         sessions.into_iter().take(limit).collect()
     }
     
+    /// Apply a change to a saved session and persist it
+    pub fn update_session<F>(&mut self, name: &str, update: F) -> Result<bool>
+    where
+        F: FnOnce(&mut ChatSession),
+    {
+        match self.saved_sessions.get_mut(name) {
+            Some(session) => {
+                update(session);
+                self.save_with_merge()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// All tags in use across saved sessions, sorted
+    pub fn all_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.saved_sessions
+            .values()
+            .flat_map(|session| session.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// List saved session names matching the filter, in the requested order
+    pub fn filter_sessions(&self, filter: &SessionFilter) -> Vec<&String> {
+        let name_regex = filter.name_pattern.as_ref().and_then(|pattern| glob_to_regex(pattern));
+
+        let mut sessions: Vec<(&String, &ChatSession)> = self.saved_sessions
+            .iter()
+            .filter(|(name, session)| {
+                if !filter.tags.iter().all(|tag| session.has_tag(tag)) {
+                    return false;
+                }
+                if let Some(model) = &filter.model {
+                    let model = model.to_lowercase();
+                    let uses_model = session.current_model.to_lowercase().contains(&model)
+                        || session.messages.iter().any(|msg| {
+                            msg.model.as_ref().map(|m| m.to_lowercase().contains(&model)).unwrap_or(false)
+                        });
+                    if !uses_model {
+                        return false;
+                    }
+                }
+                if let Some(provider) = &filter.provider {
+                    let uses_provider = session.current_provider.as_ref().map(|p| p.eq_ignore_ascii_case(provider)).unwrap_or(false)
+                        || session.messages.iter().any(|msg| {
+                            msg.provider.as_ref().map(|p| p.eq_ignore_ascii_case(provider)).unwrap_or(false)
+                        });
+                    if !uses_provider {
+                        return false;
+                    }
+                }
+                if let Some(since) = filter.since {
+                    if session.updated_at < since {
+                        return false;
+                    }
+                }
+                if let Some(until) = filter.until {
+                    if session.updated_at > until {
+                        return false;
+                    }
+                }
+                if let Some(regex) = &name_regex {
+                    if !regex.is_match(name) {
+                        return false;
+                    }
+                }
+                true
+            })
+            .collect();
+
+        match filter.sort {
+            SessionSort::Updated => sessions.sort_by(|a, b| b.1.updated_at.cmp(&a.1.updated_at)),
+            SessionSort::Created => sessions.sort_by(|a, b| b.1.created_at.cmp(&a.1.created_at)),
+            SessionSort::Name => sessions.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase())),
+            SessionSort::Messages => sessions.sort_by(|a, b| b.1.messages.len().cmp(&a.1.messages.len())),
+        }
+        if filter.reverse {
+            sessions.reverse();
+        }
+
+        sessions.into_iter().map(|(name, _)| name).collect()
+    }
+
     pub fn get_most_recent_session(&self) -> Option<&ChatSession> {
         self.saved_sessions
             .values()
             .max_by_key(|session| session.updated_at)
     }
     
-    pub fn search_all_sessions(&self, term: &str, current_session: &ChatSession) -> Vec<SearchResult> {
+    pub fn search_all_sessions(&self, query: &SearchQuery, current_session: &ChatSession) -> Vec<SearchResult> {
         let mut results = Vec::new();
-        let term_lower = term.to_lowercase();
         
         // Search current session first
-        if !current_session.messages.is_empty() {
-            let session_results = self.search_session_messages("current", &current_session.messages, &term_lower);
+        if !current_session.messages.is_empty() && query.matches_session("current") {
+            let session_results = self.search_session_messages("current", current_session, query);
             results.extend(session_results);
         }
         
         // Search saved sessions
         for (session_name, session) in &self.saved_sessions {
-            let session_results = self.search_session_messages(session_name, &session.messages, &term_lower);
-            results.extend(session_results);
+            if query.matches_session(session_name) {
+                let session_results = self.search_session_messages(session_name, session, query);
+                results.extend(session_results);
+            }
         }
         
         // Sort by session name (current first) then by message number
@@ -175,18 +484,23 @@ impl History {
         results
     }
     
-    fn search_session_messages(&self, session_name: &str, messages: &[crate::session::NumberedMessage], term_lower: &str) -> Vec<SearchResult> {
+    fn search_session_messages(&self, session_name: &str, session: &ChatSession, query: &SearchQuery) -> Vec<SearchResult> {
         let mut results = Vec::new();
+        let mut exchange = 0;
         
-        for numbered_message in messages {
-            let content_lower = numbered_message.message.content.to_lowercase();
-            if content_lower.contains(term_lower) {
-                let excerpt = self.create_excerpt(&numbered_message.message.content, term_lower);
+        for numbered_message in &session.messages {
+            if numbered_message.message.role == "user" {
+                exchange += 1;
+            }
+            if let Some((content, matched)) = query.find_match(numbered_message, &session.current_model) {
+                let excerpt = self.create_excerpt(content, matched);
                 results.push(SearchResult {
                     session_name: session_name.to_string(),
                     message_number: numbered_message.number,
+                    exchange,
                     role: numbered_message.message.role.clone(),
                     excerpt,
+                    bookmark: numbered_message.bookmark.clone(),
                 });
             }
         }
@@ -275,17 +589,18 @@ impl History {
         self.default_preferences = DefaultPreferences::default();
         self.save_with_merge()
     }
+    
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) -> Result<()> {
+        self.retention = policy;
+        self.save_with_merge()
+    }
 
-    fn create_excerpt(&self, content: &str, term_lower: &str) -> String {
+    fn create_excerpt(&self, content: &str, matched: Option<(usize, usize)>) -> String {
         const CONTEXT_SIZE: usize = 80;  // Context on each side of match
         const MAX_EXCERPT_LENGTH: usize = 300;  // Maximum total excerpt length
         
-        let content_lower = content.to_lowercase();
-        
-        // Find the first occurrence of the term
-        if let Some(match_start) = content_lower.find(term_lower) {
-            let match_end = match_start + term_lower.len();
-            
+        // Byte range of the first match, if the query had a pattern
+        if let Some((match_start, match_end)) = matched {
             // Calculate excerpt bounds: (match_start - CONTEXT_SIZE) to (match_end + CONTEXT_SIZE)
             let excerpt_start = match_start.saturating_sub(CONTEXT_SIZE);
             let excerpt_end = std::cmp::min(content.len(), match_end + CONTEXT_SIZE);
@@ -293,7 +608,7 @@ impl History {
             // Ensure we don't exceed maximum length
             let (final_start, final_end) = if excerpt_end - excerpt_start > MAX_EXCERPT_LENGTH {
                 // Truncate while keeping the match centered
-                let available_space = MAX_EXCERPT_LENGTH.saturating_sub(term_lower.len());
+                let available_space = MAX_EXCERPT_LENGTH.saturating_sub(match_end - match_start);
                 let context_per_side = available_space / 2;
                 
                 let new_start = match_start.saturating_sub(context_per_side);
@@ -303,6 +618,10 @@ impl History {
                 (excerpt_start, excerpt_end)
             };
             
+            // Byte offsets must not split a multi-byte character
+            let final_start = floor_char_boundary(content, final_start);
+            let final_end = ceil_char_boundary(content, final_end);
+            
             // Find good break points (word boundaries) to avoid cutting words
             // But don't move too close to the match - preserve reasonable context
             let actual_start = if final_start > 0 {
@@ -360,7 +679,7 @@ impl History {
         } else {
             // Fallback: just show the beginning of the content
             if content.len() > CONTEXT_SIZE * 2 {
-                format!("{}...", &content[..CONTEXT_SIZE * 2])
+                format!("{}...", &content[..floor_char_boundary(content, CONTEXT_SIZE * 2)])
             } else {
                 content.to_string()
             }
@@ -370,7 +689,11 @@ impl History {
     fn save_with_merge(&self) -> Result<()> {
         // Reload from disk to merge any changes from other instances
         let mut merged = self.clone();
-        if let Ok(disk_version) = Self::load(self.history_file_path.clone()) {
+        let disk_version = match Self::load(self.history_file_path.clone()) {
+            Err(e) if schema::is_newer_schema(&e) => return Err(e),
+            disk_version => disk_version,
+        };
+        if let Ok(disk_version) = disk_version {
             // Merge saved_sessions from disk version, keeping our changes
             for (name, session) in disk_version.saved_sessions {
                 // Only add sessions from disk that we don't have locally
@@ -379,18 +702,447 @@ impl History {
                     merged.saved_sessions.insert(name, session);
                 }
             }
+            // Same for the trash, so a restore or purge here isn't undone by another instance
+            for (name, trashed) in disk_version.trash {
+                if !merged.trash.contains_key(&name) && !merged.removed_trash.contains(&name) {
+                    merged.trash.insert(name, trashed);
+                }
+            }
         }
         
-        let content = serde_json::to_string_pretty(&merged)?;
-        fs::write(&self.history_file_path, content)?;
+        let content = schema::to_string(&merged, StateFile::Sessions)?;
+        write_state_file(Path::new(&self.history_file_path), &content)?;
         Ok(())
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SessionSort {
+    #[default]
+    Updated,  // Most recently updated first
+    Created,  // Most recently created first
+    Name,     // Alphabetical
+    Messages, // Longest first
+}
+
+impl SessionSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "updated" | "recent" => Some(SessionSort::Updated),
+            "created" => Some(SessionSort::Created),
+            "name" => Some(SessionSort::Name),
+            "messages" | "size" => Some(SessionSort::Messages),
+            _ => None,
+        }
+    }
+}
+
+/// Criteria for `/chat list`; empty fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub tags: Vec<String>,
+    pub model: Option<String>,        // Substring of the session or any message model
+    pub provider: Option<String>,
+    pub since: Option<DateTime<Utc>>, // Updated at or after
+    pub until: Option<DateTime<Utc>>, // Updated at or before
+    pub name_pattern: Option<String>, // Glob (* and ?) or plain substring
+    pub sort: SessionSort,
+    pub reverse: bool,
+}
+
+/// Parse a date bound such as `2025-01-31`, an RFC 3339 timestamp,
+/// `today`, `yesterday`, or a relative age like `7d`, `12h` or `2w`.
+/// Plain dates cover the whole day, so `end_of_day` selects 23:59:59.
+pub fn parse_date_bound(value: &str, end_of_day: bool) -> Option<DateTime<Utc>> {
+    let value = value.trim().to_lowercase();
+    let now = Utc::now();
+
+    let day = match value.as_str() {
+        "today" => Some(now.date_naive()),
+        "yesterday" => Some(now.date_naive() - Duration::days(1)),
+        _ => NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok(),
+    };
+    if let Some(day) = day {
+        let time = if end_of_day {
+            day.and_hms_opt(23, 59, 59)?
+        } else {
+            day.and_hms_opt(0, 0, 0)?
+        };
+        return Some(time.and_utc());
+    }
+
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(&value.to_uppercase()) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+
+    // Relative ages count back from now
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    let amount: i64 = amount.parse().ok()?;
+    let age = match unit {
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => return None,
+    };
+    Some(now - age)
+}
+
+fn glob_to_regex(pattern: &str) -> Option<regex::Regex> {
+    let body = if pattern.contains('*') || pattern.contains('?') {
+        let escaped = regex::escape(pattern).replace("\\*", ".*").replace("\\?", ".");
+        format!("^{}$", escaped)
+    } else {
+        regex::escape(pattern)
+    };
+    regex::Regex::new(&format!("(?i){}", body)).ok()
+}
+
+/// A parsed `/search` query. Free words form a case-insensitive phrase,
+/// `/.../` is a regex, and `role:`, `model:`, `session:`, `before:`, `after:`
+/// `mark:` and `in:code` narrow the messages searched. All parts must match.
+/// Notes added with /note are searched along with the message text.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub patterns: Vec<regex::Regex>,
+    pub role: Option<String>,
+    pub model: Option<String>,         // Lowercased substring of the message model
+    pub session: Option<regex::Regex>, // Glob (* and ?) or plain substring
+    pub mark: Option<regex::Regex>,    // Bookmark name, glob or substring; `mark:*` for any bookmark
+    pub before: Option<DateTime<Utc>>,
+    pub after: Option<DateTime<Utc>>,
+    pub code_only: bool,               // Only search inside code blocks
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut query = SearchQuery::default();
+        let mut words = Vec::new();
+
+        for (token, is_regex) in tokenize_search_query(input) {
+            if is_regex {
+                let regex = regex::Regex::new(&format!("(?i){}", token))
+                    .map_err(|e| anyhow::anyhow!("Invalid regex /{}/: {}", token, e))?;
+                query.patterns.push(regex);
+                continue;
+            }
+
+            let Some((key, value)) = token.split_once(':').filter(|(_, value)| !value.is_empty()) else {
+                words.push(token);
+                continue;
+            };
+            match key.to_lowercase().as_str() {
+                "role" => {
+                    query.role = Some(match value.to_lowercase().as_str() {
+                        "user" => "user".to_string(),
+                        "assistant" | "agent" => "assistant".to_string(),
+                        _ => return Err(anyhow::anyhow!("Unknown role '{}' (use user or assistant)", value)),
+                    })
+                }
+                "model" => query.model = Some(value.to_lowercase()),
+                "session" => {
+                    query.session = Some(glob_to_regex(value)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid session pattern '{}'", value))?)
+                }
+                "mark" => {
+                    query.mark = Some(glob_to_regex(value)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid bookmark pattern '{}'", value))?)
+                }
+                "before" => {
+                    query.before = Some(parse_date_bound(value, false)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid date '{}' for before:", value))?)
+                }
+                "after" => {
+                    query.after = Some(parse_date_bound(value, true)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid date '{}' for after:", value))?)
+                }
+                "in" if value.eq_ignore_ascii_case("code") => query.code_only = true,
+                "in" => return Err(anyhow::anyhow!("Unknown scope 'in:{}' (only in:code is supported)", value)),
+                // Not a filter, e.g. a URL or "note:" in the text itself
+                _ => words.push(token),
+            }
+        }
+
+        if !words.is_empty() {
+            let phrase = regex::escape(&words.join(" "));
+            query.patterns.insert(0, regex::Regex::new(&format!("(?i){}", phrase))?);
+        }
+
+        let has_filter = query.role.is_some()
+            || query.model.is_some()
+            || query.session.is_some()
+            || query.mark.is_some()
+            || query.before.is_some()
+            || query.after.is_some()
+            || query.code_only;
+        if query.patterns.is_empty() && !has_filter {
+            return Err(anyhow::anyhow!("Search query cannot be empty"));
+        }
+        Ok(query)
+    }
+
+    fn matches_session(&self, session_name: &str) -> bool {
+        self.session.as_ref().is_none_or(|pattern| pattern.is_match(session_name))
+    }
+
+    /// The searched text of a matching message and the byte range of the first
+    /// pattern's match in it. With `in:code` this is the first matching code block.
+    fn find_match<'a>(&self, msg: &'a NumberedMessage, session_model: &str) -> Option<(&'a str, Option<(usize, usize)>)> {
+        if self.role.as_ref().is_some_and(|role| *role != msg.message.role) {
+            return None;
+        }
+        if let Some(model) = &self.model {
+            let message_model = msg.model.as_deref().unwrap_or(session_model);
+            if !message_model.to_lowercase().contains(model) {
+                return None;
+            }
+        }
+        if let Some(mark) = &self.mark {
+            if !msg.bookmark.as_ref().is_some_and(|bookmark| mark.is_match(bookmark)) {
+                return None;
+            }
+        }
+        if self.before.is_some_and(|before| msg.timestamp >= before)
+            || self.after.is_some_and(|after| msg.timestamp <= after)
+        {
+            return None;
+        }
+
+        let texts: Vec<&str> = if self.code_only {
+            msg.code_blocks.iter().map(|block| block.content.as_str()).collect()
+        } else {
+            std::iter::once(msg.message.content.as_str()).chain(msg.note.as_deref()).collect()
+        };
+        texts.into_iter().find_map(|text| {
+            if !self.patterns.iter().all(|pattern| pattern.is_match(text)) {
+                return None;
+            }
+            let matched = self.patterns.first()
+                .and_then(|pattern| pattern.find(text))
+                .map(|m| (m.start(), m.end()));
+            Some((text, matched))
+        })
+    }
+}
+
+/// Split a search query on whitespace, keeping "quoted phrases" and
+/// `/regex with spaces/` together. Returns each token and whether it is a regex.
+fn tokenize_search_query(input: &str) -> Vec<(String, bool)> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while !rest.is_empty() {
+        // A regex runs to the next slash that ends a word, so paths like /usr/bin stay plain text
+        if let Some(body) = rest.strip_prefix('/') {
+            let closing = body.char_indices().find(|(i, c)| {
+                *c == '/' && *i > 0 && body[i + 1..].chars().next().is_none_or(char::is_whitespace)
+            });
+            if let Some((i, _)) = closing {
+                tokens.push((body[..i].to_string(), true));
+                rest = body[i + 1..].trim_start();
+                continue;
+            }
+        }
+
+        let mut token = String::new();
+        let mut in_quotes = false;
+        let mut end = rest.len();
+        for (i, c) in rest.char_indices() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                c if c.is_whitespace() && !in_quotes => {
+                    end = i;
+                    break;
+                }
+                c => token.push(c),
+            }
+        }
+        if !token.is_empty() {
+            tokens.push((token, false));
+        }
+        rest = rest[end..].trim_start();
+    }
+
+    tokens
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub session_name: String,
     pub message_number: usize,
+    pub exchange: usize, // User message count up to here, as used by /goto and /history
     pub role: String,
     pub excerpt: String,
+    pub bookmark: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionImportResult {
+    pub imported_count: usize,
+    pub skipped_count: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Message;
+
+    fn create_session(model: &str, tags: &[&str], days_ago: i64) -> ChatSession {
+        let mut session = ChatSession::new(model.to_string(), 0.7, 4096, 20000);
+        session.add_message(Message {
+            role: "user".to_string(),
+            content: "hello".to_string(),
+        });
+        session.add_tags(&tags.iter().map(|t| t.to_string()).collect::<Vec<_>>());
+        session.updated_at = Utc::now() - Duration::days(days_ago);
+        session
+    }
+
+    fn create_history() -> History {
+        let mut history = History::new(String::new());
+        history.saved_sessions.insert("Rust review".to_string(), create_session("claude-sonnet-4-20250514", &["rust", "work"], 1));
+        history.saved_sessions.insert("Python notes".to_string(), create_session("gpt-4o", &["python"], 10));
+        history.saved_sessions.insert("rust lifetimes".to_string(), create_session("gpt-4o", &["rust"], 30));
+        history
+    }
+
+    #[test]
+    fn test_filter_sessions() {
+        let history = create_history();
+
+        let all = history.filter_sessions(&SessionFilter::default());
+        assert_eq!(all, vec!["Rust review", "Python notes", "rust lifetimes"]);
+
+        let tagged = history.filter_sessions(&SessionFilter {
+            tags: vec!["rust".to_string()],
+            model: Some("GPT".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(tagged, vec!["rust lifetimes"]);
+
+        let recent = history.filter_sessions(&SessionFilter {
+            since: parse_date_bound("14d", false),
+            ..Default::default()
+        });
+        assert_eq!(recent.len(), 2);
+
+        let by_name = history.filter_sessions(&SessionFilter {
+            name_pattern: Some("rust*".to_string()),
+            sort: SessionSort::Name,
+            reverse: true,
+            ..Default::default()
+        });
+        assert_eq!(by_name, vec!["Rust review", "rust lifetimes"]);
+
+        assert_eq!(history.all_tags(), vec!["python", "rust", "work"]);
+    }
+
+    #[test]
+    fn test_trash_restore_and_purge() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = create_history();
+        history.history_file_path = dir.path().join("sessions").to_string_lossy().to_string();
+
+        assert!(history.delete_session("Python notes").unwrap());
+        assert!(!history.saved_sessions.contains_key("Python notes"));
+        assert_eq!(history.list_trash().len(), 1);
+
+        // A new session under the same name doesn't block the trashed copy
+        history.saved_sessions.insert("Python notes".to_string(), create_session("gpt-4o", &[], 0));
+        let error = history.restore_session("Python notes", None).unwrap_err();
+        assert!(error.to_string().contains("/chat restore Python notes --as NEW_NAME"));
+        assert_eq!(history.restore_session("Python notes", Some("Python notes (old)")).unwrap(), "Python notes (old)");
+        assert!(history.trash.is_empty());
+
+        // Deleting the same name twice keeps both copies
+        history.delete_session("Python notes").unwrap();
+        history.saved_sessions.insert("Python notes".to_string(), create_session("gpt-4o", &[], 0));
+        history.delete_session("Python notes").unwrap();
+        assert!(history.trash.contains_key("Python notes (2)"));
+
+        history.trash.get_mut("Python notes").unwrap().deleted_at = Utc::now() - Duration::days(40);
+        assert_eq!(history.purge_expired_trash(0).unwrap().len(), 0);
+        assert_eq!(history.purge_expired_trash(30).unwrap(), vec!["Python notes"]);
+
+        // Purged entries don't come back from the file on disk
+        let reloaded = History::load(history.history_file_path.clone()).unwrap();
+        assert_eq!(reloaded.trash.len(), 1);
+        assert_eq!(history.purge_trash(None).unwrap(), 1);
+        assert!(history.purge_trash(Some("missing")).is_err());
+        assert!(History::load(history.history_file_path.clone()).unwrap().trash.is_empty());
+    }
+
+    #[test]
+    fn test_parse_date_bound() {
+        assert_eq!(
+            parse_date_bound("2025-03-04", true).unwrap().to_rfc3339(),
+            "2025-03-04T23:59:59+00:00"
+        );
+        assert!(parse_date_bound("2025-03-04T10:00:00Z", false).is_some());
+        assert!(parse_date_bound("2w", false).unwrap() < Utc::now() - Duration::days(13));
+        assert!(parse_date_bound("soon", false).is_none());
+    }
+
+    #[test]
+    fn test_structured_search() {
+        let mut history = create_history();
+        let session = history.saved_sessions.get_mut("Rust review").unwrap();
+        session.add_message_with_metadata(
+            Message {
+                role: "assistant".to_string(),
+                content: "Try this:\n```rust\nfn main() {}\n```\nThen run cargo.".to_string(),
+            },
+            Some("anthropic".to_string()),
+            Some("claude-sonnet-4-20250514".to_string()),
+        );
+        let current = ChatSession::new("gpt-4o".to_string(), 0.7, 4096, 20000);
+        let search = |query: &str| history.search_all_sessions(&SearchQuery::parse(query).unwrap(), &current);
+
+        // Plain words stay a case-insensitive phrase
+        assert_eq!(search("HELLO").len(), 3);
+        assert_eq!(search("role:assistant").len(), 1);
+        assert_eq!(search("hello role:user session:rust*").len(), 2);
+        assert_eq!(search("hello model:gpt").len(), 2);
+
+        let code = search("/fn \\w+/ in:code");
+        assert_eq!(code.len(), 1);
+        assert_eq!((code[0].session_name.as_str(), code[0].message_number, code[0].exchange), ("Rust review", 2, 1));
+        assert!(search("cargo in:code").is_empty());
+        assert_eq!(search("cargo").len(), 1);
+
+        assert!(search("hello before:2000-01-01").is_empty());
+        assert_eq!(search("hello after:2000-01-01").len(), 3);
+
+        // Bookmarks and notes
+        let session = history.saved_sessions.get_mut("Python notes").unwrap();
+        session.set_bookmark(1, "greeting");
+        session.set_note(1, Some("Asked about decorators".to_string()));
+        let search = |query: &str| history.search_all_sessions(&SearchQuery::parse(query).unwrap(), &current);
+        let marked = search("mark:*");
+        assert_eq!(marked.len(), 1);
+        assert_eq!(marked[0].bookmark.as_deref(), Some("greeting"));
+        assert_eq!(search("hello mark:greet*").len(), 1);
+        assert!(search("mark:other").is_empty());
+        assert_eq!(search("decorators")[0].session_name, "Python notes");
+
+        assert!(SearchQuery::parse("role:robot").is_err());
+        assert!(SearchQuery::parse("/[unclosed/").is_err());
+        assert!(SearchQuery::parse("in:prose").is_err());
+        assert_eq!(SearchQuery::parse("see https://example.com").unwrap().patterns[0].as_str(), "(?i)see https://example\\.com");
+    }
 }
@@ -0,0 +1,570 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::providers::{get_provider_for_model, Message};
+use crate::session::{ChatSession, NameSource};
+
+/// Third-party export formats understood by `/chat import`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    ChatGpt,
+    Claude,
+}
+
+impl ImportFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "chatgpt" | "openai" => Ok(ImportFormat::ChatGpt),
+            "claude" | "anthropic" => Ok(ImportFormat::Claude),
+            _ => Err(anyhow!("Unknown import format '{}'. Supported formats: chatgpt, claude", name)),
+        }
+    }
+
+    fn provider(&self) -> &'static str {
+        match self {
+            ImportFormat::ChatGpt => "openai",
+            ImportFormat::Claude => "anthropic",
+        }
+    }
+
+    fn source_prefix(&self) -> &'static str {
+        match self {
+            ImportFormat::ChatGpt => "chatgpt",
+            ImportFormat::Claude => "claude",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ImportedMessage {
+    role: String,
+    content: String,
+    timestamp: Option<DateTime<Utc>>,
+    model: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ImportedNode {
+    id: String,
+    parent: Option<String>,
+    message: Option<ImportedMessage>,
+}
+
+#[derive(Debug, Clone)]
+struct ImportedConversation {
+    id: String,
+    title: Option<String>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    nodes: Vec<ImportedNode>,
+    current_leaf: Option<String>,
+}
+
+/// Convert a ChatGPT or Claude data export into sessions.
+///
+/// `path` may be the `conversations.json` file itself, the extracted export
+/// directory, or the downloaded `.zip` archive. Every branch of a conversation
+/// becomes its own session; the branch that was active in the original app
+/// comes first. Settings not present in the export are taken from `template`.
+pub fn import_conversations(format: ImportFormat, path: &str, template: &ChatSession) -> Result<Vec<ChatSession>> {
+    let content = read_conversations_file(Path::new(path))?;
+    let data: Value = serde_json::from_str(&content)?;
+    let conversations = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected a list of conversations in {}", path))?;
+
+    let mut sessions = Vec::new();
+    for conversation in conversations {
+        let parsed = match format {
+            ImportFormat::ChatGpt => parse_chatgpt_conversation(conversation),
+            ImportFormat::Claude => parse_claude_conversation(conversation),
+        };
+        if let Some(parsed) = parsed {
+            sessions.extend(conversation_to_sessions(&parsed, format, template));
+        }
+    }
+
+    Ok(sessions)
+}
+
+fn read_conversations_file(path: &Path) -> Result<String> {
+    if path.is_dir() {
+        let file = path.join("conversations.json");
+        return fs::read_to_string(&file)
+            .map_err(|e| anyhow!("Failed to read {}: {}", file.display(), e));
+    }
+
+    let is_zip = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false);
+    if !is_zip {
+        return Ok(fs::read_to_string(path)?);
+    }
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+        if name == "conversations.json" || name.ends_with("/conversations.json") {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(content);
+        }
+    }
+
+    Err(anyhow!("No conversations.json found in {}", path.display()))
+}
+
+fn timestamp_from_seconds(value: &Value) -> Option<DateTime<Utc>> {
+    let seconds = value.as_f64()?;
+    DateTime::from_timestamp(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32)
+}
+
+fn timestamp_from_rfc3339(value: &Value) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.as_str()?)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+fn non_empty_string(value: &Value) -> Option<String> {
+    value
+        .as_str()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.to_string())
+}
+
+fn parse_chatgpt_conversation(conversation: &Value) -> Option<ImportedConversation> {
+    let id = non_empty_string(&conversation["conversation_id"])
+        .or_else(|| non_empty_string(&conversation["id"]))?;
+    let mapping = conversation["mapping"].as_object()?;
+    let default_model = non_empty_string(&conversation["default_model_slug"]);
+
+    let nodes = mapping
+        .iter()
+        .map(|(node_id, node)| ImportedNode {
+            id: node_id.clone(),
+            parent: non_empty_string(&node["parent"]),
+            message: parse_chatgpt_message(&node["message"], default_model.as_ref()),
+        })
+        .collect();
+
+    Some(ImportedConversation {
+        id,
+        title: non_empty_string(&conversation["title"]),
+        created_at: timestamp_from_seconds(&conversation["create_time"]),
+        updated_at: timestamp_from_seconds(&conversation["update_time"]),
+        nodes,
+        current_leaf: non_empty_string(&conversation["current_node"]),
+    })
+}
+
+fn parse_chatgpt_message(message: &Value, default_model: Option<&String>) -> Option<ImportedMessage> {
+    let role = message["author"]["role"].as_str()?;
+    if role != "user" && role != "assistant" {
+        return None;
+    }
+    if message["metadata"]["is_visually_hidden_from_conversation"].as_bool() == Some(true) {
+        return None;
+    }
+
+    let content = &message["content"];
+    let text = match content["content_type"].as_str() {
+        Some("code") => {
+            let code = content["text"].as_str()?;
+            let language = content["language"]
+                .as_str()
+                .filter(|lang| *lang != "unknown")
+                .unwrap_or("");
+            format!("```{}\n{}\n```", language, code)
+        }
+        _ => content["parts"]
+            .as_array()?
+            .iter()
+            .filter_map(|part| part.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    let model = if role == "assistant" {
+        non_empty_string(&message["metadata"]["model_slug"]).or_else(|| default_model.cloned())
+    } else {
+        None
+    };
+
+    Some(ImportedMessage {
+        role: role.to_string(),
+        content: text,
+        timestamp: timestamp_from_seconds(&message["create_time"]),
+        model,
+    })
+}
+
+fn parse_claude_conversation(conversation: &Value) -> Option<ImportedConversation> {
+    let id = non_empty_string(&conversation["uuid"])?;
+    let model = non_empty_string(&conversation["model"]);
+    let messages = conversation["chat_messages"].as_array()?;
+
+    let mut nodes: Vec<ImportedNode> = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        let node_id = non_empty_string(&message["uuid"]).unwrap_or_else(|| format!("{}-{}", id, index));
+        // Older exports are a flat list; treat each message as the child of the previous one
+        let parent = non_empty_string(&message["parent_message_uuid"])
+            .or_else(|| nodes.last().map(|node| node.id.clone()));
+
+        let role = match message["sender"].as_str() {
+            Some("human") => Some("user"),
+            Some("assistant") => Some("assistant"),
+            _ => None,
+        };
+
+        let text = non_empty_string(&message["text"]).unwrap_or_else(|| {
+            message["content"]
+                .as_array()
+                .map(|blocks| {
+                    blocks
+                        .iter()
+                        .filter(|block| block["type"].as_str() == Some("text"))
+                        .filter_map(|block| block["text"].as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .unwrap_or_default()
+        });
+
+        let imported = role.filter(|_| !text.trim().is_empty()).map(|role| ImportedMessage {
+            role: role.to_string(),
+            content: text,
+            timestamp: timestamp_from_rfc3339(&message["created_at"]),
+            model: if role == "assistant" { model.clone() } else { None },
+        });
+
+        nodes.push(ImportedNode {
+            id: node_id,
+            parent,
+            message: imported,
+        });
+    }
+
+    Some(ImportedConversation {
+        id,
+        title: non_empty_string(&conversation["name"]),
+        created_at: timestamp_from_rfc3339(&conversation["created_at"]),
+        updated_at: timestamp_from_rfc3339(&conversation["updated_at"]),
+        nodes,
+        current_leaf: non_empty_string(&conversation["current_leaf_message_uuid"]),
+    })
+}
+
+fn path_to<'a>(leaf: &'a ImportedNode, by_id: &HashMap<&str, &'a ImportedNode>) -> Vec<&'a ImportedNode> {
+    let mut path = Vec::new();
+    let mut visited = HashSet::new();
+    let mut current = Some(leaf);
+    while let Some(node) = current {
+        // Guard against malformed exports with parent cycles
+        if !visited.insert(node.id.as_str()) {
+            break;
+        }
+        path.push(node);
+        current = node.parent.as_deref().and_then(|parent| by_id.get(parent).copied());
+    }
+    path.reverse();
+    path
+}
+
+/// Walk the message tree and return every root-to-leaf path, active branch first
+fn conversation_branches(conversation: &ImportedConversation) -> Vec<(String, Vec<&ImportedMessage>)> {
+    let by_id: HashMap<&str, &ImportedNode> = conversation
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node))
+        .collect();
+    let parents: HashSet<&str> = conversation
+        .nodes
+        .iter()
+        .filter_map(|node| node.parent.as_deref())
+        .collect();
+
+    let latest_timestamp = |node: &ImportedNode| {
+        path_to(node, &by_id)
+            .iter()
+            .rev()
+            .find_map(|n| n.message.as_ref().and_then(|m| m.timestamp))
+    };
+
+    let mut leaves: Vec<&ImportedNode> = conversation
+        .nodes
+        .iter()
+        .filter(|node| !parents.contains(node.id.as_str()))
+        .collect();
+    leaves.sort_by_key(|node| latest_timestamp(node));
+
+    // The active branch is the one the app was showing; fall back to the newest leaf
+    let main_leaf = conversation
+        .current_leaf
+        .as_deref()
+        .and_then(|id| by_id.get(id).copied())
+        .or_else(|| leaves.last().copied());
+
+    let mut ordered: Vec<&ImportedNode> = main_leaf.into_iter().collect();
+    ordered.extend(leaves.iter().filter(|leaf| Some(leaf.id.as_str()) != main_leaf.map(|m| m.id.as_str())));
+
+    let mut branches: Vec<(String, Vec<&ImportedMessage>)> = Vec::new();
+    let mut seen: Vec<Vec<&str>> = Vec::new();
+    for leaf in ordered {
+        let path = path_to(leaf, &by_id);
+        let message_ids: Vec<&str> = path
+            .iter()
+            .filter(|node| node.message.is_some())
+            .map(|node| node.id.as_str())
+            .collect();
+
+        // Skip branches that add no visible messages beyond one we already have
+        if message_ids.is_empty() || seen.iter().any(|other| other.starts_with(&message_ids)) {
+            continue;
+        }
+        seen.push(message_ids);
+
+        let messages = path.iter().filter_map(|node| node.message.as_ref()).collect();
+        branches.push((leaf.id.clone(), messages));
+    }
+
+    branches
+}
+
+fn conversation_to_sessions(conversation: &ImportedConversation, format: ImportFormat, template: &ChatSession) -> Vec<ChatSession> {
+    let branches = conversation_branches(conversation);
+
+    branches
+        .iter()
+        .enumerate()
+        .map(|(index, (leaf_id, messages))| {
+            let mut session = ChatSession::new(
+                template.current_model.clone(),
+                template.temperature,
+                template.max_tokens,
+                template.thinking_budget,
+            );
+            session.current_provider = template.current_provider.clone();
+
+            let fallback_time = conversation
+                .created_at
+                .or_else(|| messages.iter().find_map(|m| m.timestamp))
+                .unwrap_or(session.created_at);
+
+            for message in messages {
+                let msg = Message {
+                    role: message.role.clone(),
+                    content: message.content.clone(),
+                };
+                if message.role == "assistant" {
+                    session.add_message_with_metadata(msg, Some(format.provider().to_string()), message.model.clone());
+                } else {
+                    session.add_message(msg);
+                }
+                if let Some(last) = session.messages.last_mut() {
+                    last.timestamp = message.timestamp.unwrap_or(fallback_time);
+                }
+            }
+
+            // Continue with the last model used, as long as njord knows how to talk to it
+            if let Some(model) = messages.iter().rev().find_map(|m| m.model.clone()) {
+                if let Some(provider) = get_provider_for_model(&model) {
+                    session.current_model = model;
+                    session.current_provider = Some(provider.to_string());
+                }
+            }
+
+            session.created_at = fallback_time;
+            session.updated_at = if index == 0 {
+                conversation.updated_at
+            } else {
+                None
+            }
+            .or_else(|| session.messages.last().map(|m| m.timestamp))
+            .unwrap_or(fallback_time);
+
+            if let Some(title) = &conversation.title {
+                session.name = Some(if index == 0 {
+                    title.clone()
+                } else {
+                    format!("{} (branch {})", title, index + 1)
+                });
+                session.name_source = Some(NameSource::UserProvided);
+            }
+
+            session.imported_from = Some(if index == 0 {
+                format!("{}:{}", format.source_prefix(), conversation.id)
+            } else {
+                format!("{}:{}:{}", format.source_prefix(), conversation.id, leaf_id)
+            });
+            session.mark_llm_interaction();
+            session
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn template() -> ChatSession {
+        ChatSession::new("claude-sonnet-4-20250514".to_string(), 0.7, 4096, 20000)
+    }
+
+    fn write_export(content: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file
+    }
+
+    const CHATGPT_EXPORT: &str = r#"[
+      {
+        "title": "Sorting help",
+        "create_time": 1700000000.5,
+        "update_time": 1700000500.0,
+        "conversation_id": "conv-1",
+        "current_node": "a1",
+        "default_model_slug": "gpt-4o",
+        "mapping": {
+          "root": {"id": "root", "message": null, "parent": null, "children": ["sys"]},
+          "sys": {"id": "sys", "parent": "root", "children": ["u1"], "message": {
+            "author": {"role": "system"}, "content": {"content_type": "text", "parts": [""]},
+            "metadata": {"is_visually_hidden_from_conversation": true}}},
+          "u1": {"id": "u1", "parent": "sys", "children": ["a1", "a2"], "message": {
+            "author": {"role": "user"}, "create_time": 1700000100.0,
+            "content": {"content_type": "text", "parts": ["How do I sort a list?"]}, "metadata": {}}},
+          "a1": {"id": "a1", "parent": "u1", "children": [], "message": {
+            "author": {"role": "assistant"}, "create_time": 1700000200.0,
+            "content": {"content_type": "text", "parts": ["Use sorted:\n```python\nsorted(xs)\n```"]},
+            "metadata": {"model_slug": "gpt-4o"}}},
+          "a2": {"id": "a2", "parent": "u1", "children": [], "message": {
+            "author": {"role": "assistant"}, "create_time": 1700000150.0,
+            "content": {"content_type": "text", "parts": ["Call list.sort()"]},
+            "metadata": {"model_slug": "gpt-4"}}}
+        }
+      },
+      {
+        "title": null,
+        "create_time": 1700001000.0,
+        "update_time": 1700001000.0,
+        "id": "conv-2",
+        "mapping": {
+          "u": {"id": "u", "parent": null, "children": ["a"], "message": {
+            "author": {"role": "user"}, "content": {"content_type": "text", "parts": ["hi"]}, "metadata": {}}},
+          "a": {"id": "a", "parent": "u", "children": [], "message": {
+            "author": {"role": "assistant"}, "content": {"content_type": "text", "parts": ["hello"]}, "metadata": {}}}
+        }
+      }
+    ]"#;
+
+    const CLAUDE_EXPORT: &str = r#"[
+      {
+        "uuid": "claude-1",
+        "name": "Rust lifetimes",
+        "created_at": "2024-05-01T10:00:00.000000Z",
+        "updated_at": "2024-05-01T10:05:00.000000Z",
+        "chat_messages": [
+          {"uuid": "m1", "sender": "human", "text": "    let s: &'a str;\nExplain 'a\n", "created_at": "2024-05-01T10:00:00Z"},
+          {"uuid": "m2", "sender": "assistant", "text": "",
+           "content": [{"type": "text", "text": "A lifetime:\n```rust\nfn f<'a>(x: &'a str) {}\n```"}],
+           "created_at": "2024-05-01T10:01:00Z"}
+        ]
+      },
+      {"uuid": "claude-empty", "name": "", "chat_messages": []}
+    ]"#;
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!(ImportFormat::parse("ChatGPT").unwrap(), ImportFormat::ChatGpt);
+        assert_eq!(ImportFormat::parse("claude").unwrap(), ImportFormat::Claude);
+        assert!(ImportFormat::parse("bard").is_err());
+    }
+
+    #[test]
+    fn test_chatgpt_import_with_branches() {
+        let file = write_export(CHATGPT_EXPORT);
+        let sessions = import_conversations(ImportFormat::ChatGpt, file.path().to_str().unwrap(), &template()).unwrap();
+
+        assert_eq!(sessions.len(), 3);
+
+        let main = &sessions[0];
+        assert_eq!(main.name.as_deref(), Some("Sorting help"));
+        assert!(matches!(main.name_source, Some(NameSource::UserProvided)));
+        assert_eq!(main.imported_from.as_deref(), Some("chatgpt:conv-1"));
+        assert_eq!(main.messages.len(), 2);
+        assert_eq!(main.messages[1].model.as_deref(), Some("gpt-4o"));
+        assert_eq!(main.messages[1].provider.as_deref(), Some("openai"));
+        assert_eq!(main.messages[1].code_blocks.len(), 1);
+        assert_eq!(main.messages[0].timestamp.timestamp(), 1700000100);
+        assert_eq!(main.created_at.timestamp(), 1700000000);
+        assert_eq!(main.current_model, "gpt-4o");
+        assert!(main.should_auto_save());
+
+        let branch = &sessions[1];
+        assert_eq!(branch.name.as_deref(), Some("Sorting help (branch 2)"));
+        assert_eq!(branch.imported_from.as_deref(), Some("chatgpt:conv-1:a2"));
+        assert_eq!(branch.messages[1].message.content, "Call list.sort()");
+
+        // Untitled chats are left for History to name
+        assert!(sessions[2].name.is_none());
+        assert_eq!(sessions[2].messages[1].model.as_deref(), None);
+    }
+
+    #[test]
+    fn test_claude_import() {
+        let file = write_export(CLAUDE_EXPORT);
+        let sessions = import_conversations(ImportFormat::Claude, file.path().to_str().unwrap(), &template()).unwrap();
+
+        assert_eq!(sessions.len(), 1);
+        let session = &sessions[0];
+        assert_eq!(session.name.as_deref(), Some("Rust lifetimes"));
+        assert_eq!(session.messages[0].message.role, "user");
+        assert_eq!(session.messages[0].message.content, "    let s: &'a str;\nExplain 'a\n");
+        assert_eq!(session.messages[1].provider.as_deref(), Some("anthropic"));
+        assert_eq!(session.messages[1].code_blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(session.updated_at.to_rfc3339(), "2024-05-01T10:05:00+00:00");
+    }
+
+    #[test]
+    fn test_import_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("conversations.json"), CLAUDE_EXPORT).unwrap();
+
+        let sessions = import_conversations(ImportFormat::Claude, dir.path().to_str().unwrap(), &template()).unwrap();
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_reimport_skips_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions_file = dir.path().join("sessions");
+        let mut history = History::new(sessions_file.to_str().unwrap().to_string());
+        let file = write_export(CHATGPT_EXPORT);
+        let path = file.path().to_str().unwrap();
+
+        let first = history
+            .import_sessions(import_conversations(ImportFormat::ChatGpt, path, &template()).unwrap())
+            .unwrap();
+        assert_eq!(first.imported_count, 3);
+        assert_eq!(first.skipped_count, 0);
+
+        let second = history
+            .import_sessions(import_conversations(ImportFormat::ChatGpt, path, &template()).unwrap())
+            .unwrap();
+        assert_eq!(second.imported_count, 0);
+        assert_eq!(second.skipped_count, 3);
+        assert_eq!(history.saved_sessions.len(), 3);
+    }
+}
@@ -1,10 +1,12 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
 
+use crate::encryption::write_state_file;
+use crate::schema::{self, StateFile};
+
 const MAX_HISTORY_ENTRIES: usize = 1000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,19 +37,26 @@ impl InputHistory {
             return Ok(Self::new(file_path));
         }
         
-        let content = fs::read_to_string(&path)?;
-        let mut history: InputHistory = serde_json::from_str(&content)?;
+        let mut history: InputHistory = schema::load(&path, StateFile::Inputs)?;
         history.file_path = file_path;
         
         Ok(history)
     }
     
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+    
     pub fn save(&self) -> Result<()> {
         // Reload from disk to merge any changes from other instances
         let mut merged = self.clone();
         merged.file_path = self.file_path.clone(); // Restore file path after clone
         
-        if let Ok(disk_version) = Self::load(self.file_path.clone()) {
+        let disk_version = match Self::load(self.file_path.clone()) {
+            Err(e) if schema::is_newer_schema(&e) => return Err(e),
+            disk_version => disk_version,
+        };
+        if let Ok(disk_version) = disk_version {
             // Merge entries from disk version, avoiding duplicates
             for entry in disk_version.entries {
                 // Only add entries that we don't already have
@@ -62,11 +71,28 @@ impl InputHistory {
             }
         }
         
-        let content = serde_json::to_string_pretty(&merged)?;
-        fs::write(&self.file_path, content)?;
+        let content = schema::to_string(&merged, StateFile::Inputs)?;
+        write_state_file(Path::new(&self.file_path), &content)?;
         Ok(())
     }
     
+    pub fn count_before(&self, cutoff: DateTime<Utc>) -> usize {
+        self.entries.iter().filter(|entry| entry.timestamp < cutoff).count()
+    }
+    
+    /// Drop entries older than `cutoff` and write the file directly, since merging
+    /// with the disk version would bring them back. Returns how many were removed.
+    pub fn prune_before(&mut self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.timestamp >= cutoff);
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            let content = schema::to_string(&self, StateFile::Inputs)?;
+            write_state_file(Path::new(&self.file_path), &content)?;
+        }
+        Ok(removed)
+    }
+    
     pub fn add_entry(&mut self, input: String) {
         // Skip empty or whitespace-only inputs
         if input.trim().is_empty() {
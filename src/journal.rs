@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::session::NumberedMessage;
+
+/// How many operations a journal keeps before dropping the oldest
+pub const JOURNAL_LIMIT: usize = 50;
+
+/// A destructive operation, with what is needed to reverse it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Operation {
+    Undo { removed: Vec<NumberedMessage> },
+    Goto { removed: Vec<NumberedMessage> },
+    Edit { number: usize, previous: String, replacement: String }, // Message number, not User/Agent N
+    Delete { session: String, trash_name: String },
+    Rename { from: String, to: String },
+}
+
+impl Operation {
+    pub fn describe(&self) -> String {
+        match self {
+            Operation::Undo { removed } => format!("/undo removed {}", describe_messages(removed)),
+            Operation::Goto { removed } => format!("/goto removed {}", describe_messages(removed)),
+            Operation::Edit { number, previous, .. } => {
+                let preview: String = previous.chars().take(40).collect::<String>().replace('\n', " ");
+                format!("/edit changed message {} (was \"{}{}\")", number, preview, if previous.chars().count() > 40 { "..." } else { "" })
+            }
+            Operation::Delete { session, .. } => format!("/chat delete moved '{}' to the trash", session),
+            Operation::Rename { from, to } => format!("/chat rename '{}' to '{}'", from, to),
+        }
+    }
+}
+
+fn describe_messages(messages: &[NumberedMessage]) -> String {
+    match (messages.first(), messages.last()) {
+        (Some(first), Some(last)) if first.number != last.number => {
+            format!("{} messages ({}-{})", messages.len(), first.number, last.number)
+        }
+        (Some(first), _) => format!("1 message ({})", first.number),
+        _ => "0 messages".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    pub operation: Operation,
+    #[serde(default)]
+    pub reverted: bool,
+}
+
+/// Append an operation, keeping at most `JOURNAL_LIMIT` entries
+pub fn record(journal: &mut Vec<JournalEntry>, operation: Operation) {
+    journal.push(JournalEntry {
+        timestamp: Utc::now(),
+        operation,
+        reverted: false,
+    });
+    if journal.len() > JOURNAL_LIMIT {
+        journal.drain(..journal.len() - JOURNAL_LIMIT);
+    }
+}
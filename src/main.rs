@@ -1,5 +1,5 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches};
 use tokio::signal;
 use tokio::sync::mpsc;
 
@@ -14,16 +14,57 @@ mod ui;
 mod prompts;
 mod input_history;
 mod variable;
+mod export;
+mod importer;
+mod backup;
+mod encryption;
+mod diff;
+mod workspace;
+mod retention;
+mod stats;
+mod template;
+mod bundle;
+mod journal;
+mod schema;
+mod recovery;
+mod compare;
+mod oneshot;
+mod script;
+mod events;
 
-use cli::Args;
+use cli::{Args, CliCommand, OutputFormat};
 use config::Config;
+use oneshot::OneShot;
 use repl::Repl;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
     let config = Config::from_args(&args)?;
     
+    if let Some(CliCommand::Doctor) = args.command {
+        let healthy = recovery::run_doctor(&config)?;
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+    
+    let model_given = matches.value_source("model") == Some(ValueSource::CommandLine);
+    let oneshot = OneShot::from_args(&args, model_given)?;
+    if args.output != OutputFormat::Text && oneshot.is_none() && args.script.is_none() {
+        Args::command().error(ErrorKind::MissingRequiredArgument, "--output json|jsonl needs --prompt, ask or --script").exit();
+    }
+    events::set_output_format(args.output);
+    
+    // One-shot requests leave Ctrl-C alone, so it ends the process as usual
+    if let Some(oneshot) = oneshot {
+        let (_ctrl_c_tx, ctrl_c_rx) = mpsc::unbounded_channel();
+        let result = match Repl::new(config, ctrl_c_rx).await {
+            Ok(mut repl) => repl.ask(oneshot).await,
+            Err(e) => Err(e),
+        };
+        exit_with(result.map(|_| true));
+    }
+    
     // Create a channel for Ctrl-C signals
     let (ctrl_c_tx, ctrl_c_rx) = mpsc::unbounded_channel();
     
@@ -36,8 +77,26 @@ async fn main() -> Result<()> {
         }
     });
     
+    if let Some(script) = &args.script {
+        let result = match Repl::new(config, ctrl_c_rx).await {
+            Ok(mut repl) => repl.run_script(script, args.keep_going).await,
+            Err(e) => Err(e),
+        };
+        exit_with(result.map(|outcome| outcome.failed == 0));
+    }
+    
     let mut repl = Repl::new(config, ctrl_c_rx).await?;
     repl.run().await?;
     
     Ok(())
 }
+
+/// End a one-shot or script run, exiting non-zero if it failed
+fn exit_with(result: Result<bool>) -> ! {
+    let success = result.unwrap_or_else(|e| {
+        events::report_error(&e.to_string());
+        false
+    });
+    events::finish();
+    std::process::exit(if success { 0 } else { 1 });
+}
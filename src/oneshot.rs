@@ -0,0 +1,96 @@
+use anyhow::Result;
+use std::io::{IsTerminal, Read};
+
+use crate::cli::{Args, CliCommand};
+
+/// A single non-interactive request from `njord -p` or `njord ask`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OneShot {
+    pub prompt: String,
+    pub model: Option<String>, // Only set when given on the command line
+    pub system: Option<String>,
+    pub apply_prompt: Option<String>, // Saved prompt to use as the system prompt
+}
+
+impl OneShot {
+    /// The one-shot request described by `args`, if any. `model_given` says whether
+    /// --model was passed explicitly rather than left at its default.
+    pub fn from_args(args: &Args, model_given: bool) -> Result<Option<Self>> {
+        let prompt = match (&args.prompt, &args.command) {
+            (Some(_), Some(CliCommand::Ask { .. })) => {
+                return Err(anyhow::anyhow!("Use either --prompt or 'ask', not both"));
+            }
+            (Some(prompt), _) => prompt.clone(),
+            (None, Some(CliCommand::Ask { prompt })) => prompt.join(" "),
+            (None, _) => {
+                if args.system.is_some() || args.apply_prompt.is_some() {
+                    return Err(anyhow::anyhow!("--system and --apply-prompt need --prompt or 'ask'"));
+                }
+                return Ok(None);
+            }
+        };
+        Ok(Some(Self {
+            prompt,
+            model: model_given.then(|| args.model.clone()),
+            system: args.system.clone(),
+            apply_prompt: args.apply_prompt.clone(),
+        }))
+    }
+}
+
+/// Whatever was piped into stdin, or None when stdin is a terminal
+pub fn read_piped_stdin() -> Result<Option<String>> {
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Ok(None);
+    }
+    let mut input = String::new();
+    stdin.read_to_string(&mut input)?;
+    Ok(Some(input))
+}
+
+/// The message to send: the prompt followed by the piped context
+pub fn compose_prompt(prompt: &str, context: Option<&str>) -> Result<String> {
+    let prompt = prompt.trim();
+    let context = context.map(str::trim_end).filter(|context| !context.trim().is_empty());
+    match context {
+        Some(context) if prompt.is_empty() => Ok(context.to_string()),
+        Some(context) => Ok(format!("{}\n\n{}", prompt, context)),
+        None if prompt.is_empty() => Err(anyhow::anyhow!("Nothing to send: the prompt is empty and nothing was piped in")),
+        None => Ok(prompt.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_from_args() {
+        let args = Args::parse_from(["njord", "-p", "review this", "--system", "Be terse"]);
+        let oneshot = OneShot::from_args(&args, false).unwrap().unwrap();
+        assert_eq!(oneshot.prompt, "review this");
+        assert_eq!(oneshot.system.as_deref(), Some("Be terse"));
+        assert_eq!(oneshot.model, None);
+
+        let args = Args::parse_from(["njord", "-m", "gpt-4o", "ask", "what", "is", "this?"]);
+        let oneshot = OneShot::from_args(&args, true).unwrap().unwrap();
+        assert_eq!(oneshot.prompt, "what is this?");
+        assert_eq!(oneshot.model.as_deref(), Some("gpt-4o"));
+
+        assert!(OneShot::from_args(&Args::parse_from(["njord"]), false).unwrap().is_none());
+        assert!(OneShot::from_args(&Args::parse_from(["njord", "--system", "x"]), false).is_err());
+        assert!(OneShot::from_args(&Args::parse_from(["njord", "-p", "a", "ask", "b"]), false).is_err());
+        assert!(Args::try_parse_from(["njord", "-p", "a", "--system", "x", "--apply-prompt", "y"]).is_err());
+    }
+
+    #[test]
+    fn test_compose_prompt() {
+        assert_eq!(compose_prompt("review this", Some("diff\n")).unwrap(), "review this\n\ndiff");
+        assert_eq!(compose_prompt("review this", None).unwrap(), "review this");
+        assert_eq!(compose_prompt("review this", Some(" \n")).unwrap(), "review this");
+        assert_eq!(compose_prompt("", Some("just the input")).unwrap(), "just the input");
+        assert!(compose_prompt("  ", None).is_err());
+    }
+}
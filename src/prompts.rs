@@ -2,9 +2,11 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
 
+use crate::encryption::write_state_file;
+use crate::schema::{self, StateFile};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SystemPrompt {
@@ -48,6 +50,8 @@ pub struct PromptLibrary {
     pub prompts: HashMap<String, SystemPrompt>,
     #[serde(skip)]
     pub prompts_file_path: String,
+    #[serde(skip)]
+    pub inherited: HashMap<String, SystemPrompt>, // Global prompts visible from a workspace
 }
 
 impl PromptLibrary {
@@ -55,6 +59,7 @@ impl PromptLibrary {
         Self {
             prompts: HashMap::new(),
             prompts_file_path,
+            inherited: HashMap::new(),
         }
     }
     
@@ -65,8 +70,7 @@ impl PromptLibrary {
             return Ok(Self::new(prompts_file_path));
         }
         
-        let content = fs::read_to_string(&path)?;
-        let mut library: PromptLibrary = serde_json::from_str(&content)?;
+        let mut library: PromptLibrary = schema::load(&path, StateFile::Prompts)?;
         library.prompts_file_path = prompts_file_path;
         Ok(library)
     }
@@ -76,7 +80,11 @@ impl PromptLibrary {
         let mut merged = self.clone();
         merged.prompts_file_path = self.prompts_file_path.clone(); // Restore file path after clone
         
-        if let Ok(disk_version) = Self::load(self.prompts_file_path.clone()) {
+        let disk_version = match Self::load(self.prompts_file_path.clone()) {
+            Err(e) if schema::is_newer_schema(&e) => return Err(e),
+            disk_version => disk_version,
+        };
+        if let Ok(disk_version) = disk_version {
             // Merge prompts from disk version, keeping our changes
             for (name, prompt) in disk_version.prompts {
                 // Only add prompts from disk that we don't have locally
@@ -85,8 +93,8 @@ impl PromptLibrary {
             }
         }
         
-        let content = serde_json::to_string_pretty(&merged)?;
-        fs::write(&self.prompts_file_path, content)?;
+        let content = schema::to_string(&merged, StateFile::Prompts)?;
+        write_state_file(Path::new(&self.prompts_file_path), &content)?;
         Ok(())
     }
     
@@ -97,8 +105,35 @@ impl PromptLibrary {
         Ok(())
     }
     
+    /// Layer another library (the global one) underneath this one. Its prompts can be
+    /// read and applied, and a local prompt with the same name takes precedence.
+    pub fn inherit(&mut self, library: PromptLibrary) {
+        self.inherited = library.prompts;
+    }
+    
+    /// True if the prompt only exists in the inherited library
+    pub fn is_inherited(&self, name: &str) -> bool {
+        !self.prompts.contains_key(name) && self.inherited.contains_key(name)
+    }
+    
+    fn visible_prompts(&self) -> impl Iterator<Item = (&String, &SystemPrompt)> {
+        self.prompts.iter().chain(
+            self.inherited.iter().filter(|(name, _)| !self.prompts.contains_key(*name)),
+        )
+    }
+    
+    fn ensure_local(&self, name: &str) -> Result<()> {
+        if self.is_inherited(name) {
+            return Err(anyhow::anyhow!(
+                "Prompt '{}' belongs to the global library. Use '/workspace switch global' to change it",
+                name
+            ));
+        }
+        Ok(())
+    }
+    
     pub fn get_prompt(&self, name: &str) -> Option<&SystemPrompt> {
-        self.prompts.get(name)
+        self.prompts.get(name).or_else(|| self.inherited.get(name))
     }
     
     
@@ -109,16 +144,17 @@ impl PromptLibrary {
             let _ = self.save(); // Best effort save
             Some(content)
         } else {
-            None
+            // Usage of inherited prompts isn't tracked; that would write to the global library
+            self.inherited.get(name).map(|prompt| prompt.content.clone())
         }
     }
     
     pub fn list_prompts(&self) -> Vec<&String> {
-        let mut names: Vec<_> = self.prompts.keys().collect();
+        let mut names: Vec<_> = self.visible_prompts().map(|(name, _)| name).collect();
         names.sort_by(|a, b| {
             // Sort by usage count (descending), then by name
-            let prompt_a = &self.prompts[*a];
-            let prompt_b = &self.prompts[*b];
+            let prompt_a = self.get_prompt(a).unwrap();
+            let prompt_b = self.get_prompt(b).unwrap();
             prompt_b.usage_count.cmp(&prompt_a.usage_count)
                 .then(a.cmp(b))
         });
@@ -126,6 +162,7 @@ impl PromptLibrary {
     }
     
     pub fn delete_prompt(&mut self, name: &str) -> Result<bool> {
+        self.ensure_local(name)?;
         let existed = self.prompts.remove(name).is_some();
         if existed {
             self.save()?;
@@ -134,6 +171,7 @@ impl PromptLibrary {
     }
     
     pub fn rename_prompt(&mut self, old_name: &str, new_name: &str) -> Result<bool> {
+        self.ensure_local(old_name)?;
         if !self.prompts.contains_key(old_name) {
             return Ok(false);
         }
@@ -157,7 +195,7 @@ impl PromptLibrary {
         let mut results = Vec::new();
         let term_lower = term.to_lowercase();
         
-        for (name, prompt) in &self.prompts {
+        for (name, prompt) in self.visible_prompts() {
             let mut relevance_score = 0;
             let mut matched_fields = Vec::new();
             
@@ -211,6 +249,11 @@ impl PromptLibrary {
     }
     
     pub fn update_prompt_content(&mut self, name: &str, content: String) -> Result<bool> {
+        // Editing an inherited prompt gives this library its own copy
+        if self.is_inherited(name) {
+            let prompt = self.inherited[name].clone();
+            self.prompts.insert(name.to_string(), prompt);
+        }
         if let Some(prompt) = self.prompts.get_mut(name) {
             prompt.update_content(content);
             self.save()?;
@@ -234,7 +277,11 @@ impl PromptLibrary {
     pub fn import_prompts(&mut self, file_path: &str, overwrite: bool) -> Result<ImportResult> {
         let content = fs::read_to_string(file_path)?;
         let imported_prompts: HashMap<String, SystemPrompt> = serde_json::from_str(&content)?;
-        
+        self.add_prompts(imported_prompts, overwrite)
+    }
+    
+    /// Add prompts from elsewhere (an import file or a shared session bundle)
+    pub fn add_prompts(&mut self, imported_prompts: impl IntoIterator<Item = (String, SystemPrompt)>, overwrite: bool) -> Result<ImportResult> {
         let mut imported_count = 0;
         let mut skipped_count = 0;
         let mut overwritten_count = 0;
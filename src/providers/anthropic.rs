@@ -5,7 +5,7 @@ use reqwest::Client;
 use serde_json::json;
 use tokio::time::{sleep, Duration};
 
-use super::{LLMProvider, ChatRequest, Message};
+use super::{LLMProvider, ChatRequest, Message, ThinkingBlock};
 
 #[allow(dead_code)]
 pub struct AnthropicProvider {
@@ -96,13 +96,30 @@ impl AnthropicProvider {
         true
     }
     
-    fn convert_messages(&self, messages: &[Message]) -> (Option<String>, Vec<serde_json::Value>) {
+    /// Replies with signed thinking get it back as `thinking` blocks ahead of their text
+    fn convert_messages(&self, messages: &[Message], thinking_blocks: &[Vec<ThinkingBlock>]) -> (Option<String>, Vec<serde_json::Value>) {
         let mut system_message = None;
         let mut anthropic_messages = Vec::new();
         
-        for msg in messages {
+        for (index, msg) in messages.iter().enumerate() {
+            let blocks = thinking_blocks.get(index).filter(|blocks| msg.role == "assistant" && !blocks.is_empty());
             if msg.role == "system" {
                 system_message = Some(msg.content.clone());
+            } else if let Some(blocks) = blocks {
+                let mut content: Vec<serde_json::Value> = blocks.iter()
+                    .map(|block| json!({
+                        "type": "thinking",
+                        "thinking": block.thinking,
+                        "signature": block.signature
+                    }))
+                    .collect();
+                if !msg.content.is_empty() {
+                    content.push(json!({ "type": "text", "text": msg.content }));
+                }
+                anthropic_messages.push(json!({
+                    "role": msg.role,
+                    "content": content
+                }));
             } else {
                 anthropic_messages.push(json!({
                     "role": msg.role,
@@ -120,7 +137,9 @@ impl LLMProvider for AnthropicProvider {
     async fn chat(&self, request: ChatRequest) -> Result<Box<dyn Stream<Item = Result<String>> + Unpin + Send>> {
         let url = "https://api.anthropic.com/v1/messages";
         
-        let (system_message, anthropic_messages) = self.convert_messages(&request.messages);
+        // Signed thinking only goes back on requests that think themselves
+        let thinking_blocks = if request.thinking { request.thinking_blocks.as_slice() } else { &[] };
+        let (system_message, anthropic_messages) = self.convert_messages(&request.messages, thinking_blocks);
         
         // Set max_tokens based on whether thinking is enabled
         let max_tokens = if request.thinking && self.supports_thinking(&request.model) {
@@ -223,6 +242,13 @@ impl LLMProvider for AnthropicProvider {
                                                                     if !thinking_content.is_empty() {
                                                                         pending_content.insert(0, format!("thinking:{}", thinking_content));
                                                                     }
+                                                                } else if let Some(signature) = json_val
+                                                                    .get("delta")
+                                                                    .and_then(|delta| delta.get("signature"))
+                                                                    .and_then(|signature| signature.as_str())
+                                                                {
+                                                                    // Closes the thinking block; it is sent back with the reply in later requests
+                                                                    pending_content.insert(0, format!("signature:{}", signature));
                                                                 }
                                                             } else {
                                                                 // Handle regular text deltas
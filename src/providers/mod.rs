@@ -13,10 +13,48 @@ pub struct Message {
     pub content: String,
 }
 
+/// A thinking block as Anthropic streamed it, with the signature that lets it be sent back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThinkingBlock {
+    pub thinking: String,
+    pub signature: String,
+}
+
+/// Gathers the `thinking:` and `signature:` chunks of a streamed reply. A signature closes
+/// the block before it; thinking from providers that don't sign it only ends up in `text`.
+#[derive(Debug, Default)]
+pub struct ThinkingCollector {
+    pub text: String, // All of the thinking, blocks separated by a blank line
+    pub blocks: Vec<ThinkingBlock>,
+    block: String,
+}
+
+impl ThinkingCollector {
+    /// Add a piece of thinking, returning it as it should be shown
+    pub fn push_text(&mut self, chunk: &str) -> String {
+        let shown = if self.block.is_empty() && !self.blocks.is_empty() {
+            format!("\n\n{}", chunk)
+        } else {
+            chunk.to_string()
+        };
+        self.block.push_str(chunk);
+        self.text.push_str(&shown);
+        shown
+    }
+
+    pub fn push_signature(&mut self, signature: &str) {
+        self.blocks.push(ThinkingBlock {
+            thinking: std::mem::take(&mut self.block),
+            signature: signature.to_string(),
+        });
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct ChatRequest {
     pub messages: Vec<Message>,
+    pub thinking_blocks: Vec<Vec<ThinkingBlock>>, // Signed thinking to send back with messages[i]
     pub model: String,
     pub temperature: f32,
     pub max_tokens: u32,
@@ -112,6 +150,7 @@ mod tests {
         
         let request = ChatRequest {
             messages: messages.clone(),
+            thinking_blocks: Vec::new(),
             model: "gpt-4".to_string(),
             temperature: 0.7,
             max_tokens: 1000,
@@ -128,4 +167,20 @@ mod tests {
         assert!(request.stream);
         assert!(!request.thinking);
     }
+
+    #[test]
+    fn test_thinking_collector() {
+        let mut collector = ThinkingCollector::default();
+        assert_eq!(collector.push_text("First "), "First ");
+        collector.push_text("block");
+        collector.push_signature("c2ln");
+        assert_eq!(collector.push_text("Second"), "\n\nSecond");
+        collector.push_signature("c2lnMg");
+
+        assert_eq!(collector.text, "First block\n\nSecond");
+        assert_eq!(collector.blocks, vec![
+            ThinkingBlock { thinking: "First block".to_string(), signature: "c2ln".to_string() },
+            ThinkingBlock { thinking: "Second".to_string(), signature: "c2lnMg".to_string() },
+        ]);
+    }
 }
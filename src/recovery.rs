@@ -0,0 +1,459 @@
+use anyhow::Result;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup::{BackupManager, STATE_FILES};
+use crate::config::Config;
+use crate::encryption::{self, decode_state_content, read_state_file};
+use crate::history::{History, TrashedSession};
+use crate::input_history::InputHistory;
+use crate::prompts::PromptLibrary;
+use crate::retention::RETENTION_LOG;
+use crate::schema::{self, StateFile};
+use crate::session::ChatSession;
+use crate::template::{TemplateLibrary, TEMPLATES_FILE};
+use crate::workspace;
+
+/// What could be read back from a damaged sessions file
+pub struct Salvage {
+    pub history: History,
+    pub recovered: usize,   // Sessions, including the current one and the trash
+    pub lost: Vec<String>, // What couldn't be read, e.g. "session 'design'"
+}
+
+/// The complete `"key": value` pairs of a JSON object, in order, read until the
+/// text ends or stops making sense
+struct ObjectScan<'a> {
+    entries: Vec<(String, &'a str)>,
+    cut_off: Option<(String, &'a str)>, // Entry whose value runs past the end of the text
+}
+
+fn skip_whitespace(text: &str, pos: usize) -> usize {
+    text[pos..].find(|c: char| !c.is_whitespace()).map_or(text.len(), |offset| pos + offset)
+}
+
+/// End of the string starting at `start` (just past the closing quote)
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start + 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+/// End of the JSON value starting at `start`, matching brackets outside strings
+fn value_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    match bytes.get(start)? {
+        b'"' => string_end(bytes, start),
+        b'{' | b'[' => {
+            let mut depth = 0usize;
+            let mut pos = start;
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'"' => {
+                        pos = string_end(bytes, pos)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(pos + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                pos += 1;
+            }
+            None
+        }
+        _ => {
+            let end = text[start..].find(|c: char| c == ',' || c == '}' || c == ']' || c.is_whitespace())?;
+            Some(start + end)
+        }
+    }
+}
+
+fn scan_object(text: &str) -> ObjectScan<'_> {
+    let mut scan = ObjectScan { entries: Vec::new(), cut_off: None };
+    let bytes = text.as_bytes();
+    let mut pos = skip_whitespace(text, 0);
+    if bytes.get(pos) != Some(&b'{') {
+        return scan;
+    }
+    pos += 1;
+    loop {
+        pos = skip_whitespace(text, pos);
+        if bytes.get(pos) != Some(&b'"') {
+            return scan; // End of the object, or garbage
+        }
+        let Some(key_end) = string_end(bytes, pos) else {
+            return scan;
+        };
+        let Ok(key) = serde_json::from_str::<String>(&text[pos..key_end]) else {
+            return scan;
+        };
+        pos = skip_whitespace(text, key_end);
+        if bytes.get(pos) != Some(&b':') {
+            return scan;
+        }
+        pos = skip_whitespace(text, pos + 1);
+        if pos >= text.len() {
+            return scan;
+        }
+        let Some(end) = value_end(text, pos) else {
+            scan.cut_off = Some((key, &text[pos..]));
+            return scan;
+        };
+        scan.entries.push((key, &text[pos..end]));
+        pos = skip_whitespace(text, end);
+        if bytes.get(pos) != Some(&b',') {
+            return scan;
+        }
+        pos += 1;
+    }
+}
+
+fn describe_session(collection: &str, name: &str) -> String {
+    match collection {
+        "trash" => format!("trashed session '{}'", name),
+        _ => format!("session '{}'", name),
+    }
+}
+
+/// Read every session that still parses out of a damaged sessions file.
+/// Fails only if the file was written by a newer njord.
+pub fn salvage_history(content: &str, history_file_path: String) -> Result<Salvage> {
+    let path = PathBuf::from(&history_file_path);
+    let top = scan_object(content);
+    let mut lost = Vec::new();
+    let mut document = Map::new();
+    let complete = top.entries.iter().map(|(key, raw)| (key, raw, false));
+    let partial = top.cut_off.iter().map(|(key, raw)| (key, raw, true));
+    for (key, raw, is_partial) in complete.chain(partial) {
+        if key == "saved_sessions" || key == "trash" {
+            // A truncated collection still holds every session written before the cut
+            let inner = scan_object(raw);
+            let mut sessions = Map::new();
+            for (name, raw_session) in &inner.entries {
+                match serde_json::from_str::<Value>(raw_session) {
+                    Ok(session) => {
+                        sessions.insert(name.clone(), session);
+                    }
+                    Err(_) => lost.push(describe_session(key, name)),
+                }
+            }
+            if let Some((name, _)) = &inner.cut_off {
+                lost.push(describe_session(key, name));
+            }
+            document.insert(key.clone(), Value::Object(sessions));
+        } else if let Some(value) = (!is_partial).then(|| serde_json::from_str::<Value>(raw).ok()).flatten() {
+            document.insert(key.clone(), value);
+        } else {
+            lost.push(key.replace('_', " "));
+        }
+    }
+
+    let mut document = Value::Object(document);
+    schema::migrate(&mut document, &path, StateFile::Sessions)?;
+
+    let mut history = History::new(history_file_path);
+    let mut recovered = 0;
+    let mut take = |key: &str| document.get_mut(key).map(Value::take);
+    if let Some(value) = take("current_session") {
+        match serde_json::from_value::<Option<ChatSession>>(value) {
+            Ok(session) => {
+                recovered += session.is_some() as usize;
+                history.current_session = session;
+            }
+            Err(_) => lost.push("current session".to_string()),
+        }
+    }
+    for (collection, value) in [("saved_sessions", take("saved_sessions")), ("trash", take("trash"))] {
+        let Some(Value::Object(entries)) = value else {
+            continue;
+        };
+        for (name, value) in entries {
+            let inserted = if collection == "trash" {
+                serde_json::from_value::<TrashedSession>(value).map(|trashed| {
+                    history.trash.insert(name.clone(), trashed);
+                })
+            } else {
+                serde_json::from_value::<ChatSession>(value).map(|session| {
+                    history.saved_sessions.insert(name.clone(), session);
+                })
+            };
+            match inserted {
+                Ok(_) => recovered += 1,
+                Err(_) => lost.push(describe_session(collection, &name)),
+            }
+        }
+    }
+    fn restore<T: DeserializeOwned>(value: Option<Value>, field: &mut T, label: &str, lost: &mut Vec<String>) {
+        if let Some(value) = value {
+            match serde_json::from_value(value) {
+                Ok(parsed) => *field = parsed,
+                Err(_) => lost.push(label.to_string()),
+            }
+        }
+    }
+    restore(take("default_preferences"), &mut history.default_preferences, "default preferences", &mut lost);
+    restore(take("retention"), &mut history.retention, "retention", &mut lost);
+    restore(take("journal"), &mut history.journal, "journal", &mut lost);
+
+    Ok(Salvage { history, recovered, lost })
+}
+
+/// The newest backup whose sessions file loads, with its timestamp
+fn newest_readable_backup(backups: &BackupManager, path: &Path) -> Option<(String, History)> {
+    for backup in backups.list_backups().ok()? {
+        let Ok(Some(content)) = backups.read_file(&backup.timestamp, "sessions") else {
+            continue;
+        };
+        let Ok(content) = decode_state_content(String::from_utf8_lossy(&content).to_string(), path) else {
+            continue;
+        };
+        if let Ok((history, _)) = schema::parse::<History>(&content, path, StateFile::Sessions) {
+            return Some((backup.timestamp, history));
+        }
+    }
+    None
+}
+
+fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Whether a state file failed to load because its content is damaged (bad JSON, a layout
+/// that doesn't deserialize, invalid UTF-8), rather than because it couldn't be read or
+/// decrypted. Only damaged files are salvaged; anything else is reported as it is.
+pub fn is_damaged(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<serde_json::Error>()
+            || cause.is::<std::string::FromUtf8Error>()
+            || cause.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::InvalidData)
+    })
+}
+
+/// Read and decrypt a damaged state file, replacing invalid UTF-8 (e.g. a character cut
+/// in half by truncation) so the rest can still be salvaged
+fn read_damaged_file(path: &Path) -> Result<String> {
+    let content = fs::read(path)?;
+    decode_state_content(String::from_utf8_lossy(&content).into_owned(), path)
+}
+
+/// Start from a sessions file that failed to load because it is damaged: salvage what
+/// parses, move the damaged file aside, and offer to merge in the newest backup. A file
+/// that can't be read at all is left in place and the error returned.
+pub fn recover_history(config: &Config, backups: &BackupManager, error: anyhow::Error) -> Result<History> {
+    let path = PathBuf::from(config.sessions_file());
+    let content = read_damaged_file(&path)
+        .map_err(|e| e.context(format!("{} is damaged ({:#}) and could not be read to recover it", path.display(), error)))?;
+    eprintln!("The sessions file {} is damaged: {:#}", path.display(), error);
+
+    let salvage = salvage_history(&content, config.sessions_file())?;
+    eprintln!("Salvaged {} session(s)", salvage.recovered);
+    if !salvage.lost.is_empty() {
+        eprintln!("Could not recover: {}", salvage.lost.join(", "));
+    }
+    let mut history = salvage.history;
+
+    if config.ephemeral {
+        eprintln!("Ephemeral mode: the damaged file is left in place and nothing is written");
+        return Ok(history);
+    }
+
+    let quarantine = path.with_file_name(format!("sessions.damaged-{}", Utc::now().format("%Y%m%d-%H%M%S")));
+    fs::rename(&path, &quarantine)?;
+    eprintln!("Moved the damaged file to {}", quarantine.display());
+
+    if let Some((timestamp, mut restored)) = newest_readable_backup(backups, &path) {
+        let question = format!(
+            "Restore the {} session(s) in backup {}? Salvaged sessions that are newer or missing from it are kept",
+            restored.saved_sessions.len(), timestamp
+        );
+        if confirm(&question) {
+            for (name, session) in history.saved_sessions {
+                let newer = restored.saved_sessions.get(&name).is_none_or(|backup| backup.updated_at < session.updated_at);
+                if newer {
+                    restored.saved_sessions.insert(name, session);
+                }
+            }
+            for (name, trashed) in history.trash {
+                restored.trash.entry(name).or_insert(trashed);
+            }
+            restored.current_session = history.current_session.or(restored.current_session);
+            restored.history_file_path = config.sessions_file();
+            history = restored;
+            eprintln!("Restored from backup {}", timestamp);
+        } else {
+            eprintln!("Backup {} left alone; use /backup restore to roll back later", timestamp);
+        }
+    }
+
+    history.save()?;
+    Ok(history)
+}
+
+/// One line of the doctor report
+fn report(name: &str, result: Result<String>) -> bool {
+    match result {
+        Ok(summary) => {
+            println!("  \x1b[32mok\x1b[0m       {:<14} {}", name, summary);
+            true
+        }
+        Err(e) => {
+            println!("  \x1b[1;31mproblem\x1b[0m  {:<14} {:#}", name, e);
+            false
+        }
+    }
+}
+
+fn check_sessions(path: &Path) -> Result<String> {
+    let file = path.to_string_lossy().to_string();
+    match History::load(file.clone()) {
+        Ok(history) => Ok(format!("{} session(s), {} in the trash", history.saved_sessions.len(), history.trash.len())),
+        Err(e) if !is_damaged(&e) => Err(e),
+        Err(e) => {
+            let salvage = read_damaged_file(path).and_then(|content| salvage_history(&content, file));
+            let detail = match salvage {
+                Ok(salvage) if salvage.lost.is_empty() => format!("{} session(s) can be salvaged", salvage.recovered),
+                Ok(salvage) => format!("{} session(s) can be salvaged, lost: {}", salvage.recovered, salvage.lost.join(", ")),
+                Err(_) => "nothing can be salvaged".to_string(),
+            };
+            Err(e.context(format!("damaged; {}. Start njord to recover it", detail)))
+        }
+    }
+}
+
+/// `njord doctor`: check every state file of the active state directory.
+/// Returns whether all of them are healthy.
+pub fn run_doctor(config: &Config) -> Result<bool> {
+    encryption::unlock_state_directory(&config.state_directory, config.keyfile.as_deref())?;
+
+    println!("\x1b[1;36mChecking {}\x1b[0m", config.state_directory);
+    let directory = Path::new(&config.state_directory);
+    let mut healthy = true;
+    for name in STATE_FILES {
+        let path = directory.join(name);
+        if !path.exists() {
+            println!("  \x1b[2m-        {:<14} not created yet\x1b[0m", name);
+            continue;
+        }
+        let file = path.to_string_lossy().to_string();
+        let result = match name {
+            "sessions" => check_sessions(&path),
+            "prompts" => PromptLibrary::load(file).map(|library| format!("{} prompt(s)", library.prompts.len())),
+            "inputs" => InputHistory::load(file).map(|history| format!("{} entries", history.len())),
+            "variables" => workspace::load_variables(&config.state_directory).map(|vars| format!("{} variable(s)", vars.len())),
+            TEMPLATES_FILE => match TemplateLibrary::load(&config.state_directory, None) {
+                (library, warnings) if warnings.is_empty() => Ok(format!("{} template(s)", library.names().len())),
+                (_, warnings) => Err(anyhow::anyhow!(warnings.join("; "))),
+            },
+            RETENTION_LOG => read_state_file(&path).map(|log| format!("{} entries", log.lines().count())),
+            _ => read_state_file(&path).map(|_| "readable".to_string()),
+        };
+        healthy &= report(name, result);
+    }
+
+    let backups = BackupManager::new(&config.state_directory, config.backup_count).list_backups();
+    let summary = backups.map(|backups| match backups.first() {
+        Some(newest) => format!("{} snapshot(s), newest {}", backups.len(), newest.timestamp),
+        None => "none yet".to_string(),
+    });
+    healthy &= report("backups", summary);
+    Ok(healthy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Message;
+
+    fn sessions_file(dir: &Path) -> String {
+        let mut history = History::new(dir.join("sessions").to_string_lossy().to_string());
+        for name in ["alpha", "beta", "gamma"] {
+            let mut session = ChatSession::new("gpt-4o".to_string(), 0.7, 1000, 5000);
+            session.add_message(Message { role: "user".to_string(), content: format!("About {} {{with braces}} and \"quotes\"", name) });
+            history.saved_sessions.insert(name.to_string(), session);
+        }
+        history.default_preferences.model = Some("gpt-4o".to_string());
+        history.save().unwrap();
+        fs::read_to_string(dir.join("sessions")).unwrap()
+    }
+
+    #[test]
+    fn test_salvage_truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = sessions_file(dir.path());
+        assert_eq!(salvage_history(&content, String::new()).unwrap().recovered, 3);
+
+        // Cut the file off in the middle of the last session written
+        let names: Vec<usize> = ["\"alpha\"", "\"beta\"", "\"gamma\""].iter().map(|name| content.find(name).unwrap()).collect();
+        let last = *names.iter().max().unwrap();
+        let truncated = &content[..last + 40];
+
+        let salvage = salvage_history(truncated, String::new()).unwrap();
+        assert_eq!(salvage.recovered, 2);
+        assert_eq!(salvage.history.saved_sessions.len(), 2);
+        assert_eq!(salvage.lost.len(), 1);
+        assert!(salvage.lost[0].starts_with("session '"));
+
+        let garbage = salvage_history("\0\0\0", String::new()).unwrap();
+        assert_eq!(garbage.recovered, 0);
+    }
+
+    #[test]
+    fn test_only_damaged_files_are_recovered() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = sessions_file(dir.path());
+        let path = dir.path().join("sessions");
+
+        // Cut off in the middle of a multi-byte character
+        let accent = content.find("About alpha").unwrap();
+        let mut bytes = content[..accent].as_bytes().to_vec();
+        bytes.extend_from_slice(&"é".as_bytes()[..1]);
+        fs::write(&path, &bytes).unwrap();
+        let error = History::load(path.to_string_lossy().to_string()).unwrap_err();
+        assert!(is_damaged(&error));
+        assert!(read_damaged_file(&path).unwrap().starts_with("{"));
+
+        fs::write(&path, "{\"saved_sessions\": [").unwrap();
+        assert!(is_damaged(&History::load(path.to_string_lossy().to_string()).unwrap_err()));
+
+        // A file that can't be read is not damaged, and must not be moved aside
+        let unreadable = History::load(dir.path().to_string_lossy().to_string()).unwrap_err();
+        assert!(!is_damaged(&unreadable));
+    }
+
+    #[test]
+    fn test_salvage_keeps_preferences_after_damage() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = sessions_file(dir.path());
+        // Damage one session, leaving the rest of the file intact
+        let colon = content.find("\"beta\":").unwrap() + "\"beta\":".len();
+        let start = skip_whitespace(&content, colon);
+        let end = value_end(&content, start).unwrap();
+        let damaged = format!("{}{{\"id\": 42, \"oops\": [1, 2]}}{}", &content[..start], &content[end..]);
+
+        let salvage = salvage_history(&damaged, String::new()).unwrap();
+        assert_eq!(salvage.lost, vec!["session 'beta'"]);
+        assert!(salvage.history.saved_sessions.contains_key("gamma"));
+        assert_eq!(salvage.history.default_preferences.model.as_deref(), Some("gpt-4o"));
+    }
+}
@@ -1,24 +1,56 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use futures::StreamExt;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use chrono::Utc;
-use std::path::Path;
-use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use tempfile::NamedTempFile;
 
 use crate::{
-    commands::{Command, CommandParser, CopyType, EditTarget, SaveType, SessionReference},
+    commands::{Command, CommandParser, CopyType, EditTarget, EncryptionAction, ExportTarget, MessageTarget, RetentionAction, SaveType, SessionReference, TemplateAction, WorkspaceAction},
     config::Config,
-    history::History,
-    providers::{create_provider, get_provider_for_model, LLMProvider, Message, ChatRequest},
-    session::{ChatSession, CodeBlock},
+    history::{History, SearchQuery, SearchResult, SessionFilter},
+    providers::{create_provider, get_provider_for_model, LLMProvider, Message, ChatRequest, ThinkingBlock, ThinkingCollector},
+    session::{alternate_turns, ChatSession, CodeBlock, GenerationParams, MergeResolution, MessageTiming, NumberedMessage, SystemPromptChoice},
     ui::{UI, CompletionContext},
     prompts::PromptLibrary,
     variable::{Variable, VariableSource},
+    export::{export_sessions, ExportFormat},
+    importer::{import_conversations, ImportFormat},
+    backup::BackupManager,
+    bundle::SessionBundle,
+    compare::{self, Candidate, Comparison},
+    oneshot::{self, OneShot},
+    script::{self, ScriptLine, ScriptOutcome, ScriptStep},
+    events::{self, out, outln, Event, Usage},
+    journal::{JournalEntry, Operation},
+    recovery,
+    encryption::{self, StateCipher},
+    diff::{diff_sessions, render_session_diff, terminal_width},
+    stats::{estimate_cost, estimate_tokens, render_stats, Stats},
+    template::{SessionTemplate, TemplateLibrary, TemplateOrigin},
+    workspace,
+    retention::{self, PruneAction},
+    input_history::InputHistory,
 };
 
+/// A complete reply streamed from a provider
+struct StreamedReply {
+    content: String,
+    thinking: String,
+    thinking_blocks: Vec<ThinkingBlock>,
+    timing: MessageTiming,
+}
+
+/// Which journal a /journal entry lives in
+enum JournalSource {
+    Session(usize),
+    History(usize),
+}
+
 #[derive(Debug, Clone)]
 struct CodeBlockReference {
     global_number: usize,
@@ -33,6 +65,7 @@ pub struct Repl {
     session: ChatSession,
     history: History,
     prompts: PromptLibrary,
+    templates: TemplateLibrary,
     command_parser: CommandParser,
     ui: UI,
     queued_message: Option<String>,
@@ -40,7 +73,22 @@ pub struct Repl {
     interrupted_message: Option<String>,
     ctrl_c_rx: mpsc::UnboundedReceiver<()>,
     last_session_list: Vec<String>, // For ephemeral session references
+    session_list_filter: SessionFilter, // Filter from the last /chat list, keeps #N stable
+    last_search_results: Vec<SearchResult>, // For /search #N
+    last_comparison: Option<Comparison>, // For /compare adopt N
+    sourcing: Vec<PathBuf>, // Scripts currently running, so one can't source itself
     variables: HashMap<String, Variable>, // For file content variables
+    saved_variables: HashMap<String, Variable>, // From the global and workspace variables files
+    backups: BackupManager,
+    command_failed: AtomicBool, // Set by `fail` while a command runs
+}
+
+/// How a command or message finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Done,
+    Failed,
+    Quit,
 }
 
 impl Repl {
@@ -68,8 +116,48 @@ impl Repl {
             config.ensure_state_directory()?;
         }
 
-        let history = History::load(config.sessions_file())?;
-        let prompts = PromptLibrary::load(config.prompts_file())?;
+        // Unlock encrypted state before anything reads it
+        Self::unlock_state_directories(&config)?;
+
+        // Snapshot the state files before this run touches them
+        let backups = BackupManager::new(&config.state_directory, if config.ephemeral { 0 } else { config.backup_count });
+        if let Err(e) = backups.backup_if_changed(chrono::Duration::zero()) {
+            eprintln!("Failed to back up state directory: {}", e);
+        }
+
+        let mut history = match History::load(config.sessions_file()) {
+            Ok(history) => history,
+            Err(e) if recovery::is_damaged(&e) => recovery::recover_history(&config, &backups, e)?,
+            Err(e) => return Err(e),
+        };
+        if !config.ephemeral {
+            // Sessions deleted longer ago than the retention period are gone for good
+            match history.purge_expired_trash(config.trash_retention_days) {
+                Ok(expired) if !expired.is_empty() => {
+                    eprintln!("Purged {} expired session(s) from the trash", expired.len());
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to purge expired trash: {}", e),
+            }
+            
+            if history.retention.is_enabled() {
+                let pruned = Self::apply_retention(&mut history, &config, config.load_session.as_deref(), |cutoff| {
+                    InputHistory::load(config.inputs_file())?.prune_before(cutoff)
+                });
+                match pruned {
+                    Ok(summary) if !summary.is_empty() => {
+                        eprintln!("Retention: {} (logged in {}/{})", summary.join(", "), config.state_directory, retention::RETENTION_LOG);
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Failed to apply retention policy: {}", e),
+                }
+            }
+        }
+        let prompts = Self::load_prompt_library(&config)?;
+        let (templates, warnings) = Self::load_templates(&config);
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
         
         // Always start with a fresh session unless explicitly loading one
         let mut session = if let Some(session_name) = &config.load_session {
@@ -106,9 +194,15 @@ impl Repl {
             UI::with_input_history_file(config.inputs_file())?
         };
         
+        // Saved variables are available in every session from the start
+        let (saved_variables, warnings) = Self::load_saved_variables(&config);
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
+        
         // Set up initial completion context
-        let variables = HashMap::new();
-        let completion_context = Self::build_completion_context(&providers, &history, &prompts, &variables);
+        let variables = saved_variables.clone();
+        let completion_context = Self::build_completion_context(&providers, &history, &prompts, &templates, &variables, &backups);
         ui.update_completion_context(completion_context)?;
         
         // Auto-populate ephemeral session list on startup (newest first)
@@ -120,6 +214,7 @@ impl Repl {
             session,
             history,
             prompts,
+            templates,
             command_parser,
             ui,
             queued_message: None,
@@ -127,10 +222,143 @@ impl Repl {
             interrupted_message: None,
             ctrl_c_rx,
             last_session_list,
+            session_list_filter: SessionFilter::default(),
+            last_search_results: Vec::new(),
+            last_comparison: None,
+            sourcing: Vec::new(),
             variables,
+            saved_variables,
+            backups,
+            command_failed: AtomicBool::new(false),
         })
     }
     
+    /// Prune sessions and input history according to the retention policy and log it.
+    /// `protected` (the active session) is never pruned. Returns a summary of what went.
+    fn apply_retention<F>(history: &mut History, config: &Config, protected: Option<&str>, prune_inputs: F) -> Result<Vec<String>>
+    where
+        F: FnOnce(chrono::DateTime<Utc>) -> Result<usize>,
+    {
+        let policy = history.retention.clone();
+        let mut summary = Vec::new();
+        
+        let plan = policy.plan(history, protected, Utc::now());
+        if !plan.is_empty() {
+            let pruned = retention::apply_plan(history, policy.action, &plan, &config.state_directory)?;
+            let verb = match policy.action {
+                PruneAction::Trash => "moved to the trash",
+                PruneAction::Archive => "archived",
+            };
+            summary.push(format!("{} session(s) {}", pruned.len(), verb));
+        }
+        
+        if let Some(cutoff) = policy.input_cutoff(Utc::now()) {
+            let removed = prune_inputs(cutoff)?;
+            if removed > 0 {
+                retention::log_input_pruning(&config.state_directory, removed, policy.input_max_age_days)?;
+                summary.push(format!("{} input history entries removed", removed));
+            }
+        }
+        Ok(summary)
+    }
+    
+    /// Unlock the active state directory and, inside a workspace, the global one layered under it
+    fn unlock_state_directories(config: &Config) -> Result<()> {
+        encryption::unlock_state_directory(&config.state_directory, config.keyfile.as_deref())?;
+        if config.workspace.is_some() {
+            encryption::unlock_state_directory(&config.global_state_directory, config.keyfile.as_deref())?;
+        }
+        Ok(())
+    }
+    
+    /// The active prompt library; inside a workspace the global prompts are layered underneath
+    fn load_prompt_library(config: &Config) -> Result<PromptLibrary> {
+        let mut prompts = PromptLibrary::load(config.prompts_file())?;
+        if config.workspace.is_some() {
+            match PromptLibrary::load(format!("{}/prompts", config.global_state_directory)) {
+                Ok(global) => prompts.inherit(global),
+                Err(e) => eprintln!("Failed to load global prompts: {}", e),
+            }
+        }
+        Ok(prompts)
+    }
+    
+    /// Session templates; inside a workspace the global ones are layered underneath
+    fn load_templates(config: &Config) -> (TemplateLibrary, Vec<String>) {
+        let global = config.workspace.is_some().then_some(config.global_state_directory.as_str());
+        TemplateLibrary::load(&config.state_directory, global)
+    }
+    
+    fn load_saved_variables(config: &Config) -> (HashMap<String, Variable>, Vec<String>) {
+        let mut layers = vec![config.global_state_directory.as_str()];
+        if config.workspace.is_some() {
+            layers.push(config.state_directory.as_str());
+        }
+        workspace::layered_variables(&layers)
+    }
+    
+    fn workspace_scope(&self) -> String {
+        match &self.config.workspace {
+            Some(dir) => format!("workspace '{}'", workspace::display_name(Path::new(dir))),
+            None => "global state".to_string(),
+        }
+    }
+    
+    /// Move to another state directory. The current session is saved where it belongs,
+    /// then sessions, prompts, input history, variables and backups come from the new one.
+    fn switch_workspace(&mut self, workspace: Option<PathBuf>) -> Result<()> {
+        let mut config = self.config.clone();
+        config.set_workspace(workspace);
+        if config.state_directory == self.config.state_directory {
+            self.ui.print_info("Already using this workspace");
+            return Ok(());
+        }
+        if !config.ephemeral {
+            config.ensure_state_directory()?;
+        }
+        
+        // Load before changing anything, so a failure leaves the current workspace in place
+        Self::unlock_state_directories(&config)?;
+        let history = History::load(config.sessions_file())?;
+        let prompts = Self::load_prompt_library(&config)?;
+        
+        if let Err(e) = self.history.auto_save_session(&self.session) {
+            self.ui.print_error(&format!("Failed to auto-save current session: {}", e));
+        }
+        self.ui.switch_input_history(config.inputs_file())?;
+        self.backups = BackupManager::new(&config.state_directory, if config.ephemeral { 0 } else { config.backup_count });
+        if let Err(e) = self.backups.backup_if_changed(chrono::Duration::zero()) {
+            self.ui.print_error(&format!("Failed to back up state directory: {}", e));
+        }
+        
+        self.config = config;
+        self.history = history;
+        self.prompts = prompts;
+        self.reload_templates();
+        
+        // Like /chat new, loaded variables carry over; only the saved layer is swapped
+        let (saved_variables, warnings) = Self::load_saved_variables(&self.config);
+        for warning in warnings {
+            self.ui.print_error(&warning);
+        }
+        let previous_saved = std::mem::replace(&mut self.saved_variables, saved_variables);
+        for name in previous_saved.keys() {
+            if !self.session.variables.contains_key(name) {
+                self.variables.remove(name);
+            }
+        }
+        self.variables.extend(self.saved_variables.clone());
+        
+        self.session = Self::create_session_with_defaults(&self.config, &self.history);
+        self.session.current_provider = get_provider_for_model(&self.session.current_model).map(|s| s.to_string());
+        
+        self.session_list_filter = SessionFilter::default();
+        self.update_session_list();
+        self.last_search_results.clear();
+        let _ = self.update_completion_context();
+        Ok(())
+    }
+    
     fn find_default_model(providers: &HashMap<String, Box<dyn LLMProvider>>) -> String {
         // Prefer Anthropic, then OpenAI, then Gemini
         if providers.contains_key("anthropic") {
@@ -172,7 +400,7 @@ impl Repl {
         get_provider_for_model(&self.session.current_model)
     }
     
-    fn build_completion_context(providers: &HashMap<String, Box<dyn LLMProvider>>, history: &History, prompts: &PromptLibrary, variables: &HashMap<String, Variable>) -> CompletionContext {
+    fn build_completion_context(providers: &HashMap<String, Box<dyn LLMProvider>>, history: &History, prompts: &PromptLibrary, templates: &TemplateLibrary, variables: &HashMap<String, Variable>, backups: &BackupManager) -> CompletionContext {
         let mut available_models = Vec::new();
         
         // Collect all models from all providers
@@ -185,28 +413,50 @@ impl Repl {
         
         // Get session names
         let session_names = history.list_sessions().into_iter().cloned().collect();
+        let session_tags = history.all_tags();
+        let trashed_session_names = history.list_trash().into_iter().map(|(name, _)| name.clone()).collect();
         
         // Get prompt names
         let prompt_names = prompts.list_prompts().into_iter().cloned().collect();
+        let template_names = templates.names();
         
         // Get variable names
         let variable_names = variables.keys().cloned().collect();
         
+        let backup_timestamps = backups.list_backups()
+            .map(|list| list.into_iter().map(|backup| backup.timestamp).collect())
+            .unwrap_or_default();
+        
         CompletionContext {
             available_models,
             session_names,
+            session_tags,
+            trashed_session_names,
             prompt_names,
+            template_names,
             variable_names,
+            backup_timestamps,
+            bookmark_names: Vec::new(), // Filled in from the current session by update_completion_context
         }
     }
     
     fn update_completion_context(&mut self) -> Result<()> {
-        let context = Self::build_completion_context(&self.providers, &self.history, &self.prompts, &self.variables);
+        let mut context = Self::build_completion_context(&self.providers, &self.history, &self.prompts, &self.templates, &self.variables, &self.backups);
+        context.bookmark_names = self.session.bookmark_names();
         self.ui.update_completion_context(context)
     }
     
+    /// Re-read templates so edits to template files are picked up
+    fn reload_templates(&mut self) {
+        let (templates, warnings) = Self::load_templates(&self.config);
+        for warning in warnings {
+            self.ui.print_error(&warning);
+        }
+        self.templates = templates;
+    }
+    
     fn update_session_list(&mut self) {
-        self.last_session_list = self.history.list_sessions().iter().map(|s| s.to_string()).collect();
+        self.last_session_list = self.history.filter_sessions(&self.session_list_filter).iter().map(|s| s.to_string()).collect();
     }
     
     fn get_all_code_blocks(&self) -> Vec<CodeBlockReference> {
@@ -292,7 +542,7 @@ impl Repl {
         
         // Emit OSC52 escape sequence: \033]52;c;<base64>\033\\
         // 'c' means clipboard (as opposed to 'p' for primary selection)
-        print!("\x1b]52;c;{}\x1b\\", encoded);
+        out!("\x1b]52;c;{}\x1b\\", encoded);
         std::io::stdout().flush().unwrap_or(());
         
         true // OSC52 emission always "succeeds" (we can't know if terminal supports it)
@@ -321,8 +571,8 @@ impl Repl {
         } else {
             self.ui.print_error("Failed to copy to clipboard");
             self.ui.print_info("Content displayed below:");
-            println!();
-            println!("{}", content);
+            outln!();
+            outln!("{}", content);
         }
         
         // Save input history on exit
@@ -345,10 +595,10 @@ impl Repl {
                     .output()?;
                 
                 if !output.stdout.is_empty() {
-                    println!("Output:\n{}", String::from_utf8_lossy(&output.stdout));
+                    outln!("Output:\n{}", String::from_utf8_lossy(&output.stdout));
                 }
                 if !output.stderr.is_empty() {
-                    println!("Error:\n{}", String::from_utf8_lossy(&output.stderr));
+                    outln!("Error:\n{}", String::from_utf8_lossy(&output.stderr));
                 }
                 self.ui.print_info(&format!("Process exited with code: {}", output.status.code().unwrap_or(-1)));
             }
@@ -360,10 +610,10 @@ impl Repl {
                     .output()?;
                 
                 if !output.stdout.is_empty() {
-                    println!("Output:\n{}", String::from_utf8_lossy(&output.stdout));
+                    outln!("Output:\n{}", String::from_utf8_lossy(&output.stdout));
                 }
                 if !output.stderr.is_empty() {
-                    println!("Error:\n{}", String::from_utf8_lossy(&output.stderr));
+                    outln!("Error:\n{}", String::from_utf8_lossy(&output.stderr));
                 }
                 self.ui.print_info(&format!("Process exited with code: {}", output.status.code().unwrap_or(-1)));
             }
@@ -378,10 +628,10 @@ impl Repl {
                     .output()?;
                 
                 if !output.stdout.is_empty() {
-                    println!("Output:\n{}", String::from_utf8_lossy(&output.stdout));
+                    outln!("Output:\n{}", String::from_utf8_lossy(&output.stdout));
                 }
                 if !output.stderr.is_empty() {
-                    println!("Error:\n{}", String::from_utf8_lossy(&output.stderr));
+                    outln!("Error:\n{}", String::from_utf8_lossy(&output.stderr));
                 }
                 self.ui.print_info(&format!("Process exited with code: {}", output.status.code().unwrap_or(-1)));
             }
@@ -394,6 +644,79 @@ impl Repl {
         Ok(())
     }
     
+    /// Answer a single prompt without the interactive loop, streaming the reply to stdout as
+    /// plain text. With --load-session the exchange is appended to that session.
+    pub async fn ask(&mut self, oneshot: OneShot) -> Result<()> {
+        if let Some(model) = oneshot.model {
+            let provider = get_provider_for_model(&model)
+                .ok_or_else(|| anyhow::anyhow!("Unknown model: {}", model))?;
+            if !self.providers.contains_key(provider) {
+                return Err(anyhow::anyhow!("{} needs a {} API key", model, provider));
+            }
+            self.session.current_model = model;
+            self.session.current_provider = Some(provider.to_string());
+        }
+        if let Some(name) = &oneshot.apply_prompt {
+            let content = self.prompts.apply_prompt(name)
+                .ok_or_else(|| anyhow::anyhow!("Prompt '{}' not found", name))?;
+            self.session.system_prompt = Some(content);
+        }
+        if let Some(system) = oneshot.system {
+            self.session.system_prompt = Some(system);
+        }
+        
+        let provider_name = self.get_current_provider()
+            .filter(|name| self.providers.contains_key(*name))
+            .ok_or_else(|| anyhow::anyhow!("No provider available for {}", self.session.current_model))?
+            .to_string();
+        let context = oneshot::read_piped_stdin()?;
+        let user_message = Message {
+            role: "user".to_string(),
+            content: oneshot::compose_prompt(&oneshot.prompt, context.as_deref())?,
+        };
+        let (messages, thinking_blocks, params) = self.prepare_request_messages(&user_message);
+        let request = ChatRequest {
+            messages,
+            thinking_blocks,
+            model: self.session.current_model.clone(),
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            thinking_budget: params.thinking_budget,
+            stream: true,
+            thinking: params.thinking_enabled,
+        };
+        
+        let agent_number = self.get_next_agent_number();
+        let provider = self.providers.get(&provider_name).expect("provider checked above");
+        let candidate = compare::collect_with(provider.as_ref(), &provider_name, request, |text| {
+            if events::is_structured() {
+                events::emit(Event::ContentDelta { number: agent_number, text: text.to_string() });
+            } else {
+                out!("{}", text);
+                let _ = std::io::stdout().flush();
+            }
+        }).await?;
+        if !events::is_structured() && !candidate.content.ends_with('\n') {
+            outln!();
+        }
+        
+        // Recorded either way so the reply is reported; only kept with --load-session
+        let model = candidate.model.clone();
+        let reply = StreamedReply {
+            content: candidate.content,
+            thinking: candidate.thinking,
+            thinking_blocks: candidate.thinking_blocks,
+            timing: candidate.timing,
+        };
+        self.record_exchange(user_message, reply, Some(candidate.provider), model, params);
+        if let Some(name) = self.config.load_session.clone() {
+            if !self.config.ephemeral {
+                self.history.save_session(name, self.session.clone())?;
+            }
+        }
+        Ok(())
+    }
+    
     pub async fn run(&mut self) -> Result<()> {
         self.ui.draw_welcome()?;
         
@@ -408,6 +731,11 @@ impl Repl {
         }
         
         loop {
+            // Take a periodic backup if the state changed since the last one
+            if let Err(e) = self.backups.backup_if_changed(chrono::Duration::minutes(self.config.backup_interval as i64)) {
+                self.ui.print_error(&format!("Automatic backup failed: {}", e));
+            }
+            
             // Determine what message to show in prompt
             let prompt_message = if let Some(interrupted) = &self.interrupted_message {
                 Some((interrupted.as_str(), "interrupted"))
@@ -481,33 +809,21 @@ impl Repl {
 
                 if input.starts_with('/') {
                     // Check if this is a heredoc command
-                    if let Some((command_part, delimiter)) = self.ui.parse_command_heredoc(&input) {
+                    if let Some((command_part, delimiter)) = UI::parse_command_heredoc(&input) {
                         // This is a heredoc command - read the multi-line content
                         match self.ui.read_command_heredoc(&delimiter, session_name, is_anonymous, self.config.ephemeral, user_msg_number) {
                             Ok(content) => {
                                 // Handle heredoc commands specially - don't reconstruct as single line
                                 // Instead, parse the command part and handle the content directly
                                 if let Some(command) = self.command_parser.parse(&command_part) {
-                                    // For commands that support heredoc, we need to modify them to accept the content
-                                    let modified_command = match command {
-                                        Command::System(_) => {
-                                            Command::System(content)
-                                        }
-                                        Command::PromptsSave(name, _) => {
-                                            Command::PromptsSave(name, Some(content))
-                                        }
-                                        _ => {
-                                            self.ui.print_error(&format!("Command '{}' does not support heredoc syntax", command_part));
-                                            continue;
-                                        }
+                                    let Some(modified_command) = Self::with_heredoc_content(command, content) else {
+                                        self.ui.print_error(&format!("Command '{}' does not support heredoc syntax", command_part));
+                                        continue;
                                     };
                                     
                                     match self.handle_command(modified_command).await {
-                                        Ok(should_continue) => {
-                                            if !should_continue {
-                                                break;
-                                            }
-                                        }
+                                        Ok(Status::Quit) => break,
+                                        Ok(_) => {}
                                         Err(e) => {
                                             self.ui.print_error(&e.to_string());
                                         }
@@ -524,11 +840,8 @@ impl Repl {
                         // Regular command processing
                         if let Some(command) = self.command_parser.parse(&input) {
                             match self.handle_command(command).await {
-                                Ok(should_continue) => {
-                                    if !should_continue {
-                                        break;
-                                    }
-                                }
+                                Ok(Status::Quit) => break,
+                                Ok(_) => {}
                                 Err(e) => {
                                     self.ui.print_error(&e.to_string());
                                 }
@@ -563,59 +876,140 @@ impl Repl {
         Ok(())
     }
     
+    /// For commands that support heredoc, the command with the heredoc content as its argument
+    fn with_heredoc_content(command: Command, content: String) -> Option<Command> {
+        match command {
+            Command::System(_) => Some(Command::System(content)),
+            Command::PromptsSave(name, _) => Some(Command::PromptsSave(name, Some(content))),
+            _ => None,
+        }
+    }
+    
+    /// Run the commands and messages in a script file, echoing each one as it runs.
+    /// Stops at the first command or message that fails unless `keep_going` is set.
+    pub async fn run_script(&mut self, path: &str, keep_going: bool) -> Result<ScriptOutcome> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read script '{}': {}", path, e))?;
+        let canonical = std::fs::canonicalize(path)?;
+        if self.sourcing.contains(&canonical) {
+            return Err(anyhow::anyhow!("Script '{}' is already running; refusing to source it again", path));
+        }
+        let steps = script::parse_script(&text).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        
+        // A Ctrl-C from before the script started shouldn't stop it
+        while self.ctrl_c_rx.try_recv().is_ok() {}
+        self.sourcing.push(canonical);
+        let mut outcome = ScriptOutcome::default();
+        for ScriptLine { line, source, step } in steps {
+            if events::is_structured() {
+                events::emit(Event::Step { line, input: source });
+            } else {
+                outln!("\x1b[2m> {}\x1b[0m", source);
+            }
+            let result = match step {
+                ScriptStep::Command(input) => match self.command_parser.parse(&input) {
+                    Some(command) => Box::pin(self.handle_command(command)).await,
+                    None => Err(anyhow::anyhow!("Invalid command: '{}'", input)),
+                },
+                ScriptStep::HeredocCommand(input, content) => {
+                    match self.command_parser.parse(&input).and_then(|command| Self::with_heredoc_content(command, content)) {
+                        Some(command) => Box::pin(self.handle_command(command)).await,
+                        None => Err(anyhow::anyhow!("Command '{}' does not support heredoc syntax", input)),
+                    }
+                }
+                ScriptStep::Message(message) => self.handle_message(message).await,
+            };
+            let status = result.unwrap_or_else(|e| {
+                self.ui.print_error(&e.to_string());
+                Status::Failed
+            });
+            if let Err(e) = self.history.auto_save_session(&self.session) {
+                self.ui.print_error(&format!("Failed to auto-save session: {}", e));
+            }
+            events::flush_text();
+            
+            // A failed message isn't queued for retry as it would be at the prompt
+            self.queued_message = None;
+            if self.interrupted_message.take().is_some() || self.ctrl_c_rx.try_recv().is_ok() {
+                self.ui.print_error(&format!("Script interrupted at line {} of {}", line, path));
+                outcome.failed += 1;
+                break;
+            }
+            if status == Status::Quit {
+                outcome.quit = true;
+                break;
+            }
+            if status == Status::Failed {
+                outcome.failed += 1;
+                if !keep_going {
+                    self.ui.print_error(&format!("Script stopped at line {} of {} (use --keep-going to continue past errors)", line, path));
+                    break;
+                }
+            }
+        }
+        self.sourcing.pop();
+        if keep_going && outcome.failed > 0 {
+            self.ui.print_error(&format!("{} step(s) of {} failed", outcome.failed, path));
+        }
+        Ok(outcome)
+    }
+    
     fn display_startup_status(&self) {
         if let Some(provider_name) = self.get_current_provider() {
-            println!("\x1b[1;36mCurrent Configuration:\x1b[0m");
-            println!("  Provider: {}", provider_name);
-            println!("  Model: {}", self.session.current_model);
+            outln!("\x1b[1;36mCurrent Configuration:\x1b[0m");
+            outln!("  Provider: {}", provider_name);
+            outln!("  Model: {}", self.session.current_model);
             
             // Show temperature with capability check
             let temp_display = self.get_temperature_display();
-            println!("  Temperature: {}", temp_display);
+            outln!("  Temperature: {}", temp_display);
             
             if let Some(system_prompt) = &self.session.system_prompt {
-                println!("  System prompt: {}", system_prompt);
+                outln!("  System prompt: {}", system_prompt);
             } else {
-                println!("  System prompt: (none)");
+                outln!("  System prompt: (none)");
             }
             
             // Show thinking with capability check
             let thinking_display = self.get_thinking_display();
-            println!("  Thinking: {}", thinking_display);
+            outln!("  Thinking: {}", thinking_display);
             
-            println!("  Max tokens: {}", self.session.max_tokens);
-            println!("  Thinking budget: {}", self.session.thinking_budget);
-            println!("  State directory: {}", self.config.state_directory);
+            outln!("  Max tokens: {}", self.session.max_tokens);
+            outln!("  Thinking budget: {}", self.session.thinking_budget);
+            outln!("  State directory: {}", self.config.state_directory);
+            if let Some(dir) = &self.config.workspace {
+                outln!("  Workspace: {}", workspace::display_name(Path::new(dir)));
+            }
             
             // Show session info if we have messages
             if !self.session.messages.is_empty() {
-                println!("  Session: {} messages", self.session.messages.len());
+                outln!("  Session: {} messages", self.session.messages.len());
                 if let Some(name) = &self.session.name {
-                    println!("  Session name: {}", name);
+                    outln!("  Session name: {}", name);
                 }
             } else {
-                println!("  Session: new");
+                outln!("  Session: new");
             }
         } else {
-            println!("\x1b[1;31mNo provider available\x1b[0m");
+            outln!("\x1b[1;31mNo provider available\x1b[0m");
         }
-        println!();
+        outln!();
     }
     
     fn display_recent_sessions(&self) {
         let recent_sessions = self.history.get_recent_sessions(3);
         if !recent_sessions.is_empty() {
-            println!("\x1b[1;36mRecent sessions:\x1b[0m");
+            outln!("\x1b[1;36mRecent sessions:\x1b[0m");
             for (index, (name, session)) in recent_sessions.iter().enumerate() {
                 let message_count = session.messages.len();
                 let updated = session.updated_at.format("%m-%d %H:%M");
-                println!("  #{}: /chat load {} - {} messages ({})", 
+                outln!("  #{}: /chat load {} - {} messages ({})", 
                     index + 1, name, message_count, updated);
             }
             if let Some(_most_recent) = self.history.get_most_recent_session() {
-                println!("  /chat continue - Continue most recent session");
+                outln!("  /chat continue - Continue most recent session");
             }
-            println!();
+            outln!();
         }
     }
     
@@ -759,6 +1153,48 @@ impl Repl {
         None
     }
 
+    fn message_target_index(&self, target: &MessageTarget) -> Option<usize> {
+        match target {
+            MessageTarget::User(number) => self.get_user_message_by_number(*number).map(|(index, _)| index),
+            MessageTarget::Agent(number) => self.get_agent_message_index_by_number(*number),
+        }
+    }
+    
+    /// How message `index` is numbered in /history
+    fn message_target_at(&self, index: usize) -> MessageTarget {
+        let messages = &self.session.messages[..=index];
+        if messages[index].message.role == "user" {
+            MessageTarget::User(messages.iter().filter(|msg| msg.message.role == "user").count())
+        } else {
+            MessageTarget::Agent(messages.iter().filter(|msg| msg.message.role != "user").count())
+        }
+    }
+    
+    /// Bookmark and context flags shown after a message header in /history
+    fn message_labels(msg: &NumberedMessage) -> String {
+        let mut labels = msg.bookmark.as_ref().map(|name| format!(" \x1b[1;33m[{}]\x1b[0m", name)).unwrap_or_default();
+        if msg.pinned {
+            labels.push_str(" \x1b[1;35m(pinned)\x1b[0m");
+        }
+        if msg.hidden {
+            labels.push_str(" \x1b[2m(hidden from the model)\x1b[0m");
+        }
+        labels
+    }
+    
+    /// Thinking shown above an agent message in /history --thinking
+    fn print_thinking(msg: &NumberedMessage) {
+        if let Some(thinking) = &msg.thinking {
+            outln!("\x1b[2;3mThinking: {}\x1b[0m", thinking.trim_end());
+        }
+    }
+    
+    fn print_note(msg: &NumberedMessage) {
+        if let Some(note) = &msg.note {
+            outln!("\x1b[2mNote: {}\x1b[0m", note);
+        }
+    }
+    
     fn get_agent_message_index_by_number(&self, agent_number: usize) -> Option<usize> {
         let mut agent_count = 0;
         for (i, msg) in self.session.messages.iter().enumerate() {
@@ -833,6 +1269,83 @@ impl Repl {
         None
     }
     
+    /// Apply a metadata change (tags, description) to the active session or a saved one.
+    /// Returns a label describing the session that was changed.
+    fn update_session_metadata<F>(&mut self, session_ref: Option<&SessionReference>, update: F) -> Result<String>
+    where
+        F: Fn(&mut ChatSession),
+    {
+        let target_name = match session_ref {
+            Some(session_ref) => Some(self.resolve_session_reference(session_ref)?),
+            None => None,
+        };
+
+        // Keep the active session in sync so auto-save doesn't undo the change
+        let is_active = target_name.is_none() || target_name == self.session.name;
+        if is_active {
+            update(&mut self.session);
+        }
+
+        let saved_name = target_name.or_else(|| self.session.name.clone());
+        if let Some(name) = &saved_name {
+            let found = self.history.update_session(name, &update)?;
+            if !found && !is_active {
+                return Err(anyhow::anyhow!("Session '{}' not found", name));
+            }
+            Ok(format!("'{}'", name))
+        } else {
+            Ok("current session".to_string())
+        }
+    }
+    
+    /// Resolve a reference against the trash; #N follows the `/chat trash` numbering
+    fn resolve_trash_reference(&self, session_ref: &SessionReference) -> Result<String> {
+        match session_ref {
+            SessionReference::Named(name) => Ok(name.clone()),
+            SessionReference::Ephemeral(number) => {
+                let trashed = self.history.list_trash();
+                if *number == 0 || *number > trashed.len() {
+                    Err(anyhow::anyhow!("Invalid trash reference #{}", number))
+                } else {
+                    Ok(trashed[*number - 1].0.clone())
+                }
+            }
+            SessionReference::Invalid(error) => {
+                Err(anyhow::anyhow!("{}", error))
+            }
+        }
+    }
+    
+    /// Build a cipher from a keyfile, or from a passphrase typed twice
+    fn read_new_cipher(keyfile: Option<&str>) -> Result<StateCipher> {
+        if let Some(keyfile) = keyfile {
+            return StateCipher::from_keyfile(keyfile);
+        }
+        let passphrase = rpassword::prompt_password("New passphrase: ")?;
+        let confirmation = rpassword::prompt_password("Repeat passphrase: ")?;
+        if passphrase != confirmation {
+            return Err(anyhow::anyhow!("Passphrases do not match"));
+        }
+        StateCipher::from_passphrase(&passphrase)
+    }
+    
+    /// Re-encrypt state files and backups under a new cipher (None decrypts them), then
+    /// make it the active cipher. Returns the number of backup files rewritten.
+    fn rekey_state(&mut self, new_cipher: Option<std::sync::Arc<StateCipher>>) -> Result<usize> {
+        // Keep a copy of the state as it was, in case the new key gets lost
+        self.backups.create_backup()?;
+        // Convert everything before writing anything, so a failure leaves it all under the old key
+        let current = encryption::state_cipher(&self.config.state_directory);
+        let mut writes = self.backups.stage_transform_backups(|content| {
+            encryption::reencrypt(content, current.as_deref(), new_cipher.as_deref())
+        })?;
+        let count = writes.len();
+        writes.extend(encryption::stage_rekey_state_files(&self.config.state_directory, new_cipher.as_deref())?);
+        encryption::commit_writes(&writes)?;
+        encryption::set_state_cipher(&self.config.state_directory, new_cipher);
+        Ok(count)
+    }
+    
     fn resolve_session_reference(&self, session_ref: &SessionReference) -> Result<String> {
         match session_ref {
             SessionReference::Named(name) => Ok(name.clone()),
@@ -849,141 +1362,245 @@ impl Repl {
         }
     }
     
-    async fn handle_command(&mut self, command: Command) -> Result<bool> {
+    /// Run a command and report whether it failed, so a script can stop there.
+    /// Warnings printed along the way don't count; only messages printed with `fail` do.
+    async fn handle_command(&mut self, command: Command) -> Result<Status> {
+        self.command_failed.store(false, Ordering::Relaxed);
+        let keep_running = self.run_command(command).await?;
+        Ok(if !keep_running {
+            Status::Quit
+        } else if self.command_failed.swap(false, Ordering::Relaxed) {
+            Status::Failed
+        } else {
+            Status::Done
+        })
+    }
+    
+    /// Print an error that means the running command failed
+    fn fail(&self, message: &str) {
+        self.ui.print_error(message);
+        self.command_failed.store(true, Ordering::Relaxed);
+    }
+    
+    async fn run_command(&mut self, command: Command) -> Result<bool> {
         match command {
             Command::Quit => return Ok(false),
             Command::Help => {
                 self.ui.print_info("Available commands:");
-                println!();
+                outln!();
                 
                 // Basic Commands
-                println!("\x1b[1;36mBasic Commands:\x1b[0m");
-                println!("  /help - Show all commands");
-                println!("  /status - Show current configuration");
-                println!("  /models - List available models across all providers");
-                println!("  /quit - Exit Njord");
-                println!();
+                outln!("\x1b[1;36mBasic Commands:\x1b[0m");
+                outln!("  /help - Show all commands");
+                outln!("  /status - Show current configuration");
+                outln!("  /models - List available models across all providers");
+                outln!("  /quit - Exit Njord");
+                outln!();
                 
                 // Session Management
-                println!("\x1b[1;36mSession Management:\x1b[0m");
-                println!("  /chat new - Start a new chat session");
-                println!("  /chat save NAME - Save current session with given name");
-                println!("  /chat name NAME - Name the current session (replaces any existing name)");
-                println!("  /chat load NAME|#N - Replace current session history with copy of saved session");
-                println!("  /chat continue [NAME|#N] - Continue most recent or specified session");
-                println!("  /chat list - List all saved sessions with ephemeral numbers");
-                println!("  /chat recent - Show recent sessions");
-                println!("  /chat delete [NAME|#N] - Delete a saved session (defaults to current)");
-                println!("  /chat fork NAME - Create copy of current session and activate it");
-                println!("  /chat branch SOURCE_SESSION [NEW_NAME] - Create new session from existing one");
-                println!("  /chat rename NEW_NAME [OLD_NAME] - Rename a session");
-                println!("  /chat auto-rename [NAME] - Auto-generate title for session");
-                println!("  /chat auto-rename-all - Auto-generate titles for all anonymous sessions");
-                println!("  /summarize [NAME] - Generate summary of session");
-                println!();
+                outln!("\x1b[1;36mSession Management:\x1b[0m");
+                outln!("  /chat new - Start a new chat session");
+                outln!("  /chat new --template NAME - Start a new session from a template");
+                outln!("  /chat save NAME - Save current session with given name");
+                outln!("  /chat name NAME - Name the current session (replaces any existing name)");
+                outln!("  /chat load NAME|#N - Replace current session history with copy of saved session");
+                outln!("  /chat continue [NAME|#N] - Continue most recent or specified session");
+                outln!("  /chat list [OPTIONS] - List saved sessions with ephemeral numbers");
+                outln!("    Options: --tag TAG, --model M, --provider P, --since DATE, --until DATE, --name PATTERN,");
+                outln!("             --sort updated|created|name|messages, --reverse, --limit N, --page N");
+                outln!("    \x1b[1;32mEx:\x1b[0m /chat list --tag work --since 7d --limit 10");
+                outln!("  /chat recent - Show recent sessions");
+                outln!("  /chat delete [NAME|#N] - Move a saved session to the trash (defaults to current)");
+                outln!("  /chat trash - List deleted sessions with ephemeral numbers");
+                outln!("  /chat restore NAME|#N [--as NEW_NAME] - Restore a session from the trash");
+                outln!("  /chat purge [--yes] [NAME|#N] - Permanently delete a trashed session (or empty the trash, after asking)");
+                outln!("  /chat fork NAME - Create copy of current session and activate it");
+                outln!("  /chat branch SOURCE_SESSION [NEW_NAME] - Create new session from existing one");
+                outln!("  /chat diff A [B] - Show where two sessions diverge, side by side (B defaults to current)");
+                outln!("  /chat merge SOURCE [TARGET] [--append|--interleave] - Merge SOURCE's messages into TARGET (defaults to current)");
+                outln!("  /chat rename NEW_NAME [OLD_NAME] - Rename a session");
+                outln!("  /chat auto-rename [NAME] - Auto-generate title for session");
+                outln!("  /chat auto-rename-all - Auto-generate titles for all anonymous sessions");
+                outln!("  /chat tag TAG... [--session NAME|#N] - Add tags to a session (defaults to current)");
+                outln!("  /chat untag TAG... [--session NAME|#N] - Remove tags from a session");
+                outln!("  /chat describe [--session NAME|#N] [TEXT] - Show or set a session description ('clear' removes it)");
+                outln!("  /chat import chatgpt|claude PATH - Import conversations.json, export folder or .zip");
+                outln!("  /chat share NAME FILE [--strip-secrets] - Write a session, its variable values, files and prompts to one file");
+                outln!("  /chat receive FILE [--allow-commands] [--as NAME] - Add a shared session (command variables become their shared output unless --allow-commands)");
+                outln!("  /summarize [NAME] - Generate summary of session");
+                outln!();
                 
                 // Message Navigation
-                println!("\x1b[1;36mMessage Navigation:\x1b[0m");
-                println!("  /history [--expand]- Show conversation history");
-                println!("  /undo [N] - Undo last N agent responses (restores user message for editing)");
-                println!("  /goto N - Jump back to User N (removes later messages and queues user message for editing)");
-                println!("  /search TERM - Search through chat history");
-                println!("  /retry - Regenerate last response");
-                println!("  /edit - Open $EDITOR to compose a new message");
-                println!("  /edit N - Edit user message N in $EDITOR (modifies history)");
-                println!("  /edit user N - Edit user message N in $EDITOR (explicit)");
-                println!("  /edit agent N - Edit agent message N in $EDITOR");
-                println!();
+                outln!("\x1b[1;36mMessage Navigation:\x1b[0m");
+                outln!("  /history [--expand] [--thinking] - Show conversation history, optionally with recorded thinking");
+                outln!("  /undo [N] - Undo last N agent responses (restores user message for editing)");
+                outln!("  /goto N - Jump back to User N (removes later messages and queues user message for editing)");
+                outln!("  /goto NAME - Jump to a bookmark (keeps a bookmarked agent response, stages a bookmarked user message)");
+                outln!("  /redo - Restore the messages removed by the last /undo or /goto");
+                outln!("  /mark [user|agent] N NAME - Bookmark Agent N (or User N) as NAME");
+                outln!("  /unmark NAME - Remove a bookmark");
+                outln!("  /marks - List the bookmarks and notes in this session");
+                outln!("  /note [user|agent] N [TEXT] - Attach a note to Agent N (or User N); without TEXT, remove it");
+                outln!("  /hide [user|agent] N - Stop sending Agent N (or User N) to the model, keeping it in the transcript");
+                outln!("  /unhide [user|agent] N - Send a hidden message to the model again");
+                outln!("  /pin [user|agent] N / /unpin [user|agent] N - Keep a message (and a reply's question) from being hidden / stop pinning it; the context is never compacted");
+                outln!("  /inspect N - Show the model, temperature, token limits, thinking, system prompt and variables behind Agent N");
+                outln!("  /reproduce N - Re-run the request behind Agent N with the same settings and compare the answers");
+                outln!("  /journal - List recent destructive operations (undo, goto, edit, chat delete/rename)");
+                outln!("  /journal revert N - Reverse operation #N from the journal");
+                outln!("  /search QUERY - Search through chat history");
+                outln!("    Filters: /regex/ role:user|assistant model:M session:NAME mark:NAME before:DATE after:DATE in:code");
+                outln!("  /search #N - Open result N from the last search in its session");
+                outln!("  /retry - Regenerate last response");
+                outln!("  /edit - Open $EDITOR to compose a new message");
+                outln!("  /edit N - Edit user message N in $EDITOR (modifies history)");
+                outln!("  /edit user N - Edit user message N in $EDITOR (explicit)");
+                outln!("  /edit agent N - Edit agent message N in $EDITOR");
+                outln!();
                 
                 // Content Management
-                println!("\x1b[1;36mContent Management:\x1b[0m");
-                println!("  /blocks - List all code blocks in session");
-                println!("  /block N - Display code block N");
-                println!("  /copy [TYPE] [N] - Copy message/block to clipboard");
-                println!("    \x1b[1;32mEx:\x1b[0m /copy - Copy most recent agent response");
-                println!("    \x1b[1;32mEx:\x1b[0m /copy agent 2 - Copy Agent #2 response");
-                println!("    \x1b[1;32mEx:\x1b[0m /copy user 1 - Copy User #1 message");
-                println!("    \x1b[1;32mEx:\x1b[0m /copy block 3 - Copy code block #3");
-                println!("  /save [TYPE] [N] FILE - Save message/block to file");
-                println!("    \x1b[1;32mEx:\x1b[0m /save response.md - Save most recent agent response");
-                println!("    \x1b[1;32mEx:\x1b[0m /save agent 2 analysis.md - Save Agent #2 response");
-                println!("    \x1b[1;32mEx:\x1b[0m /save user 1 question.txt - Save User #1 message");
-                println!("    \x1b[1;32mEx:\x1b[0m /save block 3 code.py - Save code block #3");
-                println!("  /exec N - Execute code block N (with confirmation)");
-                println!("  /export FORMAT - Export chat (markdown, json, txt)");
-                println!();
+                outln!("\x1b[1;36mContent Management:\x1b[0m");
+                outln!("  /blocks - List all code blocks in session");
+                outln!("  /block N - Display code block N");
+                outln!("  /copy [TYPE] [N] - Copy message/block to clipboard");
+                outln!("    \x1b[1;32mEx:\x1b[0m /copy - Copy most recent agent response");
+                outln!("    \x1b[1;32mEx:\x1b[0m /copy agent 2 - Copy Agent #2 response");
+                outln!("    \x1b[1;32mEx:\x1b[0m /copy user 1 - Copy User #1 message");
+                outln!("    \x1b[1;32mEx:\x1b[0m /copy block 3 - Copy code block #3");
+                outln!("    \x1b[1;32mEx:\x1b[0m /copy good - Copy the message bookmarked as 'good'");
+                outln!("    \x1b[1;32mEx:\x1b[0m /copy agent 2 --thinking - Copy Agent #2 with its thinking");
+                outln!("  /save [TYPE] [N] FILE - Save message/block to file");
+                outln!("    \x1b[1;32mEx:\x1b[0m /save response.md - Save most recent agent response");
+                outln!("    \x1b[1;32mEx:\x1b[0m /save agent 2 analysis.md - Save Agent #2 response");
+                outln!("    \x1b[1;32mEx:\x1b[0m /save user 1 question.txt - Save User #1 message");
+                outln!("    \x1b[1;32mEx:\x1b[0m /save block 3 code.py - Save code block #3");
+                outln!("    \x1b[1;32mEx:\x1b[0m /save good answer.md - Save the message bookmarked as 'good'");
+                outln!("  /exec N - Execute code block N (with confirmation)");
+                outln!("  /export FORMAT [FILE] [--session NAME|#N] [--all] [--thinking] - Export chat (markdown, json, html, txt)");
+                outln!("    \x1b[1;32mEx:\x1b[0m /export markdown - Print current session as Markdown");
+                outln!("    \x1b[1;32mEx:\x1b[0m /export html chat.html --thinking - Write HTML including thinking");
+                outln!("    \x1b[1;32mEx:\x1b[0m /export json backup.json --all - Export every saved session");
+                outln!();
                 
                 // File & Variable Operations
-                println!("\x1b[1;36mFile & Variable Operations:\x1b[0m");
-                println!("  /load SOURCE VAR   - Load variable from source");
-                println!("    Sources: =literal, @filepath, !command");
-                println!("    \x1b[1;32mEx:\x1b[0m /load \"=hello\" greeting");
-                println!("    \x1b[1;32mEx:\x1b[0m /load \"@src/main.rs\" code");
-                println!("    \x1b[1;32mEx:\x1b[0m /load \"!git diff\" changes");
-                println!("    \x1b[1;32mEx:\x1b[0m /load \"!slow-cmd\" x --timeout 60");
-                println!("  /freeze VAR        - Toggle frozen state on variable");
-                println!("  /vars              - List loaded variables");
-                println!("  /var show VAR      - Show variable content");
-                println!("  /var reload [VAR]  - Reload frozen variable(s)");
-                println!("  /var delete VAR    - Delete a variable");
-                println!();
+                outln!("\x1b[1;36mFile & Variable Operations:\x1b[0m");
+                outln!("  /load SOURCE VAR   - Load variable from source");
+                outln!("    Sources: =literal, @filepath, !command");
+                outln!("    \x1b[1;32mEx:\x1b[0m /load \"=hello\" greeting");
+                outln!("    \x1b[1;32mEx:\x1b[0m /load \"@src/main.rs\" code");
+                outln!("    \x1b[1;32mEx:\x1b[0m /load \"!git diff\" changes");
+                outln!("    \x1b[1;32mEx:\x1b[0m /load \"!slow-cmd\" x --timeout 60");
+                outln!("  /freeze VAR        - Toggle frozen state on variable");
+                outln!("  /vars              - List loaded variables");
+                outln!("  /var show VAR      - Show variable content");
+                outln!("  /var reload [VAR]  - Reload frozen variable(s)");
+                outln!("  /var delete VAR    - Delete a variable");
+                outln!("  /source FILE [--keep-going] - Run the commands and messages in FILE, stopping at the first error");
+                outln!();
                 
                 // System Prompts & Library
-                println!("\x1b[1;36mSystem Prompts & Library:\x1b[0m");
-                println!("  /system [PROMPT] - Set system prompt (empty to view, 'clear' to remove)");
-                println!("    \x1b[1;32mEx:\x1b[0m /system <<EOF (multi-line with heredoc)");
-                println!("  /prompts list - List all saved system prompts");
-                println!("  /prompts show NAME - Display a specific prompt");
-                println!("  /prompts save NAME [CONTENT] - Save current or specified system prompt");
-                println!("    \x1b[1;32mEx:\x1b[0m /prompts save my-prompt <<DESC (multi-line with heredoc)");
-                println!("  /prompts apply NAME - Apply a saved prompt to current session");
-                println!("  /prompts delete NAME - Remove a saved prompt");
-                println!("  /prompts rename OLD_NAME NEW_NAME - Rename a prompt");
-                println!("  /prompts search TERM - Search prompt names and content");
-                println!("  /prompts auto-name [NAME] - Auto-generate name for prompt");
-                println!("  /prompts edit NAME - Edit an existing prompt");
-                println!("  /prompts import FILE - Import prompts from JSON file");
-                println!("  /prompts export [FILE] - Export prompts to JSON file");
-                println!();
+                outln!("\x1b[1;36mSystem Prompts & Library:\x1b[0m");
+                outln!("  /system [PROMPT] - Set system prompt (empty to view, 'clear' to remove)");
+                outln!("    \x1b[1;32mEx:\x1b[0m /system <<EOF (multi-line with heredoc)");
+                outln!("  /prompts list - List all saved system prompts");
+                outln!("  /prompts show NAME - Display a specific prompt");
+                outln!("  /prompts save NAME [CONTENT] - Save current or specified system prompt");
+                outln!("    \x1b[1;32mEx:\x1b[0m /prompts save my-prompt <<DESC (multi-line with heredoc)");
+                outln!("  /prompts apply NAME - Apply a saved prompt to current session");
+                outln!("  /prompts delete NAME - Remove a saved prompt");
+                outln!("  /prompts rename OLD_NAME NEW_NAME - Rename a prompt");
+                outln!("  /prompts search TERM - Search prompt names and content");
+                outln!("  /prompts auto-name [NAME] - Auto-generate name for prompt");
+                outln!("  /prompts edit NAME - Edit an existing prompt");
+                outln!("  /prompts import FILE - Import prompts from JSON file");
+                outln!("  /prompts export [FILE] - Export prompts to JSON file");
+                outln!();
                 
                 // Model & Settings
-                println!("\x1b[1;36mModel & Settings:\x1b[0m");
-                println!("  /model MODEL - Switch to a different model (auto-detects provider)");
-                println!("  /temp TEMPERATURE - Set temperature (0.0-2.0)");
-                println!("  /max-tokens TOKENS - Set maximum output tokens");
-                println!("  /thinking on|off - Enable/disable thinking for supported models");
-                println!("  /thinking-budget TOKENS - Set thinking token budget");
-                println!("  /tokens - Show token usage stats");
-                println!("  /stats - Show session statistics");
-                println!("  /clear - Clear terminal display (keep history)");
-                println!();
+                outln!("\x1b[1;36mModel & Settings:\x1b[0m");
+                outln!("  /model MODEL - Switch to a different model (auto-detects provider)");
+                outln!("  /compare MODEL1 MODEL2 [...] -- PROMPT - Send the context plus PROMPT to several models at once");
+                outln!("  /compare adopt N - Add reply N from the last comparison to the session");
+                outln!("  /temp TEMPERATURE - Set temperature (0.0-2.0)");
+                outln!("  /max-tokens TOKENS - Set maximum output tokens");
+                outln!("  /thinking on|off - Enable/disable thinking for supported models");
+                outln!("  /thinking show [N] - Show the recorded thinking behind Agent N (default: latest)");
+                outln!("  /thinking-budget TOKENS - Set thinking token budget");
+                outln!("  /tokens - Show token usage stats");
+                outln!("  /stats [--all] - Show session statistics (--all: across saved sessions, per model per week)");
+                outln!("  /clear - Clear terminal display (keep history)");
+                outln!();
                 
                 // Input History
-                println!("\x1b[1;36mInput History:\x1b[0m");
-                println!("  /input-history - Show input history information");
-                println!("  /input-history clear - Clear all input history");
-                println!("  /input-history stats - Show detailed input history statistics");
-                println!();
+                outln!("\x1b[1;36mInput History:\x1b[0m");
+                outln!("  /input-history - Show input history information");
+                outln!("  /input-history clear - Clear all input history");
+                outln!("  /input-history stats - Show detailed input history statistics");
+                outln!();
                 
                 // Default Preferences
-                println!("\x1b[1;36mDefault Preferences:\x1b[0m");
-                println!("  /set-default SETTING VALUE - Set default value for new sessions");
-                println!("    \x1b[1;32mEx:\x1b[0m /set-default model gpt-4o - Set default model");
-                println!("    \x1b[1;32mEx:\x1b[0m /set-default temperature 0.8 - Set default temperature");
-                println!("    \x1b[1;32mEx:\x1b[0m /set-default thinking on - Set default thinking mode");
-                println!("    \x1b[1;32mEx:\x1b[0m /set-default system-prompt \"You are helpful\" - Set default system prompt");
-                println!("  /get-defaults - Show current default preferences");
-                println!("  /reset-defaults - Reset all defaults to built-in values");
-                println!("    \x1b[1;32mSettings:\x1b[0m model, temperature, max-tokens, thinking-budget, thinking, system-prompt");
-                println!();
+                outln!("\x1b[1;36mDefault Preferences:\x1b[0m");
+                outln!("  /set-default SETTING VALUE - Set default value for new sessions");
+                outln!("    \x1b[1;32mEx:\x1b[0m /set-default model gpt-4o - Set default model");
+                outln!("    \x1b[1;32mEx:\x1b[0m /set-default temperature 0.8 - Set default temperature");
+                outln!("    \x1b[1;32mEx:\x1b[0m /set-default thinking on - Set default thinking mode");
+                outln!("    \x1b[1;32mEx:\x1b[0m /set-default system-prompt \"You are helpful\" - Set default system prompt");
+                outln!("  /get-defaults - Show current default preferences");
+                outln!("  /reset-defaults - Reset all defaults to built-in values");
+                outln!("    \x1b[1;32mSettings:\x1b[0m model, temperature, max-tokens, thinking-budget, thinking, system-prompt");
+                outln!();
+                
+                // Backups
+                outln!("\x1b[1;36mBackups:\x1b[0m");
+                outln!("  /backup list - List compressed backups of the state directory");
+                outln!("  /backup now - Take a backup immediately");
+                outln!("  /backup restore TIMESTAMP|#N - Roll sessions, prompts and input history back to a backup");
+                outln!();
+                
+                // Encryption
+                outln!("\x1b[1;36mEncryption:\x1b[0m");
+                outln!("  /encryption [status] - Show whether state files are encrypted");
+                outln!("  /encryption enable [--keyfile PATH] - Encrypt sessions, prompts, input history and backups");
+                outln!("  /encryption rotate [--keyfile PATH] - Re-encrypt everything under a new passphrase or keyfile");
+                outln!("  /encryption disable - Decrypt state files back to plaintext");
+                outln!("    Unlock at startup with NJORD_PASSPHRASE, --keyfile, or the passphrase prompt");
+                outln!();
+                
+                // Workspaces
+                outln!("\x1b[1;36mWorkspaces:\x1b[0m");
+                outln!("  /workspace [show] - Show the active workspace (found by walking up from the cwd)");
+                outln!("  /workspace init [DIR] - Create DIR/.njord (default: cwd) and switch to it");
+                outln!("  /workspace switch PATH|global - Switch to the workspace containing PATH, or global state");
+                outln!("  /workspace add-var VAR - Save a loaded variable for every session in this workspace");
+                outln!("  /workspace remove-var VAR - Stop saving a variable in this workspace");
+                outln!("    Workspaces keep their own sessions and input history; global prompts and variables still apply");
+                outln!();
+                
+                // Retention
+                outln!("\x1b[1;36mRetention:\x1b[0m");
+                outln!("  /retention [show] - Show the rules for pruning old sessions and input history");
+                outln!("  /retention set KEY VALUE - Change a rule (VALUE 'off' disables a limit)");
+                outln!("    Keys: max-age DAYS, max-sessions N, max-size SIZE, action trash|archive,");
+                outln!("          exempt-tags TAG,..., exempt-named on|off, input-max-age DAYS");
+                outln!("    \x1b[1;32mEx:\x1b[0m /retention set max-age 90");
+                outln!("  /retention preview - Dry run: list what the rules would prune");
+                outln!("  /retention run - Prune now (rules also run at startup)");
+                outln!();
+                
+                // Session Templates
+                outln!("\x1b[1;36mTemplates:\x1b[0m");
+                outln!("  /template [list] - List session templates (saved, and files in templates.d/)");
+                outln!("  /template save NAME - Save the current model, settings, system prompt, variables and messages");
+                outln!("  /template delete NAME - Delete a saved template");
+                outln!();
                 
                 // Usage Tips
-                println!("\x1b[1;36mUsage Tips:\x1b[0m");
-                println!("  • Multi-line input: {{TAG ... TAG}} or <<DELIMITER ... DELIMITER");
-                println!("  • Multi-line commands: /system <<EOF or /prompts save name <<DESC");
-                println!("  • Use {{{{VARIABLE_NAME}}}} in messages to reference loaded file content");
-                println!("  • Use #N for ephemeral session references (e.g., /chat load #1)");
-                println!("  • Quote session names with spaces (e.g., /chat load \"My Session\")");
+                outln!("\x1b[1;36mUsage Tips:\x1b[0m");
+                outln!("  • Multi-line input: {{TAG ... TAG}} or <<DELIMITER ... DELIMITER");
+                outln!("  • Multi-line commands: /system <<EOF or /prompts save name <<DESC");
+                outln!("  • Use {{{{VARIABLE_NAME}}}} in messages to reference loaded file content");
+                outln!("  • Use #N for ephemeral session references (e.g., /chat load #1)");
+                outln!("  • Quote session names with spaces (e.g., /chat load \"My Session\")");
             }
             Command::Models => {
                 self.ui.print_info("Available models:");
@@ -1003,12 +1620,12 @@ impl Repl {
                 for (provider_name, model) in all_models {
                     if provider_name != current_provider {
                         if !current_provider.is_empty() {
-                            println!();
+                            outln!();
                         }
-                        println!("  \x1b[1;36m{}:\x1b[0m", provider_name);
+                        outln!("  \x1b[1;36m{}:\x1b[0m", provider_name);
                         current_provider = provider_name;
                     }
-                    println!("    {}", model);
+                    outln!("    {}", model);
                 }
             }
             Command::ChatNew => {
@@ -1025,9 +1642,46 @@ impl Repl {
                 self.session.current_provider = get_provider_for_model(&self.session.current_model).map(|s| s.to_string());
                 self.ui.print_info("Started new chat session");
             }
+            Command::ChatNewFromTemplate(name) => {
+                self.reload_templates();
+                let Some(template) = self.templates.get(&name) else {
+                    self.fail(&format!("Template '{}' not found. Use '/template list' to see available templates", name));
+                    return Ok(true);
+                };
+                let base = Self::create_session_with_defaults(&self.config, &self.history);
+                let session = match template.instantiate(base) {
+                    Ok(session) => session,
+                    Err(e) => {
+                        self.fail(&format!("Invalid template '{}': {}", name, e));
+                        return Ok(true);
+                    }
+                };
+                match get_provider_for_model(&session.current_model) {
+                    Some(provider) if self.providers.contains_key(provider) => {}
+                    _ => {
+                        self.fail(&format!("Template '{}' uses model '{}', whose provider is not available", name, session.current_model));
+                        return Ok(true);
+                    }
+                }
+                
+                if let Err(e) = self.history.auto_save_session(&self.session) {
+                    self.ui.print_error(&format!("Failed to auto-save current session: {}", e));
+                } else {
+                    let _ = self.update_completion_context();
+                    self.update_session_list();
+                }
+                
+                self.session = session;
+                let session_clone = self.session.clone();
+                self.restore_session_variables(&session_clone);
+                self.ui.print_info(&format!(
+                    "Started new chat session from template '{}' ({}, {} seed message(s), {} variable(s))",
+                    name, self.session.current_model, self.session.messages.len(), self.session.variables.len()
+                ));
+            }
             Command::ChatContinue(session_ref_opt) => {
                 if self.config.ephemeral {
-                    self.ui.print_error("Cannot continue sessions in ephemeral mode (would modify original session)");
+                    self.fail("Cannot continue sessions in ephemeral mode (would modify original session)");
                     self.ui.print_info("Use '/chat load SESSION' to copy a session instead");
                     return Ok(true);
                 }
@@ -1039,7 +1693,7 @@ impl Repl {
                             // Don't continue the current session
                             if let Some(current_name) = &self.session.name {
                                 if current_name == &name {
-                                    self.ui.print_error("Cannot continue the current session");
+                                    self.fail("Cannot continue the current session");
                                     self.ui.print_info("You are already in this session");
                                     return Ok(true);
                                 }
@@ -1047,7 +1701,7 @@ impl Repl {
                             self.history.load_session(&name).cloned()
                         }
                         Err(e) => {
-                            self.ui.print_error(&e.to_string());
+                            self.fail(&e.to_string());
                             return Ok(true);
                         }
                     }
@@ -1105,7 +1759,7 @@ impl Repl {
                         self.ui.print_info(&format!("Session model: {}", self.session.current_model));
                     }
                 } else {
-                    self.ui.print_error("No sessions found to continue");
+                    self.fail("No sessions found to continue");
                 }
             }
             Command::ChatRecent => {
@@ -1116,28 +1770,29 @@ impl Repl {
                     self.ui.print_info("Recent sessions:");
                     
                     // Update the ephemeral session list with recent sessions
+                    self.session_list_filter = SessionFilter::default();
                     self.last_session_list = recent_sessions.iter().map(|(name, _)| (*name).clone()).collect();
                     
                     for (index, (name, session)) in recent_sessions.iter().enumerate() {
                         let message_count = session.messages.len();
                         let updated = session.updated_at.format("%Y-%m-%d %H:%M");
-                        println!("  #{}: \"{}\" ({} messages, updated {})", 
+                        outln!("  #{}: \"{}\" ({} messages, updated {})", 
                             index + 1, name, message_count, updated);
                     }
-                    println!();
+                    outln!();
                     self.ui.print_info("Use '/chat continue #N' to continue by number or '/chat continue \"name\"' to continue by name");
                 }
             }
             Command::ChatFork(name_opt) => {
                 if self.session.messages.is_empty() {
-                    self.ui.print_error("Cannot fork empty session");
+                    self.fail("Cannot fork empty session");
                 } else {
                     // Create a copy of the current session
                     let mut forked_session = self.session.create_copy();
                     
                     if let Some(name) = name_opt {
                         if name.trim().is_empty() {
-                            self.ui.print_error("Session name cannot be empty");
+                            self.fail("Session name cannot be empty");
                             return Ok(true);
                         }
                         
@@ -1163,7 +1818,7 @@ impl Repl {
                                 self.update_session_list();
                             }
                             Err(e) => {
-                                self.ui.print_error(&format!("Failed to fork session: {}", e));
+                                self.fail(&format!("Failed to fork session: {}", e));
                             }
                         }
                     } else {
@@ -1206,7 +1861,7 @@ impl Repl {
                             
                             if let Some(name) = new_name_opt {
                                 if name.trim().is_empty() {
-                                    self.ui.print_error("Session name cannot be empty");
+                                    self.fail("Session name cannot be empty");
                                     return Ok(true);
                                 }
                                 
@@ -1246,7 +1901,7 @@ impl Repl {
                                         self.update_session_list();
                                     }
                                     Err(e) => {
-                                        self.ui.print_error(&format!("Failed to save branched session: {}", e));
+                                        self.fail(&format!("Failed to save branched session: {}", e));
                                     }
                                 }
                             } else {
@@ -1281,31 +1936,31 @@ impl Repl {
                                 self.update_session_list();
                             }
                         } else {
-                            self.ui.print_error(&format!("Session '{}' not found", source_name));
+                            self.fail(&format!("Session '{}' not found", source_name));
                             let available_sessions = self.history.list_sessions();
                             if !available_sessions.is_empty() {
                                 self.ui.print_info("Available sessions:");
                                 for session_name in available_sessions {
-                                    println!("  {}", session_name);
+                                    outln!("  {}", session_name);
                                 }
                             }
                         }
                     }
                     Err(e) => {
-                        self.ui.print_error(&e.to_string());
+                        self.fail(&e.to_string());
                     }
                 }
             }
             Command::ChatRename(new_name, old_session_ref) => {
                 if new_name.trim().is_empty() {
-                    self.ui.print_error("New session name cannot be empty");
+                    self.fail("New session name cannot be empty");
                 } else {
                     let target_name = if let Some(ref session_ref) = old_session_ref {
                         // Rename specific session by reference
                         match self.resolve_session_reference(session_ref) {
                             Ok(name) => name,
                             Err(e) => {
-                                self.ui.print_error(&e.to_string());
+                                self.fail(&e.to_string());
                                 return Ok(true);
                             }
                         }
@@ -1321,11 +1976,11 @@ impl Repl {
                                     auto_name
                                 }
                                 Ok(None) => {
-                                    self.ui.print_error("Cannot rename empty session");
+                                    self.fail("Cannot rename empty session");
                                     return Ok(true);
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&format!("Failed to auto-save session: {}", e));
+                                    self.fail(&format!("Failed to auto-save session: {}", e));
                                     return Ok(true);
                                 }
                             }
@@ -1335,6 +1990,7 @@ impl Repl {
                     match self.history.rename_session(&target_name, &new_name) {
                         Ok(true) => {
                             self.ui.print_info(&format!("Session \"{}\" renamed to \"{}\"", target_name, new_name));
+                            self.journal_session_operation(Operation::Rename { from: target_name.clone(), to: new_name.clone() });
                         
                             // If we renamed the current session, update its name and show context
                             if old_session_ref.is_none() || self.session.name.as_ref() == Some(&target_name) {
@@ -1349,17 +2005,17 @@ impl Repl {
                             self.update_session_list();
                         }
                         Ok(false) => {
-                            self.ui.print_error(&format!("Session '{}' not found", target_name));
+                            self.fail(&format!("Session '{}' not found", target_name));
                             let available_sessions = self.history.list_sessions();
                             if !available_sessions.is_empty() {
                                 self.ui.print_info("Available sessions:");
                                 for session_name in available_sessions.iter().take(5) {
-                                    println!("  {}", session_name);
+                                    outln!("  {}", session_name);
                                 }
                             }
                         }
                         Err(e) => {
-                            self.ui.print_error(&format!("Failed to rename session: {}", e));
+                            self.fail(&format!("Failed to rename session: {}", e));
                         }
                     }
                 }
@@ -1370,7 +2026,7 @@ impl Repl {
                         // Success message already printed in handle_auto_rename
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to auto-rename session: {}", e));
+                        self.fail(&format!("Failed to auto-rename session: {}", e));
                     }
                 }
             }
@@ -1380,26 +2036,148 @@ impl Repl {
                         // Success message already printed in handle_auto_rename_all
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to auto-rename sessions: {}", e));
+                        self.fail(&format!("Failed to auto-rename sessions: {}", e));
+                    }
+                }
+            }
+            Command::ChatImport(format_name, path) => {
+                let format = match ImportFormat::parse(&format_name) {
+                    Ok(format) => format,
+                    Err(e) => {
+                        self.fail(&e.to_string());
+                        return Ok(true);
+                    }
+                };
+
+                // Imported sessions inherit the current default settings for anything the export lacks
+                let template = Self::create_session_with_defaults(&self.config, &self.history);
+                let sessions = match import_conversations(format, &path, &template) {
+                    Ok(sessions) => sessions,
+                    Err(e) => {
+                        self.fail(&format!("Failed to read export: {}", e));
+                        return Ok(true);
+                    }
+                };
+
+                if sessions.is_empty() {
+                    self.ui.print_info("No conversations found in export");
+                    return Ok(true);
+                }
+
+                match self.history.import_sessions(sessions) {
+                    Ok(result) => {
+                        self.ui.print_info(&format!("Import complete: {} imported, {} already present",
+                            result.imported_count, result.skipped_count));
+                        let _ = self.update_completion_context();
+                        self.update_session_list();
+                    }
+                    Err(e) => {
+                        self.fail(&format!("Failed to import sessions: {}", e));
+                    }
+                }
+            }
+            Command::ChatShare(session_ref, path, strip_secrets) => {
+                let name = match self.resolve_session_reference(&session_ref) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        self.fail(&e.to_string());
+                        return Ok(true);
+                    }
+                };
+                // The live copy of the current session may be ahead of the saved one
+                let session = if self.session.name.as_ref() == Some(&name) {
+                    self.session.clone()
+                } else if let Some(session) = self.history.load_session(&name) {
+                    session.clone()
+                } else {
+                    self.fail(&format!("Session '{}' not found", name));
+                    return Ok(true);
+                };
+
+                let (mut bundle, warnings) = SessionBundle::create(&session, &self.prompts);
+                for warning in warnings {
+                    self.ui.print_info(&format!("Warning: {}", warning));
+                }
+                if strip_secrets {
+                    let stripped = bundle.strip_secrets();
+                    if !stripped.is_empty() {
+                        self.ui.print_info(&format!("Command sources replaced by their output: {}", stripped.join(", ")));
+                    }
+                }
+                match bundle.write(Path::new(&path)) {
+                    Ok(()) => self.ui.print_info(&format!(
+                        "Shared '{}' to {} ({} messages, {} variable value(s), {} file(s), {} prompt(s))",
+                        name, path, session.messages.len(), bundle.variable_values.len(), bundle.files.len(), bundle.prompts.len()
+                    )),
+                    Err(e) => self.fail(&format!("Failed to write bundle: {}", e)),
+                }
+            }
+            Command::ChatReceive(path, new_name, allow_commands) => {
+                let mut bundle = match SessionBundle::read(Path::new(&path)) {
+                    Ok(bundle) => bundle,
+                    Err(e) => {
+                        self.fail(&format!("Failed to read bundle: {}", e));
+                        return Ok(true);
+                    }
+                };
+                // Never bring in a command that would run here unless the recipient asked for it
+                let stripped = if allow_commands { Vec::new() } else { bundle.strip_secrets() };
+                let prompts = std::mem::take(&mut bundle.prompts);
+                let session = bundle.into_session();
+
+                let name = new_name.clone()
+                    .or_else(|| session.name.clone())
+                    .unwrap_or_else(|| session.generate_auto_name());
+                if self.history.load_session(&name).is_some() {
+                    self.fail(&format!("A session named '{}' already exists", name));
+                    self.ui.print_info(&format!("Receive it under another name with --as NAME, e.g. --as \"{}\"", self.ensure_unique_session_name(&name)));
+                    return Ok(true);
+                }
+
+                let message_count = session.messages.len();
+                if let Err(e) = self.history.save_session(name.clone(), session) {
+                    self.fail(&format!("Failed to save received session: {}", e));
+                    return Ok(true);
+                }
+                self.ui.print_info(&format!("Received '{}' ({} messages)", name, message_count));
+                if !stripped.is_empty() {
+                    self.ui.print_info(&format!(
+                        "Command sources replaced by their shared output: {} (receive with --allow-commands to keep them)",
+                        stripped.join(", ")
+                    ));
+                }
+                if !prompts.is_empty() {
+                    match self.prompts.add_prompts(prompts, false) {
+                        Ok(result) => {
+                            let mut summary = format!("{} prompt(s) added to your library", result.imported_count);
+                            if result.skipped_count > 0 {
+                                summary.push_str(&format!(", {} kept as you had them (same name)", result.skipped_count));
+                            }
+                            self.ui.print_info(&summary);
+                        }
+                        Err(e) => self.fail(&format!("Failed to add prompts: {}", e)),
                     }
                 }
+                self.ui.print_info(&format!("Use '/chat continue \"{}\"' to pick up the conversation", name));
+                let _ = self.update_completion_context();
+                self.update_session_list();
             }
             Command::ChatName(name) => {
                 if name.trim().is_empty() {
-                    self.ui.print_error("Session name cannot be empty");
+                    self.fail("Session name cannot be empty");
                 } else if self.session.messages.is_empty() {
-                    self.ui.print_error("Cannot name empty session");
+                    self.fail("Cannot name empty session");
                 } else {
                     // Check if new name already exists
                     if self.history.load_session(&name).is_some() {
-                        self.ui.print_error(&format!("Session '{}' already exists", name));
+                        self.fail(&format!("Session '{}' already exists", name));
                     } else {
                         let old_name = self.session.name.clone();
                         
                         // Remove old session from history if it had a name
                         if let Some(ref old_session_name) = old_name {
-                            if let Err(e) = self.history.delete_session(old_session_name) {
-                                self.ui.print_error(&format!("Failed to remove old session: {}", e));
+                            if let Err(e) = self.history.forget_session(old_session_name) {
+                                self.fail(&format!("Failed to remove old session: {}", e));
                                 return Ok(true);
                             }
                         }
@@ -1424,7 +2202,7 @@ impl Repl {
                             Err(e) => {
                                 // Restore old name on failure
                                 self.session.name = old_name;
-                                self.ui.print_error(&format!("Failed to name session: {}", e));
+                                self.fail(&format!("Failed to name session: {}", e));
                             }
                         }
                     }
@@ -1436,15 +2214,18 @@ impl Repl {
                         // Success - summary already printed
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to summarize session: {}", e));
+                        self.fail(&format!("Failed to summarize session: {}", e));
                     }
                 }
             }
             Command::Undo(count) => {
                 let count = count.unwrap_or(1);
+                let before = self.session.messages.clone();
                 match self.session.undo(count) {
                     Ok(last_user_message) => {
-                        self.ui.print_info(&format!("Undid last {} agent response(s)", count));
+                        let removed = before[self.session.messages.len()..].to_vec();
+                        self.session.journal_operation(Operation::Undo { removed });
+                        self.ui.print_info(&format!("Undid last {} agent response(s) (/redo restores them)", count));
                         
                         // If we have a user message to restore, queue it for editing
                         if let Some(user_msg) = last_user_message {
@@ -1453,15 +2234,585 @@ impl Repl {
                         }
                     }
                     Err(e) => {
-                        self.ui.print_error(&e.to_string());
+                        self.fail(&e.to_string());
                     }
                 }
             }
-            Command::Model(model_name) => {
-                // Determine which provider this model belongs to
-                if let Some(required_provider) = get_provider_for_model(&model_name) {
-                    // Check if we have this provider available
-                    if let Some(provider) = self.providers.get(required_provider) {
+            Command::Mark(target, name) => {
+                if !ChatSession::is_valid_bookmark_name(&name) {
+                    self.fail(&format!(
+                        "Invalid bookmark name '{}': start with a letter, then letters, digits, '_', '-' or '.' (agent, user and block are reserved)",
+                        name
+                    ));
+                    return Ok(true);
+                }
+                let Some(index) = self.message_target_index(&target) else {
+                    self.fail(&format!("{} not found", target));
+                    return Ok(true);
+                };
+                let number = self.session.messages[index].number;
+                let moved = self.session.find_bookmark(&name).is_some_and(|(_, msg)| msg.number != number);
+                match self.update_session_metadata(None, |session| session.set_bookmark(number, &name)) {
+                    Ok(_) => {
+                        let moved = if moved { " (moved from the message it was on)" } else { "" };
+                        self.ui.print_info(&format!("Bookmarked {} as '{}'{}", target, name, moved));
+                        let _ = self.update_completion_context();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Unmark(name) => {
+                if self.session.find_bookmark(&name).is_none() {
+                    self.fail(&format!("No bookmark named '{}' in this session", name));
+                    return Ok(true);
+                }
+                match self.update_session_metadata(None, |session| {
+                    session.remove_bookmark(&name);
+                }) {
+                    Ok(_) => {
+                        self.ui.print_info(&format!("Removed bookmark '{}'", name));
+                        let _ = self.update_completion_context();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Marks => {
+                let mut user_count = 0;
+                let mut agent_count = 0;
+                let mut annotated = Vec::new();
+                for msg in &self.session.messages {
+                    let target = if msg.message.role == "user" {
+                        user_count += 1;
+                        MessageTarget::User(user_count)
+                    } else {
+                        agent_count += 1;
+                        MessageTarget::Agent(agent_count)
+                    };
+                    if msg.bookmark.is_some() || msg.note.is_some() {
+                        annotated.push((target, msg));
+                    }
+                }
+                if annotated.is_empty() {
+                    self.ui.print_info("No bookmarks or notes in this session. Add them with /mark N NAME and /note N TEXT");
+                    return Ok(true);
+                }
+                self.ui.print_info(&format!("Bookmarks and notes ({}):", annotated.len()));
+                let width = annotated.iter().filter_map(|(_, msg)| msg.bookmark.as_ref()).map(|name| name.len()).max().unwrap_or(1);
+                for (target, msg) in annotated {
+                    let preview: String = msg.message.content.chars().take(60).collect::<String>().replace('\n', " ");
+                    let name = msg.bookmark.as_deref().unwrap_or("-");
+                    outln!("  \x1b[1;33m{:<width$}\x1b[0m  {}: {}", name, target, preview, width = width);
+                    if let Some(note) = &msg.note {
+                        outln!("    \x1b[2mNote: {}\x1b[0m", note);
+                    }
+                }
+            }
+            Command::Note(target, note) => {
+                let Some(index) = self.message_target_index(&target) else {
+                    self.fail(&format!("{} not found", target));
+                    return Ok(true);
+                };
+                let number = self.session.messages[index].number;
+                let removing = note.is_none();
+                match self.update_session_metadata(None, |session| session.set_note(number, note.clone())) {
+                    Ok(_) if removing => self.ui.print_info(&format!("Removed the note on {}", target)),
+                    Ok(_) => self.ui.print_info(&format!("Added a note to {}", target)),
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Hide(target, hidden) => {
+                let Some(index) = self.message_target_index(&target) else {
+                    self.fail(&format!("{} not found", target));
+                    return Ok(true);
+                };
+                let msg = &self.session.messages[index];
+                if hidden && msg.pinned {
+                    self.fail(&format!("{} is pinned; /unpin it before hiding it", target));
+                    return Ok(true);
+                }
+                if hidden && self.session.must_send(msg.number) {
+                    self.fail(&format!("{} is the question of a pinned reply; /unpin the reply before hiding it", target));
+                    return Ok(true);
+                }
+                if msg.hidden == hidden {
+                    let state = if hidden { "already hidden" } else { "not hidden" };
+                    self.ui.print_info(&format!("{} is {}", target, state));
+                    return Ok(true);
+                }
+                let number = msg.number;
+                match self.update_session_metadata(None, |session| session.set_hidden(number, hidden)) {
+                    Ok(_) if hidden => self.ui.print_info(&format!("{} is hidden from the model; /unhide to send it again", target)),
+                    Ok(_) => self.ui.print_info(&format!("{} will be sent to the model again", target)),
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Pin(target, pinned) => {
+                let Some(index) = self.message_target_index(&target) else {
+                    self.fail(&format!("{} not found", target));
+                    return Ok(true);
+                };
+                let msg = &self.session.messages[index];
+                if msg.pinned == pinned {
+                    let state = if pinned { "already pinned" } else { "not pinned" };
+                    self.ui.print_info(&format!("{} is {}", target, state));
+                    return Ok(true);
+                }
+                let (number, was_hidden) = (msg.number, msg.hidden);
+                let question_hidden = self.session.question_of(number).is_some_and(|question| question.hidden);
+                match self.update_session_metadata(None, |session| session.set_pinned(number, pinned)) {
+                    Ok(_) if pinned && was_hidden => self.ui.print_info(&format!("Pinned {}; it was hidden and will be sent again", target)),
+                    Ok(_) if pinned && question_hidden => self.ui.print_info(&format!("Pinned {}; its question was hidden and will be sent again", target)),
+                    Ok(_) if pinned => self.ui.print_info(&format!("Pinned {}; it will always be sent to the model", target)),
+                    Ok(_) => self.ui.print_info(&format!("Unpinned {}", target)),
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Inspect(agent_number) => {
+                let Some(index) = self.get_agent_message_index_by_number(agent_number) else {
+                    self.fail(&format!("Agent {} not found", agent_number));
+                    return Ok(true);
+                };
+                let msg = &self.session.messages[index];
+                let Some(params) = &msg.params else {
+                    self.ui.print_info(&format!("Agent {} has no recorded generation parameters; it predates them or wasn't generated here", agent_number));
+                    return Ok(true);
+                };
+                self.ui.print_info(&format!("Generation parameters for Agent {}:", agent_number));
+                outln!("  Model: {} ({})", msg.model.as_deref().unwrap_or("unknown"), msg.provider.as_deref().unwrap_or("unknown provider"));
+                outln!("  Temperature: {}", params.temperature);
+                outln!("  Max tokens: {}", params.max_tokens);
+                if params.thinking_enabled {
+                    outln!("  Thinking: on, budget {} tokens", params.thinking_budget);
+                } else {
+                    outln!("  Thinking: off");
+                }
+                match &params.system_prompt_hash {
+                    Some(hash) if self.session.system_prompt.as_deref().map(GenerationParams::hash).as_ref() == Some(hash) => {
+                        outln!("  System prompt: {} (same as now)", hash);
+                    }
+                    Some(hash) if params.system_prompt.is_some() => outln!("  System prompt: {} \x1b[2m(changed since, recorded)\x1b[0m", hash),
+                    Some(hash) => outln!("  System prompt: {} \x1b[2m(changed since)\x1b[0m", hash),
+                    None => outln!("  System prompt: none"),
+                }
+                if !params.variable_hashes.is_empty() {
+                    outln!("  Variables:");
+                    for (name, hash) in &params.variable_hashes {
+                        let recorded = if params.variable_values.contains_key(name) { " \x1b[2m(value recorded)\x1b[0m" } else { "" };
+                        outln!("    {} {}{}", GenerationParams::describe_key(name), hash, recorded);
+                    }
+                }
+                if !params.excluded.is_empty() {
+                    let excluded: Vec<String> = params.excluded.iter()
+                        .filter_map(|number| self.session.messages.iter().position(|msg| msg.number == *number))
+                        .map(|index| self.message_target_at(index).to_string())
+                        .collect();
+                    outln!("  Hidden from the request: {}", excluded.join(", "));
+                }
+                if let Some(timing) = &msg.timing {
+                    outln!("  Latency: {} ms ({} ms to first token)", timing.latency_ms, timing.first_token_ms);
+                }
+            }
+            Command::Reproduce(agent_number) => {
+                let Some(index) = self.get_agent_message_index_by_number(agent_number) else {
+                    self.fail(&format!("Agent {} not found", agent_number));
+                    return Ok(true);
+                };
+                let original = self.session.messages[index].clone();
+                let (Some(params), Some(provider_name), Some(model)) = (original.params, original.provider, original.model) else {
+                    self.fail(&format!("Agent {} has no recorded generation parameters to reproduce", agent_number));
+                    return Ok(true);
+                };
+                if !self.providers.contains_key(&provider_name) {
+                    self.fail(&format!("Provider '{}' used for Agent {} is not configured", provider_name, agent_number));
+                    return Ok(true);
+                }
+                
+                // Replay the recorded system prompt and values; current ones only do if they still match
+                let mut request_messages = Vec::new();
+                match params.replay_system_prompt(self.session.system_prompt.as_deref()) {
+                    Ok(Some(system_prompt)) => request_messages.push((Message { role: "system".to_string(), content: system_prompt }, Vec::new())),
+                    Ok(None) => {}
+                    Err(e) => {
+                        self.fail(&format!("Cannot reproduce Agent {}: {}", agent_number, e));
+                        return Ok(true);
+                    }
+                }
+                let mut variable_values = BTreeMap::new();
+                for msg in self.session.messages[..index].iter().filter(|msg| !params.excluded.contains(&msg.number)) {
+                    let mut message = msg.message.clone();
+                    if message.role == "user" {
+                        match self.substitute_variables_recording(&message.content, &params.variable_values, &mut variable_values) {
+                            Ok(substituted) => message.content = substituted,
+                            Err(errors) => {
+                                let failures: Vec<String> = errors.iter().map(|(name, error)| format!("'{}' failed: {}", name, error)).collect();
+                                self.fail(&format!("Cannot reproduce Agent {}: variable {}", agent_number, failures.join(", ")));
+                                return Ok(true);
+                            }
+                        }
+                    }
+                    let blocks = if msg.model.as_ref() == Some(&model) { msg.thinking_blocks.clone() } else { Vec::new() };
+                    request_messages.push((message, blocks));
+                }
+                let changed: Vec<String> = params.variable_hashes.iter()
+                    .filter(|(name, hash)| variable_values.get(*name).map(|value| GenerationParams::hash(value)).as_ref() != Some(*hash))
+                    .map(|(name, _)| GenerationParams::describe_key(name))
+                    .collect();
+                if !changed.is_empty() {
+                    self.fail(&format!(
+                        "Cannot reproduce Agent {}: variable values have changed since and were not recorded: {}",
+                        agent_number, changed.join(", ")
+                    ));
+                    self.ui.print_info("Start njord with --record-values to keep them with each reply");
+                    return Ok(true);
+                }
+                
+                let (request_messages, thinking_blocks) = alternate_turns(request_messages);
+                let chat_request = ChatRequest {
+                    messages: request_messages,
+                    thinking_blocks,
+                    model: model.clone(),
+                    temperature: params.temperature,
+                    max_tokens: params.max_tokens,
+                    thinking_budget: params.thinking_budget,
+                    stream: true,
+                    thinking: params.thinking_enabled,
+                };
+                self.ui.print_info(&format!("Reproducing Agent {} with {} at temperature {}", agent_number, model, params.temperature));
+                
+                let cancel_token = CancellationToken::new();
+                self.active_request_token = Some(cancel_token.clone());
+                let mut ctrl_c_rx = std::mem::replace(&mut self.ctrl_c_rx, tokio::sync::mpsc::unbounded_channel().1);
+                let result = tokio::select! {
+                    result = self.stream_reply(&provider_name, chat_request, agent_number, 3, cancel_token.clone()) => result,
+                    _ = ctrl_c_rx.recv() => {
+                        cancel_token.cancel();
+                        Err(anyhow::anyhow!("Request interrupted by Ctrl-C"))
+                    }
+                };
+                self.ctrl_c_rx = ctrl_c_rx;
+                self.active_request_token = None;
+                
+                match result {
+                    Ok(reply) if reply.content == original.message.content => {
+                        self.ui.print_info(&format!("Reproduced Agent {} exactly", agent_number));
+                    }
+                    Ok(reply) => {
+                        let similarity = similar::TextDiff::from_lines(&original.message.content, &reply.content).ratio();
+                        let expected = if params.temperature > 0.0 { "; some variation is expected above temperature 0" } else { "" };
+                        self.ui.print_info(&format!(
+                            "The reproduction differs from Agent {} ({:.0}% similar{}). The transcript is unchanged",
+                            agent_number, similarity * 100.0, expected
+                        ));
+                    }
+                    Err(e) => self.fail(&format!("Failed to reproduce Agent {}: {}", agent_number, e)),
+                }
+            }
+            Command::Compare(models, prompt) => {
+                if models.len() < 2 {
+                    self.fail("Give at least two models: /compare MODEL1 MODEL2 [...] -- PROMPT");
+                    return Ok(true);
+                }
+                let mut targets = Vec::new();
+                for model in &models {
+                    match get_provider_for_model(model) {
+                        Some(provider) if self.providers.contains_key(provider) => targets.push((model.clone(), provider.to_string())),
+                        Some(provider) => {
+                            self.fail(&format!("Provider '{}' for {} is not configured", provider, model));
+                            return Ok(true);
+                        }
+                        None => {
+                            self.fail(&format!("Unknown model '{}'. Use /models to list available models", model));
+                            return Ok(true);
+                        }
+                    }
+                }
+                if let Err(errors) = self.substitute_variables(&prompt) {
+                    for (var_name, error) in &errors {
+                        self.fail(&format!("Variable '{}' failed: {}", var_name, error));
+                    }
+                    return Ok(true);
+                }
+                
+                let user_message = Message { role: "user".to_string(), content: prompt.clone() };
+                let (request_messages, thinking_blocks, params) = self.prepare_request_messages(&user_message);
+                self.ui.print_info(&format!("Sending to {} models...", targets.len()));
+                
+                // All requests run at once. The first model to answer streams live; replies that
+                // complete meanwhile are printed whole once it is done
+                let mut ctrl_c_rx = std::mem::replace(&mut self.ctrl_c_rx, tokio::sync::mpsc::unbounded_channel().1);
+                let (chunk_tx, mut chunk_rx) = tokio::sync::mpsc::unbounded_channel::<(usize, String)>();
+                let providers = &self.providers;
+                let mut pending: futures::stream::FuturesUnordered<_> = targets.iter().enumerate().map(|(index, (model, provider_name))| {
+                    let request = ChatRequest {
+                        messages: request_messages.clone(),
+                        // Thinking only goes back to the model that wrote it
+                        thinking_blocks: if *model == self.session.current_model { thinking_blocks.clone() } else { Vec::new() },
+                        model: model.clone(),
+                        temperature: params.temperature,
+                        max_tokens: params.max_tokens,
+                        thinking_budget: params.thinking_budget,
+                        stream: true,
+                        thinking: params.thinking_enabled,
+                    };
+                    let provider = providers[provider_name].as_ref();
+                    let chunk_tx = chunk_tx.clone();
+                    async move {
+                        let result = compare::collect_with(provider, provider_name, request, |text| {
+                            let _ = chunk_tx.send((index, text.to_string()));
+                        }).await;
+                        (index, result)
+                    }
+                }).collect();
+                drop(chunk_tx);
+                
+                let mut candidates: Vec<Result<Candidate, String>> = targets.iter().map(|_| Err("interrupted".to_string())).collect();
+                let mut partial: Vec<String> = targets.iter().map(|_| String::new()).collect();
+                let mut live: Option<usize> = None;
+                let mut waiting: Vec<usize> = Vec::new();
+                let mut remaining = targets.len();
+                let mut spinner = Some(self.ui.start_spinner(&format!("Waiting for {} models...", remaining)));
+                let interrupted = loop {
+                    let event = tokio::select! {
+                        biased;
+                        _ = ctrl_c_rx.recv() => None,
+                        Some(chunk) = chunk_rx.recv() => Some((vec![chunk], None)),
+                        next = pending.next() => {
+                            let Some(done) = next else {
+                                break false;
+                            };
+                            // The finished model's last pieces may still be queued
+                            let mut chunks = Vec::new();
+                            while let Ok(chunk) = chunk_rx.try_recv() {
+                                chunks.push(chunk);
+                            }
+                            Some((chunks, Some(done)))
+                        }
+                    };
+                    let Some((chunks, done)) = event else {
+                        break true;
+                    };
+                    
+                    for (index, text) in chunks {
+                        partial[index].push_str(&text);
+                        if live.is_none() {
+                            if let Some(s) = spinner.take() {
+                                s.stop().await;
+                            }
+                            live = Some(index);
+                            let (model, provider_name) = &targets[index];
+                            outln!("{}", compare::render_header(index + 1, model, provider_name));
+                            out!("{}", partial[index]);
+                        } else if live == Some(index) {
+                            out!("{}", text);
+                        }
+                    }
+                    
+                    let Some((index, result)) = done else {
+                        continue;
+                    };
+                    remaining -= 1;
+                    candidates[index] = result.map_err(|e| e.to_string());
+                    if live == Some(index) {
+                        live = None;
+                        outln!();
+                        match &candidates[index] {
+                            Ok(candidate) => outln!("{}", compare::render_details(candidate)),
+                            Err(e) => self.fail(e),
+                        }
+                        outln!();
+                        for index in waiting.drain(..) {
+                            self.print_comparison_reply(index, &targets[index], &candidates[index]);
+                        }
+                    } else if live.is_some() {
+                        waiting.push(index);
+                    } else {
+                        if let Some(s) = spinner.take() {
+                            s.stop().await;
+                        }
+                        self.print_comparison_reply(index, &targets[index], &candidates[index]);
+                    }
+                    if live.is_none() && remaining > 0 && spinner.is_none() {
+                        spinner = Some(self.ui.start_spinner(&format!("Waiting for {} more...", remaining)));
+                    }
+                };
+                if let Some(s) = spinner.take() {
+                    s.stop().await;
+                }
+                if live.is_some() {
+                    outln!();
+                }
+                for index in waiting {
+                    self.print_comparison_reply(index, &targets[index], &candidates[index]);
+                }
+                drop(pending);
+                self.ctrl_c_rx = ctrl_c_rx;
+                
+                let comparison = Comparison {
+                    prompt,
+                    params,
+                    session_id: self.session.id,
+                    message_count: self.session.messages.len(),
+                    candidates,
+                };
+                out!("{}", compare::render_summary(&models, &comparison));
+                if interrupted {
+                    self.ui.print_info("Comparison interrupted; replies still pending were dropped");
+                }
+                if comparison.candidates.iter().any(Result::is_ok) {
+                    self.ui.print_info("Add one of the replies to this session with /compare adopt N");
+                    self.last_comparison = Some(comparison);
+                }
+            }
+            Command::CompareAdopt(number) => {
+                let Some(comparison) = &self.last_comparison else {
+                    self.fail("Nothing to adopt. Run /compare MODEL1 MODEL2 -- PROMPT first");
+                    return Ok(true);
+                };
+                if comparison.session_id != self.session.id || comparison.message_count != self.session.messages.len() {
+                    self.fail("The session has changed since the comparison; run /compare again");
+                    return Ok(true);
+                }
+                let candidate = match number.checked_sub(1).and_then(|index| comparison.candidates.get(index)) {
+                    Some(Ok(candidate)) => candidate.clone(),
+                    Some(Err(e)) => {
+                        self.fail(&format!("Reply {} failed ({}), so there is nothing to adopt", number, e));
+                        return Ok(true);
+                    }
+                    None => {
+                        self.fail(&format!("No reply {}; the last comparison had {}", number, comparison.candidates.len()));
+                        return Ok(true);
+                    }
+                };
+                let user_message = Message { role: "user".to_string(), content: comparison.prompt.clone() };
+                let params = comparison.params.clone();
+                self.last_comparison = None;
+                
+                let agent_number = self.get_next_agent_number();
+                let model = candidate.model.clone();
+                let reply = StreamedReply {
+                    content: candidate.content,
+                    thinking: candidate.thinking,
+                    thinking_blocks: candidate.thinking_blocks,
+                    timing: candidate.timing,
+                };
+                self.record_exchange(user_message, reply, Some(candidate.provider), model.clone(), params);
+                self.ui.print_info(&format!("Added the reply from {} as Agent {}", model, agent_number));
+            }
+            Command::Source(path, keep_going) => {
+                let outcome = self.run_script(&path, keep_going).await?;
+                if outcome.quit {
+                    return Ok(false);
+                }
+            }
+            Command::GotoMark(name) => {
+                let Some((index, msg)) = self.session.find_bookmark(&name) else {
+                    self.fail(&format!("No bookmark named '{}' in this session", name));
+                    return Ok(true);
+                };
+                // A bookmarked question is staged for editing like /goto N; a bookmarked answer is kept
+                if msg.message.role == "user" {
+                    let user_number = self.session.messages[..=index].iter().filter(|msg| msg.message.role == "user").count();
+                    return Box::pin(self.run_command(Command::Goto(user_number))).await;
+                }
+                let removed = self.session.messages[index + 1..].to_vec();
+                if removed.is_empty() {
+                    self.ui.print_info(&format!("Bookmark '{}' is already the last message", name));
+                    return Ok(true);
+                }
+                match self.session.goto(index + 1) {
+                    Ok(()) => {
+                        let removed_count = removed.len();
+                        self.session.journal_operation(Operation::Goto { removed });
+                        self.ui.print_info(&format!("Jumped to bookmark '{}', removed {} later messages (/redo restores them)", name, removed_count));
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::CopyMark(name) => {
+                match self.session.find_bookmark(&name) {
+                    Some((_, msg)) => {
+                        let content = msg.message.content.clone();
+                        self.copy_content_to_clipboard(&content, &format!("Bookmark '{}'", name))?;
+                    }
+                    None => self.fail(&format!("No bookmark named '{}' in this session", name)),
+                }
+            }
+            Command::SaveMark(name, filename) => {
+                let Some((_, msg)) = self.session.find_bookmark(&name) else {
+                    // Not a bookmark, so "/save NAME FILE" was a filename with a space in it
+                    let filename = format!("{} {}", name, filename);
+                    return Box::pin(self.run_command(Command::Save(SaveType::Agent, None, filename))).await;
+                };
+                match std::fs::write(&filename, &msg.message.content) {
+                    Ok(()) => self.ui.print_info(&format!("Bookmark '{}' saved to '{}'", name, filename)),
+                    Err(e) => self.fail(&format!("Failed to save bookmark '{}': {}", name, e)),
+                }
+            }
+            Command::Redo => {
+                let last_removal = self.session.journal.iter().rposition(|entry| {
+                    !entry.reverted && matches!(entry.operation, Operation::Undo { .. } | Operation::Goto { .. })
+                });
+                let Some(index) = last_removal else {
+                    self.ui.print_info("Nothing to redo");
+                    return Ok(true);
+                };
+                match self.session.revert_operation(index) {
+                    Ok(description) => {
+                        self.queued_message = None;
+                        self.ui.print_info(&format!("Redone: {}", description.replacen("removed", "restored", 1)));
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Journal => {
+                let entries = self.journal_entries();
+                if entries.is_empty() {
+                    self.ui.print_info("No destructive operations recorded");
+                    return Ok(true);
+                }
+                self.ui.print_info(&format!("Recent operations ({}), newest first:", entries.len()));
+                for (index, (_, entry)) in entries.iter().enumerate() {
+                    let status = if entry.reverted { " \x1b[2m(reverted)\x1b[0m" } else { "" };
+                    outln!("  #{}: {} {}{}", index + 1, entry.timestamp.format("%Y-%m-%d %H:%M"), entry.operation.describe(), status);
+                }
+                outln!();
+                self.ui.print_info("Use '/journal revert #N' to reverse an operation");
+            }
+            Command::JournalRevert(number) => {
+                let entries = self.journal_entries();
+                let Some((source, entry)) = number.checked_sub(1).and_then(|index| entries.get(index)) else {
+                    self.fail(&format!("Invalid journal reference #{}", number));
+                    return Ok(true);
+                };
+                let operation = entry.operation.clone();
+                let result = match source {
+                    JournalSource::Session(index) => self.session.revert_operation(*index),
+                    JournalSource::History(index) => self.history.revert_operation(*index),
+                };
+                match result {
+                    Ok(description) => {
+                        self.ui.print_info(&format!("Reverted: {}", description));
+                        match operation {
+                            Operation::Rename { from, to } if self.session.name.as_ref() == Some(&to) => {
+                                self.session.name = Some(from);
+                            }
+                            Operation::Delete { session, .. } => {
+                                self.ui.print_info(&format!("Use '/chat load \"{}\"' to open it again", session));
+                            }
+                            Operation::Undo { .. } | Operation::Goto { .. } => self.queued_message = None,
+                            _ => {}
+                        }
+                        let _ = self.update_completion_context();
+                        self.update_session_list();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Model(model_name) => {
+                // Determine which provider this model belongs to
+                if let Some(required_provider) = get_provider_for_model(&model_name) {
+                    // Check if we have this provider available
+                    if let Some(provider) = self.providers.get(required_provider) {
                         let available_models = provider.get_models();
                         if available_models.contains(&model_name) {
                             let old_provider = self.get_current_provider().map(|s| s.to_string());
@@ -1474,15 +2825,15 @@ impl Repl {
                                 self.ui.print_info(&format!("Switched to model: {}", model_name));
                             }
                         } else {
-                            self.ui.print_error(&format!("Model '{}' not available. Available {} models: {}", 
+                            self.fail(&format!("Model '{}' not available. Available {} models: {}", 
                                 model_name, required_provider, available_models.join(", ")));
                         }
                     } else {
-                        self.ui.print_error(&format!("Provider '{}' not available (required for model '{}'). Check your API key.", 
+                        self.fail(&format!("Provider '{}' not available (required for model '{}'). Check your API key.", 
                             required_provider, model_name));
                     }
                 } else {
-                    self.ui.print_error(&format!("Unknown model: '{}'. Use /models to see available models.", model_name));
+                    self.fail(&format!("Unknown model: '{}'. Use /models to see available models.", model_name));
                 }
             }
             Command::Status => {
@@ -1509,14 +2860,40 @@ impl Repl {
                     let session_info = self.get_session_display();
                     self.ui.print_info(&format!("Session: {}", session_info));
                 } else {
-                    self.ui.print_error("No provider selected");
+                    self.fail("No provider selected");
                 }
             }
+            Command::Stats(all_sessions) => {
+                if !all_sessions {
+                    let mut stats = Stats::default();
+                    stats.add_session(&self.session);
+                    let title = match &self.session.name {
+                        Some(name) => format!("Session statistics: \"{}\"", name),
+                        None => "Session statistics".to_string(),
+                    };
+                    out!("{}", render_stats(&title, &stats, false));
+                    return Ok(true);
+                }
+
+                // The saved copy of the current session may be behind, so count the live one instead
+                let mut stats = Stats::default();
+                for name in self.history.list_sessions() {
+                    if self.session.name.as_ref() != Some(name) {
+                        if let Some(session) = self.history.load_session(name) {
+                            stats.add_session(session);
+                        }
+                    }
+                }
+                if !self.session.messages.is_empty() {
+                    stats.add_session(&self.session);
+                }
+                out!("{}", render_stats(&format!("Statistics across {} session(s)", stats.sessions), &stats, true));
+            }
             Command::ChatSave(name) => {
                 if name.trim().is_empty() {
-                    self.ui.print_error("Session name cannot be empty");
+                    self.fail("Session name cannot be empty");
                 } else if self.session.messages.is_empty() {
-                    self.ui.print_error("Cannot save empty session");
+                    self.fail("Cannot save empty session");
                 } else {
                     match self.history.save_session(name.clone(), self.session.clone()) {
                         Ok(()) => {
@@ -1526,7 +2903,7 @@ impl Repl {
                             self.update_session_list();
                         }
                         Err(e) => {
-                            self.ui.print_error(&format!("Failed to save session: {}", e));
+                            self.fail(&format!("Failed to save session: {}", e));
                         }
                     }
                 }
@@ -1582,32 +2959,45 @@ impl Repl {
                             
                             self.ui.print_info(&format!("Original session \"{}\" unchanged", name));
                         } else {
-                            self.ui.print_error(&format!("Session '{}' not found", name));
+                            self.fail(&format!("Session '{}' not found", name));
                             let available_sessions = self.history.list_sessions();
                             if !available_sessions.is_empty() {
                                 self.ui.print_info("Available sessions:");
                                 for session_name in available_sessions {
-                                    println!("  {}", session_name);
+                                    outln!("  {}", session_name);
                                 }
                             }
                         }
                     }
                     Err(e) => {
-                        self.ui.print_error(&e.to_string());
+                        self.fail(&e.to_string());
                     }
                 }
             }
-            Command::ChatList => {
-                let sessions = self.history.list_sessions();
+            Command::ChatList(options) => {
+                let sessions = self.history.filter_sessions(&options.filter);
                 if sessions.is_empty() {
-                    self.ui.print_info("No saved sessions");
+                    if self.history.saved_sessions.is_empty() {
+                        self.ui.print_info("No saved sessions");
+                    } else {
+                        self.ui.print_info("No sessions match the given filters");
+                    }
                 } else {
-                    self.ui.print_info("Saved sessions:");
+                    // Pagination: --limit sets the page size
+                    let page_size = options.limit.unwrap_or(sessions.len());
+                    let page_count = sessions.len().div_ceil(page_size);
+                    if options.page > page_count {
+                        self.fail(&format!("Page {} does not exist ({} pages)", options.page, page_count));
+                        return Ok(true);
+                    }
+                    let start = (options.page - 1) * page_size;
+
+                    self.ui.print_info(&format!("Saved sessions ({}):", sessions.len()));
                     
-                    // Update the ephemeral session list
+                    // Update the ephemeral session list; numbering covers all pages
                     self.last_session_list = sessions.iter().map(|s| s.to_string()).collect();
                     
-                    for (index, session_name) in sessions.iter().enumerate() {
+                    for (index, session_name) in sessions.iter().enumerate().skip(start).take(page_size) {
                         if let Some(session) = self.history.load_session(session_name) {
                             let message_count = session.messages.len();
                             let code_blocks = session.messages.iter()
@@ -1615,12 +3005,304 @@ impl Repl {
                                 .sum::<usize>();
                             
                             let updated = session.updated_at.format("%Y-%m-%d %H:%M");
-                            println!("  #{}: \"{}\" ({} messages, {} blocks, updated {})", 
-                                index + 1, session_name, message_count, code_blocks, updated);
+                            let tags = if session.tags.is_empty() {
+                                String::new()
+                            } else {
+                                format!(" \x1b[33m[{}]\x1b[0m", session.tags.join(", "))
+                            };
+                            outln!("  #{}: \"{}\" ({} messages, {} blocks, {}, updated {}){}", 
+                                index + 1, session_name, message_count, code_blocks, session.current_model, updated, tags);
+                            if let Some(description) = &session.description {
+                                outln!("      \x1b[2m{}\x1b[0m", description);
+                            }
+                        }
+                    }
+                    outln!();
+                    if page_count > 1 {
+                        self.ui.print_info(&format!("Page {} of {}. Use '--page N' to see more", options.page, page_count));
+                    }
+                    self.ui.print_info("Use '/chat load #N' to load by number or '/chat load \"name\"' to load by name");
+                }
+                self.session_list_filter = options.filter;
+            }
+            Command::ChatTag(tags, session_ref) => {
+                match self.update_session_metadata(session_ref.as_ref(), |session| {
+                    session.add_tags(&tags);
+                }) {
+                    Ok(label) => {
+                        let normalized: Vec<String> = tags.iter().map(|t| ChatSession::normalize_tag(t)).collect();
+                        self.ui.print_info(&format!("Tagged {} with: {}", label, normalized.join(", ")));
+                        let _ = self.update_completion_context();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::ChatUntag(tags, session_ref) => {
+                match self.update_session_metadata(session_ref.as_ref(), |session| {
+                    session.remove_tags(&tags);
+                }) {
+                    Ok(label) => {
+                        let normalized: Vec<String> = tags.iter().map(|t| ChatSession::normalize_tag(t)).collect();
+                        self.ui.print_info(&format!("Removed tags from {}: {}", label, normalized.join(", ")));
+                        let _ = self.update_completion_context();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::ChatDescribe(description_opt, session_ref) => {
+                match description_opt {
+                    None => {
+                        // Show the current description
+                        let target = match &session_ref {
+                            Some(session_ref) => match self.resolve_session_reference(session_ref) {
+                                Ok(name) => match self.history.load_session(&name) {
+                                    Some(session) => session.clone(),
+                                    None => {
+                                        self.fail(&format!("Session '{}' not found", name));
+                                        return Ok(true);
+                                    }
+                                },
+                                Err(e) => {
+                                    self.fail(&e.to_string());
+                                    return Ok(true);
+                                }
+                            },
+                            None => self.session.clone(),
+                        };
+                        match &target.description {
+                            Some(description) => outln!("{}", description),
+                            None => self.ui.print_info("No description set. Use '/chat describe TEXT' to add one"),
+                        }
+                        if !target.tags.is_empty() {
+                            self.ui.print_info(&format!("Tags: {}", target.tags.join(", ")));
+                        }
+                    }
+                    Some(text) => {
+                        let description = if text.trim().is_empty() || text.trim() == "clear" {
+                            None
+                        } else {
+                            Some(text.trim().to_string())
+                        };
+                        let cleared = description.is_none();
+                        match self.update_session_metadata(session_ref.as_ref(), |session| {
+                            session.description = description.clone();
+                        }) {
+                            Ok(label) => {
+                                if cleared {
+                                    self.ui.print_info(&format!("Description cleared for {}", label));
+                                } else {
+                                    self.ui.print_info(&format!("Description set for {}", label));
+                                }
+                            }
+                            Err(e) => self.fail(&e.to_string()),
+                        }
+                    }
+                }
+            }
+            Command::ChatDiff(left_ref, right_ref) => {
+                let mut sessions = Vec::new();
+                for session_ref in [Some(left_ref), right_ref] {
+                    let resolved = match session_ref {
+                        Some(session_ref) => self.resolve_session_reference(&session_ref).and_then(|name| {
+                            // The active session may be newer than its saved copy
+                            if self.session.name.as_ref() == Some(&name) {
+                                Ok((name, self.session.clone()))
+                            } else {
+                                self.history.load_session(&name)
+                                    .cloned()
+                                    .map(|session| (name.clone(), session))
+                                    .ok_or_else(|| anyhow::anyhow!("Session '{}' not found", name))
+                            }
+                        }),
+                        None => Ok((
+                            self.session.name.clone().unwrap_or_else(|| "current session".to_string()),
+                            self.session.clone(),
+                        )),
+                    };
+                    match resolved {
+                        Ok(entry) => sessions.push(entry),
+                        Err(e) => {
+                            self.fail(&e.to_string());
+                            return Ok(true);
+                        }
+                    }
+                }
+                
+                let (left_name, left) = &sessions[0];
+                let (right_name, right) = &sessions[1];
+                let diff = diff_sessions(left, right);
+                out!("{}", render_session_diff(left_name, right_name, &diff, terminal_width()));
+            }
+            Command::ChatMerge(source_ref, target_ref, order) => {
+                let source_name = match self.resolve_session_reference(&source_ref) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        self.fail(&e.to_string());
+                        return Ok(true);
+                    }
+                };
+                let target_name = match target_ref.as_ref().map(|r| self.resolve_session_reference(r)).transpose() {
+                    Ok(name) => name,
+                    Err(e) => {
+                        self.fail(&e.to_string());
+                        return Ok(true);
+                    }
+                };
+                
+                // The active session may be newer than its saved copy, so prefer it
+                let is_active = |name: &String| self.session.name.as_ref() == Some(name);
+                let source = if is_active(&source_name) {
+                    Some(self.session.clone())
+                } else {
+                    self.history.load_session(&source_name).cloned()
+                };
+                let Some(source) = source else {
+                    self.fail(&format!("Session '{}' not found", source_name));
+                    return Ok(true);
+                };
+                let target_is_active = target_name.as_ref().map(is_active).unwrap_or(true);
+                let mut target = if target_is_active {
+                    self.session.clone()
+                } else {
+                    let name = target_name.clone().unwrap_or_default();
+                    match self.history.load_session(&name) {
+                        Some(session) => session.clone(),
+                        None => {
+                            self.fail(&format!("Session '{}' not found", name));
+                            return Ok(true);
+                        }
+                    }
+                };
+                if target.id == source.id || target.name.as_ref() == Some(&source_name) {
+                    self.fail("Cannot merge a session into itself");
+                    return Ok(true);
+                }
+                
+                // Ask about anything that can't be combined automatically
+                let conflicts = target.merge_conflicts(&source);
+                let mut resolution = MergeResolution::default();
+                if conflicts.system_prompt {
+                    self.ui.print_info("The sessions have different system prompts:");
+                    outln!("  \x1b[1mtarget:\x1b[0m {}", target.system_prompt.as_deref().unwrap_or_default());
+                    outln!("  \x1b[1msource:\x1b[0m {}", source.system_prompt.as_deref().unwrap_or_default());
+                    self.ui.print_info("Keep [t]arget, [s]ource or [b]oth? (default: target)");
+                    if let Some(answer) = self.ui.read_input(None, None, false, self.config.ephemeral, None)? {
+                        resolution.system_prompt = match answer.trim().to_lowercase().as_str() {
+                            "s" | "source" => SystemPromptChoice::Source,
+                            "b" | "both" => SystemPromptChoice::Both,
+                            _ => SystemPromptChoice::Target,
+                        };
+                    }
+                }
+                for name in &conflicts.variables {
+                    let (Some(existing), Some(incoming)) = (target.variables.get(name), source.variables.get(name)) else {
+                        continue;
+                    };
+                    self.ui.print_info(&format!(
+                        "Variable '{}' is {} in the target but {} in the source. Use the source binding? [y/N]",
+                        name, existing.source.display_source(), incoming.source.display_source()
+                    ));
+                    if let Some(answer) = self.ui.read_input(None, None, false, self.config.ephemeral, None)? {
+                        if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                            resolution.source_variables.push(name.clone());
                         }
                     }
-                    println!();
-                    self.ui.print_info("Sessions ordered by most recent update. Use '/chat load #N' to load by number or '/chat load \"name\"' to load by name");
+                }
+                
+                let added = target.merge_from(&source, &source_name, order, &resolution);
+                let total = target.messages.len();
+                let label = match &target_name {
+                    Some(name) => format!("'{}'", name),
+                    None => "current session".to_string(),
+                };
+                
+                if target_is_active {
+                    self.session = target;
+                    let session_clone = self.session.clone();
+                    self.restore_session_variables(&session_clone);
+                    if let Err(e) = self.history.auto_save_session(&self.session) {
+                        self.fail(&format!("Failed to save merged session: {}", e));
+                    }
+                } else if let Some(name) = &target_name {
+                    if let Err(e) = self.history.update_session(name, |session| *session = target.clone()) {
+                        self.fail(&format!("Failed to save merged session: {}", e));
+                        return Ok(true);
+                    }
+                }
+                
+                self.ui.print_info(&format!("Merged {} messages from '{}' into {} ({} messages total)", added, source_name, label, total));
+                self.ui.print_info(&format!("Session '{}' was left unchanged", source_name));
+                let _ = self.update_completion_context();
+                self.update_session_list();
+            }
+            Command::ChatTrash => {
+                let trashed = self.history.list_trash();
+                if trashed.is_empty() {
+                    self.ui.print_info("Trash is empty");
+                } else {
+                    self.ui.print_info(&format!("Trash ({}):", trashed.len()));
+                    for (index, (name, entry)) in trashed.iter().enumerate() {
+                        outln!("  #{}: \"{}\" ({} messages, deleted {})",
+                            index + 1, name, entry.session.messages.len(), entry.deleted_at.format("%Y-%m-%d %H:%M"));
+                    }
+                    outln!();
+                    if self.config.trash_retention_days > 0 {
+                        self.ui.print_info(&format!("Sessions are purged {} days after deletion", self.config.trash_retention_days));
+                    }
+                    self.ui.print_info("Use '/chat restore #N' to restore or '/chat purge #N' to delete permanently");
+                }
+            }
+            Command::ChatRestore(session_ref, new_name) => {
+                let trash_name = match self.resolve_trash_reference(&session_ref) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        self.fail(&e.to_string());
+                        return Ok(true);
+                    }
+                };
+                match self.history.restore_session(&trash_name, new_name.as_deref()) {
+                    Ok(name) => {
+                        self.ui.print_info(&format!("Session '{}' restored. Use '/chat load \"{}\"' to open it", name, name));
+                        let _ = self.update_completion_context();
+                        self.update_session_list();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::ChatPurge(session_ref_opt, confirmed) => {
+                let target = match session_ref_opt {
+                    Some(session_ref) => match self.resolve_trash_reference(&session_ref) {
+                        Ok(name) => Some(name),
+                        Err(e) => {
+                            self.fail(&e.to_string());
+                            return Ok(true);
+                        }
+                    },
+                    None => None,
+                };
+                let trashed = self.history.list_trash().len();
+                if target.is_none() && trashed > 0 && !confirmed {
+                    if !std::io::stdin().is_terminal() {
+                        self.fail("Emptying the trash can't be undone; use '/chat purge --yes' to confirm");
+                        return Ok(true);
+                    }
+                    self.ui.print_info(&format!("Permanently delete all {} session(s) in the trash? [y/N]", trashed));
+                    let answer = self.ui.read_input(None, None, false, self.config.ephemeral, None)?.unwrap_or_default();
+                    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                        self.ui.print_info("Trash left as it was");
+                        return Ok(true);
+                    }
+                }
+                match self.history.purge_trash(target.as_deref()) {
+                    Ok(0) => self.ui.print_info("Trash is empty"),
+                    Ok(count) => {
+                        match target {
+                            Some(name) => self.ui.print_info(&format!("Session '{}' permanently deleted", name)),
+                            None => self.ui.print_info(&format!("Permanently deleted {} session(s) from the trash", count)),
+                        }
+                        let _ = self.update_completion_context();
+                    }
+                    Err(e) => self.fail(&e.to_string()),
                 }
             }
             Command::ChatDelete(session_ref_opt) => {
@@ -1629,14 +3311,15 @@ impl Repl {
                     let target_name = match self.resolve_session_reference(&session_ref) {
                         Ok(name) => name,
                         Err(e) => {
-                            self.ui.print_error(&e.to_string());
+                            self.fail(&e.to_string());
                             return Ok(true);
                         }
                     };
                     
-                    match self.history.delete_session(&target_name) {
-                        Ok(true) => {
-                            self.ui.print_info(&format!("Session '{}' deleted", target_name));
+                    match self.history.trash_session(&target_name) {
+                        Ok(Some(trash_name)) => {
+                            self.ui.print_info(&format!("Session '{}' moved to trash (undo with /chat restore)", target_name));
+                            self.journal_session_operation(Operation::Delete { session: target_name.clone(), trash_name });
                             
                             // Check if we deleted the current session
                             if self.session.name.as_ref() == Some(&target_name) {
@@ -1650,20 +3333,21 @@ impl Repl {
                             let _ = self.update_completion_context();
                             self.update_session_list();
                         }
-                        Ok(false) => {
-                            self.ui.print_error(&format!("Session '{}' not found", target_name));
+                        Ok(None) => {
+                            self.fail(&format!("Session '{}' not found", target_name));
                         }
                         Err(e) => {
-                            self.ui.print_error(&format!("Failed to delete session: {}", e));
+                            self.fail(&format!("Failed to delete session: {}", e));
                         }
                     }
                 } else {
                     // Delete current session (no session reference provided)
-                    if let Some(ref current_name) = self.session.name {
+                    if let Some(current_name) = self.session.name.clone() {
                         // Current session has a name - delete it from history
-                        match self.history.delete_session(current_name) {
-                            Ok(true) => {
-                                self.ui.print_info(&format!("Session '{}' deleted", current_name));
+                        match self.history.trash_session(&current_name) {
+                            Ok(Some(trash_name)) => {
+                                self.ui.print_info(&format!("Session '{}' moved to trash (undo with /chat restore)", current_name));
+                                self.journal_session_operation(Operation::Delete { session: current_name.clone(), trash_name });
                                 
                                 // Reset to a new anonymous session
                                 self.session = Self::create_session_with_defaults(&self.config, &self.history);
@@ -1674,11 +3358,11 @@ impl Repl {
                                 let _ = self.update_completion_context();
                                 self.update_session_list();
                             }
-                            Ok(false) => {
-                                self.ui.print_error(&format!("Session '{}' not found", current_name));
+                            Ok(None) => {
+                                self.fail(&format!("Session '{}' not found", current_name));
                             }
                             Err(e) => {
-                                self.ui.print_error(&format!("Failed to delete session: {}", e));
+                                self.fail(&format!("Failed to delete session: {}", e));
                             }
                         }
                     } else {
@@ -1697,24 +3381,31 @@ impl Repl {
             }
             Command::Search(term) => {
                 if term.trim().is_empty() {
-                    self.ui.print_error("Search term cannot be empty");
+                    self.fail("Search term cannot be empty");
                 } else {
-                    let results = self.history.search_all_sessions(&term, &self.session);
+                    let query = match SearchQuery::parse(&term) {
+                        Ok(query) => query,
+                        Err(e) => {
+                            self.fail(&e.to_string());
+                            return Ok(true);
+                        }
+                    };
+                    let results = self.history.search_all_sessions(&query, &self.session);
                     
                     if results.is_empty() {
                         self.ui.print_info(&format!("No results found for '{}'", term));
                     } else {
                         self.ui.print_info(&format!("Search results for '{}' ({} matches):", term, results.len()));
-                        println!();
+                        outln!();
                         
                         let mut current_session = String::new();
-                        for result in results {
+                        for (index, result) in results.iter().enumerate() {
                             // Print session header if this is a new session
                             if result.session_name != current_session {
                                 if !current_session.is_empty() {
-                                    println!(); // Add spacing between sessions
+                                    outln!(); // Add spacing between sessions
                                 }
-                                println!("\x1b[1;36m[{}]\x1b[0m", result.session_name);
+                                outln!("\x1b[1;36m[{}]\x1b[0m", result.session_name);
                                 current_session = result.session_name.clone();
                             }
                             
@@ -1725,34 +3416,79 @@ impl Repl {
                                 "\x1b[1;35m" // Magenta for assistant
                             };
                             
-                            println!("  {}Message {} ({})\x1b[0m: {}", 
+                            let bookmark = result.bookmark.as_ref()
+                                .map(|name| format!(" \x1b[1;33m[{}]\x1b[0m", name))
+                                .unwrap_or_default();
+                            outln!("  \x1b[2m#{}\x1b[0m {}Message {} ({})\x1b[0m{}: {}", 
+                                index + 1,
                                 role_color,
                                 result.message_number,
                                 result.role.chars().next().unwrap().to_uppercase().collect::<String>() + &result.role[1..],
+                                bookmark,
                                 result.excerpt
                             );
                         }
                         
-                        println!();
-                        self.ui.print_info("Use /goto N to jump to a message, or /chat load SESSION to switch sessions");
+                        outln!();
+                        self.ui.print_info("Use /search #N to open a result in its session");
+                        self.last_search_results = results;
+                    }
+                }
+            }
+            Command::SearchOpen(number) => {
+                let Some(result) = number.checked_sub(1).and_then(|i| self.last_search_results.get(i)).cloned() else {
+                    self.fail(&format!("No search result #{} (run /search first)", number));
+                    return Ok(true);
+                };
+
+                if result.session_name != "current" {
+                    if self.history.load_session(&result.session_name).is_none() {
+                        self.fail(&format!("Session '{}' no longer exists", result.session_name));
+                        return Ok(true);
+                    }
+                    Box::pin(self.run_command(Command::ChatLoad(SessionReference::Named(result.session_name.clone())))).await?;
+                    // Results for the session we loaded now point at the current one
+                    for other in &mut self.last_search_results {
+                        if other.session_name == result.session_name {
+                            other.session_name = "current".to_string();
+                        } else if other.session_name == "current" {
+                            other.session_name = "(previous session)".to_string();
+                        }
                     }
                 }
+
+                match self.session.messages.iter().find(|msg| msg.number == result.message_number) {
+                    Some(msg) => {
+                        let (label, color) = if msg.message.role == "user" {
+                            ("User", "\x1b[1;34m")
+                        } else {
+                            ("Agent", "\x1b[1;35m")
+                        };
+                        let timestamp = msg.timestamp.format("%Y-%m-%d %H:%M:%S");
+                        outln!("{}[{}] {} {}\x1b[0m: {}", color, result.exchange, label, timestamp, self.ui.style_code_blocks(&msg.message.content));
+                        outln!();
+                        if msg.message.role == "user" {
+                            self.ui.print_info(&format!("Use /goto {} to continue the conversation from this message", result.exchange));
+                        }
+                    }
+                    None => self.fail(&format!("Message {} no longer exists in this session", result.message_number)),
+                }
             }
-            Command::History(expand) => {
+            Command::History(expand, include_thinking) => {
                 if self.session.messages.is_empty() {
                     self.ui.print_info("No messages in current session");
                 } else {
                     self.ui.print_info(&format!("Session history ({} messages):", self.session.messages.len()));
                     if let Some(name) = &self.session.name {
-                        println!("Session name: {}", name);
+                        outln!("Session name: {}", name);
                     }
-                    println!("Created: {}", self.session.created_at.format("%Y-%m-%d %H:%M:%S UTC"));
-                    println!("Model: {}", self.session.current_model);
-                    println!("Temperature: {}", self.session.temperature);
+                    outln!("Created: {}", self.session.created_at.format("%Y-%m-%d %H:%M:%S UTC"));
+                    outln!("Model: {}", self.session.current_model);
+                    outln!("Temperature: {}", self.session.temperature);
                     if let Some(system_prompt) = &self.session.system_prompt {
-                        println!("System prompt: {}",system_prompt);
+                        outln!("System prompt: {}",system_prompt);
                     }
-                    println!();
+                    outln!();
 
                     let mut conversation_index = 0;
                     let mut i = 0;
@@ -1765,7 +3501,10 @@ impl Repl {
 
                             // Print user message
                             let timestamp = current_msg.timestamp.format("%H:%M:%S");
-                            let header = format!("\x1b[1;34m[{}] User {}", conversation_index, timestamp);
+                            let mut header = format!("\x1b[1;34m[{}] User {}", conversation_index, timestamp);
+                            if let Some(origin) = &current_msg.origin {
+                                header.push_str(&format!(" (from '{}')", origin.session));
+                            }
 
                             // Get content, optionally expanded
                             let content = if expand {
@@ -1778,8 +3517,9 @@ impl Repl {
                             };
 
                             let styled_content = self.ui.style_code_blocks(&content);
-                            println!("{}\x1b[0m: {}", header, styled_content);
-                            println!();
+                            outln!("{}\x1b[0m{}: {}", header, Self::message_labels(current_msg), styled_content);
+                            Self::print_note(current_msg);
+                            outln!();
 
                             // Look for the corresponding agent message
                             if i + 1 < self.session.messages.len() {
@@ -1795,9 +3535,17 @@ impl Repl {
                                         agent_header.push_str(&format!(" ({})", provider));
                                     }
 
+                                    if let Some(origin) = &next_msg.origin {
+                                        agent_header.push_str(&format!(" (from '{}')", origin.session));
+                                    }
+
+                                    if include_thinking {
+                                        Self::print_thinking(next_msg);
+                                    }
                                     let agent_styled_content = self.ui.style_code_blocks(&next_msg.message.content);
-                                    println!("{}\x1b[0m: {}", agent_header, agent_styled_content);
-                                    println!();
+                                    outln!("{}\x1b[0m{}: {}", agent_header, Self::message_labels(next_msg), agent_styled_content);
+                                    Self::print_note(next_msg);
+                                    outln!();
 
                                     i += 2; // Skip both user and agent message
                                 } else {
@@ -1817,9 +3565,13 @@ impl Repl {
                                 header.push_str(&format!(" ({})", provider));
                             }
 
+                            if include_thinking {
+                                Self::print_thinking(current_msg);
+                            }
                             let styled_content = self.ui.style_code_blocks(&current_msg.message.content);
-                            println!("{}\x1b[0m: {}", header, styled_content);
-                            println!();
+                            outln!("{}\x1b[0m{}: {}", header, Self::message_labels(current_msg), styled_content);
+                            Self::print_note(current_msg);
+                            outln!();
 
                             i += 1;
                         }
@@ -1830,10 +3582,12 @@ impl Repl {
                 // Find the user message with the given number
                 if let Some((message_index, user_message)) = self.get_user_message_by_number(user_number) {
                     let removed_count = self.session.messages.len().saturating_sub(message_index);
+                    let removed = self.session.messages[message_index..].to_vec();
                     
                     // Jump to just before this user message (so it gets queued for editing)
                     match self.session.goto(message_index) {
                         Ok(()) => {
+                            self.session.journal_operation(Operation::Goto { removed });
                             self.ui.print_info(&format!("Jumped to User {}, removed {} later messages", 
                                 user_number, removed_count));
                             
@@ -1842,11 +3596,11 @@ impl Repl {
                             self.ui.print_info("User message staged for editing - press Enter to modify and resend");
                         }
                         Err(e) => {
-                            self.ui.print_error(&e.to_string());
+                            self.fail(&e.to_string());
                         }
                     }
                 } else {
-                    self.ui.print_error(&format!("User {} not found", user_number));
+                    self.fail(&format!("User {} not found", user_number));
                 }
             }
             Command::System(prompt) => {
@@ -1854,7 +3608,7 @@ impl Repl {
                     // Show current system prompt
                     if let Some(current_prompt) = &self.session.system_prompt {
                         self.ui.print_info("Current system prompt:");
-                        println!("{}", current_prompt);
+                        outln!("{}", current_prompt);
                     } else {
                         self.ui.print_info("No system prompt is currently set");
                     }
@@ -1870,7 +3624,7 @@ impl Repl {
             }
             Command::Temperature(temp) => {
                 if !(0.0..=2.0).contains(&temp) {
-                    self.ui.print_error("Temperature must be between 0.0 and 2.0");
+                    self.fail("Temperature must be between 0.0 and 2.0");
                 } else {
                     self.session.temperature = temp;
                     self.ui.print_info(&format!("Temperature set to {}", temp));
@@ -1878,7 +3632,7 @@ impl Repl {
             }
             Command::MaxTokens(tokens) => {
                 if tokens == 0 {
-                    self.ui.print_error("Max tokens must be greater than 0");
+                    self.fail("Max tokens must be greater than 0");
                 } else {
                     self.session.max_tokens = tokens;
                     self.ui.print_info(&format!("Max tokens set to {}", tokens));
@@ -1886,12 +3640,32 @@ impl Repl {
             }
             Command::ThinkingBudget(budget) => {
                 if budget == 0 {
-                    self.ui.print_error("Thinking budget must be greater than 0");
+                    self.fail("Thinking budget must be greater than 0");
                 } else {
                     self.session.thinking_budget = budget;
                     self.ui.print_info(&format!("Thinking budget set to {}", budget));
                 }
             }
+            Command::ThinkingShow(number) => {
+                let agent_number = number.unwrap_or_else(|| {
+                    self.session.messages.iter().filter(|msg| msg.message.role == "assistant").count()
+                });
+                let Some(index) = self.get_agent_message_index_by_number(agent_number) else {
+                    self.fail(&format!("Agent {} not found", agent_number));
+                    return Ok(true);
+                };
+                match &self.session.messages[index].thinking {
+                    Some(thinking) => {
+                        self.ui.print_thinking_prefix(agent_number);
+                        self.ui.print_thinking_chunk(thinking.trim_end());
+                        self.ui.print_thinking_end();
+                    }
+                    None => self.ui.print_info(&format!(
+                        "Agent {} has no recorded thinking. Enable it with /thinking on, on models that support it",
+                        agent_number
+                    )),
+                }
+            }
             Command::Thinking(enable) => {
                 self.session.thinking_enabled = enable;
                 self.ui.print_info(&format!("Thinking {}", if enable { "enabled" } else { "disabled" }));
@@ -1922,14 +3696,14 @@ impl Repl {
                             format!("{} {}", role_display, block_ref.message_number)
                         };
                         
-                        println!("  [{}] {} ({}): {}", 
+                        outln!("  [{}] {} ({}): {}", 
                             block_ref.global_number, 
                             conversation_display,
                             language_display, 
                             preview
                         );
                     }
-                    println!();
+                    outln!();
                     self.ui.print_info("Use /block N to view, /copy N to copy, /save N FILE to save, /exec N to execute");
                 }
             }
@@ -1947,12 +3721,30 @@ impl Repl {
                         format!("{} {}", role_display, block.message_number)
                     };
                     self.ui.print_info(&format!("Code block {} from {}:", block_number, conversation_display));
-                    println!();
+                    outln!();
                     self.ui.print_styled_code_block(&block.code_block.content, block.code_block.language.as_deref());
                 } else {
-                    self.ui.print_error(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
+                    self.fail(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
                 }
             }
+            Command::CopyThinking(number) => {
+                let agent_number = number.unwrap_or_else(|| {
+                    self.session.messages.iter().filter(|msg| msg.message.role == "assistant").count()
+                });
+                let Some(index) = self.get_agent_message_index_by_number(agent_number) else {
+                    self.fail(&format!("Agent #{} not found", agent_number));
+                    return Ok(true);
+                };
+                let msg = &self.session.messages[index];
+                let content = match &msg.thinking {
+                    Some(thinking) => format!("Thinking:\n{}\n\n{}", thinking.trim_end(), msg.message.content),
+                    None => {
+                        self.ui.print_info(&format!("Agent #{} has no recorded thinking; copying the answer only", agent_number));
+                        msg.message.content.clone()
+                    }
+                };
+                self.copy_content_to_clipboard(&content, &format!("Agent #{} with thinking", agent_number))?;
+            }
             Command::Copy(copy_type, number) => {
                 match copy_type {
                     CopyType::Agent => {
@@ -1965,7 +3757,7 @@ impl Repl {
                             self.copy_content_to_clipboard(content, &format!("Agent #{}", display_number))?;
                         } else {
                             let display_number = number.unwrap_or(1);
-                            self.ui.print_error(&format!("Agent #{} not found", display_number));
+                            self.fail(&format!("Agent #{} not found", display_number));
                         }
                     }
                     CopyType::User => {
@@ -1978,7 +3770,7 @@ impl Repl {
                             self.copy_content_to_clipboard(content, &format!("User #{}", display_number))?;
                         } else {
                             let display_number = number.unwrap_or(1);
-                            self.ui.print_error(&format!("User #{} not found", display_number));
+                            self.fail(&format!("User #{} not found", display_number));
                         }
                     }
                     CopyType::Block => {
@@ -1987,7 +3779,7 @@ impl Repl {
                         if let Some(block) = all_blocks.get(block_number.saturating_sub(1)) {
                             self.copy_content_to_clipboard(&block.code_block.content, &format!("Code block #{}", block_number))?;
                         } else {
-                            self.ui.print_error(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
+                            self.fail(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
                         }
                     }
                 }
@@ -2006,12 +3798,12 @@ impl Repl {
                                     self.ui.print_info(&format!("Agent #{} saved to '{}'", display_number, filename));
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&format!("Failed to save agent response: {}", e));
+                                    self.fail(&format!("Failed to save agent response: {}", e));
                                 }
                             }
                         } else {
                             let display_number = number.unwrap_or(1);
-                            self.ui.print_error(&format!("Agent #{} not found", display_number));
+                            self.fail(&format!("Agent #{} not found", display_number));
                         }
                     }
                     SaveType::User => {
@@ -2026,12 +3818,12 @@ impl Repl {
                                     self.ui.print_info(&format!("User #{} saved to '{}'", display_number, filename));
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&format!("Failed to save user message: {}", e));
+                                    self.fail(&format!("Failed to save user message: {}", e));
                                 }
                             }
                         } else {
                             let display_number = number.unwrap_or(1);
-                            self.ui.print_error(&format!("User #{} not found", display_number));
+                            self.fail(&format!("User #{} not found", display_number));
                         }
                     }
                     SaveType::Block => {
@@ -2043,11 +3835,11 @@ impl Repl {
                                     self.ui.print_info(&format!("Code block {} saved to '{}'", block_number, filename));
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&format!("Failed to save code block: {}", e));
+                                    self.fail(&format!("Failed to save code block: {}", e));
                                 }
                             }
                         } else {
-                            self.ui.print_error(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
+                            self.fail(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
                         }
                     }
                 }
@@ -2056,9 +3848,9 @@ impl Repl {
                 let all_blocks = self.get_all_code_blocks();
                 if let Some(block) = all_blocks.get(block_number.saturating_sub(1)) {
                     self.ui.print_info(&format!("Code block {} from message {}:", block_number, block.message_number));
-                    println!();
+                    outln!();
                     self.ui.print_styled_code_block(&block.code_block.content, block.code_block.language.as_deref());
-                    println!();
+                    outln!();
                     self.ui.print_info("⚠️  Execute this code? This will run the code on your system!");
                     self.ui.print_info("Type 'yes' to execute, anything else to cancel:");
                     
@@ -2070,7 +3862,7 @@ impl Repl {
                         }
                     }
                 } else {
-                    self.ui.print_error(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
+                    self.fail(&format!("Code block {} not found. Use /blocks to list all code blocks.", block_number));
                 }
             }
             // Prompt library commands
@@ -2101,10 +3893,11 @@ impl Repl {
                                 format!("\x1b[1;36m{}\x1b[0m", name)
                             };
                             
-                            println!("  {}{}: {}", display_name, usage_info, preview);
+                            let origin = if self.prompts.is_inherited(name) { " \x1b[2m(global)\x1b[0m" } else { "" };
+                            outln!("  {}{}{}: {}", display_name, origin, usage_info, preview);
                         }
                     }
-                    println!();
+                    outln!();
                     self.ui.print_info("Use '/prompts show NAME' to view, '/prompts apply NAME' to use");
                 }
             }
@@ -2112,23 +3905,23 @@ impl Repl {
                 if let Some(prompt) = self.prompts.get_prompt(&name) {
                     self.ui.print_info(&format!("Prompt: \"{}\"", name));
                     if let Some(ref description) = prompt.description {
-                        println!("Description: {}", description);
+                        outln!("Description: {}", description);
                     }
                     if !prompt.tags.is_empty() {
-                        println!("Tags: {}", prompt.tags.join(", "));
+                        outln!("Tags: {}", prompt.tags.join(", "));
                     }
-                    println!("Created: {}", prompt.created_at.format("%Y-%m-%d %H:%M UTC"));
-                    println!("Updated: {}", prompt.updated_at.format("%Y-%m-%d %H:%M UTC"));
-                    println!("Usage count: {}", prompt.usage_count);
-                    println!();
-                    println!("{}", prompt.content);
+                    outln!("Created: {}", prompt.created_at.format("%Y-%m-%d %H:%M UTC"));
+                    outln!("Updated: {}", prompt.updated_at.format("%Y-%m-%d %H:%M UTC"));
+                    outln!("Usage count: {}", prompt.usage_count);
+                    outln!();
+                    outln!("{}", prompt.content);
                 } else {
-                    self.ui.print_error(&format!("Prompt '{}' not found", name));
+                    self.fail(&format!("Prompt '{}' not found", name));
                     let available_prompts = self.prompts.list_prompts();
                     if !available_prompts.is_empty() {
                         self.ui.print_info("Available prompts:");
                         for prompt_name in available_prompts.iter().take(5) {
-                            println!("  {}", prompt_name);
+                            outln!("  {}", prompt_name);
                         }
                     }
                 }
@@ -2139,12 +3932,12 @@ impl Repl {
                 } else if let Some(ref current_prompt) = self.session.system_prompt {
                     current_prompt.clone()
                 } else {
-                    self.ui.print_error("No system prompt is currently set and no content provided");
+                    self.fail("No system prompt is currently set and no content provided");
                     return Ok(true);
                 };
                 
                 if content.trim().is_empty() {
-                    self.ui.print_error("Cannot save empty prompt");
+                    self.fail("Cannot save empty prompt");
                     return Ok(true);
                 }
                 
@@ -2161,7 +3954,7 @@ impl Repl {
                         let _ = self.update_completion_context();
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to save prompt: {}", e));
+                        self.fail(&format!("Failed to save prompt: {}", e));
                     }
                 }
             }
@@ -2178,12 +3971,12 @@ impl Repl {
                     };
                     self.ui.print_info(&format!("System prompt: {}", preview));
                 } else {
-                    self.ui.print_error(&format!("Prompt '{}' not found", name));
+                    self.fail(&format!("Prompt '{}' not found", name));
                     let available_prompts = self.prompts.list_prompts();
                     if !available_prompts.is_empty() {
                         self.ui.print_info("Available prompts:");
                         for prompt_name in available_prompts.iter().take(5) {
-                            println!("  {}", prompt_name);
+                            outln!("  {}", prompt_name);
                         }
                     }
                 }
@@ -2196,16 +3989,16 @@ impl Repl {
                         let _ = self.update_completion_context();
                     }
                     Ok(false) => {
-                        self.ui.print_error(&format!("Prompt '{}' not found", name));
+                        self.fail(&format!("Prompt '{}' not found", name));
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to delete prompt: {}", e));
+                        self.fail(&format!("Failed to delete prompt: {}", e));
                     }
                 }
             }
             Command::PromptsRename(old_name, new_name) => {
                 if new_name.trim().is_empty() {
-                    self.ui.print_error("New prompt name cannot be empty");
+                    self.fail("New prompt name cannot be empty");
                 } else {
                     match self.prompts.rename_prompt(&old_name, &new_name) {
                         Ok(true) => {
@@ -2214,17 +4007,17 @@ impl Repl {
                             let _ = self.update_completion_context();
                         }
                         Ok(false) => {
-                            self.ui.print_error(&format!("Prompt '{}' not found", old_name));
+                            self.fail(&format!("Prompt '{}' not found", old_name));
                         }
                         Err(e) => {
-                            self.ui.print_error(&format!("Failed to rename prompt: {}", e));
+                            self.fail(&format!("Failed to rename prompt: {}", e));
                         }
                     }
                 }
             }
             Command::PromptsSearch(term) => {
                 if term.trim().is_empty() {
-                    self.ui.print_error("Search term cannot be empty");
+                    self.fail("Search term cannot be empty");
                 } else {
                     let results = self.prompts.search_prompts(&term);
                     
@@ -2232,7 +4025,7 @@ impl Repl {
                         self.ui.print_info(&format!("No prompts found matching '{}'", term));
                     } else {
                         self.ui.print_info(&format!("Search results for '{}' ({} matches):", term, results.len()));
-                        println!();
+                        outln!();
                         
                         for result in results {
                             let usage_info = if result.prompt.usage_count > 0 {
@@ -2247,7 +4040,7 @@ impl Repl {
                                 result.prompt.content.replace('\n', " ")
                             };
                             
-                            println!("  \x1b[1;36m\"{}\"\x1b[0m{} [{}]: {}", 
+                            outln!("  \x1b[1;36m\"{}\"\x1b[0m{} [{}]: {}", 
                                 result.name, 
                                 usage_info,
                                 result.matched_fields.join(", "),
@@ -2255,7 +4048,7 @@ impl Repl {
                             );
                         }
                         
-                        println!();
+                        outln!();
                         self.ui.print_info("Use '/prompts show NAME' to view full prompt");
                     }
                 }
@@ -2266,16 +4059,16 @@ impl Repl {
                         // Success message already printed in handle_prompt_auto_name
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to auto-name prompt: {}", e));
+                        self.fail(&format!("Failed to auto-name prompt: {}", e));
                     }
                 }
             }
             Command::PromptsEdit(name) => {
                 if let Some(prompt) = self.prompts.get_prompt(&name) {
                     self.ui.print_info(&format!("Current content of prompt '{}':", name));
-                    println!();
-                    println!("{}", prompt.content);
-                    println!();
+                    outln!();
+                    outln!("{}", prompt.content);
+                    outln!();
                     self.ui.print_info("Enter new content (use {{ and }} for multi-line input):");
                     
                     if let Some(new_content) = self.ui.read_input(None, None, false, self.config.ephemeral, None)? {
@@ -2285,10 +4078,10 @@ impl Repl {
                                     self.ui.print_info(&format!("Prompt '{}' updated", name));
                                 }
                                 Ok(false) => {
-                                    self.ui.print_error(&format!("Prompt '{}' not found", name));
+                                    self.fail(&format!("Prompt '{}' not found", name));
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&format!("Failed to update prompt: {}", e));
+                                    self.fail(&format!("Failed to update prompt: {}", e));
                                 }
                             }
                         } else {
@@ -2296,7 +4089,7 @@ impl Repl {
                         }
                     }
                 } else {
-                    self.ui.print_error(&format!("Prompt '{}' not found", name));
+                    self.fail(&format!("Prompt '{}' not found", name));
                 }
             }
             Command::PromptsImport(filename) => {
@@ -2313,7 +4106,79 @@ impl Repl {
                         let _ = self.update_completion_context();
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to import prompts: {}", e));
+                        self.fail(&format!("Failed to import prompts: {}", e));
+                    }
+                }
+            }
+            Command::Export(format_name, target, filename_opt, include_thinking) => {
+                let format = match ExportFormat::parse(&format_name) {
+                    Ok(format) => format,
+                    Err(e) => {
+                        self.fail(&e.to_string());
+                        return Ok(true);
+                    }
+                };
+
+                let sessions: Vec<ChatSession> = match target {
+                    ExportTarget::Active => {
+                        if self.session.messages.is_empty() {
+                            self.fail("Cannot export empty session");
+                            return Ok(true);
+                        }
+                        vec![self.session.clone()]
+                    }
+                    ExportTarget::Session(session_ref) => {
+                        let name = match self.resolve_session_reference(&session_ref) {
+                            Ok(name) => name,
+                            Err(e) => {
+                                self.fail(&e.to_string());
+                                return Ok(true);
+                            }
+                        };
+                        match self.history.load_session(&name) {
+                            Some(session) => vec![session.clone()],
+                            None => {
+                                self.fail(&format!("Session '{}' not found", name));
+                                return Ok(true);
+                            }
+                        }
+                    }
+                    ExportTarget::All => self.history.list_sessions()
+                        .iter()
+                        .filter_map(|name| self.history.load_session(name).cloned())
+                        .collect(),
+                };
+
+                if sessions.is_empty() {
+                    self.ui.print_info("No saved sessions to export");
+                    return Ok(true);
+                }
+
+                let session_refs: Vec<&ChatSession> = sessions.iter().collect();
+                match export_sessions(&session_refs, format, include_thinking) {
+                    Ok(output) => {
+                        if let Some(filename) = filename_opt {
+                            // Add the conventional extension when none was given
+                            let path = if Path::new(&filename).extension().is_none() {
+                                format!("{}.{}", filename, format.extension())
+                            } else {
+                                filename
+                            };
+                            match std::fs::write(&path, output) {
+                                Ok(()) => {
+                                    self.ui.print_info(&format!("Exported {} session(s) to {}", sessions.len(), path));
+                                }
+                                Err(e) => {
+                                    self.fail(&format!("Failed to write {}: {}", path, e));
+                                }
+                            }
+                        } else {
+                            // Print to stdout
+                            outln!("{}", output);
+                        }
+                    }
+                    Err(e) => {
+                        self.fail(&format!("Failed to export: {}", e));
                     }
                 }
             }
@@ -2324,11 +4189,11 @@ impl Repl {
                             self.ui.print_info(&message);
                         } else {
                             // Print to stdout
-                            println!("{}", message);
+                            outln!("{}", message);
                         }
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to export prompts: {}", e));
+                        self.fail(&format!("Failed to export prompts: {}", e));
                     }
                 }
             }
@@ -2346,7 +4211,7 @@ impl Repl {
                         };
                         self.ui.print_info(&format!("Most recent: {}", preview));
                     }
-                    println!();
+                    outln!();
                     self.ui.print_info("Use up/down arrows to navigate history");
                     self.ui.print_info("Use '/input-history clear' to clear all history");
                 }
@@ -2357,22 +4222,22 @@ impl Repl {
                         self.ui.print_info("Input history cleared");
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("Failed to clear input history: {}", e));
+                        self.fail(&format!("Failed to clear input history: {}", e));
                     }
                 }
             }
             Command::InputHistoryStats => {
                 let (count, last_entry) = self.ui.get_input_history_stats();
                 self.ui.print_info("Input history statistics:");
-                println!("  Total entries: {}", count);
-                println!("  Max entries: 1000");
+                outln!("  Total entries: {}", count);
+                outln!("  Max entries: 1000");
                 if let Some(last) = last_entry {
                     let preview = if last.len() > 60 {
                         format!("{}...", &last[..60].replace('\n', " "))
                     } else {
                         last.replace('\n', " ")
                     };
-                    println!("  Most recent: {}", preview);
+                    outln!("  Most recent: {}", preview);
                 }
             }
             Command::SetDefault(setting, value) => {
@@ -2393,11 +4258,11 @@ impl Repl {
                                     self.ui.print_info(&format!("Default model set to '{}'", value));
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&format!("Failed to set default model: {}", e));
+                                    self.fail(&format!("Failed to set default model: {}", e));
                                 }
                             }
                         } else {
-                            self.ui.print_error(&format!("Model '{}' not found in any available provider", value));
+                            self.fail(&format!("Model '{}' not found in any available provider", value));
                             self.ui.print_info("Use '/models' to see available models");
                         }
                     }
@@ -2409,15 +4274,15 @@ impl Repl {
                                         self.ui.print_info(&format!("Default temperature set to {}", temp));
                                     }
                                     Err(e) => {
-                                        self.ui.print_error(&format!("Failed to set default temperature: {}", e));
+                                        self.fail(&format!("Failed to set default temperature: {}", e));
                                     }
                                 }
                             }
                             Ok(_) => {
-                                self.ui.print_error("Temperature must be between 0.0 and 2.0");
+                                self.fail("Temperature must be between 0.0 and 2.0");
                             }
                             Err(_) => {
-                                self.ui.print_error("Invalid temperature value. Must be a number between 0.0 and 2.0");
+                                self.fail("Invalid temperature value. Must be a number between 0.0 and 2.0");
                             }
                         }
                     }
@@ -2429,154 +4294,563 @@ impl Repl {
                                         self.ui.print_info(&format!("Default max tokens set to {}", tokens));
                                     }
                                     Err(e) => {
-                                        self.ui.print_error(&format!("Failed to set default max tokens: {}", e));
+                                        self.fail(&format!("Failed to set default max tokens: {}", e));
+                                    }
+                                }
+                            }
+                            Ok(_) => {
+                                self.fail("Max tokens must be greater than 0");
+                            }
+                            Err(_) => {
+                                self.fail("Invalid max tokens value. Must be a positive integer");
+                            }
+                        }
+                    }
+                    "thinking-budget" => {
+                        match value.parse::<u32>() {
+                            Ok(budget) if budget > 0 => {
+                                match self.history.set_default_thinking_budget(Some(budget)) {
+                                    Ok(()) => {
+                                        self.ui.print_info(&format!("Default thinking budget set to {}", budget));
+                                    }
+                                    Err(e) => {
+                                        self.fail(&format!("Failed to set default thinking budget: {}", e));
+                                    }
+                                }
+                            }
+                            Ok(_) => {
+                                self.fail("Thinking budget must be greater than 0");
+                            }
+                            Err(_) => {
+                                self.fail("Invalid thinking budget value. Must be a positive integer");
+                            }
+                        }
+                    }
+                    "thinking" => {
+                        match value.to_lowercase().as_str() {
+                            "true" | "on" | "enabled" | "yes" => {
+                                match self.history.set_default_thinking(Some(true)) {
+                                    Ok(()) => {
+                                        self.ui.print_info("Default thinking set to enabled");
+                                    }
+                                    Err(e) => {
+                                        self.fail(&format!("Failed to set default thinking: {}", e));
+                                    }
+                                }
+                            }
+                            "false" | "off" | "disabled" | "no" => {
+                                match self.history.set_default_thinking(Some(false)) {
+                                    Ok(()) => {
+                                        self.ui.print_info("Default thinking set to disabled");
+                                    }
+                                    Err(e) => {
+                                        self.fail(&format!("Failed to set default thinking: {}", e));
+                                    }
+                                }
+                            }
+                            _ => {
+                                self.fail("Invalid thinking value. Use: true/false, on/off, enabled/disabled, or yes/no");
+                            }
+                        }
+                    }
+                    "system-prompt" => {
+                        if value.trim().is_empty() {
+                            match self.history.set_default_system_prompt(None) {
+                                Ok(()) => {
+                                    self.ui.print_info("Default system prompt cleared");
+                                }
+                                Err(e) => {
+                                    self.fail(&format!("Failed to clear default system prompt: {}", e));
+                                }
+                            }
+                        } else {
+                            match self.history.set_default_system_prompt(Some(value.clone())) {
+                                Ok(()) => {
+                                    let preview = if value.len() > 100 {
+                                        format!("{}...", &value[..100].replace('\n', " "))
+                                    } else {
+                                        value.replace('\n', " ")
+                                    };
+                                    self.ui.print_info(&format!("Default system prompt set: {}", preview));
+                                }
+                                Err(e) => {
+                                    self.fail(&format!("Failed to set default system prompt: {}", e));
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        self.fail(&format!("Unknown setting '{}'. Available settings: model, temperature, max-tokens, thinking-budget, thinking, system-prompt", setting));
+                    }
+                }
+            }
+            Command::GetDefaults => {
+                let defaults = self.history.get_default_preferences();
+                self.ui.print_info("Current default preferences:");
+                
+                if let Some(ref model) = defaults.model {
+                    outln!("  Model: {}", model);
+                } else {
+                    outln!("  Model: (not set)");
+                }
+                
+                if let Some(temp) = defaults.temperature {
+                    outln!("  Temperature: {}", temp);
+                } else {
+                    outln!("  Temperature: (not set)");
+                }
+                
+                if let Some(tokens) = defaults.max_tokens {
+                    outln!("  Max tokens: {}", tokens);
+                } else {
+                    outln!("  Max tokens: (not set)");
+                }
+                
+                if let Some(budget) = defaults.thinking_budget {
+                    outln!("  Thinking budget: {}", budget);
+                } else {
+                    outln!("  Thinking budget: (not set)");
+                }
+                
+                if let Some(thinking) = defaults.thinking {
+                    outln!("  Thinking: {}", if thinking { "enabled" } else { "disabled" });
+                } else {
+                    outln!("  Thinking: (not set)");
+                }
+                
+                if let Some(ref prompt) = defaults.system_prompt {
+                    let preview = if prompt.len() > 100 {
+                        format!("{}...", &prompt[..100].replace('\n', " "))
+                    } else {
+                        prompt.replace('\n', " ")
+                    };
+                    outln!("  System prompt: {}", preview);
+                } else {
+                    outln!("  System prompt: (not set)");
+                }
+                
+                outln!();
+                self.ui.print_info("Use '/set-default <setting> <value>' to change defaults");
+                self.ui.print_info("Settings that are not set will use built-in defaults when creating new sessions");
+            }
+            Command::ResetDefaults => {
+                match self.history.reset_default_preferences() {
+                    Ok(()) => {
+                        self.ui.print_info("All default preferences have been reset");
+                        self.ui.print_info("New sessions will use built-in defaults");
+                    }
+                    Err(e) => {
+                        self.fail(&format!("Failed to reset default preferences: {}", e));
+                    }
+                }
+            }
+            Command::BackupList => {
+                if !self.backups.is_enabled() {
+                    self.ui.print_info("Backups are disabled (ephemeral mode or --backup-count 0)");
+                    return Ok(true);
+                }
+                match self.backups.list_backups() {
+                    Ok(backups) if backups.is_empty() => self.ui.print_info("No backups yet"),
+                    Ok(backups) => {
+                        self.ui.print_info(&format!("Backups ({}, keeping {}):", backups.len(), self.config.backup_count));
+                        for (index, backup) in backups.iter().enumerate() {
+                            outln!("  #{}: {} ({}, {}, {:.1} KB)",
+                                index + 1,
+                                backup.timestamp,
+                                backup.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                                backup.files.join(", "),
+                                backup.compressed_size as f64 / 1024.0);
+                        }
+                        outln!();
+                        self.ui.print_info("Use '/backup restore TIMESTAMP' or '/backup restore #N' to roll back");
+                    }
+                    Err(e) => self.fail(&format!("Failed to list backups: {}", e)),
+                }
+            }
+            Command::BackupCreate => {
+                match self.backups.create_backup() {
+                    Ok(Some(backup)) => {
+                        self.ui.print_info(&format!("Backup {} created ({})", backup.timestamp, backup.files.join(", ")));
+                        let _ = self.update_completion_context();
+                    }
+                    Ok(None) => self.ui.print_info("Nothing to back up (backups disabled or no state files yet)"),
+                    Err(e) => self.fail(&format!("Backup failed: {}", e)),
+                }
+            }
+            Command::BackupRestore(reference) => {
+                let timestamp = match reference.strip_prefix('#').map(|n| n.parse::<usize>()) {
+                    Some(Ok(number)) => {
+                        let backups = self.backups.list_backups().unwrap_or_default();
+                        if number == 0 || number > backups.len() {
+                            self.fail(&format!("Invalid backup reference #{}", number));
+                            return Ok(true);
+                        }
+                        backups[number - 1].timestamp.clone()
+                    }
+                    _ => reference,
+                };
+                
+                match self.backups.restore_backup(&timestamp) {
+                    Ok(restored) => {
+                        // Reload everything from disk so the next save doesn't merge the old state back in
+                        match (History::load(self.config.sessions_file()), PromptLibrary::load(self.config.prompts_file())) {
+                            (Ok(history), Ok(prompts)) => {
+                                self.history = history;
+                                self.prompts = prompts;
+                            }
+                            (Err(e), _) | (_, Err(e)) => {
+                                self.fail(&format!("Restored files could not be loaded: {}", e));
+                                return Ok(true);
+                            }
+                        }
+                        if let Err(e) = self.ui.reload_input_history() {
+                            self.ui.print_error(&format!("Failed to reload input history: {}", e));
+                        }
+                        self.update_session_list();
+                        let _ = self.update_completion_context();
+                        self.ui.print_info(&format!("Restored {} from backup {}", restored.join(", "), timestamp));
+                        self.ui.print_info("The previous state was backed up first; '/backup list' shows it as #1");
+                    }
+                    Err(e) => self.fail(&e.to_string()),
+                }
+            }
+            Command::Template(action) => {
+                match action {
+                    TemplateAction::List => {
+                        self.reload_templates();
+                        let _ = self.update_completion_context();
+                        let templates: Vec<_> = self.templates.list().collect();
+                        if templates.is_empty() {
+                            self.ui.print_info("No session templates. Use '/template save NAME' to save the current setup");
+                            return Ok(true);
+                        }
+                        self.ui.print_info(&format!("Session templates ({} total):", templates.len()));
+                        for (name, template, origin) in templates {
+                            let origin = match origin {
+                                TemplateOrigin::Saved => String::new(),
+                                TemplateOrigin::File(path) => format!(" \x1b[2m({})\x1b[0m", path.display()),
+                                TemplateOrigin::Global => " \x1b[2m(global)\x1b[0m".to_string(),
+                            };
+                            let mut details = vec![template.model.clone().unwrap_or_else(|| "default model".to_string())];
+                            if template.thinking == Some(true) {
+                                details.push("thinking".to_string());
+                            }
+                            if template.system_prompt.is_some() {
+                                details.push("system prompt".to_string());
+                            }
+                            if !template.variables.is_empty() {
+                                let names: Vec<String> = template.variables.keys().map(|v| format!("{{{{{}}}}}", v)).collect();
+                                details.push(names.join(" "));
+                            }
+                            if !template.messages.is_empty() {
+                                details.push(format!("{} seed message(s)", template.messages.len()));
+                            }
+                            outln!("  \x1b[1;36m{}\x1b[0m{}: {}", name, origin, details.join(", "));
+                            if let Some(description) = &template.description {
+                                outln!("    {}", description);
+                            }
+                        }
+                        outln!();
+                        self.ui.print_info("Use '/chat new --template NAME' to start a session from a template");
+                    }
+                    TemplateAction::Save(name) => {
+                        if self.config.ephemeral {
+                            self.fail("Cannot save templates in ephemeral mode");
+                            return Ok(true);
+                        }
+                        let template = SessionTemplate::from_session(&self.session);
+                        let seeds = template.messages.len();
+                        match self.templates.save(&name, template) {
+                            Ok(()) => {
+                                self.ui.print_info(&format!("Template '{}' saved with the current settings, variables and {} seed message(s)", name, seeds));
+                                let _ = self.update_completion_context();
+                            }
+                            Err(e) => self.fail(&format!("Failed to save template: {}", e)),
+                        }
+                    }
+                    TemplateAction::Delete(name) => {
+                        if self.config.ephemeral {
+                            self.fail("Cannot delete templates in ephemeral mode");
+                            return Ok(true);
+                        }
+                        match self.templates.delete(&name) {
+                            Ok(()) => {
+                                self.ui.print_info(&format!("Template '{}' deleted", name));
+                                let _ = self.update_completion_context();
+                            }
+                            Err(e) => self.fail(&e.to_string()),
+                        }
+                    }
+                }
+            }
+            Command::Retention(action) => {
+                match action {
+                    RetentionAction::Show => {
+                        let policy = &self.history.retention;
+                        self.ui.print_info(&format!("Retention policy for {}:", self.workspace_scope()));
+                        for (key, value) in policy.describe() {
+                            outln!("  {:<14} {}", key, value);
+                        }
+                        if policy.is_enabled() {
+                            self.ui.print_info("Rules run at startup. Use '/retention preview' to see what they would prune now");
+                        } else {
+                            self.ui.print_info("No limits set. Use '/retention set KEY VALUE', e.g. '/retention set max-age 90'");
+                        }
+                    }
+                    RetentionAction::Set(key, value) => {
+                        let mut policy = self.history.retention.clone();
+                        if let Err(e) = policy.set(&key, &value) {
+                            self.fail(&e.to_string());
+                            return Ok(true);
+                        }
+                        let described = policy.describe().into_iter().find(|(name, _)| *name == key).map(|(_, value)| value).unwrap_or_default();
+                        match self.history.set_retention_policy(policy) {
+                            Ok(()) => self.ui.print_info(&format!("Retention {} set to {}", key, described)),
+                            Err(e) => self.fail(&format!("Failed to save retention policy: {}", e)),
+                        }
+                    }
+                    RetentionAction::Preview => {
+                        let policy = self.history.retention.clone();
+                        if !policy.is_enabled() {
+                            self.ui.print_info("No retention limits are set, nothing would be pruned");
+                            return Ok(true);
+                        }
+                        let plan = policy.plan(&self.history, self.session.name.as_deref(), Utc::now());
+                        let input_entries = policy.input_cutoff(Utc::now())
+                            .map(|cutoff| self.ui.count_input_history_before(cutoff))
+                            .unwrap_or(0);
+                        if plan.is_empty() && input_entries == 0 {
+                            self.ui.print_info("Nothing to prune under the current retention policy");
+                            return Ok(true);
+                        }
+                        self.ui.print_info("Retention preview (nothing has been changed):");
+                        if !plan.is_empty() {
+                            let verb = match policy.action {
+                                PruneAction::Trash => "move to the trash",
+                                PruneAction::Archive => "archive",
+                            };
+                            outln!("  Would {} {} session(s):", verb, plan.len());
+                            for candidate in &plan {
+                                outln!("    \x1b[1;36m{}\x1b[0m - {} ({})", candidate.name, candidate.reason, retention::format_size(candidate.size));
+                            }
+                        }
+                        if input_entries > 0 {
+                            outln!("  Would remove {} input history entries older than {} days", input_entries, policy.input_max_age_days);
+                        }
+                        self.ui.print_info("Apply now with '/retention run', or wait for the next startup");
+                    }
+                    RetentionAction::Run => {
+                        if self.config.ephemeral {
+                            self.fail("Retention rules don't run in ephemeral mode");
+                            return Ok(true);
+                        }
+                        if !self.history.retention.is_enabled() {
+                            self.ui.print_info("No retention limits are set, nothing to prune");
+                            return Ok(true);
+                        }
+                        let protected = self.session.name.clone();
+                        let ui = &mut self.ui;
+                        let result = Self::apply_retention(&mut self.history, &self.config, protected.as_deref(), |cutoff| {
+                            ui.prune_input_history(cutoff)
+                        });
+                        match result {
+                            Ok(summary) if summary.is_empty() => self.ui.print_info("Nothing to prune under the current retention policy"),
+                            Ok(summary) => {
+                                self.ui.print_info(&format!("Pruned: {} (logged in {})", summary.join(", "), retention::RETENTION_LOG));
+                                self.update_session_list();
+                                let _ = self.update_completion_context();
+                            }
+                            Err(e) => self.fail(&format!("Failed to apply retention policy: {}", e)),
+                        }
+                    }
+                }
+            }
+            Command::Workspace(action) => {
+                let scope = self.workspace_scope();
+                match action {
+                    WorkspaceAction::Show => {
+                        match &self.config.workspace {
+                            Some(dir) => {
+                                self.ui.print_info(&format!("Workspace: {} ({})", workspace::display_name(Path::new(dir)), dir));
+                                outln!("  Global state: {}", self.config.global_state_directory);
+                            }
+                            None => self.ui.print_info(&format!("No workspace, using global state in {}", self.config.state_directory)),
+                        }
+                        outln!("  Sessions: {}", self.history.saved_sessions.len());
+                        let prompt_names = self.prompts.list_prompts();
+                        let inherited = prompt_names.iter().filter(|name| self.prompts.is_inherited(name)).count();
+                        if inherited > 0 {
+                            outln!("  Prompts: {} ({} from the global library)", prompt_names.len(), inherited);
+                        } else {
+                            outln!("  Prompts: {}", prompt_names.len());
+                        }
+                        let mut saved: Vec<&String> = self.saved_variables.keys().collect();
+                        saved.sort();
+                        if !saved.is_empty() {
+                            let names: Vec<String> = saved.iter().map(|name| format!("{{{{{}}}}}", name)).collect();
+                            outln!("  Saved variables: {}", names.join(", "));
+                        }
+                        if self.config.workspace.is_none() {
+                            self.ui.print_info("Use '/workspace init' to give the current project its own sessions");
+                        }
+                    }
+                    WorkspaceAction::Init(dir) => {
+                        if self.config.ephemeral {
+                            self.fail("Cannot create a workspace in ephemeral mode");
+                            return Ok(true);
+                        }
+                        let project = match dir {
+                            Some(dir) => PathBuf::from(dir),
+                            None => std::env::current_dir()?,
+                        };
+                        match workspace::init_workspace(&project) {
+                            Ok(dir) => match self.switch_workspace(Some(dir.clone())) {
+                                Ok(()) => {
+                                    self.ui.print_info(&format!(
+                                        "Workspace '{}' ready in {}. Sessions here are separate from the global ones",
+                                        workspace::display_name(&dir), dir.display()
+                                    ));
+                                    let global_encrypted = encryption::state_cipher(&self.config.global_state_directory).is_some();
+                                    if global_encrypted && encryption::state_cipher(&self.config.state_directory).is_none() {
+                                        self.ui.print_info("The global state is encrypted but this workspace is not. Use '/encryption enable' to encrypt it too");
                                     }
                                 }
-                            }
-                            Ok(_) => {
-                                self.ui.print_error("Max tokens must be greater than 0");
-                            }
-                            Err(_) => {
-                                self.ui.print_error("Invalid max tokens value. Must be a positive integer");
-                            }
+                                Err(e) => self.fail(&format!("Created {} but could not switch to it: {}", dir.display(), e)),
+                            },
+                            Err(e) => self.fail(&format!("Failed to create workspace: {}", e)),
                         }
                     }
-                    "thinking-budget" => {
-                        match value.parse::<u32>() {
-                            Ok(budget) if budget > 0 => {
-                                match self.history.set_default_thinking_budget(Some(budget)) {
-                                    Ok(()) => {
-                                        self.ui.print_info(&format!("Default thinking budget set to {}", budget));
-                                    }
-                                    Err(e) => {
-                                        self.ui.print_error(&format!("Failed to set default thinking budget: {}", e));
-                                    }
+                    WorkspaceAction::Switch(target) => {
+                        let target_workspace = if target == "global" {
+                            None
+                        } else {
+                            match workspace::discover_workspace(Path::new(&target)) {
+                                Some(dir) => Some(dir),
+                                None => {
+                                    self.fail(&format!("No {} workspace at or above '{}'", workspace::WORKSPACE_DIRECTORY, target));
+                                    self.ui.print_info("Create one with '/workspace init DIR'");
+                                    return Ok(true);
                                 }
                             }
-                            Ok(_) => {
-                                self.ui.print_error("Thinking budget must be greater than 0");
-                            }
-                            Err(_) => {
-                                self.ui.print_error("Invalid thinking budget value. Must be a positive integer");
-                            }
+                        };
+                        match self.switch_workspace(target_workspace) {
+                            Ok(()) => self.ui.print_info(&format!(
+                                "Switched to {} ({} saved sessions), started a new session",
+                                self.workspace_scope(), self.history.saved_sessions.len()
+                            )),
+                            Err(e) => self.fail(&format!("Failed to switch workspace: {}", e)),
                         }
                     }
-                    "thinking" => {
-                        match value.to_lowercase().as_str() {
-                            "true" | "on" | "enabled" | "yes" => {
-                                match self.history.set_default_thinking(Some(true)) {
-                                    Ok(()) => {
-                                        self.ui.print_info("Default thinking set to enabled");
-                                    }
-                                    Err(e) => {
-                                        self.ui.print_error(&format!("Failed to set default thinking: {}", e));
-                                    }
-                                }
+                    WorkspaceAction::AddVariable(name) => {
+                        if self.config.ephemeral {
+                            self.fail("Cannot save variables in ephemeral mode");
+                            return Ok(true);
+                        }
+                        let Some(variable) = self.variables.get(&name).cloned() else {
+                            self.fail(&format!("Variable '{{{{{}}}}}' not found. Load it first with /load", name));
+                            return Ok(true);
+                        };
+                        let result = workspace::load_variables(&self.config.state_directory).and_then(|mut saved| {
+                            saved.insert(name.clone(), variable.clone());
+                            workspace::save_variables(&self.config.state_directory, &saved)
+                        });
+                        match result {
+                            Ok(()) => {
+                                self.saved_variables.insert(name.clone(), variable);
+                                self.ui.print_info(&format!("Saved '{{{{{}}}}}' in {}; it is loaded in every session there", name, scope));
                             }
-                            "false" | "off" | "disabled" | "no" => {
-                                match self.history.set_default_thinking(Some(false)) {
-                                    Ok(()) => {
-                                        self.ui.print_info("Default thinking set to disabled");
-                                    }
-                                    Err(e) => {
-                                        self.ui.print_error(&format!("Failed to set default thinking: {}", e));
-                                    }
-                                }
+                            Err(e) => self.fail(&format!("Failed to save variable: {}", e)),
+                        }
+                    }
+                    WorkspaceAction::RemoveVariable(name) => {
+                        if self.config.ephemeral {
+                            self.fail("Cannot change saved variables in ephemeral mode");
+                            return Ok(true);
+                        }
+                        let result = workspace::load_variables(&self.config.state_directory).and_then(|mut saved| {
+                            let removed = saved.remove(&name).is_some();
+                            if removed {
+                                workspace::save_variables(&self.config.state_directory, &saved)?;
                             }
-                            _ => {
-                                self.ui.print_error("Invalid thinking value. Use: true/false, on/off, enabled/disabled, or yes/no");
+                            Ok(removed)
+                        });
+                        match result {
+                            Ok(true) => {
+                                // A global variable of the same name shows through again
+                                let (saved_variables, _) = Self::load_saved_variables(&self.config);
+                                self.saved_variables = saved_variables;
+                                self.ui.print_info(&format!("Removed '{{{{{}}}}}' from {} (still loaded in this session)", name, scope));
                             }
+                            Ok(false) => self.fail(&format!("Variable '{{{{{}}}}}' is not saved in {}", name, scope)),
+                            Err(e) => self.fail(&format!("Failed to update saved variables: {}", e)),
                         }
                     }
-                    "system-prompt" => {
-                        if value.trim().is_empty() {
-                            match self.history.set_default_system_prompt(None) {
-                                Ok(()) => {
-                                    self.ui.print_info("Default system prompt cleared");
-                                }
-                                Err(e) => {
-                                    self.ui.print_error(&format!("Failed to clear default system prompt: {}", e));
-                                }
+                }
+            }
+            Command::Encryption(action) => {
+                let active = encryption::state_cipher(&self.config.state_directory);
+                match action {
+                    EncryptionAction::Status => {
+                        match &active {
+                            Some(cipher) => {
+                                let key = match cipher.kind() {
+                                    encryption::KeyKind::Passphrase => "passphrase",
+                                    encryption::KeyKind::Keyfile => "keyfile",
+                                };
+                                self.ui.print_info(&format!("Encryption: on ({}, XChaCha20-Poly1305)", key));
                             }
-                        } else {
-                            match self.history.set_default_system_prompt(Some(value.clone())) {
-                                Ok(()) => {
-                                    let preview = if value.len() > 100 {
-                                        format!("{}...", &value[..100].replace('\n', " "))
-                                    } else {
-                                        value.replace('\n', " ")
-                                    };
-                                    self.ui.print_info(&format!("Default system prompt set: {}", preview));
-                                }
-                                Err(e) => {
-                                    self.ui.print_error(&format!("Failed to set default system prompt: {}", e));
-                                }
+                            None => self.ui.print_info("Encryption: off"),
+                        }
+                        for name in crate::backup::STATE_FILES {
+                            let path = Path::new(&self.config.state_directory).join(name);
+                            if let Ok(content) = std::fs::read_to_string(&path) {
+                                let state = if encryption::is_encrypted(&content) { "encrypted" } else { "plaintext" };
+                                outln!("  {}: {}", name, state);
                             }
                         }
+                        if active.is_none() {
+                            self.ui.print_info("Use '/encryption enable' to encrypt the state directory");
+                        }
+                        return Ok(true);
                     }
-                    _ => {
-                        self.ui.print_error(&format!("Unknown setting '{}'. Available settings: model, temperature, max-tokens, thinking-budget, thinking, system-prompt", setting));
+                    EncryptionAction::Enable(_) if active.is_some() => {
+                        self.fail("State is already encrypted. Use '/encryption rotate' to change the key");
+                        return Ok(true);
                     }
+                    EncryptionAction::Rotate(_) | EncryptionAction::Disable if active.is_none() => {
+                        self.fail("State is not encrypted. Use '/encryption enable' first");
+                        return Ok(true);
+                    }
+                    _ => {}
                 }
-            }
-            Command::GetDefaults => {
-                let defaults = self.history.get_default_preferences();
-                self.ui.print_info("Current default preferences:");
-                
-                if let Some(ref model) = defaults.model {
-                    println!("  Model: {}", model);
-                } else {
-                    println!("  Model: (not set)");
-                }
-                
-                if let Some(temp) = defaults.temperature {
-                    println!("  Temperature: {}", temp);
-                } else {
-                    println!("  Temperature: (not set)");
-                }
-                
-                if let Some(tokens) = defaults.max_tokens {
-                    println!("  Max tokens: {}", tokens);
-                } else {
-                    println!("  Max tokens: (not set)");
-                }
-                
-                if let Some(budget) = defaults.thinking_budget {
-                    println!("  Thinking budget: {}", budget);
-                } else {
-                    println!("  Thinking budget: (not set)");
-                }
-                
-                if let Some(thinking) = defaults.thinking {
-                    println!("  Thinking: {}", if thinking { "enabled" } else { "disabled" });
-                } else {
-                    println!("  Thinking: (not set)");
-                }
-                
-                if let Some(ref prompt) = defaults.system_prompt {
-                    let preview = if prompt.len() > 100 {
-                        format!("{}...", &prompt[..100].replace('\n', " "))
-                    } else {
-                        prompt.replace('\n', " ")
-                    };
-                    println!("  System prompt: {}", preview);
-                } else {
-                    println!("  System prompt: (not set)");
+                if self.config.ephemeral {
+                    self.fail("Cannot change encryption in ephemeral mode");
+                    return Ok(true);
                 }
-                
-                println!();
-                self.ui.print_info("Use '/set-default <setting> <value>' to change defaults");
-                self.ui.print_info("Settings that are not set will use built-in defaults when creating new sessions");
-            }
-            Command::ResetDefaults => {
-                match self.history.reset_default_preferences() {
-                    Ok(()) => {
-                        self.ui.print_info("All default preferences have been reset");
-                        self.ui.print_info("New sessions will use built-in defaults");
+
+                let new_cipher = match &action {
+                    EncryptionAction::Enable(keyfile) | EncryptionAction::Rotate(keyfile) => {
+                        match Self::read_new_cipher(keyfile.as_deref()) {
+                            Ok(cipher) => Some(std::sync::Arc::new(cipher)),
+                            Err(e) => {
+                                self.fail(&e.to_string());
+                                return Ok(true);
+                            }
+                        }
                     }
-                    Err(e) => {
-                        self.ui.print_error(&format!("Failed to reset default preferences: {}", e));
+                    _ => None,
+                };
+
+                match self.rekey_state(new_cipher) {
+                    Ok(count) => {
+                        let done = match action {
+                            EncryptionAction::Enable(_) => "encrypted",
+                            EncryptionAction::Rotate(_) => "re-encrypted with the new key",
+                            _ => "decrypted",
+                        };
+                        self.ui.print_info(&format!("State files and {} backup file(s) {}", count, done));
                     }
+                    Err(e) => self.fail(&format!("Failed to change encryption: {}", e)),
                 }
             }
             Command::Load(source_str, variable_name_opt) => {
@@ -2619,12 +4893,12 @@ impl Repl {
                                 let _ = self.update_completion_context();
                             }
                             Err(e) => {
-                                self.ui.print_error(&format!("Failed to load: {}", e));
+                                self.fail(&format!("Failed to load: {}", e));
                             }
                         }
                     }
                     Err(e) => {
-                        self.ui.print_error(&format!("{}", e));
+                        self.fail(&format!("{}", e));
                     }
                 }
             }
@@ -2641,7 +4915,7 @@ impl Repl {
                             Err(_) => 0
                         };
 
-                        println!(
+                        outln!(
                             "  {:<12} {}{:<30} {:>8} ({} bytes)",
                             format!("{{{{{}}}}}", name),
                             var.source.type_indicator(),
@@ -2650,7 +4924,7 @@ impl Repl {
                             size
                         );
                     }
-                    println!();
+                    outln!();
                     self.ui.print_info("Use {{VARIABLE_NAME}} in your messages to reference content");
                 }
             }
@@ -2659,19 +4933,19 @@ impl Repl {
                     match var.source.evaluate_sync() {
                         Ok(content) => {
                             self.ui.print_info(&format!("Variable {{{}}} ({} chars):", name, content.len()));
-                            println!();
-                            println!("{}", content);
+                            outln!();
+                            outln!("{}", content);
                         }
                         Err(e) => {
-                            self.ui.print_error(&format!("Failed to evaluate variable '{}': {}", name, e));
+                            self.fail(&format!("Failed to evaluate variable '{}': {}", name, e));
                         }
                     }
                 } else {
-                    self.ui.print_error(&format!("Variable '{}' not found", name));
+                    self.fail(&format!("Variable '{}' not found", name));
                     if !self.variables.is_empty() {
                         self.ui.print_info("Available variables:");
                         for var_name in self.variables.keys() {
-                            println!("  {}", var_name);
+                            outln!("  {}", var_name);
                         }
                     }
                 }
@@ -2685,7 +4959,7 @@ impl Repl {
                     // Update completion context after deletion
                     let _ = self.update_completion_context();
                 } else {
-                    self.ui.print_error(&format!("Variable '{}' not found", name));
+                    self.fail(&format!("Variable '{}' not found", name));
                 }
             }
             Command::VariableReload(name_opt) => {
@@ -2721,16 +4995,16 @@ impl Repl {
                                 ));
                             }
                             Err(e) => {
-                                self.ui.print_error(&format!("Failed to freeze '{}': {}", var_name, e));
+                                self.fail(&format!("Failed to freeze '{}': {}", var_name, e));
                             }
                         }
                     }
                 } else {
-                    self.ui.print_error(&format!("Variable '{}' not found", var_name));
+                    self.fail(&format!("Variable '{}' not found", var_name));
                     if !self.variables.is_empty() {
                         self.ui.print_info("Available variables:");
                         for name in self.variables.keys() {
-                            println!("  {}", name);
+                            outln!("  {}", name);
                         }
                     }
                 }
@@ -2748,7 +5022,7 @@ impl Repl {
                                 self.ui.print_info("Edit cancelled (empty content)");
                             }
                             Err(e) => {
-                                self.ui.print_error(&e.to_string());
+                                self.fail(&e.to_string());
                             }
                         }
                     }
@@ -2757,6 +5031,7 @@ impl Repl {
                             let content = self.session.messages[idx].message.content.clone();
                             match self.open_in_editor(&content) {
                                 Ok(Some(new_content)) => {
+                                    self.record_edit(idx, &new_content);
                                     self.session.messages[idx].message.content = new_content;
                                     self.ui.print_info(&format!("User {} updated", user_number));
                                 }
@@ -2764,11 +5039,11 @@ impl Repl {
                                     self.ui.print_info("Edit cancelled (empty content)");
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&e.to_string());
+                                    self.fail(&e.to_string());
                                 }
                             }
                         } else {
-                            self.ui.print_error(&format!("User {} not found", user_number));
+                            self.fail(&format!("User {} not found", user_number));
                         }
                     }
                     EditTarget::Agent(agent_number) => {
@@ -2776,6 +5051,7 @@ impl Repl {
                             let content = self.session.messages[idx].message.content.clone();
                             match self.open_in_editor(&content) {
                                 Ok(Some(new_content)) => {
+                                    self.record_edit(idx, &new_content);
                                     self.session.messages[idx].message.content = new_content;
                                     self.ui.print_info(&format!("Agent {} updated", agent_number));
                                 }
@@ -2783,24 +5059,24 @@ impl Repl {
                                     self.ui.print_info("Edit cancelled (empty content)");
                                 }
                                 Err(e) => {
-                                    self.ui.print_error(&e.to_string());
+                                    self.fail(&e.to_string());
                                 }
                             }
                         } else {
-                            self.ui.print_error(&format!("Agent {} not found", agent_number));
+                            self.fail(&format!("Agent {} not found", agent_number));
                         }
                     }
                 }
             }
             _ => {
-                self.ui.print_info(&format!("Command not yet implemented: {:?}", command));
+                self.fail(&format!("Command not yet implemented: {:?}", command));
             }
         }
 
         Ok(true)
     }
     
-    async fn handle_message(&mut self, message: String) -> Result<()> {
+    async fn handle_message(&mut self, message: String) -> Result<Status> {
         // Validate that variables can be substituted (catch errors early)
         // But keep the original message with {{var}} templates for storage
         match self.substitute_variables(&message) {
@@ -2815,7 +5091,7 @@ impl Repl {
                     self.ui.print_error(&format!("Variable '{}' failed: {}", var_name, error));
                 }
 
-                println!("  [s]kip (use empty) / [a]bort / [r]etry / [e]dit source?");
+                outln!("  [s]kip (use empty) / [a]bort / [r]etry / [e]dit source?");
 
                 let mut input = String::new();
                 std::io::stdin().read_line(&mut input)?;
@@ -2851,7 +5127,7 @@ impl Repl {
                     }
                     "a" | "abort" => {
                         self.ui.print_info("Request aborted");
-                        return Ok(());
+                        return Ok(Status::Failed);
                     }
                     "r" | "retry" => {
                         return Box::pin(self.handle_message(message)).await;
@@ -2859,11 +5135,11 @@ impl Repl {
                     "e" | "edit" => {
                         self.queued_message = Some(message);
                         self.ui.print_info("Message queued for editing. Use /edit to modify.");
-                        return Ok(());
+                        return Ok(Status::Failed);
                     }
                     _ => {
                         self.ui.print_info("Invalid choice. Aborting.");
-                        return Ok(());
+                        return Ok(Status::Failed);
                     }
                 }
             }
@@ -2890,7 +5166,7 @@ impl Repl {
                 // Request was cancelled - queue as interrupted message (original, not processed)
                 self.interrupted_message = Some(message);
                 self.ui.print_info("Request interrupted. Message available for editing.");
-                return Ok(());
+                return Ok(Status::Failed);
             }
             _ = ctrl_c_rx.recv() => {
                 // Ctrl-C received - cancel local token and queue message
@@ -2899,7 +5175,7 @@ impl Repl {
                 self.ctrl_c_rx = ctrl_c_rx;
                 self.interrupted_message = Some(message);
                 self.ui.print_info("Request interrupted by Ctrl-C. Message available for editing.");
-                return Ok(());
+                return Ok(Status::Failed);
             }
         };
         
@@ -2915,17 +5191,18 @@ impl Repl {
                 if cancel_token.is_cancelled() {
                     self.interrupted_message = Some(message);
                     self.ui.print_info("Request interrupted. Message available for editing.");
-                    return Ok(());
+                    return Ok(Status::Failed);
                 }
                 
                 // All retries failed - queue the original message for retry
                 self.queued_message = Some(message);
                 self.ui.print_error(&format!("Failed to send message after 3 attempts: {}", e));
                 self.ui.print_info("Message queued for retry. Press Enter to retry, or modify and press Enter.");
+                return Ok(Status::Failed);
             }
         }
         
-        Ok(())
+        Ok(Status::Done)
     }
     
     async fn send_message_with_retry(&mut self, message: &str, max_retries: u32, cancel_token: CancellationToken) -> Result<()> {
@@ -2933,15 +5210,162 @@ impl Repl {
             role: "user".to_string(),
             content: message.to_string(),
         };
-        
+
         // Don't add user message to history until we have a successful response
         let agent_number = self.get_next_agent_number();
+        let provider_name = self.get_current_provider()
+            .ok_or_else(|| anyhow::anyhow!("No provider available"))?
+            .to_string();
+
+        // Create request with user message included but not yet in session history
+        let (request_messages, thinking_blocks, params) = self.prepare_request_messages(&user_message);
+        let chat_request = ChatRequest {
+            messages: request_messages,
+            thinking_blocks,
+            model: self.session.current_model.clone(),
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            thinking_budget: params.thinking_budget,
+            stream: true,
+            thinking: params.thinking_enabled,
+        };
+
+        let reply = self.stream_reply(&provider_name, chat_request, agent_number, max_retries, cancel_token).await?;
+        let (provider, model) = (self.session.current_provider.clone(), self.session.current_model.clone());
+        self.record_exchange(user_message, reply, provider, model, params);
+        Ok(())
+    }
+
+    /// One complete /compare reply under its numbered header
+    fn print_comparison_reply(&self, index: usize, (model, provider_name): &(String, String), result: &Result<Candidate, String>) {
+        outln!("{}", compare::render_header(index + 1, model, provider_name));
+        match result {
+            Ok(candidate) => {
+                outln!("{}", self.ui.style_code_blocks(&candidate.content));
+                outln!("{}", compare::render_details(candidate));
+            }
+            Err(e) => self.ui.print_error(e),
+        }
+        outln!();
+    }
+    
+    /// Add a prompt and the reply it got to the session
+    fn record_exchange(&mut self, user_message: Message, reply: StreamedReply, provider: Option<String>, model: String, params: GenerationParams) {
+        let input_tokens: usize = self.session.system_prompt.iter().map(|prompt| estimate_tokens(prompt))
+            .chain(self.session.context_messages().map(|msg| estimate_tokens(&msg.content)))
+            .sum::<usize>() + estimate_tokens(&user_message.content);
+        
+        // Mark that this session has had LLM interaction
+        self.session.mark_llm_interaction();
+
+        // Now that we have a successful response, add both user and assistant messages
+        self.session.add_message(user_message);
+        let assistant_message = Message {
+            role: "assistant".to_string(),
+            content: reply.content,
+        };
+        self.session.add_message_with_metadata(assistant_message, provider, Some(model));
+        if let Some(last) = self.session.messages.last_mut() {
+            // Keep the reasoning alongside the answer so it can be exported later
+            if !reply.thinking.is_empty() {
+                last.thinking = Some(reply.thinking);
+            }
+            last.thinking_blocks = reply.thinking_blocks;
+            last.timing = Some(reply.timing);
+            last.params = Some(params);
+        }
         
+        if events::is_structured() {
+            let count = self.session.messages.len();
+            for index in count.saturating_sub(2)..count {
+                let number = match self.message_target_at(index) {
+                    MessageTarget::User(number) | MessageTarget::Agent(number) => number,
+                };
+                let msg = &self.session.messages[index];
+                let usage = msg.timing.as_ref().map(|timing| {
+                    let output_tokens = estimate_tokens(&msg.message.content) + msg.thinking.as_deref().map_or(0, estimate_tokens);
+                    Usage {
+                        input_tokens,
+                        output_tokens,
+                        cost: msg.model.as_deref().and_then(|model| estimate_cost(model, input_tokens, output_tokens)),
+                        latency_ms: timing.latency_ms,
+                        first_token_ms: timing.first_token_ms,
+                    }
+                });
+                events::emit(Event::Message {
+                    number,
+                    role: msg.message.role.clone(),
+                    content: msg.message.content.clone(),
+                    thinking: msg.thinking.clone(),
+                    model: msg.model.clone(),
+                    provider: msg.provider.clone(),
+                    code_blocks: msg.code_blocks.clone(),
+                    usage,
+                });
+            }
+        }
+    }
+
+    /// The messages to send for `user_message` on top of the current context, with variables
+    /// substituted, and the settings to record with the reply
+    /// The messages to send for `user_message`, the signed thinking to send back with each
+    /// (for replies from the current model) and the settings to record with the reply
+    fn prepare_request_messages(&self, user_message: &Message) -> (Vec<Message>, Vec<Vec<ThinkingBlock>>, GenerationParams) {
+        let mut request_messages: Vec<(Message, Vec<ThinkingBlock>)> = Vec::new();
+
+        // Add system prompt if present
+        if let Some(system_prompt) = &self.session.system_prompt {
+            request_messages.push((Message {
+                role: "system".to_string(),
+                content: system_prompt.clone(),
+            }, Vec::new()));
+        }
+
+        // Add conversation history, leaving out hidden messages
+        request_messages.extend(self.session.context_turns(&self.session.current_model));
+
+        //Add current user message
+        request_messages.push((user_message.clone(), Vec::new()));
+        let (request_messages, thinking_blocks) = alternate_turns(request_messages);
+
+        // Substitute variables in all user messages at send time
+        // This ensures templates like {{date}} get fresh values each time
+        let mut variable_values = BTreeMap::new();
+        let substituted_messages: Vec<Message> = request_messages
+            .into_iter()
+            .map(|mut msg| {
+                if msg.role == "user" {
+                    // Only substitute user messages (assistant/system don't have variables)
+                    if let Ok(substituted) = self.substitute_variables_recording(&msg.content, &BTreeMap::new(), &mut variable_values) {
+                        msg.content = substituted;
+                    }
+                }
+                msg
+            })
+            .collect();
+
+        let params = GenerationParams {
+            temperature: self.session.temperature,
+            max_tokens: self.session.max_tokens,
+            thinking_enabled: self.session.thinking_enabled,
+            thinking_budget: self.session.thinking_budget,
+            system_prompt_hash: self.session.system_prompt.as_deref().map(GenerationParams::hash),
+            system_prompt: self.session.system_prompt.clone(),
+            variable_hashes: variable_values.iter().map(|(name, value)| (name.clone(), GenerationParams::hash(value))).collect(),
+            variable_values: if self.config.record_values { variable_values } else { BTreeMap::new() },
+            excluded: self.session.messages.iter().filter(|msg| msg.hidden && !msg.pinned).map(|msg| msg.number).collect(),
+        };
+        (substituted_messages, thinking_blocks, params)
+    }
+
+    /// Send `request`, streaming the reply to the terminal as Agent `agent_number`.
+    /// Failed, interrupted and empty responses are retried up to `max_retries` times.
+    async fn stream_reply(&mut self, provider_name: &str, request: ChatRequest, agent_number: usize, max_retries: u32, cancel_token: CancellationToken) -> Result<StreamedReply> {
         for attempt in 1..=max_retries {
             if attempt > 1 {
                 let delay = std::time::Duration::from_millis(1000 * (1 << (attempt - 2))); // Exponential backoff: 1s, 2s, 4s
                 self.ui.print_info(&format!("Retrying in {}s... (attempt {}/{})", delay.as_secs(), attempt, max_retries));
-                
+
                 // Check for cancellation during delay
                 tokio::select! {
                     _ = tokio::time::sleep(delay) => {},
@@ -2950,7 +5374,11 @@ impl Repl {
                     }
                 }
             }
-            
+
+            let Some(provider) = self.providers.get(provider_name) else {
+                break;
+            };
+
             // Start spinner for this attempt
             let spinner_message = if attempt == 1 {
                 "Sending message...".to_string()
@@ -2958,186 +5386,140 @@ impl Repl {
                 format!("Retrying... (attempt {}/{})", attempt, max_retries)
             };
             let spinner = self.ui.start_spinner(&spinner_message);
-            
-            // Send to LLM provider and handle streaming response
-            if let Some(provider_name) = self.get_current_provider() {
-                if let Some(provider) = self.providers.get(provider_name) {
-                    // Create request with user message included but not yet in session history
-                    let mut request_messages: Vec<Message> = Vec::new();
-                    
-                    // Add system prompt if present
-                    if let Some(system_prompt) = &self.session.system_prompt {
-                        request_messages.push(Message {
-                            role: "system".to_string(),
-                            content: system_prompt.clone(),
-                        });
-                    }
-                    
-                    // Add conversation history
-                    request_messages.extend(self.session.messages.iter().map(|nm| nm.message.clone()));
 
-                    //Add current user message
-                    request_messages.push(user_message.clone());
+            let request_start = std::time::Instant::now();
+            match provider.chat(request.clone()).await {
+                Ok(mut stream) => {
+                    let mut first_token_ms = None;
+                    let mut full_response = String::new();
+                    let mut thinking = ThinkingCollector::default();
+                    let mut stream_error = false;
+                    let mut has_thinking = false;
+                    let mut has_content = false;
+                    let mut thinking_started = false;
+                    let mut spinner = Some(spinner);
 
-                    // Substitute variables in all user messages at send time
-                    // This ensures templates like {{date}} get fresh values each time
-                    let substituted_messages: Vec<Message> = request_messages
-                        .into_iter()
-                        .map(|mut msg| {
-                            if msg.role == "user" {
-                                // Only substitute user messages (assistant/system don't have variables)
-                                if let Ok(substituted) = self.substitute_variables(&msg.content) {
-                                    msg.content = substituted;
-                                }
-                            }
-                            msg
-                        })
-                        .collect();
+                    let stream_result = loop {
+                        tokio::select! {
+                            chunk_result = stream.next() => {
+                                match chunk_result {
+                                    Some(chunk) => {
+                                        match chunk {
+                                            Ok(content) => {
+                                                // Stop spinner on first content received
+                                                if let Some(s) = spinner.take() {
+                                                    s.stop().await;
+                                                }
 
-                    let chat_request = ChatRequest {
-                        messages: substituted_messages,
-                        model: self.session.current_model.clone(),
-                        temperature: self.session.temperature,
-                        max_tokens: self.session.max_tokens,
-                        thinking_budget: self.session.thinking_budget,
-                        stream: true,
-                        thinking: self.session.thinking_enabled,
-                    };
-                    
-                    match provider.chat(chat_request).await {
-                        Ok(mut stream) => {
-                            let mut full_response = String::new();
-                            let mut stream_error = false;
-                            let mut has_thinking = false;
-                            let mut has_content = false;
-                            let mut thinking_started = false;
-                            let mut spinner = Some(spinner);
-                            
-                            let stream_result = loop {
-                                tokio::select! {
-                                    chunk_result = stream.next() => {
-                                        match chunk_result {
-                                            Some(chunk) => {
-                                                match chunk {
-                                                    Ok(content) => {
-                                                        // Stop spinner on first content received
-                                                        if let Some(s) = spinner.take() {
-                                                            s.stop().await;
+                                                if !content.is_empty() {
+                                                    first_token_ms.get_or_insert(request_start.elapsed().as_millis() as u64);
+                                                    if let Some(thinking_text) = content.strip_prefix("thinking:") {
+                                                        if !thinking_started {
+                                                            self.ui.print_thinking_prefix(agent_number);
+                                                            thinking_started = true;
+                                                            has_thinking = true;
                                                         }
-                                                        
-                                                        if !content.is_empty() {
-                                                            if let Some(thinking_text) = content.strip_prefix("thinking:") {
-                                                                if !thinking_started {
-                                                                    self.ui.print_thinking_prefix(agent_number);
-                                                                    thinking_started = true;
-                                                                    has_thinking = true;
-                                                                }
-                                                                self.ui.print_thinking_chunk(thinking_text);
-                                                            } else if let Some(content_text) = content.strip_prefix("content:") {
-                                                                if has_thinking && !has_content {
-                                                                    self.ui.print_thinking_end();
-                                                                    self.ui.print_agent_prefix(agent_number);
-                                                                    has_content = true;
-                                                                } else if !has_content {
-                                                                    self.ui.print_agent_prefix(agent_number);
-                                                                    has_content = true;
-                                                                }
-                                                                self.ui.print_agent_chunk(content_text);
-                                                                full_response.push_str(content_text);
-                                                            } else {
-                                                                // Fallback for providers that don't prefix
-                                                                if !has_content {
-                                                                    if has_thinking {
-                                                                        self.ui.print_thinking_end();
-                                                                    }
-                                                                    self.ui.print_agent_prefix(agent_number);
-                                                                    has_content = true;
-                                                                }
-                                                                self.ui.print_agent_chunk(&content);
-                                                                full_response.push_str(&content);
+                                                        self.ui.print_thinking_chunk(&thinking.push_text(thinking_text));
+                                                    } else if let Some(signature) = content.strip_prefix("signature:") {
+                                                        thinking.push_signature(signature);
+                                                    } else if let Some(content_text) = content.strip_prefix("content:") {
+                                                        if has_thinking && !has_content {
+                                                            self.ui.print_thinking_end();
+                                                            self.ui.print_agent_prefix(agent_number);
+                                                            has_content = true;
+                                                        } else if !has_content {
+                                                            self.ui.print_agent_prefix(agent_number);
+                                                            has_content = true;
+                                                        }
+                                                        self.ui.print_agent_chunk(content_text);
+                                                        full_response.push_str(content_text);
+                                                    } else {
+                                                        // Fallback for providers that don't prefix
+                                                        if !has_content {
+                                                            if has_thinking {
+                                                                self.ui.print_thinking_end();
                                                             }
+                                                            self.ui.print_agent_prefix(agent_number);
+                                                            has_content = true;
                                                         }
-                                                    }
-                                                    Err(e) => {
-                                                        self.ui.print_error(&format!("Stream error: {}", e));
-                                                        stream_error = true;
-                                                        break Ok(());
+                                                        self.ui.print_agent_chunk(&content);
+                                                        full_response.push_str(&content);
                                                     }
                                                 }
                                             }
-                                            None => break Ok(()), // Stream ended
+                                            Err(e) => {
+                                                self.ui.print_error(&format!("Stream error: {}", e));
+                                                stream_error = true;
+                                                break Ok(());
+                                            }
                                         }
                                     }
-                                    _ = cancel_token.cancelled() => {
-                                        self.ui.print_info("\nRequest cancelled");
-                                        // User message was never added to history, so nothing to remove
-                                        break Err(anyhow::anyhow!("Request cancelled"));
-                                    }
-                                }
-                            };
-                            
-                            // Ensure spinner is stopped if we haven't stopped it yet
-                            if let Some(s) = spinner.take() {
-                                s.stop().await;
-                            }
-                            
-                            // Check if we should return early due to cancellation
-                            stream_result?;
-                            
-                            if stream_error {
-                                if attempt < max_retries {
-                                    continue; // Retry on stream error
-                                } else {
-                                    // User message was never added to history, so nothing to remove
-                                    return Err(anyhow::anyhow!("Stream error on final attempt"));
+                                    None => break Ok(()), // Stream ended
                                 }
                             }
-                            
-                            // Print newline after successful stream completion
-                            self.ui.print_agent_newline();
-                            
-                            // Add the complete response to the session with metadata
-                            if !full_response.is_empty() {
-                                // Mark that this session has had LLM interaction
-                                self.session.mark_llm_interaction();
-                                
-                                // Now that we have a successful response, add both user and assistant messages
-                                self.session.add_message(user_message.clone());
-                                let assistant_message = Message {
-                                    role: "assistant".to_string(),
-                                    content: full_response,
-                                };
-                                self.session.add_message_with_metadata(
-                                    assistant_message,
-                                    self.session.current_provider.clone(),
-                                    Some(self.session.current_model.clone())
-                                );
-                                return Ok(()); // Success!
-                            } else if attempt < max_retries {
-                                self.ui.print_error("Empty response received, retrying...");
-                                continue;
-                            } else {
+                            _ = cancel_token.cancelled() => {
+                                self.ui.print_info("\nRequest cancelled");
                                 // User message was never added to history, so nothing to remove
-                                return Err(anyhow::anyhow!("Empty response on final attempt"));
+                                break Err(anyhow::anyhow!("Request cancelled"));
                             }
                         }
-                        Err(e) => {
-                            // Stop spinner on error
-                            spinner.stop().await;
-                            
-                            if attempt < max_retries {
-                                self.ui.print_error(&format!("API error (attempt {}/{}): {}", attempt, max_retries, e));
-                                continue; // Retry on API error
-                            } else {
-                                // User message was never added to history, so nothing to remove
-                                return Err(e);
-                            }
+                    };
+
+                    // Ensure spinner is stopped if we haven't stopped it yet
+                    if let Some(s) = spinner.take() {
+                        s.stop().await;
+                    }
+
+                    // Check if we should return early due to cancellation
+                    stream_result?;
+
+                    if stream_error {
+                        if attempt < max_retries {
+                            continue; // Retry on stream error
+                        } else {
+                            // User message was never added to history, so nothing to remove
+                            return Err(anyhow::anyhow!("Stream error on final attempt"));
                         }
                     }
+
+                    let latency_ms = request_start.elapsed().as_millis() as u64;
+
+                    // Print newline after successful stream completion
+                    self.ui.print_agent_newline();
+
+                    if !full_response.is_empty() {
+                        return Ok(StreamedReply {
+                            content: full_response,
+                            thinking: thinking.text,
+                            thinking_blocks: thinking.blocks,
+                            timing: MessageTiming {
+                                latency_ms,
+                                first_token_ms: first_token_ms.unwrap_or(latency_ms),
+                            },
+                        });
+                    } else if attempt < max_retries {
+                        self.ui.print_error("Empty response received, retrying...");
+                        continue;
+                    } else {
+                        // User message was never added to history, so nothing to remove
+                        return Err(anyhow::anyhow!("Empty response on final attempt"));
+                    }
+                }
+                Err(e) => {
+                    // Stop spinner on error
+                    spinner.stop().await;
+
+                    if attempt < max_retries {
+                        self.ui.print_error(&format!("API error (attempt {}/{}): {}", attempt, max_retries, e));
+                        continue; // Retry on API error
+                    } else {
+                        // User message was never added to history, so nothing to remove
+                        return Err(e);
+                    }
                 }
             }
         }
-        
+
         Err(anyhow::anyhow!("No provider available"))
     }
     
@@ -3261,6 +5643,7 @@ impl Repl {
         // Create chat request (non-streaming for simplicity)
         let chat_request = ChatRequest {
             messages,
+            thinking_blocks: Vec::new(),
             model: model.to_string(),
             temperature: 0.7,
             max_tokens: 50, // Short response expected
@@ -3296,6 +5679,32 @@ impl Repl {
         Ok(response.trim().to_string())
     }
     
+    fn record_edit(&mut self, index: usize, replacement: &str) {
+        let msg = &self.session.messages[index];
+        let operation = Operation::Edit {
+            number: msg.number,
+            previous: msg.message.content.clone(),
+            replacement: replacement.to_string(),
+        };
+        self.session.journal_operation(operation);
+    }
+    
+    fn journal_session_operation(&mut self, operation: Operation) {
+        if let Err(e) = self.history.journal_operation(operation) {
+            self.ui.print_error(&format!("Failed to record operation in the journal: {}", e));
+        }
+    }
+    
+    /// The current session's journal and the session deletes and renames, newest first
+    fn journal_entries(&self) -> Vec<(JournalSource, &JournalEntry)> {
+        let mut entries: Vec<_> = self.session.journal.iter().enumerate()
+            .map(|(index, entry)| (JournalSource::Session(index), entry))
+            .chain(self.history.journal.iter().enumerate().map(|(index, entry)| (JournalSource::History(index), entry)))
+            .collect();
+        entries.sort_by(|a, b| b.1.timestamp.cmp(&a.1.timestamp));
+        entries
+    }
+    
     fn ensure_unique_session_name(&self, base_name: &str) -> String {
         if self.history.load_session(base_name).is_none() {
             return base_name.to_string();
@@ -3387,17 +5796,17 @@ impl Repl {
         let summary = self.generate_session_summary(&target_session).await?;
         
         // Display the summary
-        println!();
-        println!("\x1b[1;36mSession Summary\x1b[0m");
+        outln!();
+        outln!("\x1b[1;36mSession Summary\x1b[0m");
         if let Some(name) = &target_session.name {
-            println!("Session: {}", name);
+            outln!("Session: {}", name);
         }
-        println!("Messages: {}", target_session.messages.len());
-        println!("Created: {}", target_session.created_at.format("%Y-%m-%d %H:%M UTC"));
-        println!("Model: {}", target_session.current_model);
-        println!();
-        println!("{}", summary);
-        println!();
+        outln!("Messages: {}", target_session.messages.len());
+        outln!("Created: {}", target_session.created_at.format("%Y-%m-%d %H:%M UTC"));
+        outln!("Model: {}", target_session.current_model);
+        outln!();
+        outln!("{}", summary);
+        outln!();
         
         Ok(())
     }
@@ -3474,6 +5883,7 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
         // Create chat request (non-streaming for simplicity)
         let chat_request = ChatRequest {
             messages,
+            thinking_blocks: Vec::new(),
             model: model.to_string(),
             temperature: 0.3, // Lower temperature for more consistent summaries
             max_tokens: 1000, // Allow for detailed summaries
@@ -3600,7 +6010,7 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
         self.update_session_list();
         
         // Print summary
-        println!();
+        outln!();
         self.ui.print_info(&format!("Auto-rename complete: {} renamed, {} failed, {} skipped", 
             renamed_count, failed_count, skipped_count));
         
@@ -3724,6 +6134,7 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
         // Create chat request (non-streaming for simplicity)
         let chat_request = crate::providers::ChatRequest {
             messages,
+            thinking_blocks: Vec::new(),
             model: model.to_string(),
             temperature: 0.7,
             max_tokens: 30, // Short response expected
@@ -3911,9 +6322,24 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
     }
 
     fn substitute_variables(&self, input: &str) -> Result<String, Vec<(String, String)>> {
+        self.substitute_variables_recording(input, &BTreeMap::new(), &mut BTreeMap::new())
+    }
+    
+    /// Like `substitute_variables`, also adding a hash of each substituted value to `hashes`
+    fn substitute_variables_recording(&self, input: &str, replay: &BTreeMap<String, String>, values: &mut BTreeMap<String, String>) -> Result<String, Vec<(String, String)>> {
         let mut result = input.to_string();
         let mut errors: Vec<(String, String)> = Vec::new();
 
+        // Values replayed from an earlier request are used as they were, not evaluated again.
+        // Inline patterns are replayed below, by their key
+        for (key, value) in replay.iter().filter(|(key, _)| !key.starts_with(['@', '!', '='])) {
+            let pattern = format!("{{{{{}}}}}", key);
+            if result.contains(&pattern) {
+                values.insert(key.clone(), value.clone());
+                result = result.replace(&pattern, value);
+            }
+        }
+
         // First, substitute named variables {{varname}}
         for (var_name, var) in &self.variables {
             let pattern = format!("{{{{{}}}}}", var_name);
@@ -3926,6 +6352,7 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
 
                 match value {
                     Ok(content) => {
+                        values.insert(var_name.clone(), content.clone());
                         result = result.replace(&pattern, &content);
                     }
                     Err(e) => {
@@ -3946,10 +6373,17 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
             .collect();
 
         for (full_match, source_str) in matches {
+            let key = GenerationParams::inline_key(&source_str);
+            if let Some(value) = replay.get(&key) {
+                values.insert(key, value.clone());
+                result = result.replace(&full_match, value);
+                continue;
+            }
             match VariableSource::parse(&source_str) {
                 Ok(source) => {
                     match source.evaluate_sync() {
                         Ok(content) => {
+                            values.insert(key, content.clone());
                             result = result.replace(&full_match, &content);
                         }
                         Err(e) => {
@@ -3973,8 +6407,9 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
     }
     
     fn restore_session_variables(&mut self, session: &ChatSession) {
-        // Clear current variables
+        // Clear current variables, keeping the saved ones available
         self.variables.clear();
+        self.variables.extend(self.saved_variables.clone());
 
         // Restore variables from session
         for (var_name, var) in &session.variables {
@@ -4034,7 +6469,7 @@ Format your summary in clear, readable paragraphs. Be objective and factual.";
             if !self.variables.is_empty() {
                 self.ui.print_info("Available variables:");
                 for var_name in self.variables.keys() {
-                    println!("  {}", var_name);
+                    outln!("  {}", var_name);
                 }
             }
         }
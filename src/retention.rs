@@ -0,0 +1,395 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::encryption::{read_state_file, write_state_file};
+use crate::export::{export_sessions, ExportFormat};
+use crate::history::History;
+use crate::session::{ChatSession, NameSource};
+
+/// Log of everything retention rules removed, kept in the state directory
+pub const RETENTION_LOG: &str = "retention.log";
+const ARCHIVE_DIRECTORY: &str = "archive";
+
+/// What happens to sessions selected by a retention rule
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PruneAction {
+    #[default]
+    Trash,   // Recoverable with /chat restore until the trash expires
+    Archive, // Exported as JSON to <state>/archive/ and removed
+}
+
+/// Rules for pruning old sessions and input history; zero disables a limit.
+/// Stored with the sessions, so every workspace has its own policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    pub max_age_days: u32,       // Sessions not updated for this long
+    pub max_sessions: usize,     // Oldest sessions beyond this count
+    pub max_size_bytes: u64,     // Oldest sessions until the total fits
+    pub action: PruneAction,
+    pub exempt_tags: Vec<String>,
+    pub exempt_named: bool,      // Sessions the user named are never pruned
+    pub input_max_age_days: u32, // Input history entries older than this
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: 0,
+            max_sessions: 0,
+            max_size_bytes: 0,
+            action: PruneAction::Trash,
+            exempt_tags: Vec::new(),
+            exempt_named: true,
+            input_max_age_days: 0,
+        }
+    }
+}
+
+/// A session a retention rule selected, and why
+#[derive(Debug, Clone)]
+pub struct PruneCandidate {
+    pub name: String,
+    pub reason: String,
+    pub size: u64,
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days > 0 || self.max_sessions > 0 || self.max_size_bytes > 0 || self.input_max_age_days > 0
+    }
+
+    /// Change one rule as typed in `/retention set KEY VALUE`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        let off = matches!(value.to_lowercase().as_str(), "off" | "none" | "0");
+        match key {
+            "max-age" => self.max_age_days = if off { 0 } else { parse_days(value)? },
+            "input-max-age" => self.input_max_age_days = if off { 0 } else { parse_days(value)? },
+            "max-sessions" => {
+                self.max_sessions = if off {
+                    0
+                } else {
+                    value.parse().map_err(|_| anyhow::anyhow!("Invalid session count '{}'", value))?
+                }
+            }
+            "max-size" => self.max_size_bytes = if off { 0 } else { parse_size(value)? },
+            "action" => {
+                self.action = match value.to_lowercase().as_str() {
+                    "trash" | "delete" => PruneAction::Trash,
+                    "archive" => PruneAction::Archive,
+                    _ => return Err(anyhow::anyhow!("Invalid action '{}'. Use trash or archive", value)),
+                }
+            }
+            "exempt-tags" => {
+                self.exempt_tags = if off {
+                    Vec::new()
+                } else {
+                    value.split(',').map(|tag| tag.trim().to_lowercase()).filter(|tag| !tag.is_empty()).collect()
+                }
+            }
+            "exempt-named" => {
+                self.exempt_named = match value.to_lowercase().as_str() {
+                    "on" | "true" | "yes" => true,
+                    "off" | "false" | "no" => false,
+                    _ => return Err(anyhow::anyhow!("Invalid value '{}'. Use on or off", value)),
+                }
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown retention setting '{}'. Available: max-age, max-sessions, max-size, action, exempt-tags, exempt-named, input-max-age",
+                    key
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_exempt(&self, session: &ChatSession) -> bool {
+        (self.exempt_named && matches!(session.name_source, Some(NameSource::UserProvided)))
+            || session.tags.iter().any(|tag| self.exempt_tags.contains(&tag.to_lowercase()))
+    }
+
+    /// Sessions the rules would remove, oldest first. Age is applied first, then the
+    /// count and size caps remove the oldest remaining sessions until they fit.
+    /// Exempt sessions and `protected` (the active session) are never selected but
+    /// still count towards the caps.
+    pub fn plan(&self, history: &History, protected: Option<&str>, now: DateTime<Utc>) -> Vec<PruneCandidate> {
+        let mut sessions: Vec<(&String, &ChatSession, u64)> = history
+            .saved_sessions
+            .iter()
+            .map(|(name, session)| {
+                let size = serde_json::to_string(session).map(|json| json.len() as u64).unwrap_or(0);
+                (name, session, size)
+            })
+            .collect();
+        sessions.sort_by(|a, b| a.1.updated_at.cmp(&b.1.updated_at).then(a.0.cmp(b.0)));
+
+        let mut remaining_count = sessions.len();
+        let mut remaining_size: u64 = sessions.iter().map(|(_, _, size)| size).sum();
+        let mut selected = Vec::new();
+        let prunable: Vec<bool> = sessions
+            .iter()
+            .map(|(name, session, _)| !self.is_exempt(session) && Some(name.as_str()) != protected)
+            .collect();
+
+        let cutoff = now - Duration::days(self.max_age_days as i64);
+        for (index, (name, session, size)) in sessions.iter().enumerate() {
+            let reason = if self.max_age_days > 0 && session.updated_at < cutoff {
+                format!("not updated in {} days", self.max_age_days)
+            } else if self.max_sessions > 0 && remaining_count > self.max_sessions {
+                format!("over the {} session limit", self.max_sessions)
+            } else if self.max_size_bytes > 0 && remaining_size > self.max_size_bytes {
+                format!("over the {} size limit", format_size(self.max_size_bytes))
+            } else {
+                continue;
+            };
+            if !prunable[index] {
+                continue;
+            }
+            remaining_count -= 1;
+            remaining_size -= size;
+            selected.push(PruneCandidate { name: name.to_string(), reason, size: *size });
+        }
+        selected
+    }
+
+    pub fn input_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        (self.input_max_age_days > 0).then(|| now - Duration::days(self.input_max_age_days as i64))
+    }
+
+    pub fn describe(&self) -> Vec<(&'static str, String)> {
+        let limit = |value: String, enabled: bool| if enabled { value } else { "off".to_string() };
+        vec![
+            ("max-age", limit(format!("{} days", self.max_age_days), self.max_age_days > 0)),
+            ("max-sessions", limit(self.max_sessions.to_string(), self.max_sessions > 0)),
+            ("max-size", limit(format_size(self.max_size_bytes), self.max_size_bytes > 0)),
+            ("action", match self.action {
+                PruneAction::Trash => "trash".to_string(),
+                PruneAction::Archive => "archive".to_string(),
+            }),
+            ("exempt-tags", if self.exempt_tags.is_empty() { "none".to_string() } else { self.exempt_tags.join(", ") }),
+            ("exempt-named", if self.exempt_named { "on" } else { "off" }.to_string()),
+            ("input-max-age", limit(format!("{} days", self.input_max_age_days), self.input_max_age_days > 0)),
+        ]
+    }
+}
+
+/// Remove the planned sessions with the policy's action and append them to the
+/// retention log. Returns the names that were pruned.
+pub fn apply_plan(history: &mut History, action: PruneAction, plan: &[PruneCandidate], state_directory: &str) -> Result<Vec<String>> {
+    let now = Utc::now();
+    let mut pruned = Vec::new();
+    let mut log = Vec::new();
+
+    for candidate in plan {
+        let Some(session) = history.saved_sessions.get(&candidate.name).cloned() else {
+            continue;
+        };
+        match action {
+            PruneAction::Trash => {
+                history.delete_session(&candidate.name)?;
+            }
+            PruneAction::Archive => {
+                let archive_dir = Path::new(state_directory).join(ARCHIVE_DIRECTORY);
+                fs::create_dir_all(&archive_dir)?;
+                let path = archive_path(&archive_dir, &candidate.name, now);
+                write_state_file(&path, &export_sessions(&[&session], ExportFormat::Json, true)?)?;
+                history.forget_session(&candidate.name)?;
+            }
+        }
+        let verb = match action {
+            PruneAction::Trash => "trashed",
+            PruneAction::Archive => "archived",
+        };
+        log.push(format!("{} {} '{}' ({})", now.to_rfc3339(), verb, candidate.name, candidate.reason));
+        pruned.push(candidate.name.clone());
+    }
+
+    append_log(state_directory, &log)?;
+    Ok(pruned)
+}
+
+pub fn log_input_pruning(state_directory: &str, removed: usize, max_age_days: u32) -> Result<()> {
+    append_log(state_directory, &[format!(
+        "{} removed {} input history entries older than {} days",
+        Utc::now().to_rfc3339(), removed, max_age_days
+    )])
+}
+
+fn append_log(state_directory: &str, lines: &[String]) -> Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let path = Path::new(state_directory).join(RETENTION_LOG);
+    let mut content = if path.exists() { read_state_file(&path)? } else { String::new() };
+    for line in lines {
+        content.push_str(line);
+        content.push('\n');
+    }
+    write_state_file(&path, &content)
+}
+
+/// A new file in the archive directory for a session. Names that sanitize to the same
+/// file in the same second get a counter rather than overwriting an earlier archive.
+fn archive_path(archive_dir: &Path, name: &str, now: DateTime<Utc>) -> PathBuf {
+    let safe: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    let stem = format!("{}-{}", now.format("%Y%m%d-%H%M%S"), safe.trim_matches('-'));
+    let mut path = archive_dir.join(format!("{}.json", stem));
+    let mut counter = 2;
+    while path.exists() {
+        path = archive_dir.join(format!("{}-{}.json", stem, counter));
+        counter += 1;
+    }
+    path
+}
+
+/// Every archived session file of a state directory, so rekeying can include them
+pub fn archive_files(state_directory: &str) -> Result<Vec<PathBuf>> {
+    let archive_dir = Path::new(state_directory).join(ARCHIVE_DIRECTORY);
+    if !archive_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&archive_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn parse_days(value: &str) -> Result<u32> {
+    value
+        .trim_end_matches(['d', 'D'])
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid number of days '{}'", value))
+}
+
+/// Parse sizes like `500KB`, `50MB` or `1GB`; a bare number is in MB
+fn parse_size(value: &str) -> Result<u64> {
+    let upper = value.to_uppercase();
+    let (number, multiplier) = if let Some(number) = upper.strip_suffix("GB") {
+        (number, 1024 * 1024 * 1024)
+    } else if let Some(number) = upper.strip_suffix("MB") {
+        (number, 1024 * 1024)
+    } else if let Some(number) = upper.strip_suffix("KB") {
+        (number, 1024)
+    } else {
+        (upper.as_str(), 1024 * 1024)
+    };
+    let number: f64 = number.trim().parse().map_err(|_| anyhow::anyhow!("Invalid size '{}'. Use e.g. 500KB or 50MB", value))?;
+    if number <= 0.0 {
+        return Err(anyhow::anyhow!("Size must be positive"));
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 * 1024 {
+        format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Message;
+
+    fn add_session(history: &mut History, name: &str, days_ago: i64, source: NameSource, tags: &[&str]) {
+        let mut session = ChatSession::new("gpt-4o".to_string(), 0.7, 4096, 20000);
+        session.add_message(Message { role: "user".to_string(), content: "hello".to_string() });
+        session.name = Some(name.to_string());
+        session.name_source = Some(source);
+        session.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        session.updated_at = Utc::now() - Duration::days(days_ago);
+        history.saved_sessions.insert(name.to_string(), session);
+    }
+
+    fn create_history() -> History {
+        let mut history = History::new(String::new());
+        add_session(&mut history, "ancient", 400, NameSource::Timestamp, &[]);
+        add_session(&mut history, "old named", 300, NameSource::UserProvided, &[]);
+        add_session(&mut history, "old kept", 200, NameSource::AutoGenerated, &["keep"]);
+        add_session(&mut history, "recent", 5, NameSource::AutoGenerated, &[]);
+        add_session(&mut history, "today", 0, NameSource::Timestamp, &[]);
+        history
+    }
+
+    fn names(plan: &[PruneCandidate]) -> Vec<&str> {
+        plan.iter().map(|candidate| candidate.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_retention_plan() {
+        let history = create_history();
+        let mut policy = RetentionPolicy::default();
+        assert!(policy.plan(&history, None, Utc::now()).is_empty());
+
+        policy.set("max-age", "90d").unwrap();
+        policy.set("exempt-tags", "keep, Pinned").unwrap();
+        assert_eq!(names(&policy.plan(&history, None, Utc::now())), vec!["ancient"]);
+
+        policy.set("exempt-named", "off").unwrap();
+        assert_eq!(names(&policy.plan(&history, None, Utc::now())), vec!["ancient", "old named"]);
+
+        // Exempt sessions still count towards the cap, so the oldest prunable ones go
+        policy.set("max-age", "off").unwrap();
+        policy.set("max-sessions", "2").unwrap();
+        let plan = policy.plan(&history, Some("ancient"), Utc::now());
+        assert_eq!(names(&plan), vec!["old named", "recent", "today"]);
+        assert_eq!(plan[1].reason, "over the 2 session limit");
+
+        assert!(policy.set("max-size", "lots").is_err());
+        assert!(policy.set("action", "shred").is_err());
+        assert!(policy.set("colour", "blue").is_err());
+        policy.set("max-size", "1.5MB").unwrap();
+        assert_eq!(policy.max_size_bytes, 1_572_864);
+    }
+
+    #[test]
+    fn test_apply_plan_archives_and_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_directory = dir.path().to_string_lossy().to_string();
+        let mut history = create_history();
+        history.history_file_path = dir.path().join("sessions").to_string_lossy().to_string();
+
+        let mut policy = RetentionPolicy::default();
+        policy.set("max-age", "100").unwrap();
+        policy.set("action", "archive").unwrap();
+        let plan = policy.plan(&history, None, Utc::now());
+        let pruned = apply_plan(&mut history, policy.action, &plan, &state_directory).unwrap();
+
+        assert_eq!(pruned, vec!["ancient", "old kept"]);
+        assert!(!history.saved_sessions.contains_key("ancient"));
+        assert!(history.list_trash().is_empty());
+        assert_eq!(fs::read_dir(dir.path().join(ARCHIVE_DIRECTORY)).unwrap().count(), 2);
+        let log = fs::read_to_string(dir.path().join(RETENTION_LOG)).unwrap();
+        assert!(log.contains("archived 'ancient' (not updated in 100 days)"));
+        assert_eq!(archive_files(&state_directory).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_archive_names_do_not_collide() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        let first = archive_path(dir.path(), "Bug hunt", now);
+        fs::write(&first, "{}").unwrap();
+        let second = archive_path(dir.path(), "Bug/hunt", now);
+        assert_ne!(first, second);
+        assert!(second.to_string_lossy().ends_with("-Bug-hunt-2.json"));
+    }
+}
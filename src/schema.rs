@@ -0,0 +1,261 @@
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup::STATE_FILES;
+use crate::encryption::read_state_file;
+
+/// Top-level key holding the layout version of a state file. Files without it are version 0.
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a file's JSON from version N to N + 1
+type Migration = fn(&mut Value) -> Result<()>;
+
+/// State files with a versioned layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateFile {
+    Sessions,
+    Prompts,
+    Inputs,
+}
+
+impl StateFile {
+    /// Migrations in order; entry N upgrades version N to N + 1
+    fn migrations(self) -> &'static [Migration] {
+        match self {
+            StateFile::Sessions => &[migrate_sessions_v0],
+            StateFile::Prompts => &[mark_versioned],
+            StateFile::Inputs => &[mark_versioned],
+        }
+    }
+
+    pub fn current_version(self) -> u32 {
+        self.migrations().len() as u32
+    }
+}
+
+/// A state file written by a newer njord, which this build must neither read nor overwrite
+#[derive(Debug)]
+pub struct NewerSchemaError {
+    pub path: PathBuf,
+    pub version: u32,
+    pub supported: u32,
+}
+
+impl fmt::Display for NewerSchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} was written by a newer version of njord (schema version {}, this build supports up to {}). \
+             Upgrade njord to use this state directory",
+            self.path.display(), self.version, self.supported
+        )
+    }
+}
+
+impl std::error::Error for NewerSchemaError {}
+
+pub fn is_newer_schema(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<NewerSchemaError>().is_some()
+}
+
+/// Read a state file, migrating it to the current layout in memory. The file on disk is
+/// copied to `FILE.vN.bak` before its first migration and rewritten on the next save.
+pub fn load<T: DeserializeOwned>(path: &Path, kind: StateFile) -> Result<T> {
+    let content = read_state_file(path)?;
+    let (data, version) = parse(&content, path, kind)?;
+    if version < kind.current_version() {
+        backup_before_migration(path, version)?;
+    }
+    Ok(data)
+}
+
+/// Parse decrypted state file content, migrating it in memory. Also returns the version it
+/// was written with. `path` is only used in error messages.
+pub fn parse<T: DeserializeOwned>(content: &str, path: &Path, kind: StateFile) -> Result<(T, u32)> {
+    let mut value: Value = serde_json::from_str(content)
+        .with_context(|| format!("{} is not valid JSON", path.display()))?;
+    let version = migrate(&mut value, path, kind)?;
+    let data = serde_json::from_value(value).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok((data, version))
+}
+
+/// Bring a state file's JSON up to the current layout, returning the version it had
+pub fn migrate(value: &mut Value, path: &Path, kind: StateFile) -> Result<u32> {
+    let version = schema_version(value);
+    let supported = kind.current_version();
+    if version > supported {
+        return Err(NewerSchemaError { path: path.to_path_buf(), version, supported }.into());
+    }
+    for (from, migration) in kind.migrations().iter().enumerate().skip(version as usize) {
+        migration(value)
+            .with_context(|| format!("Failed to migrate {} from schema version {}", path.display(), from))?;
+    }
+    Ok(version)
+}
+
+/// Serialize state for writing, stamped with the current schema version
+pub fn to_string<T: Serialize>(data: &T, kind: StateFile) -> Result<String> {
+    let mut value = serde_json::to_value(data)?;
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("State must serialize to a JSON object"))?;
+    object.insert(SCHEMA_VERSION_KEY.to_string(), Value::from(kind.current_version()));
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+fn schema_version(value: &Value) -> u32 {
+    value.get(SCHEMA_VERSION_KEY).and_then(Value::as_u64).map_or(0, |version| version as u32)
+}
+
+/// The backup made before migrating `path` away from `version`
+pub fn migration_backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    path.with_file_name(name)
+}
+
+/// Every migration backup in a state directory, so rekeying can include them
+pub fn migration_backups(state_directory: &str) -> Result<Vec<PathBuf>> {
+    let mut backups = Vec::new();
+    if !Path::new(state_directory).is_dir() {
+        return Ok(backups);
+    }
+    for entry in fs::read_dir(state_directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_backup = STATE_FILES.iter().any(|file| {
+            name.strip_prefix(file)
+                .and_then(|rest| rest.strip_prefix(".v"))
+                .and_then(|rest| rest.strip_suffix(".bak"))
+                .is_some_and(|version| version.parse::<u32>().is_ok())
+        });
+        if is_backup && entry.file_type()?.is_file() {
+            backups.push(entry.path());
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// Copy the file as-is (still encrypted, if it was). An existing backup is kept,
+/// since the file is migrated again on every load until it is saved.
+fn backup_before_migration(path: &Path, version: u32) -> Result<()> {
+    let backup = migration_backup_path(path, version);
+    if !backup.exists() {
+        fs::copy(path, &backup)
+            .with_context(|| format!("Failed to back up {} before migrating it", path.display()))?;
+    }
+    Ok(())
+}
+
+fn mark_versioned(_: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// Version 0 sessions may bind variables in the old `filename -> name` form,
+/// under either `variables` or `variable_bindings`
+fn migrate_sessions_v0(history: &mut Value) -> Result<()> {
+    if let Some(session) = history.get_mut("current_session").filter(|session| !session.is_null()) {
+        migrate_session_variables(session);
+    }
+    if let Some(Value::Object(sessions)) = history.get_mut("saved_sessions") {
+        sessions.values_mut().for_each(migrate_session_variables);
+    }
+    if let Some(Value::Object(trash)) = history.get_mut("trash") {
+        for trashed in trash.values_mut() {
+            if let Some(session) = trashed.get_mut("session") {
+                migrate_session_variables(session);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn migrate_session_variables(session: &mut Value) {
+    let Some(session) = session.as_object_mut() else {
+        return;
+    };
+    if let Some(bindings) = session.remove("variable_bindings") {
+        session.entry("variables").or_insert(bindings);
+    }
+    let Some(Value::Object(variables)) = session.get_mut("variables") else {
+        return;
+    };
+    if !variables.values().all(Value::is_string) {
+        return;
+    }
+    let old_bindings = std::mem::take(variables);
+    for (filename, name) in old_bindings {
+        let Value::String(name) = name else { continue };
+        let mut variable = Map::new();
+        variable.insert("name".to_string(), Value::from(name.clone()));
+        variable.insert("source".to_string(), serde_json::json!({ "File": filename }));
+        variables.insert(name, Value::Object(variable));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use crate::variable::VariableSource;
+
+    const LEGACY_SESSIONS: &str = r#"{
+        "current_session": null,
+        "saved_sessions": {
+            "old": {
+                "id": "00000000-0000-0000-0000-000000000000",
+                "created_at": "2024-01-01T00:00:00Z",
+                "updated_at": "2024-01-01T00:00:00Z",
+                "messages": [],
+                "current_model": "gpt-4",
+                "temperature": 0.7,
+                "max_tokens": 1000,
+                "thinking_budget": 5000,
+                "thinking_enabled": false,
+                "variable_bindings": {
+                    "src/main.rs": "code"
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_sessions_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sessions");
+        fs::write(&path, LEGACY_SESSIONS).unwrap();
+
+        let mut history = History::load(path.to_string_lossy().to_string()).unwrap();
+        let var = &history.saved_sessions["old"].variables["code"];
+        assert_eq!(var.source, VariableSource::File(PathBuf::from("src/main.rs")));
+        assert_eq!(fs::read_to_string(migration_backup_path(&path, 0)).unwrap(), LEGACY_SESSIONS);
+        let state_directory = dir.path().to_string_lossy().to_string();
+        assert_eq!(migration_backups(&state_directory).unwrap(), vec![migration_backup_path(&path, 0)]);
+
+        history.set_default_model(None).unwrap(); // Any save rewrites the file
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved[SCHEMA_VERSION_KEY], StateFile::Sessions.current_version());
+        assert!(History::load(path.to_string_lossy().to_string()).unwrap().saved_sessions.contains_key("old"));
+    }
+
+    #[test]
+    fn test_newer_schema_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sessions");
+        let newer = r#"{"schema_version": 99, "saved_sessions": {"x": {"layout": "unknown"}}}"#;
+        fs::write(&path, newer).unwrap();
+
+        let error = History::load(path.to_string_lossy().to_string()).unwrap_err();
+        assert!(is_newer_schema(&error));
+        assert!(error.to_string().contains("newer version of njord"));
+
+        // Saving must not overwrite what the newer build wrote
+        let mut history = History::new(path.to_string_lossy().to_string());
+        assert!(history.set_default_model(None).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+
+use crate::ui::UI;
+
+/// One step of a script for `njord --script` or /source
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptStep {
+    Command(String),
+    HeredocCommand(String, String), // (command, content)
+    Message(String),
+}
+
+/// A step and the line it starts on, for error messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLine {
+    pub line: usize,
+    pub source: String, // The line as written, echoed when the step runs
+    pub step: ScriptStep,
+}
+
+/// How a script run ended
+#[derive(Debug, Default)]
+pub struct ScriptOutcome {
+    pub failed: usize, // Steps that failed
+    pub quit: bool,    // The script ran /quit
+}
+
+/// Split a script into steps. Blank lines and lines starting with `#` are skipped; a
+/// `<<EOF` line or a `/command <<EOF` line takes everything up to a line reading `EOF`.
+pub fn parse_script(text: &str) -> Result<Vec<ScriptLine>> {
+    let mut steps = Vec::new();
+    let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim_end_matches('\r')));
+
+    while let Some((line, source)) = lines.next() {
+        let trimmed = source.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let step = if let Some(rest) = trimmed.strip_prefix("<<") {
+            // Message heredoc; text after the delimiter is the first line, as at the prompt
+            let (delimiter, first) = rest.trim_start().split_once(char::is_whitespace).unwrap_or((rest.trim_start(), ""));
+            if delimiter.is_empty() {
+                return Err(anyhow::anyhow!("Line {}: missing heredoc delimiter", line));
+            }
+            let first = first.trim();
+            let mut content = read_heredoc(&mut lines, delimiter, line)?;
+            if !first.is_empty() {
+                content.insert(0, first.to_string());
+            }
+            ScriptStep::Message(content.join("\n"))
+        } else if trimmed.starts_with('/') {
+            match UI::parse_command_heredoc(trimmed) {
+                Some((command, delimiter)) => {
+                    let content = read_heredoc(&mut lines, &delimiter, line)?;
+                    ScriptStep::HeredocCommand(command, content.join("\n"))
+                }
+                None => ScriptStep::Command(trimmed.to_string()),
+            }
+        } else {
+            ScriptStep::Message(trimmed.to_string())
+        };
+        steps.push(ScriptLine { line, source: trimmed.to_string(), step });
+    }
+    Ok(steps)
+}
+
+fn read_heredoc<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>, delimiter: &str, start: usize) -> Result<Vec<String>> {
+    let mut content = Vec::new();
+    for (_, line) in lines.by_ref() {
+        if line.trim() == delimiter {
+            return Ok(content);
+        }
+        content.push(line.to_string());
+    }
+    Err(anyhow::anyhow!("Line {}: heredoc is never closed with '{}'", start, delimiter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = "# Setup\n/model gpt-4o\n\n/system <<EOF\nBe terse.\n  Really.\nEOF\nWhat is {{topic}}?\n<<END First line\nsecond line\nEND\n/export md\n";
+        let steps = parse_script(script).unwrap();
+        let kinds: Vec<&ScriptStep> = steps.iter().map(|step| &step.step).collect();
+        assert_eq!(kinds, vec![
+            &ScriptStep::Command("/model gpt-4o".to_string()),
+            &ScriptStep::HeredocCommand("/system".to_string(), "Be terse.\n  Really.".to_string()),
+            &ScriptStep::Message("What is {{topic}}?".to_string()),
+            &ScriptStep::Message("First line\nsecond line".to_string()),
+            &ScriptStep::Command("/export md".to_string()),
+        ]);
+        assert_eq!(steps.iter().map(|step| step.line).collect::<Vec<_>>(), vec![2, 4, 8, 9, 12]);
+        assert_eq!(steps[1].source, "/system <<EOF");
+
+        let error = parse_script("/model gpt-4o\n/system <<EOF\nnever closed\n").unwrap_err();
+        assert!(error.to_string().contains("Line 2"));
+        assert!(parse_script("<<\n").is_err());
+
+        // A space before the delimiter, and delimiters that aren't ASCII
+        let steps = parse_script("<< EOF\nhello\nEOF\n<<ÉOF Bonjour\nÉOF\n").unwrap();
+        assert_eq!(steps[0].step, ScriptStep::Message("hello".to_string()));
+        assert_eq!(steps[1].step, ScriptStep::Message("Bonjour".to_string()));
+    }
+}
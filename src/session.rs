@@ -1,9 +1,12 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
-use crate::providers::Message;
+use crate::journal::{self, JournalEntry, Operation};
+use crate::providers::{Message, ThinkingBlock};
 use crate::variable::{Variable, VariableSource};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,8 +36,18 @@ pub struct ChatSession {
     pub has_llm_interaction: bool,
     #[serde(default, deserialize_with = "deserialize_variables", alias = "variable_bindings")]
     pub variables: std::collections::HashMap<String, Variable>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_from: Option<String>, // Source conversation id for sessions created by /chat import
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub journal: Vec<JournalEntry>, // Undo, goto and edit operations, for /redo and /journal
 }
 
+// The sessions file is migrated by schema::migrate_sessions_v0, but sessions also arrive in
+// bundles and backups that never go through it, so the old format is still accepted here
 fn deserialize_variables<'de, D>(deserializer: D) -> Result<std::collections::HashMap<String, Variable>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -72,9 +85,144 @@ pub struct NumberedMessage {
     pub code_blocks: Vec<CodeBlock>,
     pub provider: Option<String>,
     pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thinking_blocks: Vec<ThinkingBlock>, // Anthropic's signed thinking, sent back in later requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<MessageOrigin>, // Set on messages brought in by /chat merge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<MessageTiming>, // Recorded while streaming assistant responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bookmark: Option<String>, // Set with /mark; unique within the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>, // Set with /note
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool, // Kept in the transcript but not sent to the model; set with /hide
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool, // Always sent to the model and never hidden; set with /pin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<GenerationParams>, // Recorded on assistant messages, for /inspect and /reproduce
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The request settings that produced an assistant message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub temperature: f32,
+    pub max_tokens: u32,
+    pub thinking_enabled: bool,
+    pub thinking_budget: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt_hash: Option<String>,
+    /// The system prompt as sent, so /reproduce can replay it after the session's changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Hashes of the values substituted into the request, keyed by variable name
+    /// (or `GenerationParams::inline_key` for inline patterns)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variable_hashes: BTreeMap<String, String>,
+    /// The substituted values themselves, same keys; only recorded with --record-values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variable_values: BTreeMap<String, String>,
+    /// Earlier messages left out of the request because they were hidden
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded: Vec<usize>,
+}
+
+impl GenerationParams {
+    /// Short, stable fingerprint of a system prompt or variable value
+    pub fn hash(content: &str) -> String {
+        format!("{:x}", Sha256::digest(content.as_bytes()))[..16].to_string()
+    }
+    
+    /// The key an inline `{{@path}}`, `{{!command}}` or `{{=literal}}` pattern is recorded
+    /// under: its sigil and a hash of its text, so paths and command lines aren't stored
+    pub fn inline_key(source: &str) -> String {
+        format!("{}{}", source.chars().next().unwrap_or('='), Self::hash(source))
+    }
+    
+    /// A recorded key as shown to the user: `{{name}}`, or `{{!…}}` for an inline pattern
+    pub fn describe_key(key: &str) -> String {
+        match key.chars().next() {
+            Some(sigil @ ('@' | '!' | '=')) => format!("{{{{{}…}}}}", sigil),
+            _ => format!("{{{{{}}}}}", key),
+        }
+    }
+    
+    /// Drop the recorded values and the hashes of command output (named `commands` and
+    /// inline `{{!command}}` patterns), so a shared session doesn't carry them
+    pub fn strip_secrets(&mut self, commands: &[String]) {
+        self.variable_values.clear();
+        self.variable_hashes.retain(|key, _| !key.starts_with('!') && !commands.contains(key));
+    }
+    
+    /// The system prompt to replay the request with: the recorded text, or `current` if
+    /// only a hash was recorded and it still matches. Fails when the prompt can't be recovered.
+    pub fn replay_system_prompt(&self, current: Option<&str>) -> Result<Option<String>> {
+        let Some(hash) = &self.system_prompt_hash else {
+            return Ok(None);
+        };
+        if let Some(system_prompt) = &self.system_prompt {
+            return Ok(Some(system_prompt.clone()));
+        }
+        match current {
+            Some(current) if &Self::hash(current) == hash => Ok(Some(current.to_string())),
+            _ => Err(anyhow::anyhow!("The system prompt has changed since, and only its hash was recorded")),
+        }
+    }
+}
+
+/// How long a streamed response took, measured from sending the request
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MessageTiming {
+    pub latency_ms: u64,
+    pub first_token_ms: u64,
+}
+
+/// Where a merged message originally came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageOrigin {
+    pub session: String,
+    pub number: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeOrder {
+    Append,     // Source messages after the target's
+    Interleave, // Both sessions ordered by timestamp
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SystemPromptChoice {
+    Target,
+    Source,
+    Both, // Target prompt followed by the source prompt
+}
+
+/// Things the user has to decide before two sessions can be merged
+#[derive(Debug, Clone, Default)]
+pub struct MergeConflicts {
+    pub system_prompt: bool,
+    pub variables: Vec<String>, // Same name, different source
+}
+
+/// How conflicts are resolved; variables not listed keep the target's binding
+#[derive(Debug, Clone)]
+pub struct MergeResolution {
+    pub system_prompt: SystemPromptChoice,
+    pub source_variables: Vec<String>,
+}
+
+impl Default for MergeResolution {
+    fn default() -> Self {
+        Self {
+            system_prompt: SystemPromptChoice::Target,
+            source_variables: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CodeBlock {
     pub number: usize,
     pub language: Option<String>,
@@ -100,6 +248,10 @@ impl ChatSession {
             thinking_enabled: false,
             has_llm_interaction: false,
             variables: std::collections::HashMap::new(),
+            imported_from: None,
+            tags: Vec::new(),
+            description: None,
+            journal: Vec::new(),
         }
     }
     
@@ -114,6 +266,15 @@ impl ChatSession {
             code_blocks,
             provider: None,
             model: None,
+            thinking: None,
+            thinking_blocks: Vec::new(),
+            origin: None,
+            timing: None,
+            bookmark: None,
+            note: None,
+            hidden: false,
+            pinned: false,
+            params: None,
         });
         
         self.updated_at = Utc::now();
@@ -131,6 +292,15 @@ impl ChatSession {
             code_blocks,
             provider,
             model,
+            thinking: None,
+            thinking_blocks: Vec::new(),
+            origin: None,
+            timing: None,
+            bookmark: None,
+            note: None,
+            hidden: false,
+            pinned: false,
+            params: None,
         });
         
         self.updated_at = Utc::now();
@@ -203,6 +373,124 @@ impl ChatSession {
         Ok(last_user_message)
     }
     
+    /// Bookmark names must not look like the numbers and message types that
+    /// /goto, /copy and /save take in the same position
+    pub fn is_valid_bookmark_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            && !matches!(name, "agent" | "user" | "block")
+    }
+    
+    pub fn find_bookmark(&self, name: &str) -> Option<(usize, &NumberedMessage)> {
+        self.messages.iter().enumerate().find(|(_, msg)| msg.bookmark.as_deref() == Some(name))
+    }
+    
+    /// Bookmark message `number`, taking the name off whichever message had it
+    pub fn set_bookmark(&mut self, number: usize, name: &str) {
+        for msg in self.messages.iter_mut() {
+            if msg.number == number {
+                msg.bookmark = Some(name.to_string());
+            } else if msg.bookmark.as_deref() == Some(name) {
+                msg.bookmark = None;
+            }
+        }
+        self.updated_at = Utc::now();
+    }
+    
+    pub fn remove_bookmark(&mut self, name: &str) -> bool {
+        let Some(msg) = self.messages.iter_mut().find(|msg| msg.bookmark.as_deref() == Some(name)) else {
+            return false;
+        };
+        msg.bookmark = None;
+        self.updated_at = Utc::now();
+        true
+    }
+    
+    pub fn bookmark_names(&self) -> Vec<String> {
+        self.messages.iter().filter_map(|msg| msg.bookmark.clone()).collect()
+    }
+    
+    /// Set or (with None) clear the note on message `number`
+    pub fn set_note(&mut self, number: usize, note: Option<String>) {
+        if let Some(msg) = self.messages.iter_mut().find(|msg| msg.number == number) {
+            msg.note = note;
+            self.updated_at = Utc::now();
+        }
+    }
+    
+    /// Exclude message `number` from (or, with false, return it to) the model context.
+    /// Messages that `must_send` can't be hidden.
+    pub fn set_hidden(&mut self, number: usize, hidden: bool) {
+        if hidden && self.must_send(number) {
+            return;
+        }
+        if let Some(msg) = self.messages.iter_mut().find(|msg| msg.number == number) {
+            msg.hidden = hidden;
+            self.updated_at = Utc::now();
+        }
+    }
+    
+    /// Pin message `number` so it is always sent, unhiding it if needed. Pinning a reply
+    /// also unhides its question, since a reply can't be sent without one.
+    pub fn set_pinned(&mut self, number: usize, pinned: bool) {
+        let Some(index) = self.messages.iter().position(|msg| msg.number == number) else {
+            return;
+        };
+        self.messages[index].pinned = pinned;
+        if pinned {
+            self.messages[index].hidden = false;
+            if let Some(question) = self.question_index(index) {
+                self.messages[question].hidden = false;
+            }
+        }
+        self.updated_at = Utc::now();
+    }
+    
+    /// The question a reply at `index` answers: the nearest user message before it
+    fn question_index(&self, index: usize) -> Option<usize> {
+        if self.messages[index].message.role != "assistant" {
+            return None;
+        }
+        self.messages[..index].iter().rposition(|msg| msg.message.role == "user")
+    }
+    
+    /// The question Agent message `number` answers, if it has one
+    pub fn question_of(&self, number: usize) -> Option<&NumberedMessage> {
+        let index = self.messages.iter().position(|msg| msg.number == number)?;
+        self.question_index(index).map(|question| &self.messages[question])
+    }
+    
+    /// Whether message `number` has to stay in the context: it is pinned, or it is the
+    /// question of a pinned reply
+    pub fn must_send(&self, number: usize) -> bool {
+        let Some(index) = self.messages.iter().position(|msg| msg.number == number) else {
+            return false;
+        };
+        self.messages[index].pinned
+            || (self.messages[index].message.role == "user"
+                && self.messages[index + 1..].iter()
+                    .take_while(|msg| msg.message.role != "user")
+                    .any(|msg| msg.pinned))
+    }
+    
+    /// The messages sent to the model: everything but hidden messages. Pinned messages
+    /// and their questions can't be hidden, so a pinned reply is never sent without its
+    /// question. Hiding can leave turns out of order; pass the request through
+    /// `alternate_turns` before sending.
+    pub fn context_messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter().filter(|msg| msg.pinned || !msg.hidden).map(|msg| &msg.message)
+    }
+    
+    /// `context_messages` along with the signed thinking of the replies `model` wrote;
+    /// thinking is only sent back to the model that produced it
+    pub fn context_turns<'a>(&'a self, model: &'a str) -> impl Iterator<Item = (Message, Vec<ThinkingBlock>)> + 'a {
+        self.messages.iter().filter(|msg| msg.pinned || !msg.hidden).map(move |msg| {
+            let blocks = if msg.model.as_deref() == Some(model) { msg.thinking_blocks.clone() } else { Vec::new() };
+            (msg.message.clone(), blocks)
+        })
+    }
+    
     pub fn goto(&mut self, message_number: usize) -> Result<()> {
         if message_number == 0 || message_number > self.messages.len() {
             return Err(anyhow::anyhow!("Invalid message number: {}", message_number));
@@ -213,6 +501,48 @@ impl ChatSession {
         Ok(())
     }
     
+    /// Journal a destructive change to this session's messages
+    pub fn journal_operation(&mut self, operation: Operation) {
+        journal::record(&mut self.journal, operation);
+    }
+    
+    /// Reverse a journaled undo, goto or edit. Refuses when later changes to the
+    /// conversation would make the result inconsistent. Returns what was reverted.
+    pub fn revert_operation(&mut self, index: usize) -> Result<String> {
+        let entry = self.journal.get(index)
+            .ok_or_else(|| anyhow::anyhow!("No such journal entry"))?;
+        if entry.reverted {
+            return Err(anyhow::anyhow!("Already reverted: {}", entry.operation.describe()));
+        }
+        
+        match &entry.operation {
+            Operation::Undo { removed } | Operation::Goto { removed } => {
+                let expected = removed.first().map_or(0, |msg| msg.number - 1);
+                if self.messages.len() != expected {
+                    return Err(anyhow::anyhow!(
+                        "The conversation has changed since ({} messages now, {} then), so the removed messages can't be put back",
+                        self.messages.len(), expected
+                    ));
+                }
+                self.messages.extend(removed.iter().cloned());
+            }
+            Operation::Edit { number, previous, replacement } => {
+                match self.messages.get_mut(number.wrapping_sub(1)) {
+                    Some(msg) if &msg.message.content == replacement => msg.message.content = previous.clone(),
+                    _ => return Err(anyhow::anyhow!("Message {} has changed since the edit", number)),
+                }
+            }
+            Operation::Delete { .. } | Operation::Rename { .. } => {
+                return Err(anyhow::anyhow!("Session operations are reverted through the session history"));
+            }
+        }
+        
+        let description = entry.operation.describe();
+        self.journal[index].reverted = true;
+        self.updated_at = Utc::now();
+        Ok(description)
+    }
+    
     pub fn mark_llm_interaction(&mut self) {
         self.has_llm_interaction = true;
     }
@@ -244,9 +574,151 @@ impl ChatSession {
             thinking_enabled: self.thinking_enabled,
             has_llm_interaction: false, // Reset for new copy
             variables: self.variables.clone(), // Copy variables
+            imported_from: None, // Copies are not the imported original
+            tags: self.tags.clone(),
+            description: self.description.clone(),
+            journal: Vec::new(), // Copies start their own history
         }
     }
     
+    /// Normalize a tag for storage and comparison (lowercase, no leading '#')
+    pub fn normalize_tag(tag: &str) -> String {
+        tag.trim().trim_start_matches('#').to_lowercase()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = Self::normalize_tag(tag);
+        self.tags.iter().any(|t| *t == tag)
+    }
+
+    /// Add tags, returning the ones that were not already present
+    pub fn add_tags(&mut self, tags: &[String]) -> Vec<String> {
+        let mut added = Vec::new();
+        for tag in tags {
+            let tag = Self::normalize_tag(tag);
+            if !tag.is_empty() && !self.tags.contains(&tag) {
+                self.tags.push(tag.clone());
+                added.push(tag);
+            }
+        }
+        self.tags.sort();
+        added
+    }
+
+    /// Remove tags, returning the ones that were actually present
+    pub fn remove_tags(&mut self, tags: &[String]) -> Vec<String> {
+        let mut removed = Vec::new();
+        for tag in tags {
+            let tag = Self::normalize_tag(tag);
+            if let Some(pos) = self.tags.iter().position(|t| *t == tag) {
+                self.tags.remove(pos);
+                removed.push(tag);
+            }
+        }
+        removed
+    }
+
+    pub fn merge_conflicts(&self, source: &ChatSession) -> MergeConflicts {
+        let system_prompt = matches!(
+            (&self.system_prompt, &source.system_prompt),
+            (Some(target), Some(incoming)) if target.trim() != incoming.trim()
+        );
+
+        let mut variables: Vec<String> = source.variables
+            .iter()
+            .filter(|(name, variable)| {
+                self.variables.get(*name).map(|existing| existing.source != variable.source).unwrap_or(false)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        variables.sort();
+
+        MergeConflicts { system_prompt, variables }
+    }
+
+    /// Merge another session's messages into this one. Messages are renumbered and
+    /// keep a record of the session they came from; the source is left untouched.
+    /// Returns the number of messages added.
+    pub fn merge_from(&mut self, source: &ChatSession, source_name: &str, order: MergeOrder, resolution: &MergeResolution) -> usize {
+        let incoming: Vec<NumberedMessage> = source.messages
+            .iter()
+            .map(|msg| {
+                let mut msg = msg.clone();
+                // Messages merged in earlier keep pointing at their first session
+                if msg.origin.is_none() {
+                    msg.origin = Some(MessageOrigin {
+                        session: source_name.to_string(),
+                        number: msg.number,
+                    });
+                }
+                msg
+            })
+            .collect();
+        let added = incoming.len();
+
+        let existing = std::mem::take(&mut self.messages);
+        self.messages = match order {
+            MergeOrder::Append => existing.into_iter().chain(incoming).collect(),
+            MergeOrder::Interleave => {
+                // Whole exchanges are ordered by their user message, so a reply stays with its
+                // question. Stable merge: on equal timestamps the target's exchange goes first
+                let mut merged = Vec::with_capacity(existing.len() + incoming.len());
+                let mut existing = Self::split_exchanges(existing).into_iter().peekable();
+                let mut incoming = Self::split_exchanges(incoming).into_iter().peekable();
+                loop {
+                    let take_existing = match (existing.peek(), incoming.peek()) {
+                        (Some(a), Some(b)) => a[0].timestamp <= b[0].timestamp,
+                        (Some(_), None) => true,
+                        (None, Some(_)) => false,
+                        (None, None) => break,
+                    };
+                    let next = if take_existing { existing.next() } else { incoming.next() };
+                    merged.extend(next.into_iter().flatten());
+                }
+                merged
+            }
+        };
+        for (index, msg) in self.messages.iter_mut().enumerate() {
+            msg.number = index + 1;
+        }
+
+        self.system_prompt = match (&self.system_prompt, &source.system_prompt, resolution.system_prompt) {
+            (None, incoming, _) => incoming.clone(),
+            (target, None, _) => target.clone(),
+            (_, Some(incoming), SystemPromptChoice::Source) => Some(incoming.clone()),
+            (Some(target), Some(incoming), SystemPromptChoice::Both) if target.trim() != incoming.trim() => {
+                Some(format!("{}\n\n{}", target, incoming))
+            }
+            (target, _, _) => target.clone(),
+        };
+
+        for (name, variable) in &source.variables {
+            if !self.variables.contains_key(name) || resolution.source_variables.contains(name) {
+                self.variables.insert(name.clone(), variable.clone());
+            }
+        }
+
+        self.add_tags(&source.tags);
+        if source.has_llm_interaction {
+            self.mark_llm_interaction();
+        }
+        self.updated_at = Utc::now();
+        added
+    }
+
+    /// Group messages into exchanges: a user message and the replies that follow it.
+    /// Anything before the first user message forms an exchange of its own.
+    fn split_exchanges(messages: Vec<NumberedMessage>) -> Vec<Vec<NumberedMessage>> {
+        let mut exchanges: Vec<Vec<NumberedMessage>> = Vec::new();
+        for msg in messages {
+            match exchanges.last_mut() {
+                Some(exchange) if msg.message.role != "user" => exchange.push(msg),
+                _ => exchanges.push(vec![msg]),
+            }
+        }
+        exchanges
+    }
+
     pub fn get_next_user_message_number(&self) -> usize {
         self.messages.iter()
             .filter(|msg| msg.message.role == "user")
@@ -254,6 +726,33 @@ impl ChatSession {
     }
 }
 
+/// Make a conversation valid for the providers, which expect user and assistant turns to
+/// alternate starting with the user: consecutive turns from the same role are merged, and
+/// replies before the first user turn (whose question was hidden) are dropped. A pinned
+/// reply keeps its question in the context, so it is never one of them.
+/// System messages are left as they are. Each message's signed thinking is carried along,
+/// merged replies keeping all of their blocks; returns the turns and the blocks of each.
+pub fn alternate_turns(messages: Vec<(Message, Vec<ThinkingBlock>)>) -> (Vec<Message>, Vec<Vec<ThinkingBlock>>) {
+    let mut turns: Vec<Message> = Vec::new();
+    let mut thinking: Vec<Vec<ThinkingBlock>> = Vec::new();
+    for (msg, blocks) in messages {
+        let orphan_reply = msg.role == "assistant" && turns.iter().all(|turn| turn.role == "system");
+        match turns.last_mut() {
+            _ if orphan_reply => {}
+            Some(last) if last.role == msg.role => {
+                last.content.push_str("\n\n");
+                last.content.push_str(&msg.content);
+                thinking.last_mut().expect("one entry per turn").extend(blocks);
+            }
+            _ => {
+                turns.push(msg);
+                thinking.push(blocks);
+            }
+        }
+    }
+    (turns, thinking)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -295,6 +794,66 @@ mod tests {
         assert_eq!(session.messages[0].message.role, "user");
     }
 
+    #[test]
+    fn test_merge_sessions() {
+        let mut target = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        let mut source = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        target.system_prompt = Some("Be brief".to_string());
+        source.system_prompt = Some("Be thorough".to_string());
+        for (session, text) in [(&mut target, "target"), (&mut source, "source")] {
+            session.add_message(Message { role: "user".to_string(), content: format!("{} question", text) });
+            session.add_message(Message { role: "assistant".to_string(), content: format!("{} answer", text) });
+        }
+        // Put the source's exchange first in time
+        let base = target.messages[0].timestamp;
+        source.messages[0].timestamp = base - chrono::Duration::minutes(10);
+        source.messages[1].timestamp = base - chrono::Duration::minutes(9);
+        source.variables.insert("X".to_string(), Variable::new("X".to_string(), VariableSource::Literal("1".to_string())));
+
+        let conflicts = target.merge_conflicts(&source);
+        assert!(conflicts.system_prompt);
+        assert!(conflicts.variables.is_empty());
+
+        let mut appended = target.clone();
+        let added = appended.merge_from(&source, "Other", MergeOrder::Append, &MergeResolution::default());
+        assert_eq!(added, 2);
+        assert_eq!(appended.messages[2].message.content, "source question");
+        assert_eq!(appended.messages.iter().map(|m| m.number).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(appended.messages[2].origin, Some(MessageOrigin { session: "Other".to_string(), number: 1 }));
+        assert!(appended.messages[0].origin.is_none());
+        assert_eq!(appended.system_prompt, Some("Be brief".to_string()));
+        assert!(appended.variables.contains_key("X"));
+
+        let mut interleaved = target.clone();
+        interleaved.merge_from(&source, "Other", MergeOrder::Interleave, &MergeResolution {
+            system_prompt: SystemPromptChoice::Both,
+            source_variables: Vec::new(),
+        });
+        assert_eq!(interleaved.messages[0].message.content, "source question");
+        assert_eq!(interleaved.messages[3].number, 4);
+        assert_eq!(interleaved.system_prompt, Some("Be brief\n\nBe thorough".to_string()));
+        assert_eq!(source.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_interleave_keeps_exchanges_together() {
+        let mut target = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        let mut source = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        for (session, text) in [(&mut target, "target"), (&mut source, "source")] {
+            session.add_message(Message { role: "user".to_string(), content: format!("{} question", text) });
+            session.add_message(Message { role: "assistant".to_string(), content: format!("{} answer", text) });
+        }
+
+        // A reply stays with its question even when the other session's next message is older
+        let base = target.messages[0].timestamp;
+        source.messages[0].timestamp = base + chrono::Duration::seconds(1);
+        source.messages[1].timestamp = base + chrono::Duration::minutes(10);
+        target.messages[1].timestamp = base + chrono::Duration::minutes(5);
+        target.merge_from(&source, "Other", MergeOrder::Interleave, &MergeResolution::default());
+        let contents: Vec<&str> = target.messages.iter().map(|m| m.message.content.as_str()).collect();
+        assert_eq!(contents, vec!["target question", "target answer", "source question", "source answer"]);
+    }
+
     #[test]
     fn test_add_message_with_metadata() {
         let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
@@ -402,6 +961,213 @@ mod tests {
         assert_eq!(result, Some("First".to_string()));
     }
 
+    #[test]
+    fn test_revert_journal_operations() {
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        session.add_message(Message { role: "user".to_string(), content: "First".to_string() });
+        session.add_message(Message { role: "assistant".to_string(), content: "Response 1".to_string() });
+        
+        let removed = session.messages.clone();
+        session.undo(1).unwrap();
+        session.journal_operation(Operation::Undo { removed });
+        session.revert_operation(0).unwrap();
+        assert_eq!(session.messages.len(), 2);
+        assert!(session.journal[0].reverted);
+        assert!(session.revert_operation(0).is_err());
+        
+        let removed = session.messages.clone();
+        session.undo(1).unwrap();
+        session.journal_operation(Operation::Undo { removed });
+        session.add_message(Message { role: "user".to_string(), content: "Different".to_string() });
+        assert!(session.revert_operation(1).is_err()); // New messages since the undo
+        
+        session.journal_operation(Operation::Edit { number: 1, previous: "Before".to_string(), replacement: "Different".to_string() });
+        session.revert_operation(2).unwrap();
+        assert_eq!(session.messages[0].message.content, "Before");
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        session.add_message(Message { role: "user".to_string(), content: "First".to_string() });
+        session.add_message(Message { role: "assistant".to_string(), content: "Response 1".to_string() });
+        session.add_message(Message { role: "assistant".to_string(), content: "Response 2".to_string() });
+        
+        session.set_bookmark(2, "good");
+        session.set_bookmark(3, "good"); // Moves the name
+        assert_eq!(session.find_bookmark("good").map(|(index, _)| index), Some(2));
+        assert_eq!(session.bookmark_names(), vec!["good"]);
+        
+        session.set_note(2, Some("Too verbose".to_string()));
+        assert_eq!(session.messages[1].note.as_deref(), Some("Too verbose"));
+        assert!(session.remove_bookmark("good"));
+        assert!(!session.remove_bookmark("good"));
+        
+        assert!(ChatSession::is_valid_bookmark_name("v2.final"));
+        assert!(!ChatSession::is_valid_bookmark_name("3"));
+        assert!(!ChatSession::is_valid_bookmark_name("agent"));
+        assert!(!ChatSession::is_valid_bookmark_name("two words"));
+    }
+
+    #[test]
+    fn test_generation_params() {
+        assert_eq!(GenerationParams::hash("You are terse."), GenerationParams::hash("You are terse."));
+        assert_ne!(GenerationParams::hash("You are terse."), GenerationParams::hash("You are verbose."));
+        assert_eq!(GenerationParams::hash("").len(), 16);
+        let inline = GenerationParams::inline_key("!cat ~/.token");
+        assert!(inline.starts_with('!') && !inline.contains("token"));
+        assert_eq!(GenerationParams::describe_key(&inline), "{{!…}}");
+        assert_eq!(GenerationParams::describe_key("code"), "{{code}}");
+        
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        session.add_message(Message { role: "assistant".to_string(), content: "Hi".to_string() });
+        session.messages[0].params = Some(GenerationParams {
+            temperature: 0.2,
+            max_tokens: 1000,
+            thinking_enabled: false,
+            thinking_budget: 5000,
+            system_prompt_hash: None,
+            system_prompt: None,
+            variable_hashes: BTreeMap::from([("code".to_string(), GenerationParams::hash("fn main() {}"))]),
+            variable_values: BTreeMap::new(),
+            excluded: vec![],
+        });
+        let json = serde_json::to_string(&session.messages[0]).unwrap();
+        let restored: NumberedMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.params, session.messages[0].params);
+        
+        // Messages saved before parameters were recorded still load
+        let legacy = json.split(",\"params\"").next().unwrap().to_string() + "}";
+        assert!(serde_json::from_str::<NumberedMessage>(&legacy).unwrap().params.is_none());
+    }
+
+    #[test]
+    fn test_replay_system_prompt() {
+        let mut params = GenerationParams {
+            temperature: 0.0,
+            max_tokens: 1000,
+            thinking_enabled: false,
+            thinking_budget: 0,
+            system_prompt_hash: None,
+            system_prompt: None,
+            variable_hashes: BTreeMap::new(),
+            variable_values: BTreeMap::new(),
+            excluded: vec![],
+        };
+        assert_eq!(params.replay_system_prompt(Some("Be terse")).unwrap(), None);
+
+        // Older messages only have the hash; it is enough while the prompt is unchanged
+        params.system_prompt_hash = Some(GenerationParams::hash("Be terse"));
+        assert_eq!(params.replay_system_prompt(Some("Be terse")).unwrap().as_deref(), Some("Be terse"));
+        assert!(params.replay_system_prompt(Some("Be verbose")).is_err());
+        assert!(params.replay_system_prompt(None).is_err());
+
+        params.system_prompt = Some("Be terse".to_string());
+        assert_eq!(params.replay_system_prompt(Some("Be verbose")).unwrap().as_deref(), Some("Be terse"));
+    }
+
+    #[test]
+    fn test_context_flags() {
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        session.add_message(Message { role: "user".to_string(), content: "Huge paste".to_string() });
+        session.add_message(Message { role: "assistant".to_string(), content: "Noted".to_string() });
+        session.add_message(Message { role: "user".to_string(), content: "Always answer in French".to_string() });
+        
+        session.set_hidden(1, true);
+        let sent: Vec<&str> = session.context_messages().map(|msg| msg.content.as_str()).collect();
+        assert_eq!(sent, vec!["Noted", "Always answer in French"]);
+        // A reply whose question is hidden isn't sent on its own
+        let turns = alternate_turns(session.context_turns("gpt-4").collect()).0;
+        assert_eq!(turns.iter().map(|msg| msg.content.as_str()).collect::<Vec<_>>(), vec!["Always answer in French"]);
+        
+        // Questions left next to each other by a hidden reply are sent as one turn
+        session.set_hidden(1, false);
+        session.set_hidden(2, true);
+        let mut request = vec![(Message { role: "system".to_string(), content: "Be brief".to_string() }, Vec::new())];
+        request.extend(session.context_turns("gpt-4"));
+        request.push((Message { role: "user".to_string(), content: "Next".to_string() }, Vec::new()));
+        let (turns, _) = alternate_turns(request);
+        assert_eq!(turns.iter().map(|msg| msg.role.as_str()).collect::<Vec<_>>(), vec!["system", "user"]);
+        assert_eq!(turns[1].content, "Huge paste\n\nAlways answer in French\n\nNext");
+        session.set_hidden(2, false);
+        session.set_hidden(1, true);
+        
+        // Pinned messages can't be hidden, and pinning unhides
+        session.set_pinned(3, true);
+        session.set_hidden(3, true);
+        assert!(!session.messages[2].hidden);
+        session.set_pinned(1, true);
+        assert!(!session.messages[0].hidden);
+        assert_eq!(session.context_messages().count(), 3);
+        
+        session.set_pinned(1, false);
+        session.set_hidden(1, true);
+        session.set_hidden(1, false);
+        assert_eq!(session.context_messages().count(), 3);
+        
+        // Unset flags stay out of the saved file
+        let json = serde_json::to_string(&session.messages[1]).unwrap();
+        assert!(!json.contains("hidden") && !json.contains("pinned"));
+    }
+
+    #[test]
+    fn test_pinned_reply_keeps_its_question() {
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+        session.add_message(Message { role: "user".to_string(), content: "Design the schema".to_string() });
+        session.add_message(Message { role: "assistant".to_string(), content: "Here is the schema".to_string() });
+        session.add_message(Message { role: "user".to_string(), content: "Thanks".to_string() });
+
+        // Pinning a reply brings back its hidden question
+        session.set_hidden(1, true);
+        session.set_pinned(2, true);
+        assert!(!session.messages[0].hidden);
+        assert_eq!(session.question_of(2).map(|msg| msg.number), Some(1));
+
+        // and the question can't be hidden while the reply is pinned
+        assert!(session.must_send(1));
+        assert!(!session.must_send(3));
+        session.set_hidden(1, true);
+        assert!(!session.messages[0].hidden);
+        let turns = alternate_turns(session.context_turns("gpt-4").collect()).0;
+        assert_eq!(turns.iter().map(|msg| msg.content.as_str()).collect::<Vec<_>>(), vec!["Design the schema", "Here is the schema", "Thanks"]);
+
+        session.set_pinned(2, false);
+        session.set_hidden(1, true);
+        assert!(session.messages[0].hidden);
+    }
+
+    #[test]
+    fn test_thinking_blocks_follow_their_turns() {
+        let mut session = ChatSession::new("claude-sonnet-4-5-20250929".to_string(), 0.7, 1000, 5000);
+        for (question, answer) in [("First", "One"), ("Second", "Two")] {
+            session.add_message(Message { role: "user".to_string(), content: question.to_string() });
+            session.add_message_with_metadata(
+                Message { role: "assistant".to_string(), content: answer.to_string() },
+                Some("anthropic".to_string()),
+                Some("claude-sonnet-4-5-20250929".to_string()),
+            );
+            session.messages.last_mut().unwrap().thinking_blocks = vec![ThinkingBlock {
+                thinking: format!("Thinking about {}", question),
+                signature: format!("sig-{}", answer),
+            }];
+        }
+
+        let (turns, thinking) = alternate_turns(session.context_turns("claude-sonnet-4-5-20250929").collect());
+        assert_eq!(turns.len(), thinking.len());
+        assert!(thinking[0].is_empty());
+        assert_eq!(thinking[1][0].signature, "sig-One");
+
+        // Replies merged by a hidden question keep both blocks, in order
+        session.set_hidden(3, true);
+        let (turns, thinking) = alternate_turns(session.context_turns("claude-sonnet-4-5-20250929").collect());
+        assert_eq!(turns[1].content, "One\n\nTwo");
+        assert_eq!(thinking[1].iter().map(|block| block.signature.as_str()).collect::<Vec<_>>(), vec!["sig-One", "sig-Two"]);
+
+        // Other models don't get the thinking back
+        let (_, thinking) = alternate_turns(session.context_turns("gpt-4o").collect());
+        assert!(thinking.iter().all(|blocks| blocks.is_empty()));
+    }
+
     #[test]
     fn test_goto() {
         let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
@@ -485,6 +1251,26 @@ mod tests {
         assert_eq!(copy.messages[0].message.content, "Test");
     }
 
+    #[test]
+    fn test_tags() {
+        let mut session = ChatSession::new("gpt-4".to_string(), 0.7, 1000, 5000);
+
+        let added = session.add_tags(&["Rust".to_string(), "#work".to_string(), "rust".to_string()]);
+        assert_eq!(added, vec!["rust".to_string(), "work".to_string()]);
+        assert_eq!(session.tags, vec!["rust".to_string(), "work".to_string()]);
+        assert!(session.has_tag("#RUST"));
+
+        let removed = session.remove_tags(&["work".to_string(), "missing".to_string()]);
+        assert_eq!(removed, vec!["work".to_string()]);
+        assert!(!session.has_tag("work"));
+
+        // Tags and description survive copies
+        session.description = Some("Notes on lifetimes".to_string());
+        let copy = session.create_copy();
+        assert_eq!(copy.tags, vec!["rust".to_string()]);
+        assert_eq!(copy.description.as_deref(), Some("Notes on lifetimes"));
+    }
+
     #[test]
     fn test_variable_bindings_migration() {
         // Old format: HashMap<String, String> (filename -> var_name)
@@ -0,0 +1,280 @@
+use chrono::Datelike;
+use std::collections::BTreeMap;
+
+use crate::providers::get_provider_for_model;
+use crate::session::{ChatSession, MessageTiming};
+
+const HEADER: &str = "\x1b[1;36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Rough token count for display; providers don't report usage while streaming
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// List prices in USD per million (input, output) tokens. Matched by prefix, so more
+/// specific names come first.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("claude-opus-4-5", 5.0, 25.0),
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-haiku-4-5", 1.0, 5.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("gpt-5.2-pro", 21.0, 168.0),
+    ("gpt-5.2", 1.75, 14.0),
+    ("gpt-5.1", 1.25, 10.0),
+    ("gpt-5-mini", 0.25, 2.0),
+    ("gpt-5-nano", 0.05, 0.4),
+    ("gpt-5", 1.25, 10.0),
+    ("gpt-4.1-mini", 0.4, 1.6),
+    ("gpt-4.1-nano", 0.1, 0.4),
+    ("gpt-4.1", 2.0, 8.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4o", 2.5, 10.0),
+    ("o3-pro", 20.0, 80.0),
+    ("o3-mini", 1.1, 4.4),
+    ("o3", 2.0, 8.0),
+    ("o4-mini", 1.1, 4.4),
+    ("o1-pro", 150.0, 600.0),
+    ("o1", 15.0, 60.0),
+    ("gemini-3-pro", 2.0, 12.0),
+    ("gemini-2.5-pro", 1.25, 10.0),
+    ("gemini-2.5-flash-lite", 0.1, 0.4),
+    ("gemini-2.5-flash", 0.3, 2.5),
+];
+
+/// Rough cost in USD of a request, or None for models without a known price
+pub fn estimate_cost(model: &str, input_tokens: usize, output_tokens: usize) -> Option<f64> {
+    let (_, input, output) = PRICES.iter().find(|(prefix, _, _)| model.starts_with(prefix))?;
+    Some((input_tokens as f64 * input + output_tokens as f64 * output) / 1_000_000.0)
+}
+
+/// Responses and output tokens of one model within one ISO week
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WeeklyUsage {
+    pub responses: usize,
+    pub tokens: usize,
+}
+
+/// Counts collected from one or more sessions
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub sessions: usize,
+    pub messages: BTreeMap<String, usize>, // By role
+    pub words: BTreeMap<String, usize>,
+    pub tokens: BTreeMap<String, usize>,
+    pub code_blocks: BTreeMap<String, usize>, // By language
+    pub models: BTreeMap<String, usize>, // Responses per model
+    pub providers: BTreeMap<String, usize>,
+    pub timings: Vec<(MessageTiming, usize)>, // Timing and output tokens of each timed response
+    pub weekly: BTreeMap<(String, String), WeeklyUsage>, // (week, model)
+}
+
+impl Stats {
+    pub fn add_session(&mut self, session: &ChatSession) {
+        self.sessions += 1;
+        for msg in &session.messages {
+            let role = msg.message.role.clone();
+            let tokens = estimate_tokens(&msg.message.content);
+            *self.messages.entry(role.clone()).or_default() += 1;
+            *self.words.entry(role.clone()).or_default() += msg.message.content.split_whitespace().count();
+            *self.tokens.entry(role.clone()).or_default() += tokens;
+            for block in &msg.code_blocks {
+                let language = block.language.clone().unwrap_or_else(|| "plain".to_string());
+                *self.code_blocks.entry(language).or_default() += 1;
+            }
+
+            if role != "assistant" {
+                continue;
+            }
+            let model = msg.model.clone().unwrap_or_else(|| "unknown".to_string());
+            let provider = msg.provider.clone()
+                .or_else(|| get_provider_for_model(&model).map(str::to_string))
+                .unwrap_or_else(|| "unknown".to_string());
+            *self.models.entry(model.clone()).or_default() += 1;
+            *self.providers.entry(provider).or_default() += 1;
+            if let Some(timing) = msg.timing {
+                self.timings.push((timing, tokens));
+            }
+
+            let week = msg.timestamp.iso_week();
+            let usage = self.weekly
+                .entry((format!("{}-W{:02}", week.year(), week.week()), model))
+                .or_default();
+            usage.responses += 1;
+            usage.tokens += tokens;
+        }
+    }
+
+    pub fn total_messages(&self) -> usize {
+        self.messages.values().sum()
+    }
+
+    /// Output tokens per second after the first token arrived, over all timed responses
+    pub fn throughput(&self) -> Option<f64> {
+        let (tokens, streaming_ms) = self.timings.iter()
+            .filter(|(timing, _)| timing.latency_ms > timing.first_token_ms)
+            .fold((0, 0), |(tokens, ms), (timing, count)| {
+                (tokens + count, ms + timing.latency_ms - timing.first_token_ms)
+            });
+        (streaming_ms > 0).then(|| tokens as f64 * 1000.0 / streaming_ms as f64)
+    }
+}
+
+fn role_label(role: &str) -> String {
+    match role {
+        "user" => "User".to_string(),
+        "assistant" => "Agent".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Entries ordered by count, most frequent first
+fn by_count(counts: &BTreeMap<String, usize>) -> String {
+    let mut entries: Vec<_> = counts.iter().collect();
+    entries.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    entries.iter().map(|(name, count)| format!("{} {}", name, count)).collect::<Vec<_>>().join(", ")
+}
+
+fn by_role(counts: &BTreeMap<String, usize>) -> String {
+    counts.iter().map(|(role, count)| format!("{} {}", role_label(role), count)).collect::<Vec<_>>().join(", ")
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+pub fn render_stats(title: &str, stats: &Stats, show_weekly: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("{}{}{}\n", HEADER, title, RESET));
+    if stats.total_messages() == 0 {
+        out.push_str("  No messages yet\n");
+        return out;
+    }
+
+    out.push_str(&format!("  Messages: {} ({})\n", stats.total_messages(), by_role(&stats.messages)));
+    out.push_str(&format!("  Words: {}\n", by_role(&stats.words)));
+    out.push_str(&format!("  Tokens (estimated): {}\n", by_role(&stats.tokens)));
+    let block_count: usize = stats.code_blocks.values().sum();
+    if block_count > 0 {
+        out.push_str(&format!("  Code blocks: {} ({})\n", block_count, by_count(&stats.code_blocks)));
+    } else {
+        out.push_str("  Code blocks: 0\n");
+    }
+    if !stats.models.is_empty() {
+        out.push_str(&format!("  Models: {}\n", by_count(&stats.models)));
+        out.push_str(&format!("  Providers: {}\n", by_count(&stats.providers)));
+    }
+
+    let responses = stats.messages.get("assistant").copied().unwrap_or(0);
+    if stats.timings.is_empty() {
+        if responses > 0 {
+            out.push_str(&format!("  {}Timing: not recorded for these responses{}\n", DIM, RESET));
+        }
+    } else {
+        let count = stats.timings.len() as u64;
+        let latency: u64 = stats.timings.iter().map(|(timing, _)| timing.latency_ms).sum();
+        let first_token: u64 = stats.timings.iter().map(|(timing, _)| timing.first_token_ms).sum();
+        let slowest = stats.timings.iter().map(|(timing, _)| timing.latency_ms).max().unwrap_or(0);
+        out.push_str(&format!("  Timing ({} of {} responses):\n", count, responses));
+        out.push_str(&format!("    Latency: avg {}, max {}\n", seconds(latency / count), seconds(slowest)));
+        out.push_str(&format!("    Time to first token: avg {}\n", seconds(first_token / count)));
+        if let Some(rate) = stats.throughput() {
+            out.push_str(&format!("    Throughput: ~{:.1} tokens/s\n", rate));
+        }
+    }
+
+    if show_weekly && !stats.weekly.is_empty() {
+        out.push_str(&format!("\n{}Usage per model per week:{}\n", HEADER, RESET));
+        let width = stats.weekly.keys().map(|(_, model)| model.len()).max().unwrap_or(0);
+        let mut last_week = "";
+        for ((week, model), usage) in &stats.weekly {
+            let label = if week != last_week { week.as_str() } else { "" };
+            out.push_str(&format!(
+                "  {:<8}  {:<width$}  {:>4} responses  ~{} tokens\n",
+                label, model, usage.responses, usage.tokens, width = width
+            ));
+            last_week = week;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Message;
+    use chrono::{TimeZone, Utc};
+
+    fn message(role: &str, content: &str) -> Message {
+        Message { role: role.to_string(), content: content.to_string() }
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        assert_eq!(estimate_cost("gpt-4o", 1_000_000, 0), Some(2.5));
+        assert_eq!(estimate_cost("gpt-4o-mini", 0, 1_000_000), Some(0.6));
+        assert_eq!(estimate_cost("claude-opus-4-5-20251101", 1_000_000, 1_000_000), Some(30.0));
+        assert_eq!(estimate_cost("claude-opus-4-1-20250805", 0, 1_000_000), Some(75.0));
+        assert_eq!(estimate_cost("llama-3", 1000, 1000), None);
+    }
+
+    #[test]
+    fn test_session_stats() {
+        let mut session = ChatSession::new("gpt-4o".to_string(), 0.7, 1000, 5000);
+        session.add_message(message("user", "write hello world"));
+        session.add_message_with_metadata(
+            message("assistant", "Sure:\n```rust\nfn main() {}\n```\nand\n```\nplain\n```"),
+            Some("openai".to_string()),
+            Some("gpt-4o".to_string()),
+        );
+        session.messages[1].timing = Some(MessageTiming { latency_ms: 3000, first_token_ms: 1000 });
+        session.add_message(message("user", "again"));
+        session.add_message_with_metadata(message("assistant", "```rust\nfn f() {}\n```"), None, Some("claude-sonnet-4-20250514".to_string()));
+
+        let mut stats = Stats::default();
+        stats.add_session(&session);
+        assert_eq!(stats.total_messages(), 4);
+        assert_eq!(stats.messages["user"], 2);
+        assert_eq!(stats.words["user"], 4);
+        assert_eq!(stats.code_blocks["rust"], 2);
+        assert_eq!(stats.code_blocks["plain"], 1);
+        assert_eq!(stats.providers["openai"], 1);
+        assert_eq!(stats.providers["anthropic"], 1); // Inferred from the model name
+        assert_eq!(stats.timings.len(), 1);
+
+        let tokens = estimate_tokens(&session.messages[1].message.content);
+        let rate = stats.throughput().unwrap();
+        assert!((rate - tokens as f64 / 2.0).abs() < 1e-9);
+
+        let rendered = render_stats("Session statistics", &stats, false);
+        assert!(rendered.contains("Messages: 4 (Agent 2, User 2)"));
+        assert!(rendered.contains("Code blocks: 3 (rust 2, plain 1)"));
+        assert!(rendered.contains("Timing (1 of 2 responses)"));
+        assert!(rendered.contains("Time to first token: avg 1.0s"));
+        assert!(!rendered.contains("per week"));
+    }
+
+    #[test]
+    fn test_weekly_usage() {
+        let mut first = ChatSession::new("gpt-4o".to_string(), 0.7, 1000, 5000);
+        first.add_message_with_metadata(message("assistant", "12345678"), None, Some("gpt-4o".to_string()));
+        first.messages[0].timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 12, 0, 0).unwrap();
+        let mut second = first.clone();
+        second.messages[0].timestamp = Utc.with_ymd_and_hms(2026, 1, 11, 12, 0, 0).unwrap();
+        second.add_message_with_metadata(message("assistant", "1234"), None, Some("gpt-4o".to_string()));
+        second.messages[1].timestamp = Utc.with_ymd_and_hms(2026, 1, 12, 12, 0, 0).unwrap();
+
+        let mut stats = Stats::default();
+        stats.add_session(&first);
+        stats.add_session(&second);
+        assert_eq!(stats.sessions, 2);
+        assert_eq!(stats.weekly[&("2026-W02".to_string(), "gpt-4o".to_string())], WeeklyUsage { responses: 2, tokens: 4 });
+        assert_eq!(stats.weekly[&("2026-W03".to_string(), "gpt-4o".to_string())], WeeklyUsage { responses: 1, tokens: 1 });
+        assert!(stats.throughput().is_none());
+        assert!(render_stats("All sessions", &stats, true).contains("Usage per model per week"));
+    }
+}
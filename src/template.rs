@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::encryption::{read_state_file, write_state_file};
+use crate::providers::{get_provider_for_model, Message};
+use crate::session::ChatSession;
+use crate::variable::{Variable, VariableSource};
+
+/// State file holding templates saved with /template save
+pub const TEMPLATES_FILE: &str = "templates";
+
+/// Directory of hand-written `NAME.json` templates, next to the state files
+pub const TEMPLATE_DIRECTORY: &str = "templates.d";
+
+/// Starting setup for new sessions. Settings left out fall back to the defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub thinking: Option<bool>,
+    pub thinking_budget: Option<u32>,
+    pub system_prompt: Option<String>,
+    pub variables: BTreeMap<String, String>, // Name -> source as typed in /var (=text, @path, !cmd)
+    pub messages: Vec<Message>,              // Seed messages
+    pub tags: Vec<String>,
+}
+
+impl SessionTemplate {
+    pub fn from_session(session: &ChatSession) -> Self {
+        Self {
+            description: session.description.clone(),
+            model: Some(session.current_model.clone()),
+            temperature: Some(session.temperature),
+            max_tokens: Some(session.max_tokens),
+            thinking: Some(session.thinking_enabled),
+            thinking_budget: Some(session.thinking_budget),
+            system_prompt: session.system_prompt.clone(),
+            variables: session.variables.iter().map(|(name, var)| (name.clone(), var.source.spec())).collect(),
+            messages: session.messages.iter().map(|msg| msg.message.clone()).collect(),
+            tags: session.tags.clone(),
+        }
+    }
+
+    /// Apply the template on top of a session created with the defaults.
+    /// Variables are bound to their sources, not evaluated.
+    pub fn instantiate(&self, mut session: ChatSession) -> Result<ChatSession> {
+        let mut variables = HashMap::new();
+        for (name, spec) in &self.variables {
+            let source = VariableSource::parse(spec).map_err(|e| anyhow!("Variable '{}': {}", name, e))?;
+            variables.insert(name.clone(), Variable::new(name.clone(), source));
+        }
+        session.variables = variables;
+
+        if let Some(model) = &self.model {
+            session.current_model = model.clone();
+        }
+        session.current_provider = get_provider_for_model(&session.current_model).map(|s| s.to_string());
+        if let Some(temperature) = self.temperature {
+            session.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            session.max_tokens = max_tokens;
+        }
+        if let Some(thinking) = self.thinking {
+            session.thinking_enabled = thinking;
+        }
+        if let Some(thinking_budget) = self.thinking_budget {
+            session.thinking_budget = thinking_budget;
+        }
+        if self.system_prompt.is_some() {
+            session.system_prompt = self.system_prompt.clone();
+        }
+        session.tags = self.tags.clone();
+        for message in &self.messages {
+            session.add_message(message.clone());
+        }
+        Ok(session)
+    }
+}
+
+/// Where a template was found
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateOrigin {
+    Saved,
+    File(PathBuf),
+    Global, // Saved or file template of the global state directory, seen from a workspace
+}
+
+/// Templates of the active state directory, layered over the global ones inside a workspace
+#[derive(Debug, Clone, Default)]
+pub struct TemplateLibrary {
+    templates: BTreeMap<String, (SessionTemplate, TemplateOrigin)>,
+    saved: BTreeMap<String, SessionTemplate>, // The active directory's templates file
+    saved_file: PathBuf,
+}
+
+impl TemplateLibrary {
+    /// Load `state_directory`, on top of `global_directory` when given.
+    /// Unreadable template files are reported and skipped.
+    pub fn load(state_directory: &str, global_directory: Option<&str>) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let mut templates = BTreeMap::new();
+        if let Some(global) = global_directory {
+            let (saved, files) = Self::load_directory(global, &mut warnings);
+            for (name, template) in saved.into_iter().chain(files.into_iter().map(|(name, (template, _))| (name, template))) {
+                templates.insert(name, (template, TemplateOrigin::Global));
+            }
+        }
+
+        let (saved, files) = Self::load_directory(state_directory, &mut warnings);
+        for (name, template) in &saved {
+            templates.insert(name.clone(), (template.clone(), TemplateOrigin::Saved));
+        }
+        // A hand-written file wins over a saved template of the same name
+        for (name, (template, path)) in files {
+            templates.insert(name, (template, TemplateOrigin::File(path)));
+        }
+
+        let library = Self {
+            templates,
+            saved,
+            saved_file: Path::new(state_directory).join(TEMPLATES_FILE),
+        };
+        (library, warnings)
+    }
+
+    /// The templates file and the template files of one state directory
+    #[allow(clippy::type_complexity)]
+    fn load_directory(
+        state_directory: &str,
+        warnings: &mut Vec<String>,
+    ) -> (BTreeMap<String, SessionTemplate>, BTreeMap<String, (SessionTemplate, PathBuf)>) {
+        let mut saved = BTreeMap::new();
+        let saved_file = Path::new(state_directory).join(TEMPLATES_FILE);
+        if saved_file.exists() {
+            match read_state_file(&saved_file).and_then(|content| Ok(serde_json::from_str(&content)?)) {
+                Ok(templates) => saved = templates,
+                Err(e) => warnings.push(format!("Failed to load templates from {}: {}", saved_file.display(), e)),
+            }
+        }
+
+        let mut files = BTreeMap::new();
+        let Ok(entries) = fs::read_dir(Path::new(state_directory).join(TEMPLATE_DIRECTORY)) else {
+            return (saved, files);
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+                continue;
+            };
+            match fs::read_to_string(&path).map_err(anyhow::Error::from).and_then(|content| Ok(serde_json::from_str(&content)?)) {
+                Ok(template) => {
+                    files.insert(name, (template, path));
+                }
+                Err(e) => warnings.push(format!("Failed to load template {}: {}", path.display(), e)),
+            }
+        }
+        (saved, files)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SessionTemplate> {
+        self.templates.get(name).map(|(template, _)| template)
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (&String, &SessionTemplate, &TemplateOrigin)> {
+        self.templates.iter().map(|(name, (template, origin))| (name, template, origin))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.templates.keys().cloned().collect()
+    }
+
+    /// Save (or replace) a template in the active directory's templates file
+    pub fn save(&mut self, name: &str, template: SessionTemplate) -> Result<()> {
+        if let Some((_, TemplateOrigin::File(path))) = self.templates.get(name) {
+            return Err(anyhow!("Template '{}' is defined in {}. Edit that file instead", name, path.display()));
+        }
+        self.saved.insert(name.to_string(), template.clone());
+        write_state_file(&self.saved_file, &serde_json::to_string_pretty(&self.saved)?)?;
+        self.templates.insert(name.to_string(), (template, TemplateOrigin::Saved));
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<()> {
+        match self.templates.get(name).map(|(_, origin)| origin) {
+            None => return Err(anyhow!("Template '{}' not found", name)),
+            Some(TemplateOrigin::File(path)) => {
+                return Err(anyhow!("Template '{}' is defined in {}. Remove that file instead", name, path.display()));
+            }
+            Some(TemplateOrigin::Global) => {
+                return Err(anyhow!("Template '{}' belongs to the global state and can't be deleted from a workspace", name));
+            }
+            Some(TemplateOrigin::Saved) => {}
+        }
+        self.saved.remove(name);
+        write_state_file(&self.saved_file, &serde_json::to_string_pretty(&self.saved)?)?;
+        self.templates.remove(name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_round_trip() {
+        let mut session = ChatSession::new("claude-sonnet-4-20250514".to_string(), 0.2, 4000, 8000);
+        session.thinking_enabled = true;
+        session.system_prompt = Some("You review code".to_string());
+        session.variables.insert(
+            "diff".to_string(),
+            Variable::new("diff".to_string(), VariableSource::parse("!git diff").unwrap()),
+        );
+        session.add_message(Message { role: "user".to_string(), content: "Review {{diff}}".to_string() });
+
+        let template = SessionTemplate::from_session(&session);
+        assert_eq!(template.variables["diff"], "!git diff");
+
+        let fresh = template.instantiate(ChatSession::new("gpt-4o".to_string(), 0.7, 1000, 5000)).unwrap();
+        assert_eq!(fresh.current_model, "claude-sonnet-4-20250514");
+        assert_eq!(fresh.current_provider.as_deref(), Some("anthropic"));
+        assert_eq!(fresh.temperature, 0.2);
+        assert!(fresh.thinking_enabled);
+        assert_eq!(fresh.system_prompt.as_deref(), Some("You review code"));
+        assert_eq!(fresh.variables["diff"].source, session.variables["diff"].source);
+        assert_eq!(fresh.messages.len(), 1);
+        assert_ne!(fresh.id, session.id);
+        assert!(fresh.name.is_none());
+    }
+
+    #[test]
+    fn test_template_library() {
+        let global = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let global_dir = global.path().to_string_lossy().to_string();
+        let project_dir = project.path().to_string_lossy().to_string();
+
+        let (mut library, _) = TemplateLibrary::load(&global_dir, None);
+        library.save("review", SessionTemplate { model: Some("gpt-4o".to_string()), ..Default::default() }).unwrap();
+
+        fs::create_dir_all(project.path().join(TEMPLATE_DIRECTORY)).unwrap();
+        fs::write(
+            project.path().join(TEMPLATE_DIRECTORY).join("triage.json"),
+            r#"{"model": "gemini-2.5-pro", "variables": {"log": "@build.log"}}"#,
+        ).unwrap();
+        fs::write(project.path().join(TEMPLATE_DIRECTORY).join("broken.json"), "{").unwrap();
+
+        let (mut library, warnings) = TemplateLibrary::load(&project_dir, Some(&global_dir));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(library.names(), vec!["review", "triage"]);
+        assert_eq!(library.get("triage").unwrap().temperature, None);
+        assert!(library.delete("review").is_err()); // Global
+        assert!(library.save("triage", SessionTemplate::default()).is_err()); // Defined in a file
+
+        library.save("review", SessionTemplate::default()).unwrap();
+        let (reloaded, _) = TemplateLibrary::load(&project_dir, Some(&global_dir));
+        assert!(reloaded.get("review").unwrap().model.is_none());
+        let (mut reloaded, _) = TemplateLibrary::load(&project_dir, None);
+        reloaded.delete("review").unwrap();
+        assert!(TemplateLibrary::load(&project_dir, None).0.get("review").is_none());
+    }
+}
@@ -1,5 +1,7 @@
+use crate::events::{self, outln, Event};
 use crate::input_history::InputHistory;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
@@ -8,13 +10,14 @@ use rustyline::history::DefaultHistory;
 use rustyline::validate::Validator;
 use rustyline::{Config, Editor, Helper};
 use std::io::{self, Write};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::time::Duration;
 
 pub struct UI {
     editor: Editor<NjordCompleter, DefaultHistory>,
     spinner_active: Arc<AtomicBool>,
+    streaming_agent: AtomicUsize, // Agent number for content and thinking delta events
     input_history: InputHistory,
     ephemeral: bool,
 }
@@ -23,8 +26,13 @@ pub struct UI {
 pub struct CompletionContext {
     pub available_models: Vec<String>,
     pub session_names: Vec<String>,
+    pub session_tags: Vec<String>,
+    pub trashed_session_names: Vec<String>,
+    pub backup_timestamps: Vec<String>,
     pub prompt_names: Vec<String>,
+    pub template_names: Vec<String>,
     pub variable_names: Vec<String>,
+    pub bookmark_names: Vec<String>, // Of the current session
 }
 
 impl CompletionContext {
@@ -32,8 +40,13 @@ impl CompletionContext {
         Self {
             available_models: Vec::new(),
             session_names: Vec::new(),
+            session_tags: Vec::new(),
+            trashed_session_names: Vec::new(),
+            backup_timestamps: Vec::new(),
             prompt_names: Vec::new(),
+            template_names: Vec::new(),
             variable_names: Vec::new(),
+            bookmark_names: Vec::new(),
         }
     }
 }
@@ -62,7 +75,21 @@ impl NjordCompleter {
             "/history",
             "/chat",
             "/undo",
+            "/redo",
             "/goto",
+            "/mark",
+            "/unmark",
+            "/marks",
+            "/note",
+            "/hide",
+            "/unhide",
+            "/pin",
+            "/unpin",
+            "/inspect",
+            "/reproduce",
+            "/compare",
+            "/source",
+            "/journal",
             "/search",
             "/system",
             "/temp",
@@ -87,6 +114,11 @@ impl NjordCompleter {
             "/set-default",
             "/get-defaults",
             "/reset-defaults",
+            "/backup",
+            "/encryption",
+            "/workspace",
+            "/retention",
+            "/template",
         ];
 
         if line[..pos].starts_with('/') && !line[..pos].contains(' ') {
@@ -105,6 +137,18 @@ impl NjordCompleter {
         // Handle specific command completions
         if line[..pos].starts_with("/chat ") {
             return self.complete_chat_command(line, pos);
+        } else if line[..pos].starts_with("/compare ") {
+            // Model names until the prompt starts after "--"
+            if line[..pos].split_whitespace().any(|word| word == "--") {
+                return Vec::new();
+            }
+            let start_pos = self.find_completion_start(line, pos);
+            let current_word = &line[start_pos..pos];
+            let mut words: Vec<&str> = self.context.available_models.iter().map(|model| model.as_str()).collect();
+            if line[..pos].split_whitespace().count() <= 2 {
+                words.push("adopt");
+            }
+            return self.complete_words(&words, current_word);
         } else if line[..pos].starts_with("/model ") {
             return self.complete_model_command(line, pos);
         } else if line[..pos].starts_with("/thinking ") {
@@ -125,6 +169,84 @@ impl NjordCompleter {
             return self.complete_var_command(line, pos);
         } else if line[..pos].starts_with("/set-default ") {
             return self.complete_set_default_command(line, pos);
+        } else if line[..pos].starts_with("/backup ") {
+            return self.complete_backup_command(line, pos);
+        } else if line[..pos].starts_with("/encryption ") {
+            let start_pos = self.find_completion_start(line, pos);
+            let current_word = &line[start_pos..pos];
+            let parts: Vec<&str> = line[..pos].split_whitespace().collect();
+            if parts.len() == 1 || (parts.len() == 2 && !line[..pos].ends_with(' ')) {
+                return self.complete_words(&["status", "enable", "rotate", "disable"], current_word);
+            } else if matches!(parts[1], "enable" | "rotate") && current_word.starts_with('-') {
+                return self.complete_words(&["--keyfile"], current_word);
+            }
+            return Vec::new();
+        } else if line[..pos].starts_with("/workspace ") {
+            let start_pos = self.find_completion_start(line, pos);
+            let current_word = &line[start_pos..pos];
+            let parts: Vec<&str> = line[..pos].split_whitespace().collect();
+            if parts.len() == 1 || (parts.len() == 2 && !line[..pos].ends_with(' ')) {
+                return self.complete_words(&["show", "init", "switch", "add-var", "remove-var"], current_word);
+            } else if parts[1] == "switch" && (parts.len() == 2 || (parts.len() == 3 && !line[..pos].ends_with(' '))) {
+                return self.complete_words(&["global"], current_word);
+            } else if matches!(parts[1], "add-var" | "remove-var") {
+                let names: Vec<&str> = self.context.variable_names.iter().map(|name| name.as_str()).collect();
+                return self.complete_words(&names, current_word);
+            }
+            return Vec::new();
+        } else if line[..pos].starts_with("/template ") {
+            let start_pos = self.find_completion_start(line, pos);
+            let current_word = &line[start_pos..pos];
+            let parts: Vec<&str> = line[..pos].split_whitespace().collect();
+            if parts.len() == 1 || (parts.len() == 2 && !line[..pos].ends_with(' ')) {
+                return self.complete_words(&["list", "save", "delete"], current_word);
+            } else if matches!(parts[1], "save" | "delete") {
+                let names: Vec<&str> = self.context.template_names.iter().map(|name| name.as_str()).collect();
+                return self.complete_words(&names, current_word);
+            }
+            return Vec::new();
+        } else if line[..pos].starts_with("/goto ") || line[..pos].starts_with("/unmark ") {
+            let start_pos = self.find_completion_start(line, pos);
+            let names: Vec<&str> = self.context.bookmark_names.iter().map(|name| name.as_str()).collect();
+            return self.complete_words(&names, &line[start_pos..pos]);
+        } else if ["/mark ", "/note ", "/hide ", "/unhide ", "/pin ", "/unpin "].iter().any(|command| line[..pos].starts_with(command)) {
+            let start_pos = self.find_completion_start(line, pos);
+            if line[..pos].split_whitespace().count() <= 2 && !line[..pos].trim_end().contains(char::is_numeric) {
+                return self.complete_words(&["agent", "user"], &line[start_pos..pos]);
+            }
+            return Vec::new();
+        } else if line[..pos].starts_with("/journal ") {
+            let start_pos = self.find_completion_start(line, pos);
+            return self.complete_words(&["revert"], &line[start_pos..pos]);
+        } else if line[..pos].starts_with("/stats ") {
+            let start_pos = self.find_completion_start(line, pos);
+            return self.complete_words(&["--all"], &line[start_pos..pos]);
+        } else if line[..pos].starts_with("/retention ") {
+            let start_pos = self.find_completion_start(line, pos);
+            let current_word = &line[start_pos..pos];
+            let parts: Vec<&str> = line[..pos].split_whitespace().collect();
+            if parts.len() == 1 || (parts.len() == 2 && !line[..pos].ends_with(' ')) {
+                return self.complete_words(&["show", "preview", "run", "set"], current_word);
+            } else if parts[1] == "set" && (parts.len() == 2 || (parts.len() == 3 && !line[..pos].ends_with(' '))) {
+                return self.complete_words(
+                    &["max-age", "max-sessions", "max-size", "action", "exempt-tags", "exempt-named", "input-max-age"],
+                    current_word,
+                );
+            } else if parts.len() >= 3 && parts[1] == "set" && parts[2] == "action" {
+                return self.complete_words(&["trash", "archive"], current_word);
+            }
+            return Vec::new();
+        } else if line[..pos].starts_with("/search ") {
+            // Only suggest filters once a word is started, so plain search text isn't interrupted
+            let start_pos = self.find_completion_start(line, pos);
+            let current_word = &line[start_pos..pos];
+            if current_word.is_empty() {
+                return Vec::new();
+            }
+            return self.complete_words(
+                &["role:user", "role:assistant", "model:", "session:", "before:", "after:", "in:code"],
+                current_word,
+            );
         }
 
         // Check for variable references in regular text
@@ -154,9 +276,19 @@ impl NjordCompleter {
                 "fork",
                 "branch",
                 "merge",
+                "diff",
                 "rename",
                 "auto-rename",
                 "auto-rename-all",
+                "import",
+                "share",
+                "receive",
+                "tag",
+                "untag",
+                "describe",
+                "trash",
+                "restore",
+                "purge",
             ];
 
             return subcommands
@@ -170,17 +302,70 @@ impl NjordCompleter {
         } else if parts.len() >= 2 {
             // Complete session names for commands that need them
             let subcommand = parts[1];
-            if matches!(
+            let previous = if input.ends_with(' ') {
+                parts.last().copied()
+            } else {
+                parts.iter().rev().nth(1).copied()
+            };
+            if matches!(subcommand, "tag" | "untag" | "describe" | "list") && previous == Some("--session") {
+                return self.complete_session_references(current_word);
+            } else if subcommand == "list" {
+                return self.complete_chat_list_options(previous, current_word);
+            } else if matches!(subcommand, "tag" | "untag") {
+                if current_word.starts_with('-') {
+                    return self.complete_words(&["--session"], current_word);
+                }
+                let tags: Vec<&str> = self.context.session_tags.iter().map(|t| t.as_str()).collect();
+                return self.complete_words(&tags, current_word);
+            } else if subcommand == "describe" && parts.len() <= 3 && current_word.starts_with('-') {
+                return self.complete_words(&["--session"], current_word);
+            } else if subcommand == "merge" && current_word.starts_with('-') {
+                return self.complete_words(&["--append", "--interleave"], current_word);
+            } else if matches!(
                 subcommand,
-                "load" | "delete" | "continue" | "merge" | "auto-rename"
+                "load" | "delete" | "continue" | "merge" | "diff" | "auto-rename"
             ) {
                 return self.complete_session_references(current_word);
+            } else if matches!(subcommand, "restore" | "purge") {
+                // These operate on the trash rather than saved sessions
+                if subcommand == "restore" && parts.len() >= 3 && current_word.starts_with('-') {
+                    return self.complete_words(&["--as"], current_word);
+                }
+                if subcommand == "purge" && current_word.starts_with('-') {
+                    return self.complete_words(&["--yes"], current_word);
+                }
+                return self.complete_references(&self.context.trashed_session_names, current_word);
             } else if subcommand == "branch" {
                 // For branch command, complete session names for the first argument (source session)
                 return self.complete_session_references(current_word);
             } else if subcommand == "rename" && parts.len() >= 3 {
                 // For rename command, complete session names for the second argument (old_name)
                 return self.complete_session_references(current_word);
+            } else if subcommand == "import" && parts.len() <= 3 && !(parts.len() == 3 && input.ends_with(' ')) {
+                // First argument is the export format; the path is free-form
+                return ["chatgpt", "claude"]
+                    .iter()
+                    .filter(|format| format.starts_with(current_word))
+                    .map(|format| Pair {
+                        display: format.to_string(),
+                        replacement: format.to_string(),
+                    })
+                    .collect();
+            } else if subcommand == "share" {
+                if current_word.starts_with('-') {
+                    return self.complete_words(&["--strip-secrets"], current_word);
+                } else if parts.len() == 2 || (parts.len() == 3 && !input.ends_with(' ')) {
+                    return self.complete_session_references(current_word);
+                }
+                return Vec::new();
+            } else if subcommand == "receive" && current_word.starts_with('-') {
+                return self.complete_words(&["--allow-commands", "--as"], current_word);
+            } else if subcommand == "new" {
+                if previous == Some("--template") {
+                    let names: Vec<&str> = self.context.template_names.iter().map(|name| name.as_str()).collect();
+                    return self.complete_words(&names, current_word);
+                }
+                return self.complete_words(&["--template"], current_word);
             } else if matches!(subcommand, "save" | "fork") {
                 // These commands take new session names, no completion needed
                 return Vec::new();
@@ -190,14 +375,47 @@ impl NjordCompleter {
         Vec::new()
     }
 
-    fn complete_session_names(&self, current_word: &str) -> Vec<Pair> {
+    fn complete_chat_list_options(&self, previous: Option<&str>, current_word: &str) -> Vec<Pair> {
+        match previous {
+            Some("--tag") => {
+                let tags: Vec<&str> = self.context.session_tags.iter().map(|t| t.as_str()).collect();
+                self.complete_words(&tags, current_word)
+            }
+            Some("--model") => {
+                let models: Vec<&str> = self.context.available_models.iter().map(|m| m.as_str()).collect();
+                self.complete_words(&models, current_word)
+            }
+            Some("--provider") => self.complete_words(&["openai", "anthropic", "gemini"], current_word),
+            Some("--sort") => self.complete_words(&["updated", "created", "name", "messages"], current_word),
+            Some("--since") | Some("--until") | Some("--name") | Some("--limit") | Some("--page") => Vec::new(),
+            _ => self.complete_words(
+                &[
+                    "--tag", "--model", "--provider", "--since", "--until", "--name", "--sort", "--reverse",
+                    "--limit", "--page",
+                ],
+                current_word,
+            ),
+        }
+    }
+
+    fn complete_words(&self, words: &[&str], current_word: &str) -> Vec<Pair> {
+        words
+            .iter()
+            .filter(|word| word.starts_with(current_word))
+            .map(|word| Pair {
+                display: word.to_string(),
+                replacement: word.to_string(),
+            })
+            .collect()
+    }
+
+    fn complete_session_names(&self, names: &[String], current_word: &str) -> Vec<Pair> {
         // Remove quotes from current_word for matching
         let unquoted_current = self.unquote_for_matching(current_word);
         let is_quoted_input =
             current_word.trim().starts_with('"') || current_word.trim().starts_with('\'');
 
-        self.context
-            .session_names
+        names
             .iter()
             .filter(|name| name.starts_with(&unquoted_current))
             .map(|name| {
@@ -317,7 +535,7 @@ impl NjordCompleter {
         let start_pos = self.find_completion_start(line, pos);
         let current_word = &line[start_pos..pos];
 
-        ["on", "off"]
+        ["on", "off", "show"]
             .iter()
             .filter(|option| option.starts_with(current_word))
             .map(|option| Pair {
@@ -330,8 +548,31 @@ impl NjordCompleter {
     fn complete_export_command(&self, line: &str, pos: usize) -> Vec<Pair> {
         let start_pos = self.find_completion_start(line, pos);
         let current_word = &line[start_pos..pos];
+        let input = &line[..pos];
+        let parts: Vec<&str> = input.split_whitespace().collect();
 
-        ["markdown", "json", "txt"]
+        if parts.len() > 2 || (parts.len() == 2 && input.ends_with(' ')) {
+            let previous = if input.ends_with(' ') {
+                parts.last()
+            } else {
+                parts.get(parts.len() - 2)
+            };
+            if previous == Some(&"--session") {
+                return self.complete_session_references(current_word);
+            }
+
+            // Options after the format; anything else is a filename
+            return ["--all", "--session", "--thinking"]
+                .iter()
+                .filter(|option| current_word.starts_with('-') && option.starts_with(current_word))
+                .map(|option| Pair {
+                    display: option.to_string(),
+                    replacement: option.to_string(),
+                })
+                .collect();
+        }
+
+        ["markdown", "json", "html", "txt"]
             .iter()
             .filter(|format| format.starts_with(current_word))
             .map(|format| Pair {
@@ -350,6 +591,10 @@ impl NjordCompleter {
     }
 
     fn complete_session_references(&self, current_word: &str) -> Vec<Pair> {
+        self.complete_references(&self.context.session_names, current_word)
+    }
+
+    fn complete_references(&self, names: &[String], current_word: &str) -> Vec<Pair> {
         let mut completions = Vec::new();
 
         // Add ephemeral references (#1, #2, etc.) if current word starts with #
@@ -367,7 +612,7 @@ impl NjordCompleter {
         }
 
         // Add regular session name completions
-        completions.extend(self.complete_session_names(current_word));
+        completions.extend(self.complete_session_names(names, current_word));
 
         completions
     }
@@ -379,10 +624,12 @@ impl NjordCompleter {
         let parts: Vec<&str> = input.split_whitespace().collect();
 
         if parts.len() == 1 || (parts.len() == 2 && !input.ends_with(' ')) {
-            // Complete copy type
+            // Complete copy type or a bookmark of the current session
             let types = ["agent", "user", "block"];
             types
                 .iter()
+                .copied()
+                .chain(self.context.bookmark_names.iter().map(|name| name.as_str()))
                 .filter(|t| t.starts_with(current_word))
                 .map(|t| Pair {
                     display: t.to_string(),
@@ -402,10 +649,12 @@ impl NjordCompleter {
         let parts: Vec<&str> = input.split_whitespace().collect();
 
         if parts.len() == 1 || (parts.len() == 2 && !input.ends_with(' ')) {
-            // Complete save type
+            // Complete save type or a bookmark of the current session
             let types = ["agent", "user", "block"];
             types
                 .iter()
+                .copied()
+                .chain(self.context.bookmark_names.iter().map(|name| name.as_str()))
                 .filter(|t| t.starts_with(current_word))
                 .map(|t| Pair {
                     display: t.to_string(),
@@ -748,6 +997,22 @@ impl NjordCompleter {
             .collect()
     }
 
+    fn complete_backup_command(&self, line: &str, pos: usize) -> Vec<Pair> {
+        let start_pos = self.find_completion_start(line, pos);
+        let current_word = &line[start_pos..pos];
+        let input = &line[..pos];
+        let parts: Vec<&str> = input.split_whitespace().collect();
+
+        if parts.len() == 1 || (parts.len() == 2 && !input.ends_with(' ')) {
+            return self.complete_words(&["list", "now", "restore"], current_word);
+        } else if parts[1] == "restore" && parts.len() <= 3 {
+            let timestamps: Vec<&str> = self.context.backup_timestamps.iter().map(|t| t.as_str()).collect();
+            return self.complete_words(&timestamps, current_word);
+        }
+
+        Vec::new()
+    }
+
     fn complete_set_default_command(&self, line: &str, pos: usize) -> Vec<Pair> {
         let start_pos = self.find_completion_start(line, pos);
         let current_word = &line[start_pos..pos];
@@ -972,6 +1237,7 @@ impl UI {
         Ok(Self {
             editor,
             spinner_active: Arc::new(AtomicBool::new(false)),
+            streaming_agent: AtomicUsize::new(0),
             input_history,
             ephemeral: true,
         })
@@ -997,6 +1263,7 @@ impl UI {
         Ok(Self {
             editor,
             spinner_active: Arc::new(AtomicBool::new(false)),
+            streaming_agent: AtomicUsize::new(0),
             input_history,
             ephemeral: false,
         })
@@ -1428,11 +1695,20 @@ impl UI {
     }
 
     pub fn print_agent_prefix(&self, number: usize) {
+        if events::is_structured() {
+            self.streaming_agent.store(number, Ordering::Relaxed);
+            return;
+        }
         print!("\x1b[1;35mAgent {}\x1b[0m: ", number);
         io::stdout().flush().unwrap();
     }
 
     pub fn print_agent_chunk(&self, chunk: &str) {
+        if events::is_structured() {
+            let number = self.streaming_agent.load(Ordering::Relaxed);
+            events::emit(Event::ContentDelta { number, text: chunk.to_string() });
+            return;
+        }
         // Check if this chunk contains code block markers and style them
         if chunk.contains("```") {
             let styled_chunk = self.style_code_blocks(chunk);
@@ -1444,30 +1720,52 @@ impl UI {
     }
 
     pub fn print_thinking_prefix(&self, number: usize) {
+        if events::is_structured() {
+            self.streaming_agent.store(number, Ordering::Relaxed);
+            return;
+        }
         print!("\x1b[1;35mAgent {} (thinking)\x1b[0m: ", number);
         io::stdout().flush().unwrap();
     }
 
     pub fn print_thinking_chunk(&self, chunk: &str) {
+        if events::is_structured() {
+            let number = self.streaming_agent.load(Ordering::Relaxed);
+            events::emit(Event::ThinkingDelta { number, text: chunk.to_string() });
+            return;
+        }
         print!("\x1b[2;3m{}\x1b[0m", chunk); // Dimmed and italic text for thinking
         io::stdout().flush().unwrap();
     }
 
     pub fn print_thinking_end(&self) {
-        println!();
+        if !events::is_structured() {
+            println!();
+        }
     }
 
     pub fn print_agent_newline(&self) {
+        if events::is_structured() {
+            return;
+        }
         println!();
         println!("\x1b[2m────────────────────────────────────────\x1b[0m"); // Dimmed horizontal rule
         println!();
     }
 
     pub fn print_error(&self, error: &str) {
+        if events::is_structured() {
+            events::emit(Event::Error { message: error.to_string() });
+            return;
+        }
         eprintln!("\x1b[1;31mError\x1b[0m: {}", error);
     }
 
     pub fn print_info(&self, info: &str) {
+        if events::is_structured() {
+            events::emit(Event::Info { message: info.to_string() });
+            return;
+        }
         println!("\x1b[1;33mInfo\x1b[0m: {}", info);
     }
 
@@ -1507,18 +1805,18 @@ impl UI {
     pub fn print_styled_code_block(&self, content: &str, language: Option<&str>) {
         // Print opening fence with language
         if let Some(lang) = language {
-            println!("\x1b[0;36m```{}\x1b[0m", lang);
+            outln!("\x1b[0;36m```{}\x1b[0m", lang);
         } else {
-            println!("\x1b[0;36m```\x1b[0m");
+            outln!("\x1b[0;36m```\x1b[0m");
         }
 
         // Print each line with cyan coloring
         for line in content.lines() {
-            println!("\x1b[0;36m{}\x1b[0m", line);
+            outln!("\x1b[0;36m{}\x1b[0m", line);
         }
 
         // Print closing fence
-        println!("\x1b[0;36m```\x1b[0m");
+        outln!("\x1b[0;36m```\x1b[0m");
     }
 
     fn handle_multiline_history_entry(
@@ -1704,6 +2002,42 @@ impl UI {
         }
     }
 
+    /// Re-read input history from disk, e.g. after restoring a backup
+    pub fn reload_input_history(&mut self) -> Result<()> {
+        self.load_input_history(self.input_history.file_path().to_string())
+    }
+
+    /// Save the current input history and continue with another file, e.g. a workspace's
+    pub fn switch_input_history(&mut self, input_history_file: String) -> Result<()> {
+        self.save_input_history()?;
+        self.load_input_history(input_history_file)
+    }
+
+    fn load_input_history(&mut self, input_history_file: String) -> Result<()> {
+        self.input_history = if self.ephemeral {
+            InputHistory::load(input_history_file.clone()).unwrap_or_else(|_| InputHistory::new(input_history_file))
+        } else {
+            InputHistory::load(input_history_file)?
+        };
+        self.editor.clear_history()?;
+        for entry in self.input_history.get_entries() {
+            let _ = self.editor.add_history_entry(&entry);
+        }
+        Ok(())
+    }
+
+    pub fn count_input_history_before(&self, cutoff: DateTime<Utc>) -> usize {
+        self.input_history.count_before(cutoff)
+    }
+
+    pub fn prune_input_history(&mut self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let removed = self.input_history.prune_before(cutoff)?;
+        if removed > 0 {
+            self.reload_input_history()?;
+        }
+        Ok(removed)
+    }
+
     pub fn get_input_history_stats(&self) -> (usize, Option<String>) {
         let count = self.input_history.len();
         let last_entry = self.input_history.get_entries().last().cloned();
@@ -1712,7 +2046,7 @@ impl UI {
 
     /// Check if a line contains a heredoc pattern and extract the comman and delimiter
     /// Returns (command_part, delimiter) if heredoc is detected, None otherwise
-    pub fn parse_command_heredoc(&self, line: &str) -> Option<(String, String)> {
+    pub fn parse_command_heredoc(line: &str) -> Option<(String, String)> {
         let trimmed = line.trim();
 
         // Look for patterns like "/system <<EOF" or "/prompts save my-prompt <<DESC"
@@ -1823,6 +2157,14 @@ impl UI {
         let message = message.to_string();
         let spinner_active_clone = Arc::clone(&spinner_active);
 
+        // Nothing to animate when the output is being read by a program
+        if events::is_structured() {
+            return SpinnerHandle {
+                handle: tokio::spawn(async {}),
+                spinner_active,
+            };
+        }
+
         let handle = tokio::spawn(async move {
             let spinner_chars = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
             let mut interval = tokio::time::interval(Duration::from_millis(100));
@@ -43,7 +43,17 @@ impl VariableSource {
         }
     }
 
+    /// The source as it would be typed, the inverse of `parse`
+    pub fn spec(&self) -> String {
+        match self {
+            VariableSource::Literal(value) => format!("={}", value),
+            VariableSource::File(path) => format!("@{}", path.display()),
+            VariableSource::Command { cmd, .. } => format!("!{}", cmd),
+        }
+    }
+
     /// Create a command source with custom timeout
+    #[allow(dead_code)]
     pub fn command_with_timeout(cmd: String, timeout_secs: u64) -> Self {
         VariableSource::Command { cmd, timeout_secs }
     }
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::encryption::{read_state_file, write_state_file};
+use crate::variable::Variable;
+
+/// Project-local state directory, found by walking up from the current directory
+pub const WORKSPACE_DIRECTORY: &str = ".njord";
+
+/// State file holding variables that are available in every session of a state directory
+const VARIABLES_FILE: &str = "variables";
+
+/// The nearest `.njord/` directory at or above `start`
+pub fn discover_workspace(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(WORKSPACE_DIRECTORY))
+        .find(|candidate| candidate.is_dir())
+}
+
+/// Create `<project>/.njord/` if needed and return its path
+pub fn init_workspace(project: &Path) -> Result<PathBuf> {
+    let workspace = project.join(WORKSPACE_DIRECTORY);
+    fs::create_dir_all(&workspace)?;
+    Ok(workspace)
+}
+
+/// Name a workspace by the project directory that contains it
+pub fn display_name(workspace: &Path) -> String {
+    let project = workspace.parent().unwrap_or(workspace);
+    project
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| project.display().to_string())
+}
+
+pub fn load_variables(state_directory: &str) -> Result<HashMap<String, Variable>> {
+    let path = Path::new(state_directory).join(VARIABLES_FILE);
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    Ok(serde_json::from_str(&read_state_file(&path)?)?)
+}
+
+pub fn save_variables(state_directory: &str, variables: &HashMap<String, Variable>) -> Result<()> {
+    let path = Path::new(state_directory).join(VARIABLES_FILE);
+    write_state_file(&path, &serde_json::to_string_pretty(variables)?)
+}
+
+/// Saved variables of each state directory in order, later ones overriding earlier ones.
+/// Unreadable layers are reported and skipped rather than failing startup.
+pub fn layered_variables(state_directories: &[&str]) -> (HashMap<String, Variable>, Vec<String>) {
+    let mut variables = HashMap::new();
+    let mut warnings = Vec::new();
+    for state_directory in state_directories {
+        match load_variables(state_directory) {
+            Ok(layer) => variables.extend(layer),
+            Err(e) => warnings.push(format!("Failed to load variables from {}: {}", state_directory, e)),
+        }
+    }
+    (variables, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variable::VariableSource;
+
+    #[test]
+    fn test_discover_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("project/src/module");
+        fs::create_dir_all(&nested).unwrap();
+        assert!(discover_workspace(&nested).is_none_or(|found| !found.starts_with(dir.path())));
+
+        let workspace = init_workspace(&dir.path().join("project")).unwrap();
+        assert_eq!(discover_workspace(&nested), Some(workspace.clone()));
+        assert_eq!(display_name(&workspace), "project");
+    }
+
+    #[test]
+    fn test_layered_variables() {
+        let global = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let global_dir = global.path().to_string_lossy().to_string();
+        let project_dir = project.path().to_string_lossy().to_string();
+
+        let variable = |name: &str, value: &str| {
+            (name.to_string(), Variable::new(name.to_string(), VariableSource::Literal(value.to_string())))
+        };
+        save_variables(&global_dir, &HashMap::from([variable("lang", "rust"), variable("style", "terse")])).unwrap();
+        save_variables(&project_dir, &HashMap::from([variable("lang", "go")])).unwrap();
+
+        let (variables, warnings) = layered_variables(&[&global_dir, &project_dir]);
+        assert!(warnings.is_empty());
+        assert_eq!(variables.len(), 2);
+        assert_eq!(variables["lang"].source, VariableSource::Literal("go".to_string()));
+        assert_eq!(variables["style"].source, VariableSource::Literal("terse".to_string()));
+    }
+}